@@ -5,9 +5,9 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use std::env::current_dir;
 use std::fs::read_to_string;
 
-fn read_rule() -> RuleConfig<SupportLang> {
+fn read_rule(path: &str) -> RuleConfig<SupportLang> {
   let cwd = current_dir().unwrap();
-  let ts_file = cwd.join("fixtures/rules/has-rule.yml");
+  let ts_file = cwd.join(path);
   let rule = read_to_string(ts_file).unwrap();
   let mut rules = from_yaml_string(&rule, &Default::default()).unwrap();
   rules.pop().unwrap()
@@ -44,12 +44,34 @@ fn find_all_bench(c: &mut Criterion) {
 
 fn rule_bench(c: &mut Criterion) {
   let ref_sg = get_sg("fixtures/ref.ts.fixture");
-  let rule = read_rule();
+  let rule = read_rule("fixtures/rules/has-rule.yml");
   c.bench_function("test has rule", |b| {
     b.iter(|| find_pattern(&ref_sg, &rule.matcher))
   });
 }
 
+// regex in constraints and transform are compiled once when the rule is parsed
+fn regex_rule_bench(c: &mut Criterion) {
+  let files = ["checker", "ref", "tsc"];
+  let sgs: Vec<_> = files
+    .iter()
+    .cycle()
+    .take(9)
+    .map(|f| get_sg(&format!("fixtures/{f}.ts.fixture")))
+    .collect();
+  let rule = read_rule("fixtures/rules/regex-rule.yml");
+  let fixer = rule.matcher.fixer.as_ref().unwrap();
+  c.bench_function("regex rule with fix over many files", |b| {
+    b.iter(|| {
+      for sg in &sgs {
+        for m in sg.root().find_all(&rule.matcher) {
+          black_box(m.replace_by(fixer));
+        }
+      }
+    })
+  });
+}
+
 fn build_pattern_bench(c: &mut Criterion) {
   let lang = SupportLang::TypeScript;
   c.bench_function("Build Normal Pattern", |b| {
@@ -57,5 +79,11 @@ fn build_pattern_bench(c: &mut Criterion) {
   });
 }
 
-criterion_group!(benches, find_all_bench, rule_bench, build_pattern_bench);
+criterion_group!(
+  benches,
+  find_all_bench,
+  rule_bench,
+  regex_rule_bench,
+  build_pattern_bench
+);
 criterion_main!(benches);
@@ -3,7 +3,7 @@ use crate::lang::{CustomLang, LanguageGlobs, SerializableInjection, SgLang};
 
 use anyhow::{Context, Result};
 use ast_grep_config::{
-  from_str, from_yaml_string, DeserializeEnv, GlobalRules, RuleCollection, RuleConfig,
+  from_str, from_yaml_string_with_source, DeserializeEnv, GlobalRules, RuleCollection, RuleConfig,
 };
 use ast_grep_language::config_file_type;
 use ignore::WalkBuilder;
@@ -14,6 +14,9 @@ use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
+/// Default directory of snapshots in `test_dir`.
+pub const SNAPSHOT_DIR: &str = "__snapshots__";
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct TestConfig {
@@ -138,7 +141,7 @@ fn read_directory_yaml(
   global_rules: GlobalRules<SgLang>,
   rule_filter: Option<&Regex>,
 ) -> Result<RuleCollection<SgLang>> {
-  let mut configs = vec![];
+  let mut files = vec![];
   for dir in rule_dirs {
     let dir_path = base_dir.join(dir);
     let walker = WalkBuilder::new(&dir_path)
@@ -156,32 +159,130 @@ fn read_directory_yaml(
       }
       let path = config_file.path();
       let new_configs = read_rule_file(path, Some(&global_rules))?;
-      configs.extend(new_configs);
+      files.push((normalize_path(path), new_configs));
     }
   }
 
-  let configs = if let Some(filter) = rule_filter {
-    filter_rule_by_regex(configs, filter)?
-  } else {
-    configs
-  };
+  if let Some(filter) = rule_filter {
+    filter_rule_by_regex(&mut files, filter)?;
+  }
 
-  RuleCollection::try_new(configs).context(EC::GlobPattern)
+  let mut collection = RuleCollection::default();
+  for (path, configs) in files {
+    collection
+      .add_file_rules(path, configs)
+      .context(EC::GlobPattern)?;
+  }
+  Ok(collection)
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+  path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Read the rule file at `path` if it is in one of the project's rule directories.
+/// Returns `None` if the file is not a rule file, e.g. sgconfig.yml or util rules.
+/// A deleted rule file is read as empty.
+pub fn read_rule_file_in_project(
+  config_path: Option<PathBuf>,
+  path: &Path,
+) -> Result<Option<Vec<RuleConfig<SgLang>>>> {
+  let config_path =
+    find_config_path_with_default(config_path, None).context(EC::ReadConfiguration)?;
+  let config_str = read_to_string(&config_path).context(EC::ReadConfiguration)?;
+  let sg_config: AstGrepConfig = from_str(&config_str).context(EC::ParseConfiguration)?;
+  let base_dir = config_path
+    .parent()
+    .expect("config file must have parent directory");
+  if !is_rule_file(&sg_config, &config_path, path) {
+    return Ok(None);
+  }
+  if !path.exists() {
+    return Ok(Some(vec![]));
+  }
+  let global_rules = find_util_rules(base_dir, sg_config.util_dirs)?;
+  read_rule_file(path, Some(&global_rules)).map(Some)
+}
+
+/// Project config with its utility rules, read once and queried for every opened document,
+/// e.g. by the language server.
+pub struct ProjectConfig {
+  sg_config: AstGrepConfig,
+  config_path: PathBuf,
+  /// utility rules in `utilDirs`, empty if they cannot be read
+  global_rules: GlobalRules<SgLang>,
+}
+
+impl ProjectConfig {
+  /// Returns `None` if the project config cannot be found or read.
+  pub fn read_quietly(config_path: Option<PathBuf>) -> Option<Self> {
+    let config_path = find_config_path_with_default(config_path, None).ok()?;
+    let config_str = read_to_string(&config_path).ok()?;
+    let sg_config: AstGrepConfig = from_str(&config_str).ok()?;
+    let base_dir = config_path
+      .parent()
+      .expect("config file must have parent directory");
+    let global_rules = find_util_rules(base_dir, sg_config.util_dirs.clone()).unwrap_or_default();
+    Some(Self {
+      sg_config,
+      config_path,
+      global_rules,
+    })
+  }
+
+  /// Whether the path is a yaml file under `ruleDirs` of the project config.
+  pub fn is_rule_file(&self, path: &Path) -> bool {
+    is_rule_file(&self.sg_config, &self.config_path, path)
+  }
+
+  /// Whether the path is a yaml file of rule tests under `testConfigs` of the project config.
+  /// Snapshot files are not rule tests.
+  pub fn is_test_file(&self, path: &Path) -> bool {
+    let base_dir = self
+      .config_path
+      .parent()
+      .expect("config file must have parent directory");
+    let is_yaml = config_file_type().matched(path, false).is_whitelist();
+    let test_configs = self.sg_config.test_configs.as_deref().unwrap_or_default();
+    let in_test_dir = test_configs.iter().any(|test| {
+      let test_dir = normalize_path(&base_dir.join(&test.test_dir));
+      let snapshot_dir = test.snapshot_dir.as_deref();
+      let snapshot_dir = test_dir.join(snapshot_dir.unwrap_or_else(|| SNAPSHOT_DIR.as_ref()));
+      path.starts_with(&test_dir) && !path.starts_with(snapshot_dir)
+    });
+    in_test_dir && is_yaml
+  }
+
+  /// Global utility rules in `utilDirs` of the project config.
+  pub fn global_rules(&self) -> &GlobalRules<SgLang> {
+    &self.global_rules
+  }
+}
+
+fn is_rule_file(sg_config: &AstGrepConfig, config_path: &Path, path: &Path) -> bool {
+  let base_dir = config_path
+    .parent()
+    .expect("config file must have parent directory");
+  let in_rule_dir = sg_config
+    .rule_dirs
+    .iter()
+    .any(|dir| path.starts_with(normalize_path(&base_dir.join(dir))));
+  let is_yaml = config_file_type().matched(path, false).is_whitelist();
+  in_rule_dir && is_yaml && path != normalize_path(config_path)
 }
 
 fn filter_rule_by_regex(
-  configs: Vec<RuleConfig<SgLang>>,
+  files: &mut [(PathBuf, Vec<RuleConfig<SgLang>>)],
   filter: &Regex,
-) -> Result<Vec<RuleConfig<SgLang>>> {
-  let selected: Vec<_> = configs
-    .into_iter()
-    .filter(|c| filter.is_match(&c.id))
-    .collect();
+) -> Result<()> {
+  for (_, configs) in files.iter_mut() {
+    configs.retain(|c| filter.is_match(&c.id));
+  }
 
-  if selected.is_empty() {
+  if files.iter().all(|(_, configs)| configs.is_empty()) {
     Err(anyhow::anyhow!(EC::RuleNotFound(filter.to_string())))
   } else {
-    Ok(selected)
+    Ok(())
   }
 }
 
@@ -191,9 +292,9 @@ pub fn read_rule_file(
 ) -> Result<Vec<RuleConfig<SgLang>>> {
   let yaml = read_to_string(path).with_context(|| EC::ReadRule(path.to_path_buf()))?;
   let parsed = if let Some(globals) = global_rules {
-    from_yaml_string(&yaml, globals)
+    from_yaml_string_with_source(&yaml, globals, path)
   } else {
-    from_yaml_string(&yaml, &Default::default())
+    from_yaml_string_with_source(&yaml, &Default::default(), path)
   };
   parsed.with_context(|| EC::ParseRule(path.to_path_buf()))
 }
@@ -1,9 +1,18 @@
-use crate::config::{find_config_path_with_default, find_rules, register_custom_language};
+use crate::config::{
+  find_config_path_with_default, find_rules, read_rule_file_in_project, register_custom_language,
+  AstGrepConfig, ProjectConfig,
+};
 use crate::error::ErrorContext as EC;
+use crate::lang::SgLang;
 use anyhow::{Context, Result};
-use ast_grep_lsp::{Backend, LspService, Server};
+use ast_grep_config::{
+  from_str, from_yaml_string, GlobalRules, RuleCollection, RuleConfig, RuleConfigError,
+};
+use ast_grep_lsp::{Backend, RuleFinder, Server};
 use clap::Args;
-use std::path::PathBuf;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 #[derive(Args)]
 pub struct LspArg {
@@ -18,22 +27,133 @@ fn find_config_base(config: Option<PathBuf>) -> Result<PathBuf> {
   Ok(config_path)
 }
 
+// convert anyhow::Error to String with chain of causes
+fn error_to_string(e: anyhow::Error) -> String {
+  e.chain()
+    .map(|e| e.to_string())
+    .collect::<Vec<_>>()
+    .join(". ")
+}
+
+struct ProjectRuleFinder {
+  /// config passed by `--config`, restored when client unsets `configPath`
+  default_config: Option<PathBuf>,
+  config: RwLock<Option<PathBuf>>,
+  /// project config read on first use, `Some(None)` if it cannot be read.
+  /// Cleared when watched files change or the config path is set.
+  project: RwLock<Option<Option<Arc<ProjectConfig>>>>,
+}
+
+impl ProjectRuleFinder {
+  fn new(config: Option<PathBuf>) -> Self {
+    Self {
+      default_config: config.clone(),
+      config: RwLock::new(config),
+      project: RwLock::new(None),
+    }
+  }
+  fn config(&self) -> Option<PathBuf> {
+    self.config.read().unwrap().clone()
+  }
+  fn project(&self) -> Option<Arc<ProjectConfig>> {
+    if let Some(project) = &*self.project.read().unwrap() {
+      return project.clone();
+    }
+    // read with the lock held so a concurrent clear never keeps a stale config
+    let mut project = self.project.write().unwrap();
+    project
+      .get_or_insert_with(|| ProjectConfig::read_quietly(self.config()).map(Arc::new))
+      .clone()
+  }
+}
+
+impl RuleFinder<SgLang> for ProjectRuleFinder {
+  fn find_rules(&self) -> std::result::Result<RuleCollection<SgLang>, String> {
+    find_rules(self.config(), None).map_err(error_to_string)
+  }
+  fn read_rule_file(
+    &self,
+    path: &Path,
+  ) -> Option<std::result::Result<Vec<RuleConfig<SgLang>>, String>> {
+    read_rule_file_in_project(self.config(), path)
+      .map_err(error_to_string)
+      .transpose()
+  }
+  fn watch_patterns(&self) -> Vec<String> {
+    let Ok(config_path) = find_config_path_with_default(self.config(), None) else {
+      return vec![];
+    };
+    let Some(file_name) = config_path.file_name() else {
+      return vec![];
+    };
+    let mut patterns = vec![format!("**/{}", file_name.to_string_lossy())];
+    let Ok(config_str) = read_to_string(&config_path) else {
+      return patterns;
+    };
+    let Ok(sg_config) = from_str::<AstGrepConfig>(&config_str) else {
+      return patterns;
+    };
+    let util_dirs = sg_config.util_dirs.iter().flatten();
+    for dir in sg_config.rule_dirs.iter().chain(util_dirs) {
+      let dir = dir.to_string_lossy();
+      let dir = dir.trim_start_matches("./").trim_end_matches('/');
+      patterns.push(format!("**/{dir}/**/*.{{yml,yaml}}"));
+    }
+    patterns
+  }
+  fn is_rule_file(&self, path: &Path) -> bool {
+    self.project().map_or(false, |p| p.is_rule_file(path))
+  }
+  fn is_test_file(&self, path: &Path) -> bool {
+    self.project().map_or(false, |p| p.is_test_file(path))
+  }
+  fn util_rule_ids(&self) -> Vec<String> {
+    self
+      .project()
+      .map(|p| p.global_rules().ids())
+      .unwrap_or_default()
+  }
+  fn parse_rules(
+    &self,
+    text: &str,
+  ) -> std::result::Result<Vec<RuleConfig<SgLang>>, RuleConfigError> {
+    match self.project() {
+      Some(project) => from_yaml_string(text, project.global_rules()),
+      None => from_yaml_string(text, &GlobalRules::default()),
+    }
+  }
+  fn clear_cache(&self) {
+    *self.project.write().unwrap() = None;
+  }
+  fn set_config_path(&self, config_path: Option<PathBuf>) {
+    let config = config_path.or_else(|| self.default_config.clone());
+    *self.config.write().unwrap() = config;
+    self.clear_cache();
+  }
+  fn find_rules_in(
+    &self,
+    folder: &Path,
+  ) -> Option<std::result::Result<RuleCollection<SgLang>, String>> {
+    let config_path = find_config_path_with_default(None, Some(folder)).ok()?;
+    // configs of parent directories are not the folder's own
+    if config_path.parent() != Some(folder) {
+      return None;
+    }
+    Some(find_rules(Some(config_path), None).map_err(error_to_string))
+  }
+}
+
 async fn run_language_server_impl(arg: LspArg) -> Result<()> {
   // env_logger::init();
   register_custom_language(arg.config.clone())?;
   let stdin = tokio::io::stdin();
   let stdout = tokio::io::stdout();
   let config_base = find_config_base(arg.config.clone())?;
-  let config_result = find_rules(arg.config, None);
-  let config_result_std: std::result::Result<_, String> = config_result.map_err(|e| {
-    // convert anyhow::Error to String with chain of causes
-    e.chain()
-      .map(|e| e.to_string())
-      .collect::<Vec<_>>()
-      .join(". ")
+  let finder = ProjectRuleFinder::new(arg.config);
+  let config_result_std = finder.find_rules();
+  let (service, socket) = Backend::build_service(|client| {
+    Backend::new(client, config_base, config_result_std).with_rule_finder(finder)
   });
-  let (service, socket) =
-    LspService::build(|client| Backend::new(client, config_base, config_result_std)).finish();
   Server::new(stdin, stdout, socket).serve(service).await;
   Ok(())
 }
@@ -49,6 +169,7 @@ pub fn run_language_server(arg: LspArg) -> Result<()> {
 #[cfg(test)]
 mod test {
   use super::*;
+  use tempfile::TempDir;
 
   #[test]
   #[ignore = "test lsp later"]
@@ -56,4 +177,24 @@ mod test {
     let arg = LspArg { config: None };
     assert!(run_language_server(arg).is_err())
   }
+
+  #[test]
+  fn test_cache_project_config() {
+    let dir = TempDir::new().expect("should create dir");
+    let config = dir.path().join("sgconfig.yml");
+    std::fs::write(&config, "ruleDirs: [rules]\nutilDirs: [utils]").unwrap();
+    std::fs::create_dir(dir.path().join("utils")).unwrap();
+    let util = |id: &str| format!("id: {id}\nlanguage: TypeScript\nrule: {{ pattern: {id}() }}");
+    std::fs::write(dir.path().join("utils/a.yml"), util("a")).unwrap();
+    let finder = ProjectRuleFinder::new(Some(config));
+    assert_eq!(finder.util_rule_ids(), ["a"]);
+    assert!(finder.is_rule_file(&dir.path().join("rules/r.yml")));
+    std::fs::write(dir.path().join("utils/b.yml"), util("b")).unwrap();
+    // util rules are read once until watched files change
+    assert_eq!(finder.util_rule_ids(), ["a"]);
+    finder.clear_cache();
+    let mut ids = finder.util_rule_ids();
+    ids.sort();
+    assert_eq!(ids, ["a", "b"]);
+  }
 }
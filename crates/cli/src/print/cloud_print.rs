@@ -84,7 +84,7 @@ fn print_rule<'a, W: Write + Send + Sync>(
   rule: &RuleConfig<SgLang>,
 ) -> Result<()> {
   let mut writer = p.writer.lock().expect("should work");
-  let level = match rule.severity {
+  let level = match rule.get_severity(path) {
     Severity::Error => "error",
     Severity::Warning => "warning",
     Severity::Info => "notice",
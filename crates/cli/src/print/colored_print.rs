@@ -135,7 +135,8 @@ impl<W: WriteColor + Send + Sync> Printer for ColoredPrinter<W> {
   ) -> Result<()> {
     let config = &self.config;
     let mut writer = self.writer.lock().expect("should not fail");
-    let severity = match rule.severity {
+    let path: &str = file.name();
+    let severity = match rule.get_severity(path) {
       Severity::Error => diagnostic::Severity::Error,
       Severity::Warning => diagnostic::Severity::Warning,
       Severity::Info => diagnostic::Severity::Note,
@@ -190,7 +191,7 @@ impl<W: WriteColor + Send + Sync> Printer for ColoredPrinter<W> {
         continue;
       }
       start = range.end;
-      print_rule_title(rule, &diff.node_match, &self.styles.rule, writer)?;
+      print_rule_title(rule, path, &diff.node_match, &self.styles.rule, writer)?;
       let source = diff.get_root_text();
       let new_str = format!(
         "{}{}{}",
@@ -210,11 +211,12 @@ impl<W: WriteColor + Send + Sync> Printer for ColoredPrinter<W> {
 
 fn print_rule_title<W: WriteColor>(
   rule: &RuleConfig<SgLang>,
+  path: &Path,
   nm: &NodeMatch<SgLang>,
   style: &RuleStyle,
   writer: &mut W,
 ) -> Result<()> {
-  let (level, level_style) = match rule.severity {
+  let (level, level_style) = match rule.get_severity(path) {
     Severity::Error => ("error", style.error),
     Severity::Warning => ("warning", style.warning),
     Severity::Info => ("note", style.info),
@@ -144,6 +144,11 @@ fn print_diffs_interactive<'a>(
     if diff.range.start < end {
       continue;
     }
+    // unsafe fixes are never accepted in bulk, e.g. by `--update-all`
+    let fixer = rule.and_then(|r| r.matcher.fixer.as_ref());
+    if all && fixer.map_or(false, |f| f.is_unsafe()) {
+      continue;
+    }
     let confirm = all || {
       let (accept_curr, accept_all) =
         print_diff_and_prompt_action(interactive, path, (diff.clone(), rule))?;
@@ -246,6 +251,7 @@ fn open_in_editor(path: &Path, start_line: usize) -> Result<()> {
 #[cfg(test)]
 mod test {
   use super::*;
+  use crate::print::{JSONPrinter, JsonStyle};
   use ast_grep_config::{from_yaml_string, Fixer, GlobalRules};
   use ast_grep_core::traversal::Visitor;
   use ast_grep_core::{AstGrep, Matcher, StrDoc};
@@ -326,6 +332,23 @@ fix: ($B, lifecycle.update(['$A']))",
     assert_eq!("\n\n\n1", ret);
   }
 
+  #[test]
+  fn test_accept_all_skips_unsafe_fix() {
+    let root = AstGrep::new("a++; b--", SupportLang::TypeScript.into());
+    let safe = make_rule("rule: {pattern: $A++}\nfix: $A += 1");
+    let unsafe_fix = make_rule("rule: {pattern: $A--}\nfix: {template: $A -= 1, unsafe: true}");
+    let mut diffs = vec![];
+    for rule in [&safe, &unsafe_fix] {
+      let fixer = rule.matcher.fixer.as_ref().unwrap();
+      let rule_diffs = make_diffs(&root, &rule.matcher, fixer);
+      diffs.extend(rule_diffs.into_iter().map(|d| (d, Some(rule))));
+    }
+    let printer = InteractivePrinter::new(JSONPrinter::stdout(JsonStyle::Compact), true, false);
+    let printer = printer.expect("should create printer");
+    let (confirmed, _) = print_diffs_interactive(&printer, Path::new("a.ts"), diffs).unwrap();
+    assert_eq!(apply_rewrite(confirmed), "a += 1; b--");
+  }
+
   fn test_open_editor_respect_editor_env() {
     std::env::set_var("EDITOR", "echo");
     let exit = open_in_editor(&PathBuf::from("Cargo.toml"), 1);
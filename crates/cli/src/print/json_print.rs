@@ -142,18 +142,30 @@ fn get_range(n: &Node<'_, SgLang>) -> Range {
   }
 }
 
+/// Range of the match, which covers all statements matched by a multi-statement pattern.
+fn get_match_range(nm: &NodeMatch<'_, SgLang>) -> Range {
+  let mut range = get_range(nm);
+  let end_pos = nm.end_pos();
+  range.byte_offset = nm.range();
+  range.end = Position {
+    line: end_pos.0,
+    column: end_pos.1,
+  };
+  range
+}
+
 impl<'a> MatchJSON<'a> {
   fn new(nm: NodeMatch<'a, SgLang>, path: &'a str) -> Self {
     let display = nm.display_context(0, 0);
     let lines = format!("{}{}{}", display.leading, display.matched, display.trailing);
     MatchJSON {
       file: Cow::Borrowed(path),
-      text: nm.text(),
+      text: display.matched,
       lines,
       language: *nm.lang(),
       replacement: None,
       replacement_offsets: None,
-      range: get_range(&nm),
+      range: get_match_range(&nm),
       meta_variables: from_env(&nm),
     }
   }
@@ -199,7 +211,7 @@ impl<'a> RuleMatchJSON<'a> {
     Self {
       matched,
       rule_id: &rule.id,
-      severity: rule.severity.clone(),
+      severity: rule.get_severity(path),
       note: rule.note.clone(),
       message,
       labels,
@@ -213,7 +225,7 @@ impl<'a> RuleMatchJSON<'a> {
     Self {
       matched,
       rule_id: &rule.id,
-      severity: rule.severity.clone(),
+      severity: rule.get_severity(path),
       note: rule.note.clone(),
       message,
       labels,
@@ -437,6 +449,24 @@ mod test {
     }
   }
 
+  #[test]
+  fn test_multi_statement_json() {
+    let printer = make_test_printer(JsonStyle::Pretty);
+    let source = "let a = 1\nlet b = 2\nfoo()";
+    let grep = SgLang::from(SupportLang::Tsx).ast_grep(source);
+    let matches = grep.root().find_all("let $A = 1\nlet $B = 2");
+    printer.before_print().unwrap();
+    printer.print_matches(matches, "test.tsx".as_ref()).unwrap();
+    printer.after_print().unwrap();
+    let json_str = get_text(&printer);
+    let json: Vec<MatchJSON> = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(json[0].text, "let a = 1\nlet b = 2");
+    assert_eq!(json[0].lines, "let a = 1\nlet b = 2");
+    assert_eq!(json[0].range.byte_offset, 0..19);
+    assert_eq!(json[0].range.end.line, 1);
+    assert_eq!(json[0].range.end.column, 9);
+  }
+
   #[test]
   fn test_replace_json() {
     for &(source, pattern, replace, note) in MATCHES_CASES {
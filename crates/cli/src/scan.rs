@@ -147,7 +147,7 @@ impl<P: Printer> Worker for ScanWithConfig<P> {
       }
       for (idx, matches) in scanned.matches {
         let rule = combined.get_rule(idx);
-        if matches!(rule.severity, Severity::Error) {
+        if matches!(rule.get_severity(path), Severity::Error) {
           error_count = error_count.saturating_add(matches.len());
         }
         match_rule_on_file(path, matches, rule, &file_content, &self.printer)?;
@@ -201,7 +201,7 @@ impl<P: Printer> Worker for ScanWithRule<P> {
       let scanned = combined.scan(&grep, pre_scan, false);
       for (idx, matches) in scanned.matches {
         let rule = combined.get_rule(idx);
-        if matches!(rule.severity, Severity::Error) {
+        if matches!(rule.get_severity(&path), Severity::Error) {
           error_count = error_count.saturating_add(matches.len());
         }
         match_rule_on_file(&path, matches, rule, &file_content, &self.printer)?;
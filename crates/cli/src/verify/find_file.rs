@@ -1,5 +1,5 @@
 use super::{SnapshotCollection, TestCase, TestSnapshots};
-use crate::config::{find_config_path_with_default, AstGrepConfig};
+use crate::config::{find_config_path_with_default, AstGrepConfig, SNAPSHOT_DIR};
 use crate::error::ErrorContext as EC;
 
 use anyhow::{Context, Result};
@@ -13,8 +13,6 @@ use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
-const SNAPSHOT_DIR: &str = "__snapshots__";
-
 #[derive(Default)]
 pub struct TestHarness {
   pub test_cases: Vec<TestCase>,
@@ -136,3 +136,43 @@ fn test_sg_scan_html() -> Result<()> {
     .stdout(contains("rule-3").not());
   Ok(())
 }
+
+const OVERRIDDEN_RULE: &str = "
+id: no-some
+message: test rule
+severity: error
+language: TypeScript
+rule:
+  pattern: Some($A)
+severityOverrides:
+- files: ['**/legacy/**']
+  severity: warning
+";
+
+#[test]
+fn test_sg_scan_severity_overrides() -> Result<()> {
+  let dir = create_test_files([
+    ("rule.yml", OVERRIDDEN_RULE),
+    ("src/a.ts", "Some(123)"),
+    ("legacy/a.ts", "Some(123)"),
+  ])?;
+  Command::cargo_bin("sg")?
+    .current_dir(dir.path())
+    .args(["scan", "-r", "rule.yml"])
+    .assert()
+    .failure()
+    .stdout(contains("error[no-some]"))
+    .stdout(contains("warning[no-some]"));
+  // warnings of legacy files do not fail the scan
+  let output = Command::cargo_bin("sg")?
+    .current_dir(dir.path())
+    .args(["scan", "-r", "rule.yml", "--json", "legacy"])
+    .assert()
+    .success()
+    .get_output()
+    .stdout
+    .clone();
+  let json: Value = from_slice(&output)?;
+  assert_eq!(json[0]["severity"], "warning");
+  Ok(())
+}
@@ -5,10 +5,14 @@ use ast_grep_core::{AstGrep, Doc, Matcher, Node, NodeMatch};
 
 use bit_set::BitSet;
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
 
 pub struct ScanResult<'r, D: Doc> {
   pub diffs: Vec<(usize, NodeMatch<'r, D>)>,
   pub matches: HashMap<usize, Vec<NodeMatch<'r, D>>>,
+  /// matches silenced by `ast-grep-ignore` comments
+  pub suppressed: Vec<(usize, NodeMatch<'r, D>)>,
 }
 
 struct Suppressions(HashMap<usize, Suppression>);
@@ -81,20 +85,17 @@ pub struct PreScan {
   suppressions: Suppressions,
 }
 
-/// A struct to group all rules according to their potential kinds.
-/// This can greatly reduce traversal times and skip unmatchable rules.
-/// Rules are referenced by their index in the rules vector.
-pub struct CombinedScan<'r, L: Language> {
-  rules: Vec<&'r RuleConfig<L>>,
+/// Rule indexes grouped by node kinds. It only depends on the rules of a scan,
+/// so it can be computed once and shared by scans of the same rules.
+pub struct ScanKinds {
   /// a vec of vec, mapping from kind to a list of rule index
   kind_rule_mapping: Vec<Vec<usize>>,
+  /// a vec of vec, mapping from kind to rules that do not descend into the kind
+  kind_prune_mapping: Vec<Vec<usize>>,
 }
 
-impl<'r, L: Language> CombinedScan<'r, L> {
-  pub fn new(mut rules: Vec<&'r RuleConfig<L>>) -> Self {
-    // process fixable rule first, the order by id
-    // note, mapping.push will invert order so we sort fixable order in reverse
-    rules.sort_unstable_by_key(|r| (r.fix.is_some(), &r.id));
+impl ScanKinds {
+  fn new<L: Language>(rules: &[&RuleConfig<L>]) -> Self {
     let mut mapping = Vec::new();
     for (idx, rule) in rules.iter().enumerate() {
       for kind in &rule
@@ -111,9 +112,88 @@ impl<'r, L: Language> CombinedScan<'r, L> {
         mapping[kind].push(idx);
       }
     }
+    let mut prune_mapping = Vec::new();
+    for (idx, rule) in rules.iter().enumerate() {
+      // kinds are already validated when the RuleConfig is created
+      for kind in rule.get_ignored_kinds().unwrap_or_default() {
+        let kind = kind as usize;
+        while prune_mapping.len() <= kind {
+          prune_mapping.push(vec![]);
+        }
+        prune_mapping[kind].push(idx);
+      }
+    }
     Self {
-      rules,
       kind_rule_mapping: mapping,
+      kind_prune_mapping: prune_mapping,
+    }
+  }
+}
+
+/// A struct to group all rules according to their potential kinds.
+/// This can greatly reduce traversal times and skip unmatchable rules.
+/// Rules are referenced by their index in the rules vector.
+pub struct CombinedScan<'r, L: Language> {
+  rules: Vec<&'r RuleConfig<L>>,
+  kinds: Arc<ScanKinds>,
+}
+
+impl<'r, L: Language> CombinedScan<'r, L> {
+  pub fn new(mut rules: Vec<&'r RuleConfig<L>>) -> Self {
+    Self::sort_rules(&mut rules);
+    let kinds = Arc::new(ScanKinds::new(&rules));
+    Self { rules, kinds }
+  }
+
+  /// Create a scan with kinds of a previous scan, skipping computing potential kinds of rules.
+  /// `rules` must be the same rules of the previous scan, in any order,
+  /// except rules of the same id must keep their order.
+  pub fn with_kinds(mut rules: Vec<&'r RuleConfig<L>>, kinds: Arc<ScanKinds>) -> Self {
+    Self::sort_rules(&mut rules);
+    Self { rules, kinds }
+  }
+
+  /// Kinds of the scan, which can be reused by `with_kinds`.
+  pub fn kinds(&self) -> Arc<ScanKinds> {
+    self.kinds.clone()
+  }
+
+  fn sort_rules(rules: &mut [&'r RuleConfig<L>]) {
+    // process fixable rule first, the order by id
+    // note, mapping.push will invert order so we sort fixable order in reverse
+    // stable so rules of the same id keep the order of kinds computed previously
+    rules.sort_by_key(|r| (r.fix.is_some(), &r.id));
+  }
+
+  /// Traverse nodes in pre-order. Subtrees of kinds in a rule's `ignoreKinds`
+  /// are reported with that rule disabled, and skipped entirely if all rules are disabled.
+  fn traverse<'t, D, F>(&self, root: &'t AstGrep<D>, mut f: F)
+  where
+    D: Doc<Lang = L>,
+    F: FnMut(Node<'t, D>, &BitSet),
+  {
+    if self.kinds.kind_prune_mapping.is_empty() {
+      let disabled = BitSet::new();
+      for node in root.root().dfs() {
+        f(node, &disabled);
+      }
+      return;
+    }
+    let mut stack = vec![(root.root(), Rc::new(BitSet::new()))];
+    while let Some((node, mut disabled)) = stack.pop() {
+      if let Some(pruned) = self.kinds.kind_prune_mapping.get(node.kind_id() as usize) {
+        if !pruned.is_empty() {
+          let mut set = (*disabled).clone();
+          set.extend(pruned.iter().copied());
+          if set.len() == self.rules.len() {
+            continue;
+          }
+          disabled = Rc::new(set);
+        }
+      }
+      f(node.clone(), &disabled);
+      let children: Vec<_> = node.children().collect();
+      stack.extend(children.into_iter().rev().map(|c| (c, disabled.clone())));
     }
   }
 
@@ -123,14 +203,14 @@ impl<'r, L: Language> CombinedScan<'r, L> {
   {
     let mut hit = BitSet::new();
     let mut suppressions = Suppressions(HashMap::new());
-    for node in root.root().dfs() {
+    self.traverse(root, |node, disabled| {
       suppressions.collect(&node);
       let kind = node.kind_id() as usize;
-      let Some(rule_idx) = self.kind_rule_mapping.get(kind) else {
-        continue;
+      let Some(rule_idx) = self.kinds.kind_rule_mapping.get(kind) else {
+        return;
       };
       for &idx in rule_idx {
-        if hit.contains(idx) {
+        if hit.contains(idx) || disabled.contains(idx) {
           continue;
         }
         let rule = &self.rules[idx];
@@ -138,7 +218,7 @@ impl<'r, L: Language> CombinedScan<'r, L> {
           hit.insert(idx);
         }
       }
-    }
+    });
     PreScan {
       hit_set: hit,
       suppressions,
@@ -157,19 +237,20 @@ impl<'r, L: Language> CombinedScan<'r, L> {
     let mut result = ScanResult {
       diffs: vec![],
       matches: HashMap::new(),
+      suppressed: vec![],
     };
     let PreScan {
       hit_set,
       mut suppressions,
     } = pre;
-    for node in root.root().dfs() {
+    self.traverse(root, |node, disabled| {
       let kind = node.kind_id() as usize;
-      let Some(rule_idx) = self.kind_rule_mapping.get(kind) else {
-        continue;
+      let Some(rule_idx) = self.kinds.kind_rule_mapping.get(kind) else {
+        return;
       };
       let mut suppression = suppressions.check_suppression(&node);
       for &idx in rule_idx {
-        if !hit_set.contains(idx) {
+        if !hit_set.contains(idx) || disabled.contains(idx) {
           continue;
         }
         let rule = &self.rules[idx];
@@ -177,6 +258,7 @@ impl<'r, L: Language> CombinedScan<'r, L> {
           continue;
         };
         if suppression.is_suppressed(&rule.id) {
+          result.suppressed.push((idx, ret));
           continue;
         }
         if rule.fix.is_none() || !separate_fix {
@@ -186,18 +268,33 @@ impl<'r, L: Language> CombinedScan<'r, L> {
           result.diffs.push((idx, ret));
         }
       }
-    }
+    });
     result
   }
 
   pub fn get_rule(&self, idx: usize) -> &RuleConfig<L> {
     self.rules[idx]
   }
+
+  /// Get rules that can potentially match nodes of the kind.
+  /// Other constraints in the rules are not checked.
+  pub fn get_rules_for_kind(&self, kind: u16) -> Vec<&'r RuleConfig<L>> {
+    let Some(rule_idx) = self.kinds.kind_rule_mapping.get(kind as usize) else {
+      return vec![];
+    };
+    rule_idx.iter().map(|&idx| self.rules[idx]).collect()
+  }
 }
 
 fn parse_suppression_set(text: &str) -> Option<HashSet<String>> {
   let (_, after) = text.trim().split_once(IGNORE_TEXT)?;
+  // block comments end after rule ids, e.g. `/* ast-grep-ignore: id */`
   let after = after.trim();
+  let after = after
+    .strip_suffix("*/")
+    .or_else(|| after.strip_suffix("-->"))
+    .unwrap_or(after)
+    .trim();
   if after.is_empty() {
     return None;
   }
@@ -248,6 +345,69 @@ language: Tsx",
     assert_eq!(matches.len(), 2);
     assert_eq!(matches[0].text(), "console.log('no ignore')");
     assert_eq!(matches[1].text(), "console.log('ignore another')");
+    let suppressed: Vec<_> = scanned.suppressed.iter().map(|(_, m)| m.text()).collect();
+    assert_eq!(suppressed.len(), 3);
+    assert_eq!(suppressed[0], "console.log('ignored all')");
+  }
+
+  #[test]
+  fn test_parse_block_comment_suppression() {
+    let expected = HashSet::from(["a".to_string(), "b".to_string()]);
+    let css = parse_suppression_set("/* ast-grep-ignore: a, b */");
+    assert_eq!(css, Some(expected.clone()));
+    let html = parse_suppression_set("<!-- ast-grep-ignore: a, b -->");
+    assert_eq!(html, Some(expected));
+    assert_eq!(parse_suppression_set("/* ast-grep-ignore */"), None);
+  }
+
+  #[test]
+  fn test_rules_for_kind() {
+    let rule = create_rule();
+    let scan = CombinedScan::new(vec![&rule]);
+    let root = TypeScript::Tsx.ast_grep("console.log(1); a = 1");
+    let call = root.root().find("console.log(1)").expect("should find");
+    let rules = scan.get_rules_for_kind(call.kind_id());
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].id, "test");
+    let num = root.root().find("1").expect("should find");
+    assert!(scan.get_rules_for_kind(num.kind_id()).is_empty());
+  }
+
+  #[test]
+  fn test_ignore_kinds() {
+    let rule: SerializableRuleConfig<TypeScript> = from_str(
+      r"
+id: ident
+rule: {kind: identifier}
+language: Tsx
+ignoreKinds: [arguments]",
+    )
+    .expect("parse");
+    let pruned = RuleConfig::try_from(rule, &Default::default()).expect("work");
+    let rule = create_rule();
+    let root = TypeScript::Tsx.ast_grep("console.log(a)");
+    let scan = CombinedScan::new(vec![&pruned]);
+    let pre = scan.find(&root);
+    let scanned = scan.scan(&root, pre, false);
+    let texts: Vec<_> = scanned.matches[&0].iter().map(|m| m.text()).collect();
+    assert_eq!(texts, ["console"]);
+    // other rules still descend into the pruned kind
+    let scan = CombinedScan::new(vec![&pruned, &rule]);
+    let pre = scan.find(&root);
+    let scanned = scan.scan(&root, pre, false);
+    assert_eq!(scanned.matches.values().map(Vec::len).sum::<usize>(), 2);
+  }
+
+  #[test]
+  fn test_with_kinds() {
+    let rule = create_rule();
+    let root = TypeScript::Tsx.ast_grep("console.log(1)");
+    let scan = CombinedScan::new(vec![&rule]);
+    let reused = CombinedScan::with_kinds(vec![&rule], scan.kinds());
+    let pre = reused.find(&root);
+    let scanned = reused.scan(&root, pre, false);
+    assert_eq!(scanned.matches[&0].len(), 1);
+    assert_eq!(scanned.matches[&0][0].text(), "console.log(1)");
   }
 
   #[test]
@@ -17,7 +17,7 @@ use std::ops::Range;
 #[serde(untagged)]
 pub enum SerializableFixer {
   Str(String),
-  Config(SerializableFixConfig),
+  Config(Box<SerializableFixConfig>),
 }
 
 #[derive(Serialize, Deserialize, Clone, JsonSchema)]
@@ -28,6 +28,12 @@ pub struct SerializableFixConfig {
   expand_end: Maybe<Relation>,
   #[serde(default, skip_serializing_if = "Maybe::is_absent")]
   expand_start: Maybe<Relation>,
+  /// Replace leading comments and trailing comment on the same line along with the node
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  include_trivia: bool,
+  /// The fix may change the semantics of the code, so editors ask for confirmation before applying it
+  #[serde(default, rename = "unsafe", skip_serializing_if = "std::ops::Not::not")]
+  is_unsafe: bool,
   // TODO: add these
   // prepend: String,
 }
@@ -64,6 +70,8 @@ pub struct Fixer<L: Language> {
   template: TemplateFix,
   expand_start: Option<Expansion<L>>,
   expand_end: Option<Expansion<L>>,
+  include_trivia: bool,
+  is_unsafe: bool,
 }
 
 impl<L: Language> Fixer<L> {
@@ -75,6 +83,8 @@ impl<L: Language> Fixer<L> {
       template,
       expand_end,
       expand_start,
+      include_trivia,
+      is_unsafe,
     } = serialized;
     let expand_start = Expansion::parse(expand_start, env)?;
     let expand_end = Expansion::parse(expand_end, env)?;
@@ -82,6 +92,8 @@ impl<L: Language> Fixer<L> {
       template: TemplateFix::try_new(template, &env.lang)?,
       expand_start,
       expand_end,
+      include_trivia: *include_trivia,
+      is_unsafe: *is_unsafe,
     })
   }
 
@@ -102,6 +114,8 @@ impl<L: Language> Fixer<L> {
           template,
           expand_end: None,
           expand_start: None,
+          include_trivia: false,
+          is_unsafe: false,
         }
       }
       SerializableFixer::Config(cfg) => Self::do_parse(cfg, env)?,
@@ -115,9 +129,16 @@ impl<L: Language> Fixer<L> {
       template,
       expand_start: None,
       expand_end: None,
+      include_trivia: false,
+      is_unsafe: false,
     })
   }
 
+  /// Whether the fix is marked `unsafe` and should be confirmed before applied.
+  pub fn is_unsafe(&self) -> bool {
+    self.is_unsafe
+  }
+
   pub(crate) fn used_vars(&self) -> HashSet<&str> {
     self.template.used_vars()
   }
@@ -134,6 +155,21 @@ where
     self.template.generate_replacement(nm)
   }
   fn get_replaced_range(&self, nm: &NodeMatch<D>, matcher: impl Matcher<L>) -> Range<usize> {
+    let range = self.get_expanded_range(nm, matcher);
+    if !self.include_trivia {
+      return range;
+    }
+    let trivia = nm.get_node().range_with_trivia();
+    range.start.min(trivia.start)..range.end.max(trivia.end)
+  }
+}
+
+impl<L: Language> Fixer<L> {
+  fn get_expanded_range<D: Doc<Lang = L>>(
+    &self,
+    nm: &NodeMatch<D>,
+    matcher: impl Matcher<L>,
+  ) -> Range<usize> {
     let range = nm.range();
     if self.expand_start.is_none() && self.expand_end.is_none() {
       return if let Some(len) = matcher.get_match_len(nm.get_node().clone()) {
@@ -186,6 +222,7 @@ mod test {
   use crate::from_str;
   use crate::maybe::Maybe;
   use crate::test::TypeScript;
+  use ast_grep_core::matcher::KindMatcher;
 
   #[test]
   fn test_parse() {
@@ -215,9 +252,11 @@ mod test {
     let config = SerializableFixConfig {
       expand_end: Maybe::Present(relation),
       expand_start: Maybe::Absent,
+      include_trivia: false,
+      is_unsafe: false,
       template: "abcd".to_string(),
     };
-    let config = SerializableFixer::Config(config);
+    let config = SerializableFixer::Config(Box::new(config));
     let env = DeserializeEnv::new(TypeScript::Tsx);
     let ret = Fixer::parse(&config, &env, &Some(Default::default()))?;
     assert!(ret.expand_start.is_none());
@@ -226,6 +265,18 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_parse_unsafe() -> Result<(), FixerError> {
+    let config = from_str("{template: abcd, unsafe: true}").expect("should deser");
+    let env = DeserializeEnv::new(TypeScript::Tsx);
+    let ret = Fixer::parse(&config, &env, &Some(Default::default()))?;
+    assert!(ret.is_unsafe());
+    let config = SerializableFixer::Str("abcd".to_string());
+    let ret = Fixer::parse(&config, &env, &Some(Default::default()))?;
+    assert!(!ret.is_unsafe());
+    Ok(())
+  }
+
   #[test]
   fn test_parse_str() -> Result<(), FixerError> {
     let config = SerializableFixer::Str("abcd".to_string());
@@ -243,9 +294,11 @@ mod test {
     let config = SerializableFixConfig {
       expand_end: Maybe::Present(expand_end),
       expand_start: Maybe::Absent,
+      include_trivia: false,
+      is_unsafe: false,
       template: "var $A = 456".to_string(),
     };
-    let config = SerializableFixer::Config(config);
+    let config = SerializableFixer::Config(Box::new(config));
     let env = DeserializeEnv::new(TypeScript::Tsx);
     let fixer = Fixer::parse(&config, &env, &Some(Default::default()))?;
     let grep = TypeScript::Tsx.ast_grep("let a = 123");
@@ -257,14 +310,15 @@ mod test {
 
   #[test]
   fn test_relace_range() -> Result<(), FixerError> {
-    use ast_grep_core::matcher::KindMatcher;
     let expand_end = from_str("{regex: ',', stopBy: neighbor}").expect("should word");
     let config = SerializableFixConfig {
       expand_end: Maybe::Present(expand_end),
       expand_start: Maybe::Absent,
+      include_trivia: false,
+      is_unsafe: false,
       template: "c: 456".to_string(),
     };
-    let config = SerializableFixer::Config(config);
+    let config = SerializableFixer::Config(Box::new(config));
     let env = DeserializeEnv::new(TypeScript::Tsx);
     let fixer = Fixer::parse(&config, &env, &Some(Default::default()))?;
     let grep = TypeScript::Tsx.ast_grep("var a = { b: 123, }");
@@ -277,4 +331,21 @@ mod test {
     assert_eq!(edit.deleted_length, 7);
     Ok(())
   }
+
+  #[test]
+  fn test_include_trivia() -> Result<(), FixerError> {
+    let config =
+      from_str("{template: 'function b() {}', includeTrivia: true}").expect("should deser");
+    let config = SerializableFixer::Config(Box::new(config));
+    let env = DeserializeEnv::new(TypeScript::Tsx);
+    let fixer = Fixer::parse(&config, &env, &Some(Default::default()))?;
+    let src = "a()\n/** doc */\nfunction a() {}";
+    let grep = TypeScript::Tsx.ast_grep(src);
+    let matcher = KindMatcher::new("function_declaration", TypeScript::Tsx);
+    let node = grep.root().find(&matcher).expect("should found");
+    let edit = node.make_edit(&matcher, &fixer);
+    assert_eq!(edit.position, 4);
+    assert_eq!(edit.deleted_length, src.len() - 4);
+    Ok(())
+  }
 }
@@ -12,14 +12,18 @@ use serde::Deserialize;
 use serde_yaml::{with::singleton_map_recursive::deserialize, Deserializer, Error as YamlError};
 
 use ast_grep_core::language::Language;
+use std::path::Path;
 
-pub use combined::{CombinedScan, PreScan};
+pub use combined::{CombinedScan, PreScan, ScanKinds};
 pub use fixer::Fixer;
-pub use rule::referent_rule::GlobalRules;
+pub use rule::referent_rule::{GlobalRules, ReferentRuleError};
 pub use rule::DeserializeEnv;
 pub use rule::{Rule, RuleSerializeError, SerializableRule};
 pub use rule_collection::RuleCollection;
-pub use rule_config::{RuleConfig, RuleConfigError, SerializableRuleConfig, Severity};
+pub use rule_config::{
+  MetadataValue, RuleConfig, RuleConfigError, RuleSource, SerializableRuleConfig, Severity,
+  SeverityOverride,
+};
 pub use rule_core::{RuleCore, RuleCoreError, SerializableRuleCore};
 pub use transform::Transformation;
 
@@ -39,6 +43,34 @@ pub fn from_yaml_string<'a, L: Language + Deserialize<'a>>(
   }
   Ok(ret)
 }
+
+/// Same as `from_yaml_string` but every rule records `path` and the line of its `id` as source.
+/// Rules are left without source if their `id` lines cannot be located, e.g. in flow style,
+/// since a guessed line would point to the wrong rule.
+pub fn from_yaml_string_with_source<'a, L: Language + Deserialize<'a>>(
+  yamls: &'a str,
+  registration: &GlobalRules<L>,
+  path: &Path,
+) -> Result<Vec<RuleConfig<L>>, RuleConfigError> {
+  let mut configs = from_yaml_string(yamls, registration)?;
+  let id_lines: Vec<_> = yamls
+    .lines()
+    .enumerate()
+    .filter(|(_, line)| line.starts_with("id:"))
+    .map(|(i, _)| i + 1)
+    .collect();
+  if id_lines.len() != configs.len() {
+    return Ok(configs);
+  }
+  for (config, line) in configs.iter_mut().zip(id_lines) {
+    config.set_source(RuleSource {
+      path: path.to_path_buf(),
+      line,
+    });
+  }
+  Ok(configs)
+}
+
 #[cfg(test)]
 mod test {
 
@@ -214,4 +246,45 @@ rule:
 ";
     test_rule_match(yaml, "a();a(123);a();a(123)");
   }
+
+  #[test]
+  fn test_rule_source() {
+    let yaml = r"
+id: first
+language: Tsx
+rule: {pattern: a}
+---
+# comment
+id: second
+language: Tsx
+rule: {pattern: b}
+";
+    let path = Path::new("rules/test.yml");
+    let rules = from_yaml_string_with_source::<TypeScript>(yaml, &Default::default(), path)
+      .expect("rules should parse");
+    let lines: Vec<_> = rules
+      .iter()
+      .map(|rule| rule.source().expect("should have source").line)
+      .collect();
+    assert_eq!(lines, [2, 7]);
+    assert_eq!(rules[1].source().unwrap().path, path);
+    let rules = from_yaml_string::<TypeScript>(yaml, &Default::default()).expect("should parse");
+    assert!(rules[0].source().is_none());
+  }
+
+  #[test]
+  fn test_rule_source_not_located() {
+    let yaml = r"
+id: first
+language: Tsx
+rule: {pattern: a}
+---
+{id: second, language: Tsx, rule: {pattern: b}}
+";
+    let path = Path::new("rules/test.yml");
+    let rules = from_yaml_string_with_source::<TypeScript>(yaml, &Default::default(), path)
+      .expect("rules should parse");
+    assert_eq!(rules.len(), 2);
+    assert!(rules.iter().all(|rule| rule.source().is_none()));
+  }
 }
@@ -328,6 +328,21 @@ impl<L: Language> Matcher<L> for Rule<L> {
       Matches(rule) => rule.potential_kinds(),
     }
   }
+
+  /// Only pattern can match beyond the node, e.g. multiple statements.
+  fn get_match_len<D: Doc<Lang = L>>(&self, node: Node<D>) -> Option<usize> {
+    match self {
+      Rule::Pattern(pattern) => pattern.get_match_len(node),
+      _ => None,
+    }
+  }
+
+  fn may_match_beyond_node(&self) -> bool {
+    match self {
+      Rule::Pattern(pattern) => pattern.may_match_beyond_node(),
+      _ => false,
+    }
+  }
 }
 
 /// Rule matches nothing by default.
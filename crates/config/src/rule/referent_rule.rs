@@ -45,6 +45,13 @@ impl<L: Language> GlobalRules<L> {
     }
     Ok(())
   }
+
+  /// Ids of all registered utility rules, sorted.
+  pub fn ids(&self) -> Vec<String> {
+    let mut ids: Vec<_> = self.read().keys().cloned().collect();
+    ids.sort();
+    ids
+  }
 }
 
 impl<R> Default for Registration<R> {
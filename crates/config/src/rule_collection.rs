@@ -1,12 +1,14 @@
 use crate::{RuleConfig, Severity};
 use ast_grep_core::language::Language;
 use globset::{Glob, GlobSet, GlobSetBuilder};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// RuleBucket stores rules of the same language id.
 /// Rules for different language will stay in separate buckets.
 pub struct RuleBucket<L: Language> {
   rules: Vec<RuleConfig<L>>,
+  /// the rule file each rule is read from, aligned with `rules`.
+  sources: Vec<Option<PathBuf>>,
   lang: L,
 }
 
@@ -14,21 +16,33 @@ impl<L: Language> RuleBucket<L> {
   fn new(lang: L) -> Self {
     Self {
       rules: vec![],
+      sources: vec![],
       lang,
     }
   }
-  pub fn add(&mut self, rule: RuleConfig<L>) {
+  pub fn add(&mut self, rule: RuleConfig<L>, source: Option<PathBuf>) {
     self.rules.push(rule);
+    self.sources.push(source);
+  }
+  fn remove_source(&mut self, path: &Path) {
+    let (rules, sources) = std::mem::take(&mut self.rules)
+      .into_iter()
+      .zip(std::mem::take(&mut self.sources))
+      .filter(|(_, source)| source.as_deref() != Some(path))
+      .unzip();
+    self.rules = rules;
+    self.sources = sources;
   }
 }
 
 struct ContingentRule<L: Language> {
   rule: RuleConfig<L>,
+  source: Option<PathBuf>,
   files_globs: Option<GlobSet>,
   ignore_globs: Option<GlobSet>,
 }
 
-fn build_glob_set(paths: &Vec<String>) -> Result<GlobSet, globset::Error> {
+pub(crate) fn build_glob_set(paths: &Vec<String>) -> Result<GlobSet, globset::Error> {
   let mut builder = GlobSetBuilder::new();
   for path in paths {
     builder.add(Glob::new(path)?);
@@ -46,6 +60,7 @@ where
     let ignore_globs = rule.ignores.as_ref().map(build_glob_set).transpose()?;
     Ok(Self {
       rule,
+      source: None,
       files_globs,
       ignore_globs,
     })
@@ -79,21 +94,89 @@ pub struct RuleCollection<L: Language + Eq> {
 
 impl<L: Language + Eq> RuleCollection<L> {
   pub fn try_new(configs: Vec<RuleConfig<L>>) -> Result<Self, globset::Error> {
-    let mut tenured = vec![];
-    let mut contingent = vec![];
+    let mut collection = Self::default();
+    collection.add_rules(configs)?;
+    Ok(collection)
+  }
+
+  /// Add rules not read from any rule file.
+  pub fn add_rules(&mut self, configs: Vec<RuleConfig<L>>) -> Result<(), globset::Error> {
+    self.add_rules_from(configs, None)
+  }
+
+  /// Add rules read from the rule file at `path`, used for per-file update.
+  pub fn add_file_rules(
+    &mut self,
+    path: PathBuf,
+    configs: Vec<RuleConfig<L>>,
+  ) -> Result<(), globset::Error> {
+    self.add_rules_from(configs, Some(path))
+  }
+
+  fn add_rules_from(
+    &mut self,
+    configs: Vec<RuleConfig<L>>,
+    source: Option<PathBuf>,
+  ) -> Result<(), globset::Error> {
     for config in configs {
-      if matches!(config.severity, Severity::Off) {
+      if config.is_off() {
         continue;
       } else if config.files.is_none() && config.ignores.is_none() {
-        Self::add_tenured_rule(&mut tenured, config);
+        Self::add_tenured_rule(&mut self.tenured, config, source.clone());
       } else {
-        contingent.push(ContingentRule::try_from(config)?);
+        let mut rule = ContingentRule::try_from(config)?;
+        rule.source = source.clone();
+        self.contingent.push(rule);
       }
     }
-    Ok(Self {
-      tenured,
-      contingent,
-    })
+    Ok(())
+  }
+
+  /// Whether any rule in the collection is read from the file.
+  pub fn has_source(&self, path: &Path) -> bool {
+    self.sources().any(|(_, source)| source == Some(path))
+  }
+
+  fn sources(&self) -> impl Iterator<Item = (&RuleConfig<L>, Option<&Path>)> {
+    let tenured = self
+      .tenured
+      .iter()
+      .flat_map(|b| b.rules.iter().zip(b.sources.iter().map(|s| s.as_deref())));
+    let contingent = self
+      .contingent
+      .iter()
+      .map(|c| (&c.rule, c.source.as_deref()));
+    tenured.chain(contingent)
+  }
+
+  /// Replace rules previously read from the file at `path` with `configs`.
+  /// Rules of the same id read from other files are kept.
+  /// Pass an empty vec to remove rules of a deleted file.
+  pub fn update_file(
+    &mut self,
+    path: &Path,
+    configs: Vec<RuleConfig<L>>,
+  ) -> Result<(), globset::Error> {
+    // build globs first so the collection is unchanged on error
+    let added = Self::try_new(configs)?;
+    for bucket in &mut self.tenured {
+      bucket.remove_source(path);
+    }
+    self.tenured.retain(|b| !b.rules.is_empty());
+    self
+      .contingent
+      .retain(|c| c.source.as_deref() != Some(path));
+    let source = Some(path.to_path_buf());
+    for bucket in added.tenured {
+      for rule in bucket.rules {
+        Self::add_tenured_rule(&mut self.tenured, rule, source.clone());
+      }
+    }
+    for mut rule in added.contingent {
+      rule.source = source.clone();
+      self.contingent.push(rule);
+    }
+    Ok(())
   }
 
   pub fn get_rule_from_lang(&self, path: &Path, lang: L) -> Vec<&RuleConfig<L>> {
@@ -111,6 +194,8 @@ impl<L: Language + Eq> RuleCollection<L> {
         None
       }
     }));
+    // rules can be turned off for the path by severityOverrides
+    all_rules.retain(|r| !matches!(r.get_severity(path), Severity::Off));
     all_rules
   }
 
@@ -138,16 +223,26 @@ impl<L: Language + Eq> RuleCollection<L> {
     None
   }
 
-  fn add_tenured_rule(tenured: &mut Vec<RuleBucket<L>>, rule: RuleConfig<L>) {
+  /// All rules in the collection regardless of language and path.
+  pub fn iter(&self) -> impl Iterator<Item = &RuleConfig<L>> {
+    let tenured = self.tenured.iter().flat_map(|b| b.rules.iter());
+    tenured.chain(self.contingent.iter().map(|c| &c.rule))
+  }
+
+  fn add_tenured_rule(
+    tenured: &mut Vec<RuleBucket<L>>,
+    rule: RuleConfig<L>,
+    source: Option<PathBuf>,
+  ) {
     let lang = rule.language.clone();
     for bucket in tenured.iter_mut() {
       if bucket.lang == lang {
-        bucket.add(rule);
+        bucket.add(rule, source);
         return;
       }
     }
     let mut bucket = RuleBucket::new(lang);
-    bucket.add(rule);
+    bucket.add(rule, source);
     tenured.push(bucket);
   }
 }
@@ -258,6 +353,71 @@ files:
     assert!(collection.get_rule("test").is_some());
   }
 
+  fn read_rules(ids: &[&str]) -> Vec<RuleConfig<TypeScript>> {
+    let yaml: Vec<_> = ids
+      .iter()
+      .map(|id| format!("id: {id}\nlanguage: Tsx\nrule: {{pattern: a}}"))
+      .collect();
+    from_yaml_string(&yaml.join("\n---\n"), &Default::default()).expect("should parse")
+  }
+
+  #[test]
+  fn test_update_file() {
+    let mut collection = RuleCollection::default();
+    collection
+      .add_file_rules("a.yml".into(), read_rules(&["a", "b"]))
+      .expect("work");
+    collection
+      .add_file_rules("c.yml".into(), read_rules(&["c"]))
+      .expect("work");
+    assert!(collection.has_source(Path::new("a.yml")));
+    collection
+      .update_file(Path::new("a.yml"), read_rules(&["d"]))
+      .expect("should update");
+    let ids: Vec<_> = collection
+      .for_path("test.tsx")
+      .iter()
+      .map(|r| &r.id)
+      .collect();
+    assert_eq!(ids, ["c", "d"]);
+    // remove deleted file, add new file
+    collection
+      .update_file(Path::new("c.yml"), vec![])
+      .expect("work");
+    collection
+      .update_file(Path::new("new.yml"), read_rules(&["e"]))
+      .expect("work");
+    let ids: Vec<_> = collection
+      .for_path("test.tsx")
+      .iter()
+      .map(|r| &r.id)
+      .collect();
+    assert_eq!(ids, ["d", "e"]);
+    assert!(collection.has_source(Path::new("a.yml")));
+    assert!(!collection.has_source(Path::new("c.yml")));
+  }
+
+  #[test]
+  fn test_update_file_with_duplicate_id() {
+    let mut collection = RuleCollection::default();
+    collection
+      .add_file_rules("a.yml".into(), read_rules(&["dup", "a"]))
+      .expect("work");
+    collection
+      .add_file_rules("b.yml".into(), read_rules(&["dup"]))
+      .expect("work");
+    collection
+      .update_file(Path::new("a.yml"), read_rules(&["a"]))
+      .expect("should update");
+    let ids: Vec<_> = collection
+      .for_path("test.tsx")
+      .iter()
+      .map(|r| &r.id)
+      .collect();
+    assert_eq!(ids, ["dup", "a"]);
+    assert!(collection.has_source(Path::new("b.yml")));
+  }
+
   #[test]
   #[ignore]
   fn test_rules_for_path() {
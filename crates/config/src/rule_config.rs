@@ -3,12 +3,14 @@ use crate::GlobalRules;
 use crate::check_var::{check_rewriters_in_transform, CheckHint};
 use crate::fixer::Fixer;
 use crate::rule::DeserializeEnv;
+use crate::rule_collection::build_glob_set;
 use crate::rule_core::{RuleCore, RuleCoreError, SerializableRuleCore};
 
 use ast_grep_core::language::Language;
 use ast_grep_core::replacer::Replacer;
 use ast_grep_core::{NodeMatch, StrDoc};
 
+use globset::GlobSet;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_yaml::Error as YamlError;
@@ -17,6 +19,7 @@ use thiserror::Error;
 
 use std::collections::{HashMap, HashSet};
 use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
 
 #[derive(Serialize, Deserialize, Clone, Default, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -46,6 +49,21 @@ pub enum RuleConfigError {
   UndefinedRewriter(String),
   #[error("Rewriter rule `{0}` should have `fix`.")]
   NoFixInRewriter(String),
+  #[error("Kind `{0}` in `ignoreKinds` is invalid.")]
+  InvalidIgnoreKind(String),
+  #[error("Glob in `severityOverrides` is invalid.")]
+  SeverityOverride(#[from] globset::Error),
+  #[error("Label `{0}` is not a meta variable defined by the rule.")]
+  UndefinedLabel(String),
+}
+
+/// Severity of a rule in files matching the globs, e.g. warning instead of error in legacy code.
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
+pub struct SeverityOverride {
+  /// Glob patterns of files using this severity
+  pub files: Vec<String>,
+  /// One of: hint, info, warning, error, or off
+  pub severity: Severity,
 }
 
 #[derive(Serialize, Deserialize, Clone, JsonSchema)]
@@ -79,10 +97,44 @@ pub struct SerializableRuleConfig<L: Language> {
   pub files: Option<Vec<String>>,
   /// Glob patterns that exclude rules from applying to files
   pub ignores: Option<Vec<String>>,
+  /// Node kinds that scanning will not descend into for this rule, e.g. comments or strings
+  #[serde(rename = "ignoreKinds")]
+  pub ignore_kinds: Option<Vec<String>>,
+  /// Override severity for files matching globs. The last matching override wins.
+  #[serde(rename = "severityOverrides")]
+  pub severity_overrides: Option<Vec<SeverityOverride>>,
+  /// Messages of meta variables keyed by name without `$`, e.g. `{A: declared here}`.
+  /// Nodes matched by the variables are reported as secondary locations of a finding.
+  pub labels: Option<HashMap<String, String>>,
   /// Documentation link to this rule
   pub url: Option<String>,
   /// Extra information for the rule
-  pub metadata: Option<HashMap<String, String>>,
+  pub metadata: Option<HashMap<String, MetadataValue>>,
+}
+
+/// Value of a metadata entry, a string or a list of strings like `tags: [deprecated]`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(untagged)]
+pub enum MetadataValue {
+  Text(String),
+  List(Vec<String>),
+}
+
+impl MetadataValue {
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Self::Text(text) => Some(text),
+      Self::List(_) => None,
+    }
+  }
+
+  /// Items of a list, or the string as the only item.
+  pub fn items(&self) -> &[String] {
+    match self {
+      Self::Text(text) => std::slice::from_ref(text),
+      Self::List(items) => items,
+    }
+  }
 }
 
 impl<L: Language> SerializableRuleConfig<L> {
@@ -160,9 +212,19 @@ impl<L: Language> DerefMut for SerializableRuleConfig<L> {
   }
 }
 
+/// Where a rule is defined, e.g. to report the rule file alongside a finding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleSource {
+  pub path: PathBuf,
+  /// 1-based line of the rule `id` in the file
+  pub line: usize,
+}
+
 pub struct RuleConfig<L: Language> {
   inner: SerializableRuleConfig<L>,
   pub matcher: RuleCore<L>,
+  severity_globs: Vec<(GlobSet, Severity)>,
+  source: Option<RuleSource>,
 }
 
 impl<L: Language> RuleConfig<L> {
@@ -171,7 +233,50 @@ impl<L: Language> RuleConfig<L> {
     globals: &GlobalRules<L>,
   ) -> Result<Self, RuleConfigError> {
     let matcher = inner.get_matcher(globals)?;
-    Ok(Self { inner, matcher })
+    let severity_globs = inner
+      .severity_overrides
+      .iter()
+      .flatten()
+      .map(|o| Ok((build_glob_set(&o.files)?, o.severity.clone())))
+      .collect::<Result<_, globset::Error>>()?;
+    let config = Self {
+      inner,
+      matcher,
+      severity_globs,
+      source: None,
+    };
+    config.get_ignored_kinds()?;
+    config.check_labels()?;
+    Ok(config)
+  }
+
+  /// Severity of the rule in the file, considering `severityOverrides`.
+  pub fn get_severity<P: AsRef<Path>>(&self, path: P) -> Severity {
+    let path = path.as_ref();
+    self
+      .severity_globs
+      .iter()
+      .rev()
+      .find(|(globs, _)| globs.is_match(path))
+      .map_or_else(|| self.severity.clone(), |(_, severity)| severity.clone())
+  }
+
+  /// Location of the rule definition if the rule is read from a file.
+  pub fn source(&self) -> Option<&RuleSource> {
+    self.source.as_ref()
+  }
+
+  pub fn set_source(&mut self, source: RuleSource) {
+    self.source = Some(source);
+  }
+
+  /// A rule is turned off everywhere if its severity is off and no override turns it on.
+  pub fn is_off(&self) -> bool {
+    matches!(self.severity, Severity::Off)
+      && self
+        .severity_globs
+        .iter()
+        .all(|(_, s)| matches!(s, Severity::Off))
   }
 
   pub fn deserialize<'de>(
@@ -188,6 +293,33 @@ impl<L: Language> RuleConfig<L> {
   pub fn get_message(&self, node: &NodeMatch<StrDoc<L>>) -> String {
     self.inner.get_message(node)
   }
+  /// Kind ids of nodes whose subtrees are skipped when scanning with this rule.
+  pub fn get_ignored_kinds(&self) -> Result<Vec<u16>, RuleConfigError> {
+    let Some(kinds) = &self.ignore_kinds else {
+      return Ok(vec![]);
+    };
+    let ts_lang = self.language.get_ts_language();
+    kinds
+      .iter()
+      .map(
+        |kind| match ts_lang.id_for_node_kind(kind, /*named*/ true) {
+          // 0 is tree-sitter's builtin end symbol, returned for unknown kinds
+          0 => Err(RuleConfigError::InvalidIgnoreKind(kind.clone())),
+          id => Ok(id),
+        },
+      )
+      .collect()
+  }
+  fn check_labels(&self) -> Result<(), RuleConfigError> {
+    let Some(labels) = &self.labels else {
+      return Ok(());
+    };
+    let vars = self.matcher.defined_vars();
+    match labels.keys().find(|label| !vars.contains(label.as_str())) {
+      Some(label) => Err(RuleConfigError::UndefinedLabel(label.clone())),
+      None => Ok(()),
+    }
+  }
   pub fn get_fixer(&self) -> Result<Option<Fixer<L>>, RuleConfigError> {
     if let Some(fix) = &self.fix {
       let env = self.matcher.get_env(self.language.clone());
@@ -230,11 +362,74 @@ mod test {
       severity: Severity::Hint,
       files: None,
       ignores: None,
+      ignore_kinds: None,
+      severity_overrides: None,
+      labels: None,
       url: None,
       metadata: None,
     }
   }
 
+  #[test]
+  fn test_ignore_kinds() {
+    let globals = GlobalRules::default();
+    let rule = from_str("kind: identifier").expect("cannot parse rule");
+    let mut config = ts_rule_config(rule);
+    config.ignore_kinds = Some(vec!["string".into(), "comment".into()]);
+    let rule = RuleConfig::try_from(config.clone(), &globals).expect("should work");
+    assert_eq!(rule.get_ignored_kinds().expect("should work").len(), 2);
+    config.ignore_kinds = Some(vec!["not_a_kind".into()]);
+    let ret = RuleConfig::try_from(config, &globals);
+    assert!(matches!(ret, Err(RuleConfigError::InvalidIgnoreKind(_))));
+  }
+
+  #[test]
+  fn test_severity_overrides() {
+    let globals = GlobalRules::default();
+    let rule = from_str("kind: identifier").expect("cannot parse rule");
+    let mut config = ts_rule_config(rule);
+    config.severity = Severity::Error;
+    config.severity_overrides = Some(vec![
+      SeverityOverride {
+        files: vec!["./legacy/**".into()],
+        severity: Severity::Warning,
+      },
+      SeverityOverride {
+        files: vec!["./legacy/generated/**".into()],
+        severity: Severity::Off,
+      },
+    ]);
+    let rule = RuleConfig::try_from(config.clone(), &globals).expect("should work");
+    assert!(matches!(rule.get_severity("./src/a.ts"), Severity::Error));
+    assert!(matches!(
+      rule.get_severity("./legacy/a.ts"),
+      Severity::Warning
+    ));
+    let generated = rule.get_severity("./legacy/generated/a.ts");
+    assert!(matches!(generated, Severity::Off));
+    assert!(!rule.is_off());
+    config.severity_overrides = Some(vec![SeverityOverride {
+      files: vec!["[".into()],
+      severity: Severity::Warning,
+    }]);
+    let ret = RuleConfig::try_from(config, &globals);
+    assert!(matches!(ret, Err(RuleConfigError::SeverityOverride(_))));
+  }
+
+  #[test]
+  fn test_labels() {
+    let globals = GlobalRules::default();
+    let rule = from_str("pattern: let $A = $B").expect("cannot parse rule");
+    let mut config = ts_rule_config(rule);
+    let labels = HashMap::from([("A".to_string(), "declared here".to_string())]);
+    config.labels = Some(labels);
+    assert!(RuleConfig::try_from(config.clone(), &globals).is_ok());
+    let labels = HashMap::from([("C".to_string(), "undefined".to_string())]);
+    config.labels = Some(labels);
+    let ret = RuleConfig::try_from(config, &globals);
+    assert!(matches!(ret, Err(RuleConfigError::UndefinedLabel(l)) if l == "C"));
+  }
+
   #[test]
   fn test_rule_message() {
     let globals = GlobalRules::default();
@@ -272,6 +272,14 @@ impl<L: Language> Matcher<L> for RuleCore<L> {
   fn potential_kinds(&self) -> Option<BitSet> {
     self.rule.potential_kinds()
   }
+
+  fn get_match_len<D: Doc<Lang = L>>(&self, node: Node<D>) -> Option<usize> {
+    self.rule.get_match_len(node)
+  }
+
+  fn may_match_beyond_node(&self) -> bool {
+    self.rule.may_match_beyond_node()
+  }
 }
 
 #[cfg(test)]
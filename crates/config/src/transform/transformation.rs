@@ -5,8 +5,8 @@ use ast_grep_core::source::Content;
 use ast_grep_core::{Doc, Language};
 
 use regex::Regex;
-use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{de, Deserialize, Serialize};
 
 use string_case::{Separator, StringCase};
 
@@ -61,6 +61,43 @@ fn resolve_char(opt: &Option<i32>, dft: i32, len: i32) -> usize {
   }
 }
 
+/// A regex compiled when it is deserialized and reused for every match.
+/// It is (de)serialized as its source string.
+#[derive(Clone)]
+struct ReplaceRegex(Regex);
+
+impl Serialize for ReplaceRegex {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: serde::Serializer,
+  {
+    serializer.serialize_str(self.0.as_str())
+  }
+}
+
+impl<'de> Deserialize<'de> for ReplaceRegex {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: serde::Deserializer<'de>,
+  {
+    let src = String::deserialize(deserializer)?;
+    let regex = Regex::new(&src).map_err(de::Error::custom)?;
+    Ok(Self(regex))
+  }
+}
+
+impl JsonSchema for ReplaceRegex {
+  fn schema_name() -> String {
+    String::schema_name()
+  }
+  fn is_referenceable() -> bool {
+    false
+  }
+  fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+    String::json_schema(gen)
+  }
+}
+
 /// Replaces a substring in the meta variable's text content with another string.
 #[derive(Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -68,14 +105,14 @@ pub struct Replace<T> {
   /// source meta variable to be transformed
   source: T,
   /// a regex to find substring to be replaced
-  replace: String,
+  replace: ReplaceRegex,
   /// the replacement string
   by: String,
 }
 impl Replace<MetaVariable> {
   fn compute<D: Doc>(&self, ctx: &mut Ctx<D>) -> Option<String> {
     let text = get_text_from_env(&self.source, ctx)?;
-    let re = Regex::new(&self.replace).unwrap();
+    let ReplaceRegex(re) = &self.replace;
     Some(re.replace_all(&text, &self.by).into_owned())
   }
 }
@@ -296,6 +333,13 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_invalid_replace_regex() {
+    assert!(parse(r#"replace: { source: $A, replace: "(", by: b }"#).is_err());
+    let missing = parse(r#"replace: { source: $A, by: b }"#);
+    assert!(missing.is_err());
+  }
+
   #[test]
   fn test_wrong_rule() {
     let parsed = parse(
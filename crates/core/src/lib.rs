@@ -115,6 +115,25 @@ mod test {
     Ok(())
   }
 
+  #[test]
+  fn test_incremental_edit() -> Result {
+    let mut ast_grep = Tsx.ast_grep("let a = 1;\nlet b = 2;");
+    for (position, text) in [(0, "let c = 3;\n"), (31, "4"), (11, "")] {
+      let edit = Edit {
+        position,
+        deleted_length: if text.is_empty() { 11 } else { 0 },
+        inserted_text: text.into(),
+      };
+      ast_grep.edit(edit)?;
+    }
+    let expected = Tsx.ast_grep(ast_grep.source());
+    assert_eq!(ast_grep.source(), "let c = 3;\nlet b = 24;");
+    let texts =
+      |sg: &AstGrep<_>| -> Vec<String> { sg.root().dfs().map(|n| n.text().to_string()).collect() };
+    assert_eq!(texts(&ast_grep), texts(&expected));
+    Ok(())
+  }
+
   #[test]
   fn test_replace_by_rule() -> Result {
     let rule = Op::either("let a = 123").or("let b = 456");
@@ -2,6 +2,7 @@ mod match_node;
 mod strictness;
 
 use match_node::match_node_impl;
+pub(crate) use strictness::skip_comment_or_unnamed;
 use strictness::MatchOneNode;
 pub use strictness::MatchStrictness;
 
@@ -49,10 +50,39 @@ pub fn match_end_non_recursive<D: Doc>(
   candidate: Node<D>,
 ) -> Option<usize> {
   let mut end = ComputeEnd(0);
-  match match_node_impl(&goal.node, &candidate, &mut end, &goal.strictness) {
-    MatchOneNode::MatchedBoth => Some(end.0),
-    _ => None,
+  match_sequence(goal, &candidate, &mut end)?;
+  Some(end.0)
+}
+
+/// Match the pattern against candidate, then match following patterns of a multi-node pattern
+/// against next siblings of candidate, skipping comments and unnamed nodes.
+/// Returns the last matched sibling.
+fn match_sequence<'t, D: Doc>(
+  goal: &Pattern<D::Lang>,
+  candidate: &Node<'t, D>,
+  agg: &mut impl Aggregator<'t, D>,
+) -> Option<Node<'t, D>> {
+  let strictness = &goal.strictness;
+  if !matches!(
+    match_node_impl(&goal.node, candidate, agg, strictness),
+    MatchOneNode::MatchedBoth
+  ) {
+    return None;
+  }
+  let mut last = candidate.clone();
+  for pattern in goal.following() {
+    last = last.next()?;
+    while skip_comment_or_unnamed(&last) {
+      last = last.next()?;
+    }
+    if !matches!(
+      match_node_impl(pattern, &last, agg, strictness),
+      MatchOneNode::MatchedBoth
+    ) {
+      return None;
+    }
   }
+  Some(last)
 }
 
 fn match_leaf_meta_var<'tree, D: Doc>(
@@ -117,10 +147,8 @@ pub fn match_node_non_recursive<'tree, D: Doc>(
   candidate: Node<'tree, D>,
   env: &mut Cow<MetaVarEnv<'tree, D>>,
 ) -> Option<Node<'tree, D>> {
-  match match_node_impl(&goal.node, &candidate, env, &goal.strictness) {
-    MatchOneNode::MatchedBoth => Some(candidate),
-    _ => None,
-  }
+  match_sequence(goal, &candidate, env)?;
+  Some(candidate)
 }
 
 pub fn does_node_match_exactly<D: Doc>(goal: &Node<D>, candidate: &Node<D>) -> bool {
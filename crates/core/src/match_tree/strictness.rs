@@ -18,7 +18,7 @@ pub(crate) enum MatchOneNode {
   NoMatch,
 }
 
-fn skip_comment_or_unnamed(n: &Node<impl Doc>) -> bool {
+pub(crate) fn skip_comment_or_unnamed(n: &Node<impl Doc>) -> bool {
   if !n.is_named() {
     return true;
   }
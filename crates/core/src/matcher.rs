@@ -44,11 +44,17 @@ pub trait Matcher<L: Language> {
   }
 
   /// get_match_len will skip trailing anonymous child node to exclude punctuation.
-  // This is not included in NodeMatch since it is only used in replace
+  /// NodeMatch only uses it to extend matches beyond the node, e.g. multiple statements.
   fn get_match_len<D: Doc<Lang = L>>(&self, _node: Node<D>) -> Option<usize> {
     None
   }
 
+  /// Whether a match can end beyond the matched node, e.g. a multi-statement pattern.
+  /// `match_node` only computes `get_match_len` for such matchers to avoid matching twice.
+  fn may_match_beyond_node(&self) -> bool {
+    false
+  }
+
   fn match_node<'tree, D: Doc<Lang = L>>(
     &self,
     node: Node<'tree, D>,
@@ -56,7 +62,12 @@ pub trait Matcher<L: Language> {
     // in future we might need to customize initial MetaVarEnv
     let mut env = Cow::Owned(MetaVarEnv::new());
     let node = self.match_node_with_env(node, &mut env)?;
-    Some(NodeMatch::new(node, env.into_owned()))
+    let len = if self.may_match_beyond_node() {
+      self.get_match_len(node.clone())
+    } else {
+      None
+    };
+    Some(NodeMatch::new(node, env.into_owned()).with_match_len(len))
   }
 
   fn find_node<'tree, D: Doc<Lang = L>>(
@@ -86,6 +97,15 @@ impl<L: Language> Matcher<L> for str {
     let pattern = Pattern::str(self, node.lang().clone());
     pattern.get_match_len(node)
   }
+
+  fn match_node<'tree, D: Doc<Lang = L>>(
+    &self,
+    node: Node<'tree, D>,
+  ) -> Option<NodeMatch<'tree, D>> {
+    // the pattern is only known with the node's language
+    let pattern = Pattern::str(self, node.lang().clone());
+    pattern.match_node(node)
+  }
 }
 
 impl<L, T> Matcher<L> for &T
@@ -122,6 +142,10 @@ where
   fn get_match_len<D: Doc<Lang = L>>(&self, node: Node<D>) -> Option<usize> {
     (**self).get_match_len(node)
   }
+
+  fn may_match_beyond_node(&self) -> bool {
+    (**self).may_match_beyond_node()
+  }
 }
 
 pub struct FindAllNodes<'tree, D: Doc, M: Matcher<D::Lang>> {
@@ -1,21 +1,70 @@
 use super::Matcher;
 use crate::meta_var::MetaVarEnv;
+use crate::node::DisplayContext;
 use crate::replacer::Replacer;
 use crate::source::Edit;
-use crate::{Doc, Node};
+use crate::{Doc, Language, Node, StrDoc};
 
 use std::borrow::Borrow;
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 
 /// Represents the matched node with populated MetaVarEnv.
 /// It derefs to the Node so you can use it as a Node.
 /// To access the underlying MetaVarEnv, call `get_env` method.
 #[derive(Clone)]
-pub struct NodeMatch<'tree, D: Doc>(Node<'tree, D>, MetaVarEnv<'tree, D>);
+pub struct NodeMatch<'tree, D: Doc>(Node<'tree, D>, MetaVarEnv<'tree, D>, Option<MatchEnd>);
+
+/// End of a match beyond its node, e.g. the last statement matched by a multi-statement pattern.
+/// It is plain data so pinned matches stay valid after their nodes are readopted.
+#[derive(Clone, Copy)]
+struct MatchEnd {
+  offset: usize,
+  pos: (usize, usize),
+}
 
 impl<'tree, D: Doc> NodeMatch<'tree, D> {
   pub fn new(node: Node<'tree, D>, env: MetaVarEnv<'tree, D>) -> Self {
-    Self(node, env)
+    Self(node, env, None)
+  }
+
+  /// Extend the match to `len` bytes from the node start if it ends beyond the node.
+  /// `len` is returned by `Matcher::get_match_len`.
+  pub(crate) fn with_match_len(mut self, len: Option<usize>) -> Self {
+    let range = self.0.range();
+    let Some(end) = len
+      .map(|len| range.start + len)
+      .filter(|end| *end > range.end)
+    else {
+      return self;
+    };
+    let root = self.0.root.inner.root_node();
+    // the match ends with a leaf, whose end position is the match end position
+    if let Some(leaf) = root.descendant_for_byte_range(end as u32 - 1, end as u32) {
+      let pos = leaf.end_position();
+      self.2 = Some(MatchEnd {
+        offset: end,
+        pos: (pos.row() as usize, pos.column() as usize),
+      });
+    }
+    self
+  }
+
+  /// Byte range of the match. Unlike the node range, it covers all nodes matched
+  /// by a multi-node pattern, e.g. consecutive statements.
+  pub fn range(&self) -> Range<usize> {
+    let range = self.0.range();
+    match self.2 {
+      Some(end) => range.start..end.offset,
+      None => range,
+    }
+  }
+
+  /// End position of the match in rows and columns, see `range`.
+  pub fn end_pos(&self) -> (usize, usize) {
+    match self.2 {
+      Some(end) => end.pos,
+      None => self.0.end_pos(),
+    }
   }
 
   pub fn get_node(&self) -> &Node<'tree, D> {
@@ -36,6 +85,14 @@ impl<'tree, D: Doc> NodeMatch<'tree, D> {
   }
 }
 
+impl<'tree, L: Language> NodeMatch<'tree, StrDoc<L>> {
+  /// Same as `Node::display_context`, but displays the whole match, see `range`.
+  #[doc(hidden)]
+  pub fn display_context(&self, before: usize, after: usize) -> DisplayContext<'tree> {
+    self.0.display_range(self.range(), before, after)
+  }
+}
+
 impl<'tree, D: Doc> NodeMatch<'tree, D> {
   pub fn replace_by<R: Replacer<D>>(&self, replacer: R) -> Edit<D::Source> {
     let range = self.range();
@@ -67,7 +124,7 @@ impl<'tree, D: Doc> NodeMatch<'tree, D> {
 
 impl<'tree, D: Doc> From<Node<'tree, D>> for NodeMatch<'tree, D> {
   fn from(node: Node<'tree, D>) -> Self {
-    Self(node, MetaVarEnv::new())
+    Self(node, MetaVarEnv::new(), None)
   }
 }
 
@@ -131,6 +188,25 @@ mod test {
     assert_eq!(node.text(), "a");
   }
 
+  #[test]
+  fn test_multi_statement_range() {
+    let src = "const a = await foo();\nconst b = await bar();\nc()";
+    let root = Tsx.ast_grep(src);
+    let find = root
+      .root()
+      .find("const $A = await $X; const $B = await $Y;")
+      .expect("should find");
+    assert_eq!(find.text(), "const a = await foo();");
+    assert_eq!(
+      &src[find.range()],
+      "const a = await foo();\nconst b = await bar();"
+    );
+    assert_eq!(find.end_pos(), (1, 22));
+    let single = root.root().find("c()").expect("should find");
+    assert_eq!(single.range(), single.get_node().range());
+    assert_eq!(single.end_pos(), single.get_node().end_pos());
+  }
+
   #[test]
   fn test_replace_by() {
     let root = Tsx.ast_grep("var a = 1");
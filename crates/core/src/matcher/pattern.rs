@@ -1,5 +1,7 @@
 use crate::language::Language;
-use crate::match_tree::{match_end_non_recursive, match_node_non_recursive, MatchStrictness};
+use crate::match_tree::{
+  match_end_non_recursive, match_node_non_recursive, skip_comment_or_unnamed, MatchStrictness,
+};
 use crate::matcher::{KindMatcher, KindMatcherError, Matcher};
 use crate::meta_var::{MetaVarEnv, MetaVariable};
 use crate::source::TSParseError;
@@ -15,6 +17,9 @@ use std::marker::PhantomData;
 #[derive(Clone)]
 pub struct Pattern<L: Language> {
   pub node: PatternNode,
+  /// Patterns of the following siblings in a multi-node pattern, e.g. consecutive statements.
+  /// The match starts at the node matching `node` and ends at the last sibling.
+  following: Vec<PatternNode>,
   root_kind: Option<u16>,
   lang: PhantomData<L>,
   pub strictness: MatchStrictness,
@@ -76,6 +81,7 @@ impl<'r, D: Doc> From<Node<'r, D>> for Pattern<D::Lang> {
   fn from(node: Node<'r, D>) -> Self {
     Self {
       node: convert_node_to_pattern(node),
+      following: vec![],
       root_kind: None,
       lang: PhantomData,
       strictness: MatchStrictness::Smart,
@@ -84,7 +90,16 @@ impl<'r, D: Doc> From<Node<'r, D>> for Pattern<D::Lang> {
 }
 
 fn convert_node_to_pattern<D: Doc>(node: Node<D>) -> PatternNode {
-  if let Some(meta_var) = extract_var_from_node(&node) {
+  convert_node_with_expando(node, None)
+}
+
+/// `expandos` are offsets of expando chars replaced from meta var chars in pattern preprocessing.
+/// If present, nodes starting with a literal expando char are not meta variables.
+fn convert_node_with_expando<D: Doc>(
+  node: Node<D>,
+  expandos: Option<&HashSet<usize>>,
+) -> PatternNode {
+  if let Some(meta_var) = extract_var_from_node(&node, expandos) {
     PatternNode::MetaVar { meta_var }
   } else if node.is_leaf() {
     PatternNode::Terminal {
@@ -97,7 +112,7 @@ fn convert_node_to_pattern<D: Doc>(node: Node<D>) -> PatternNode {
       if n.get_ts_node().is_missing() {
         None
       } else {
-        Some(PatternNode::from(n))
+        Some(convert_node_with_expando(n, expandos))
       }
     });
     PatternNode::Internal {
@@ -107,11 +122,41 @@ fn convert_node_to_pattern<D: Doc>(node: Node<D>) -> PatternNode {
   }
 }
 
-fn extract_var_from_node<D: Doc>(goal: &Node<D>) -> Option<MetaVariable> {
+fn extract_var_from_node<D: Doc>(
+  goal: &Node<D>,
+  expandos: Option<&HashSet<usize>>,
+) -> Option<MetaVariable> {
   let key = goal.text();
+  if let Some(expandos) = expandos {
+    let is_expando = key.starts_with(goal.lang().expando_char());
+    if is_expando && !expandos.contains(&goal.range().start) {
+      return None;
+    }
+  }
   goal.lang().extract_meta_var(&key)
 }
 
+/// Find byte offsets in `processed` where the meta var char in `src` was replaced by expando char.
+/// Returns None if the language does not use expando or the pattern is not preprocessed char by char.
+fn find_expando_offsets<L: Language>(
+  lang: &L,
+  src: &str,
+  processed: &str,
+) -> Option<HashSet<usize>> {
+  let expando = lang.expando_char();
+  let meta_char = lang.meta_var_char();
+  if expando == meta_char || src.chars().count() != processed.chars().count() {
+    return None;
+  }
+  let offsets = processed
+    .char_indices()
+    .zip(src.chars())
+    .filter(|((_, p), s)| *p == expando && *s == meta_char)
+    .map(|((i, _), _)| i)
+    .collect();
+  Some(offsets)
+}
+
 #[derive(Debug, Error)]
 pub enum PatternError {
   #[error("Tree-Sitter fails to parse the pattern.")]
@@ -165,8 +210,16 @@ impl<L: Language> Pattern<L> {
   pub fn defined_vars(&self) -> HashSet<&str> {
     let mut vars = HashSet::new();
     collect_vars(&self.node, &mut vars);
+    for node in &self.following {
+      collect_vars(node, &mut vars);
+    }
     vars
   }
+
+  /// Patterns of the siblings following `node`, empty unless the pattern has multiple nodes.
+  pub fn following(&self) -> &[PatternNode] {
+    &self.following
+  }
 }
 
 fn meta_var_name(meta_var: &MetaVariable) -> Option<&str> {
@@ -200,16 +253,50 @@ fn collect_vars<'p>(p: &'p PatternNode, vars: &mut HashSet<&'p str>) {
 impl<L: Language> Pattern<L> {
   pub fn try_new(src: &str, lang: L) -> Result<Self, PatternError> {
     let processed = lang.pre_process_pattern(src);
+    let expandos = find_expando_offsets(&lang, src, &processed);
     let root = Root::<StrDoc<L>>::try_new(&processed, lang)?;
     let goal = root.root();
     if goal.inner.child_count() == 0 {
       return Err(PatternError::NoContent(src.into()));
     }
     if !is_single_node(&goal.inner) {
-      return Err(PatternError::MultipleNode(src.into()));
+      return Self::multi_node(goal, expandos.as_ref(), src);
     }
     let node = Self::single_matcher(&root);
-    Ok(Self::from(node))
+    Ok(Self {
+      node: convert_node_with_expando(node, expandos.as_ref()),
+      following: vec![],
+      root_kind: None,
+      lang: PhantomData,
+      strictness: MatchStrictness::Smart,
+    })
+  }
+
+  /// Pattern of consecutive sibling nodes, e.g. multiple statements.
+  /// Children are not unwrapped like single node patterns so `a; b` matches two statements.
+  fn multi_node<D: Doc>(
+    goal: Node<D>,
+    expandos: Option<&HashSet<usize>>,
+    src: &str,
+  ) -> Result<Self, PatternError> {
+    // a pattern failing to parse usually has multiple nodes, e.g. `12 34` in JavaScript
+    if goal.inner.has_error() {
+      return Err(PatternError::MultipleNode(src.into()));
+    }
+    let mut nodes = goal
+      .children()
+      .filter(|n| !skip_comment_or_unnamed(n))
+      .map(|n| convert_node_with_expando(n, expandos));
+    let node = nodes
+      .next()
+      .ok_or_else(|| PatternError::NoContent(src.into()))?;
+    Ok(Self {
+      node,
+      following: nodes.collect(),
+      root_kind: None,
+      lang: PhantomData,
+      strictness: MatchStrictness::Smart,
+    })
   }
 
   pub fn new(src: &str, lang: L) -> Self {
@@ -223,6 +310,7 @@ impl<L: Language> Pattern<L> {
 
   pub fn contextual(context: &str, selector: &str, lang: L) -> Result<Self, PatternError> {
     let processed = lang.pre_process_pattern(context);
+    let expandos = find_expando_offsets(&lang, context, &processed);
     let root = Root::<StrDoc<L>>::try_new(&processed, lang.clone())?;
     let goal = root.root();
     let kind_matcher = KindMatcher::try_new(selector, lang)?;
@@ -234,7 +322,8 @@ impl<L: Language> Pattern<L> {
     };
     Ok(Self {
       root_kind: Some(node.kind_id()),
-      node: convert_node_to_pattern(node.get_node().clone()),
+      node: convert_node_with_expando(node.get_node().clone(), expandos.as_ref()),
+      following: vec![],
       lang: PhantomData,
       strictness: MatchStrictness::Smart,
     })
@@ -287,6 +376,10 @@ impl<L: Language> Matcher<L> for Pattern<L> {
     let end = match_end_non_recursive(self, node)?;
     Some(end - start)
   }
+
+  fn may_match_beyond_node(&self) -> bool {
+    !self.following.is_empty()
+  }
 }
 impl std::fmt::Debug for PatternNode {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -480,7 +573,6 @@ mod test {
   }
 
   #[test]
-  #[ignore]
   fn test_multi_node_pattern() {
     let pattern = Pattern::str("a;b;c;", Tsx);
     let kinds = pattern.potential_kinds().expect("should have kinds");
@@ -489,7 +581,6 @@ mod test {
   }
 
   #[test]
-  #[ignore]
   fn test_multi_node_meta_var() {
     let env = match_env("a;$B;c", "a;b;c");
     assert_eq!(env["B"], "b");
@@ -591,4 +682,112 @@ mod test {
   fn test_gh_1087() {
     test_match("($P) => $F($P)", "(x) => bar(x)");
   }
+
+  #[test]
+  fn test_multi_statement_captures() {
+    let pattern = "const $A = await $X; const $B = await $Y;";
+    let src = "async function f() { const a = await foo(); const b = await bar(); }";
+    let env = match_env(pattern, src);
+    assert_eq!(env["A"], "a");
+    assert_eq!(env["X"], "foo()");
+    assert_eq!(env["B"], "b");
+    assert_eq!(env["Y"], "bar()");
+    // comments between statements are skipped
+    test_match(
+      pattern,
+      "const a = await foo(); /* c */ const b = await bar();",
+    );
+    test_non_match(
+      pattern,
+      "const a = await foo(); log(); const b = await bar();",
+    );
+    test_non_match(pattern, "const a = await foo();");
+  }
+
+  /// Pattern counting calls of `get_match_len`, which matches the pattern again.
+  struct CountMatchLen(Pattern<Tsx>, std::cell::Cell<usize>);
+  impl Matcher<Tsx> for CountMatchLen {
+    fn match_node_with_env<'tree, D: Doc<Lang = Tsx>>(
+      &self,
+      node: Node<'tree, D>,
+      env: &mut Cow<MetaVarEnv<'tree, D>>,
+    ) -> Option<Node<'tree, D>> {
+      self.0.match_node_with_env(node, env)
+    }
+    fn get_match_len<D: Doc<Lang = Tsx>>(&self, node: Node<D>) -> Option<usize> {
+      self.1.set(self.1.get() + 1);
+      self.0.get_match_len(node)
+    }
+    fn may_match_beyond_node(&self) -> bool {
+      self.0.may_match_beyond_node()
+    }
+  }
+
+  #[test]
+  fn test_single_node_pattern_matches_once() {
+    let root = Tsx.ast_grep("const a = await foo(); const b = await bar();");
+    let single = CountMatchLen(Pattern::str("await $X", Tsx), Default::default());
+    assert_eq!(root.root().find_all(&single).count(), 2);
+    assert_eq!(single.1.get(), 0);
+    let pattern = Pattern::str("const $A = await $X; const $B = await $Y;", Tsx);
+    let multi = CountMatchLen(pattern, Default::default());
+    assert_eq!(root.root().find_all(&multi).count(), 1);
+    assert_eq!(multi.1.get(), 1);
+  }
+
+  #[test]
+  fn test_multi_statement_match_len() {
+    let pattern = Pattern::str("const $A = await $X; const $B = await $Y;", Tsx);
+    let src = "const a = await foo(); const b = await bar(); c()";
+    let root = Tsx.ast_grep(src);
+    let nm = pattern.find_node(root.root()).expect("should match");
+    assert_eq!(nm.text(), "const a = await foo();");
+    let start = nm.range().start;
+    let len = pattern
+      .get_match_len(nm.get_node().clone())
+      .expect("should match");
+    assert_eq!(
+      &src[start..start + len],
+      "const a = await foo(); const b = await bar();"
+    );
+  }
+
+  #[test]
+  fn test_dollar_identifier() {
+    // `$foo` is a valid identifier in JS but not a meta variable
+    test_match("$foo($A)", "$foo(1)");
+    test_non_match("$foo($A)", "bar(1)");
+    test_match("$foo.$BAR", "$foo.baz");
+  }
+
+  // Tsx whose expando char differs from the meta var char, like CSS or Python
+  #[derive(Clone)]
+  struct ExpandoTsx;
+  impl Language for ExpandoTsx {
+    fn get_ts_language(&self) -> crate::language::TSLanguage {
+      Tsx.get_ts_language()
+    }
+    fn expando_char(&self) -> char {
+      'µ'
+    }
+    fn pre_process_pattern<'q>(&self, query: &'q str) -> Cow<'q, str> {
+      Cow::Owned(query.replace('$', "µ"))
+    }
+  }
+
+  fn test_expando_match(pattern: &str, src: &str) -> bool {
+    let pattern = Pattern::str(pattern, ExpandoTsx);
+    let root = Root::<StrDoc<_>>::new(src, ExpandoTsx);
+    pattern.find_node(root.root()).is_some()
+  }
+
+  #[test]
+  fn test_literal_expando() {
+    // `µFOO` is a literal identifier in the pattern, not a meta variable
+    assert!(test_expando_match("µFOO($A)", "µFOO(1)"));
+    assert!(!test_expando_match("µFOO($A)", "bar(1)"));
+    assert!(!test_expando_match("a.µB", "a.c"));
+    assert!(test_expando_match("$F(µFOO)", "bar(µFOO)"));
+    assert!(!test_expando_match("$F(µFOO)", "bar(baz)"));
+  }
 }
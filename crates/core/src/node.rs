@@ -45,6 +45,11 @@ impl<D: Doc> Root<D> {
     Self::try_doc(doc).expect("Parse doc error")
   }
 
+  /// The source of the document, e.g. to parse it again with other tree-sitter APIs.
+  pub fn get_source(&self) -> &D::Source {
+    self.doc.get_source()
+  }
+
   pub fn lang(&self) -> &D::Lang {
     self.doc.get_lang()
   }
@@ -59,8 +64,8 @@ impl<D: Doc> Root<D> {
   // extract non generic implementation to reduce code size
   pub fn do_edit(&mut self, edit: Edit<D>) -> Result<(), TSParseError> {
     let source = self.doc.get_source_mut();
-    let input_edit = perform_edit(&mut self.inner, source, &edit);
-    self.inner.edit(&input_edit);
+    // perform_edit has already applied the edit to the old tree
+    perform_edit(&mut self.inner, source, &edit);
     self.inner = self.doc.parse(Some(&self.inner))?;
     Ok(())
   }
@@ -195,6 +200,39 @@ impl<'r, D: Doc> Node<'r, D> {
     (pos.row() as usize, pos.column() as usize)
   }
 
+  /// byte offsets of the node including its trivia: comments right above the node
+  /// without blank lines in between, and a comment following the node on the same line.
+  pub fn range_with_trivia(&self) -> std::ops::Range<usize> {
+    let is_comment = |n: &Self| n.kind().contains("comment");
+    let mut start = self.range().start;
+    let mut line = self.start_pos().0;
+    let mut prev = self.prev();
+    while let Some(p) = prev {
+      // a blank line separates the comment from the node
+      if !is_comment(&p) || p.end_pos().0 + 1 < line {
+        break;
+      }
+      let before = p.prev();
+      // the comment is a trailing comment of the previous node
+      if before
+        .as_ref()
+        .map_or(false, |b| b.end_pos().0 == p.start_pos().0)
+      {
+        break;
+      }
+      start = p.range().start;
+      line = p.start_pos().0;
+      prev = before;
+    }
+    let mut end = self.range().end;
+    if let Some(next) = self.next() {
+      if is_comment(&next) && next.start_pos().0 == self.end_pos().0 {
+        end = next.range().end;
+      }
+    }
+    start..end
+  }
+
   pub fn text(&self) -> Cow<'r, str> {
     let source = self.root.doc.get_source();
     source.get_text(&self.inner)
@@ -214,10 +252,19 @@ impl<'r, D: Doc> Node<'r, D> {
 impl<'r, L: Language> Node<'r, StrDoc<L>> {
   #[doc(hidden)]
   pub fn display_context(&self, before: usize, after: usize) -> DisplayContext<'r> {
+    self.display_range(self.range(), before, after)
+  }
+
+  /// Display context of the byte range starting at the node, e.g. a match of multiple nodes.
+  pub(crate) fn display_range(
+    &self,
+    range: std::ops::Range<usize>,
+    before: usize,
+    after: usize,
+  ) -> DisplayContext<'r> {
     let source = self.root.doc.get_source().as_str();
     let bytes = source.as_bytes();
-    let start = self.inner.start_byte() as usize;
-    let end = self.inner.end_byte() as usize;
+    let (start, end) = (range.start, range.end);
     let (mut leading, mut trailing) = (start, end);
     let mut lines_before = before + 1;
     while leading > 0 {
@@ -248,8 +295,13 @@ impl<'r, L: Language> Node<'r, StrDoc<L>> {
       // otherwise, there are fewer than `before` line in src, compute the actual line
       before + 1 - lines_before
     };
+    let matched = if range == self.range() {
+      self.text()
+    } else {
+      Cow::Borrowed(&source[range])
+    };
     DisplayContext {
-      matched: self.text(),
+      matched,
       leading: &source[leading..start],
       trailing: &source[end..trailing],
       start_line: self.start_pos().0 - offset,
@@ -688,6 +740,21 @@ if (a) {
     assert!(node.child_by_field_id(id + 1).is_none());
   }
 
+  #[test]
+  fn test_range_with_trivia() {
+    let src = "a(); // a\n\n// doc\n/** more */\nfunction b() {} // b\nc()";
+    let root = Tsx.ast_grep(src);
+    let node = root.root().find("function b() {}").expect("should exist");
+    let range = node.range_with_trivia();
+    assert_eq!(&src[range], "// doc\n/** more */\nfunction b() {} // b");
+    // trailing comment of a() is not leading trivia of the next node
+    let src = "a(); // a\nb()";
+    let root = Tsx.ast_grep(src);
+    let node = root.root().find("b()").expect("should exist");
+    let node = node.parent().expect("should have statement");
+    assert_eq!(&src[node.range_with_trivia()], "b()");
+  }
+
   #[test]
   fn test_remove() {
     let root = Tsx.ast_grep("Some(Some(1)); Some(2);");
@@ -10,8 +10,8 @@ use tree_sitter_native::{Language as NativeTS, LANGUAGE_VERSION, MIN_COMPATIBLE_
 use std::borrow::Cow;
 use std::fs::canonicalize;
 use std::path::{Path, PathBuf};
-use std::ptr::{addr_of, addr_of_mut};
 use std::str::FromStr;
+use std::sync::RwLock;
 
 type LangIndex = u32;
 
@@ -37,7 +37,7 @@ impl DynamicLang {
   pub fn file_types(&self) -> Types {
     let mut builder = TypesBuilder::new();
     let inner = self.inner();
-    let mapping = unsafe { &*addr_of!(LANG_INDEX) };
+    let mapping = LANG_INDEX.read().unwrap();
     for (ext, i) in mapping.iter() {
       if *i == self.index {
         builder
@@ -88,6 +88,7 @@ impl FromStr for DynamicLang {
 
 struct Inner {
   lang: TSLanguage,
+  native: NativeTS,
   name: String,
   meta_var_char: char,
   expando_char: char,
@@ -113,7 +114,7 @@ pub enum DynamicLangError {
 unsafe fn load_ts_language(
   path: PathBuf,
   name: String,
-) -> Result<(Library, TSLanguage), DynamicLangError> {
+) -> Result<(Library, NativeTS), DynamicLangError> {
   let abs_path = canonicalize(path)?;
   let lib = Library::new(abs_path.as_os_str()).map_err(DynamicLangError::OpenLib)?;
   // NOTE: func is a symbol with lifetime bound to `lib`.
@@ -128,13 +129,14 @@ unsafe fn load_ts_language(
   } else {
     // ATTENTION: dragon ahead
     // must hold valid reference to NativeTS
-    Ok((lib, lang.into()))
+    Ok((lib, lang))
   }
 }
 
 // both use vec since lang will be small
-static mut DYNAMIC_LANG: Vec<Inner> = vec![];
-static mut LANG_INDEX: Vec<(String, u32)> = vec![];
+// languages are only appended and never freed, so references stay valid in other threads
+static DYNAMIC_LANG: RwLock<Vec<&'static Inner>> = RwLock::new(vec![]);
+static LANG_INDEX: RwLock<Vec<(String, u32)>> = RwLock::new(vec![]);
 
 #[derive(Default)]
 pub struct Registration {
@@ -148,54 +150,52 @@ pub struct Registration {
 
 impl DynamicLang {
   /// # Safety
-  /// the register function should be called exactly once before use.
-  /// It relies on a global mut static variable to be initialized.
+  /// the dynamic libs are loaded and their symbols are trusted to be tree-sitter languages.
+  /// Languages are appended to registered ones, which are kept valid,
+  /// so languages can be used in other threads while registering.
   pub unsafe fn register(regs: Vec<Registration>) -> Result<(), DynamicLangError> {
-    debug_assert!(Self::langs().is_empty());
-    let mut langs = vec![];
-    let mut mapping = vec![];
-    for reg in regs {
-      Self::register_one(reg, &mut langs, &mut mapping)?;
-    }
-    _ = std::mem::replace(&mut *addr_of_mut!(DYNAMIC_LANG), langs);
-    _ = std::mem::replace(&mut *addr_of_mut!(LANG_INDEX), mapping);
-    Ok(())
+    regs.into_iter().try_for_each(Self::register_one)
   }
 
   pub fn name(&self) -> &str {
     &self.inner().name
   }
 
-  fn register_one(
-    reg: Registration,
-    langs: &mut Vec<Inner>,
-    mapping: &mut Vec<(String, LangIndex)>,
-  ) -> Result<(), DynamicLangError> {
+  /// Grammar of the native tree-sitter crate, e.g. to run tree-sitter queries.
+  pub fn native_language(&self) -> NativeTS {
+    self.inner().native.clone()
+  }
+
+  fn register_one(reg: Registration) -> Result<(), DynamicLangError> {
     // lib must be retained!!
-    let (_lib, lang) = unsafe { load_ts_language(reg.lib_path, reg.symbol)? };
+    let (_lib, native) = unsafe { load_ts_language(reg.lib_path, reg.symbol)? };
     let meta_var_char = reg.meta_var_char.unwrap_or('$');
     let expando_char = reg.expando_char.unwrap_or(meta_var_char);
     let inner = Inner {
       name: reg.lang_name,
-      lang,
+      lang: native.clone().into(),
+      native,
       meta_var_char,
       expando_char,
       _lib,
     };
-    langs.push(inner);
+    // lock both so a language is never found by extension before it is added
+    let mut langs = DYNAMIC_LANG.write().unwrap();
+    let mut mapping = LANG_INDEX.write().unwrap();
+    langs.push(Box::leak(Box::new(inner)));
     let idx = langs.len() as LangIndex - 1;
     for ext in reg.extensions {
       mapping.push((ext, idx));
     }
     Ok(())
   }
-  fn inner(&self) -> &Inner {
-    let langs = Self::langs();
-    &langs[self.index as usize]
+  fn inner(&self) -> &'static Inner {
+    DYNAMIC_LANG.read().unwrap()[self.index as usize]
   }
 
-  fn langs() -> &'static Vec<Inner> {
-    unsafe { &*addr_of!(DYNAMIC_LANG) }
+  /// Snapshot of registered languages, whose entries are never removed.
+  fn langs() -> Vec<&'static Inner> {
+    DYNAMIC_LANG.read().unwrap().clone()
   }
 }
 
@@ -207,8 +207,9 @@ impl Language for DynamicLang {
 
   fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
     let ext = path.as_ref().extension()?.to_str()?;
-    let mapping = unsafe { &*addr_of!(LANG_INDEX) };
-    let langs = Self::langs();
+    // locked in the same order as registration
+    let langs = DYNAMIC_LANG.read().unwrap();
+    let mapping = LANG_INDEX.read().unwrap();
     mapping.iter().find_map(|(p, idx)| {
       if p == ext {
         let index = *idx;
@@ -269,7 +270,7 @@ mod test {
   fn test_load_parser() {
     let path = get_tree_sitter_path();
     let (_lib, lang) = unsafe { load_ts_language(path.into(), "tree_sitter_json".into()).unwrap() };
-    let sg = lang.ast_grep("{\"a\": 123}");
+    let sg = TSLanguage::from(lang).ast_grep("{\"a\": 123}");
     assert_eq!(
       sg.root().to_sexp(),
       "(document (object (pair key: (string (string_content)) value: (number))))"
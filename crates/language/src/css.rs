@@ -15,6 +15,21 @@ fn test_css_pattern() {
   test_match(".a { $PROP: red; }", ".a { color: red; }");
 }
 
+fn test_non_match(query: &str, source: &str) {
+  use crate::test::test_non_match_lang;
+  test_non_match_lang(query, source, Css);
+}
+
+#[test]
+fn test_css_literal_expando() {
+  // `_` is the expando char for CSS, literal underscores are not meta variables
+  test_match("._FOO { color: $C; }", "._FOO { color: red; }");
+  test_non_match("._FOO { color: $C; }", ".bar { color: red; }");
+  test_match(".a__B { color: $C; }", ".a__B { color: red; }");
+  test_non_match(".a__B { color: $C; }", ".a__C { color: red; }");
+  test_match("$SEL { color: $C; }", "._FOO { color: red; }");
+}
+
 fn test_replace(src: &str, pattern: &str, replacer: &str) -> Result<String, TSParseError> {
   use crate::test::test_replace_lang;
   test_replace_lang(src, pattern, replacer, Css)
@@ -189,6 +189,12 @@ impl SupportLang {
   pub fn file_types(&self) -> Types {
     file_types(self)
   }
+
+  /// Grammar of the native tree-sitter crate, e.g. to run tree-sitter queries.
+  #[cfg(feature = "builtin-parser")]
+  pub fn native_language(&self) -> tree_sitter_native::Language {
+    parsers::native_language(*self)
+  }
 }
 
 impl fmt::Display for SupportLang {
@@ -80,6 +80,37 @@ mod parser_implementation {
   pub fn language_typescript() -> TSLanguage {
     tree_sitter_typescript::language_typescript().into()
   }
+
+  /// Grammar of the native tree-sitter crate, for APIs the facade does not wrap, e.g. query cursors.
+  pub fn native_language(lang: crate::SupportLang) -> tree_sitter_native::Language {
+    use crate::SupportLang as S;
+    match lang {
+      S::Bash => tree_sitter_bash::language(),
+      S::C => tree_sitter_c::language(),
+      S::Cpp => tree_sitter_cpp::language(),
+      S::CSharp => tree_sitter_c_sharp::language(),
+      S::Css => tree_sitter_css::language(),
+      S::Dart => tree_sitter_dart::language(),
+      S::Elixir => tree_sitter_elixir::language(),
+      S::Go => tree_sitter_go::language(),
+      S::Haskell => tree_sitter_haskell::language(),
+      S::Html => tree_sitter_html::language(),
+      S::Java => tree_sitter_java::language(),
+      S::JavaScript => tree_sitter_javascript::language(),
+      S::Json => tree_sitter_json::language(),
+      S::Kotlin => tree_sitter_kotlin::language(),
+      S::Lua => tree_sitter_lua::language(),
+      S::Php => tree_sitter_php::language_php(),
+      S::Python => tree_sitter_python::language(),
+      S::Ruby => tree_sitter_ruby::language(),
+      S::Rust => tree_sitter_rust::language(),
+      S::Scala => tree_sitter_scala::language(),
+      S::Sql => tree_sitter_sql_bigquery::language(),
+      S::Swift => tree_sitter_swift::language(),
+      S::Tsx => tree_sitter_typescript::language_tsx(),
+      S::TypeScript => tree_sitter_typescript::language_typescript(),
+    }
+  }
 }
 
 #[cfg(not(feature = "builtin-parser"))]
@@ -0,0 +1,186 @@
+//! Open documents with their parsed trees. When more documents are open than the cap,
+//! trees of the least recently used documents are dropped and parsed again once used.
+use crate::{LSPLang, VersionedAst};
+
+use ast_grep_core::{AstGrep, StrDoc};
+use dashmap::mapref::entry::Entry;
+use dashmap::mapref::one::{Ref, RefMut};
+use dashmap::DashMap;
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Source of a document whose tree is dropped.
+struct Evicted<L> {
+  version: i32,
+  source: String,
+  lang: L,
+}
+
+pub struct Documents<L: LSPLang> {
+  parsed: DashMap<String, VersionedAst<StrDoc<L>>>,
+  evicted: DashMap<String, Evicted<L>>,
+  /// tick of the last use of every parsed document
+  last_used: DashMap<String, u64>,
+  tick: AtomicU64,
+  /// max number of parsed documents, 0 is unbounded
+  capacity: AtomicUsize,
+}
+
+impl<L: LSPLang> Default for Documents<L> {
+  fn default() -> Self {
+    Self {
+      parsed: DashMap::new(),
+      evicted: DashMap::new(),
+      last_used: DashMap::new(),
+      tick: AtomicU64::new(0),
+      capacity: AtomicUsize::new(0),
+    }
+  }
+}
+
+impl<L: LSPLang> Documents<L> {
+  /// Keep at most `capacity` parsed documents, or all of them if `None`.
+  pub fn set_capacity(&self, capacity: Option<usize>) {
+    let capacity = capacity.map_or(0, |c| c.max(1));
+    self.capacity.store(capacity, Ordering::Relaxed);
+    self.evict_except("");
+  }
+
+  /// Get the document, parsing it again if its tree is dropped.
+  /// No other document should be borrowed by the caller, which may be dropped meanwhile.
+  /// The guard must not be held across awaits, or dropping its tree blocks other tasks.
+  pub fn get(&self, uri: &str) -> Option<Ref<'_, String, VersionedAst<StrDoc<L>>>> {
+    loop {
+      self.restore(uri);
+      if let Some(versioned) = self.parsed.get(uri) {
+        self.touch(uri);
+        return Some(versioned);
+      }
+      // another task may drop the tree after it is restored, then restore it again
+      if !self.evicted.contains_key(uri) {
+        return None;
+      }
+    }
+  }
+
+  /// Same as `get` but the document can be edited.
+  pub fn get_mut(&self, uri: &str) -> Option<RefMut<'_, String, VersionedAst<StrDoc<L>>>> {
+    loop {
+      self.restore(uri);
+      if let Some(versioned) = self.parsed.get_mut(uri) {
+        self.touch(uri);
+        return Some(versioned);
+      }
+      // another task may drop the tree after it is restored, then restore it again
+      if !self.evicted.contains_key(uri) {
+        return None;
+      }
+    }
+  }
+
+  pub fn insert(&self, uri: String, versioned: VersionedAst<StrDoc<L>>) {
+    self.evicted.remove(&uri);
+    self.parsed.insert(uri.clone(), versioned);
+    self.touch(&uri);
+    self.evict_except(&uri);
+  }
+
+  pub fn remove(&self, uri: &str) {
+    self.last_used.remove(uri);
+    self.evicted.remove(uri);
+    self.parsed.remove(uri);
+  }
+
+  /// Remove the document and return it, whose tree is parsed again if it is dropped.
+  pub fn take(&self, uri: &str) -> Option<VersionedAst<StrDoc<L>>> {
+    self.last_used.remove(uri);
+    if let Some((_, evicted)) = self.evicted.remove(uri) {
+      return Some(evicted.parse());
+    }
+    self.parsed.remove(uri).map(|(_, versioned)| versioned)
+  }
+
+  /// Version of the document without parsing it again.
+  pub fn version(&self, uri: &str) -> Option<i32> {
+    if let Some(versioned) = self.parsed.get(uri) {
+      return Some(versioned.version);
+    }
+    self.evicted.get(uri).map(|evicted| evicted.version)
+  }
+
+  pub fn contains_key(&self, uri: &str) -> bool {
+    self.parsed.contains_key(uri) || self.evicted.contains_key(uri)
+  }
+
+  pub fn len(&self) -> usize {
+    self.last_used.len() + self.evicted.len()
+  }
+
+  /// Number of documents whose trees are kept.
+  pub fn parsed_len(&self) -> usize {
+    self.last_used.len()
+  }
+
+  /// Uris of all documents, including those whose trees are dropped.
+  pub fn uris(&self) -> Vec<String> {
+    let parsed = self.last_used.iter().map(|e| e.key().clone());
+    let evicted = self.evicted.iter().map(|e| e.key().clone());
+    parsed.chain(evicted).collect()
+  }
+
+  fn touch(&self, uri: &str) {
+    let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+    self.last_used.insert(uri.to_string(), tick);
+  }
+
+  fn restore(&self, uri: &str) {
+    let Some((uri, evicted)) = self.evicted.remove(uri) else {
+      return;
+    };
+    self.parsed.insert(uri.clone(), evicted.parse());
+    self.touch(&uri);
+    self.evict_except(&uri);
+  }
+
+  /// Drop trees of least recently used documents until the cap is met, except the used one.
+  fn evict_except(&self, used: &str) {
+    let capacity = self.capacity.load(Ordering::Relaxed);
+    if capacity == 0 {
+      return;
+    }
+    // `last_used` is used instead of `parsed`, whose shard may be borrowed by the caller
+    while self.last_used.len() > capacity {
+      let oldest = self
+        .last_used
+        .iter()
+        .filter(|e| e.key() != used)
+        .min_by_key(|e| *e.value())
+        .map(|e| e.key().clone());
+      let Some(oldest) = oldest else {
+        return;
+      };
+      self.last_used.remove(&oldest);
+      let Entry::Occupied(entry) = self.parsed.entry(oldest) else {
+        continue;
+      };
+      let versioned = entry.get();
+      let evicted = Evicted {
+        version: versioned.version,
+        source: versioned.root.source().to_string(),
+        lang: versioned.root.lang().clone(),
+      };
+      // stored before the tree is removed, so the document is always in one of the maps
+      self.evicted.insert(entry.key().clone(), evicted);
+      entry.remove();
+    }
+  }
+}
+
+impl<L: LSPLang> Evicted<L> {
+  fn parse(self) -> VersionedAst<StrDoc<L>> {
+    VersionedAst {
+      version: self.version,
+      root: AstGrep::new(self.source, self.lang),
+    }
+  }
+}
@@ -1,42 +1,226 @@
+mod documents;
+mod notebook;
+mod options;
+mod rule_file;
+mod transport;
 mod utils;
 
-use dashmap::DashMap;
-use serde_json::Value;
+use dashmap::{DashMap, DashSet};
+use globset::GlobSet;
+use ignore::WalkBuilder;
+use serde_json::{json, Value};
+use tokio::task::AbortHandle;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
-use ast_grep_config::{CombinedScan, RuleCollection, RuleConfig};
-use ast_grep_core::{language::Language, AstGrep, Doc, StrDoc};
+use ast_grep_config::{
+  CombinedScan, RuleCollection, RuleConfig, RuleConfigError, RuleSource, ScanKinds, Severity,
+};
+use ast_grep_core::{language::Language, AstGrep, Doc, Pattern, StrDoc};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
-use utils::{convert_match_to_diagnostic, diagnostic_to_code_action, RewriteData};
+use documents::Documents;
+use options::{DiagnosticsTrigger, ServerOptions};
+use rule_file::{
+  document_symbols, error_message, pattern_hover, rule_completions, rule_file_diagnostics,
+  verify_rule_tests,
+};
+use utils::{
+  apply_content_change, apply_text_edits, code_description_from_template, collect_fixes,
+  comment_syntax, convert_match_to_diagnostic, diagnostic_to_code_action, diagnostic_to_hover,
+  diagnostics_result_id, dump_node, encode_range, extract_pattern, find_node_at, get_rule_id,
+  inspect_nodes, is_kind_requested, is_suppressed, rule_code_lenses, rule_id_line_range,
+  rule_scaffold, search_pattern, sort_diagnostics, suppression_code_action,
+  to_suppressed_diagnostic, truncate_message, unresolved_code_action, unsafe_fix_ranges,
+  ApplyRuleFixesParams, CancelToken, CodeActionProgress, DiagnoseTextParams, DocumentStatus,
+  DocumentStatusNotification, ExtractRuleParams, FixConflict, FixFilter, InspectNodeParams,
+  PartialResult, PositionEncoding, QuickFixData, SearchParams, WorkspaceDiagnosticProgress,
+};
 
 pub use tower_lsp::{LspService, Server};
+pub use transport::{serve_tcp, serve_ws};
 
-pub trait LSPLang: Language + Eq + Send + Sync + 'static {}
-impl<T> LSPLang for T where T: Language + Eq + Send + Sync + 'static {}
+pub trait LSPLang: Language + FromStr + Eq + Send + Sync + 'static {}
+impl<T> LSPLang for T where T: Language + FromStr + Eq + Send + Sync + 'static {}
 
+/// Reads rules from the project when config files change.
+pub trait RuleFinder<L: LSPLang>: Send + Sync + 'static {
+  /// Find all rules in the project.
+  fn find_rules(&self) -> std::result::Result<RuleCollection<L>, String>;
+  /// Read rules in a single rule file, or an empty vec if the file is deleted.
+  /// Returns `None` if the path is not a rule file, which triggers a full reload.
+  fn read_rule_file(&self, path: &Path) -> Option<std::result::Result<Vec<RuleConfig<L>>, String>>;
+  /// Glob patterns of config and rule files, registered as file watchers to reload rules.
+  fn watch_patterns(&self) -> Vec<String> {
+    vec![format!("**/{CONFIG_FILE}"), "**/*.{yml,yaml}".to_string()]
+  }
+  /// Whether the path is a rule file, which gets completions for rule authoring.
+  fn is_rule_file(&self, _path: &Path) -> bool {
+    false
+  }
+  /// Whether the path is a rule test file, which gets a code lens to run its tests.
+  fn is_test_file(&self, _path: &Path) -> bool {
+    false
+  }
+  /// Ids of global utility rules that rule files can reference by `matches`.
+  fn util_rule_ids(&self) -> Vec<String> {
+    vec![]
+  }
+  /// Parse rules in the text of an opened rule file with global utility rules of the project.
+  /// Errors are published as diagnostics of the file. Finders that cannot parse rules accept all.
+  fn parse_rules(&self, _text: &str) -> std::result::Result<Vec<RuleConfig<L>>, RuleConfigError> {
+    Ok(vec![])
+  }
+  /// Forget project config and utility rules cached by the finder, called when watched files change.
+  fn clear_cache(&self) {}
+  /// Find rules with another project config set by client's `configPath` setting.
+  /// `None` restores the default config. Finders without project config can ignore it.
+  fn set_config_path(&self, _config_path: Option<PathBuf>) {}
+  /// Find rules of a workspace folder with its own project config.
+  /// Returns `None` if the folder has no config, so the default rules apply to its files.
+  fn find_rules_in(
+    &self,
+    _folder: &Path,
+  ) -> Option<std::result::Result<RuleCollection<L>, String>> {
+    None
+  }
+}
+
+/// Fixes computed for fix-all requests.
+struct AllFixes {
+  changes: HashMap<Url, Vec<TextEdit>>,
+  /// ranges of fixes marked `unsafe`, which need confirmation
+  unsafe_ranges: Vec<Range>,
+  /// fixes dropped because they overlap with other fixes
+  skipped: Vec<FixConflict>,
+}
+
+#[derive(Clone)]
 struct VersionedAst<D: Doc> {
   version: i32,
   root: AstGrep<D>,
 }
 
-pub struct Backend<L: LSPLang> {
+/// Rules found by the rule finder, or the error of loading them.
+type Rules<L> = std::result::Result<RuleCollection<L>, String>;
+
+/// The language server, a cheap handle to its state shared with tasks it spawns.
+pub struct Backend<L: LSPLang>(Arc<BackendState<L>>);
+
+pub struct BackendState<L: LSPLang> {
   client: Client,
-  map: DashMap<String, VersionedAst<StrDoc<L>>>,
+  /// open documents, parsed again if their trees are evicted by `maxCachedDocuments`
+  map: Documents<L>,
   base: PathBuf,
-  rules: std::result::Result<RuleCollection<L>, String>,
+  /// shared so scans can keep using the rules they started with while rules are reloaded
+  rules: RwLock<Arc<Rules<L>>>,
+  rule_finder: Option<Box<dyn RuleFinder<L>>>,
+  /// rules of workspace folders with their own project config, keyed by folder path.
+  /// Rules are shared so they can be used without locking the map, which is read meanwhile.
+  folders: RwLock<HashMap<PathBuf, Arc<Rules<L>>>>,
+  options: RwLock<ServerOptions>,
+  /// extensions already shown to the user as unsupported
+  reported_extensions: DashSet<String>,
+  client_capabilities: RwLock<ClientCapabilities>,
+  /// diagnostics of open documents, updated when a document or rules change
+  diagnostics: DashMap<String, Vec<Diagnostic>>,
+  /// counter to create unique progress tokens
+  progress_id: AtomicU32,
+  /// text of open rule files, used for rule authoring completions
+  rule_docs: DashMap<String, String>,
+  /// text of open rule test files, verified by the code lens to run tests
+  test_docs: DashMap<String, String>,
+  /// kinds of combined scans keyed by rules generation and sorted rule addresses,
+  /// cleared when rules are reloaded
+  scan_kinds: DashMap<(u64, Vec<usize>), Arc<ScanKinds>>,
+  /// bumped when rules are reloaded, since new rules may reuse addresses of dropped ones
+  rules_generation: AtomicU64,
+  /// compiled `exclude` option, matched against paths relative to the workspace root
+  exclude: RwLock<GlobSet>,
+  /// pending debounced scans of changed documents, aborted by later changes
+  debounced: DashMap<String, AbortHandle>,
+  /// unopened files with findings published by the last `ast-grep.scanWorkspace`,
+  /// cleared by the next scan if they have no findings anymore
+  scanned_uris: DashSet<String>,
+  /// trace setting of `initialize` and `$/setTrace`, timing is only traced if not off
+  trace: RwLock<TraceValue>,
+}
+
+impl<L: LSPLang> Clone for Backend<L> {
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+impl<L: LSPLang> Deref for Backend<L> {
+  type Target = BackendState<L>;
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
 }
 
 const FALLBACK_CODE_ACTION_PROVIDER: Option<CodeActionProviderCapability> =
   Some(CodeActionProviderCapability::Simple(true));
 
 const APPLY_ALL_FIXES: &str = "ast-grep.applyAllFixes";
+const APPLY_FIXES_RETURN_TEXT: &str = "ast-grep.applyFixesReturnText";
+const DIAGNOSE_TEXT: &str = "ast-grep.diagnoseText";
+const DUMP_CONFIG: &str = "ast-grep.dumpConfig";
+const APPLY_RULE_FIXES: &str = "ast-grep.applyRuleFixes";
+const SCAN_WORKSPACE: &str = "ast-grep.scanWorkspace";
+const SEARCH: &str = "ast-grep.search";
+const RUN_RULE_TESTS: &str = "ast-grep.runRuleTests";
+const INSPECT_NODE: &str = "ast-grep.inspectNode";
+const RELOAD_RULES: &str = "ast-grep.reloadRules";
+const EXTRACT_RULE: &str = "ast-grep.extractRule";
+const OPEN_RULE: &str = "ast-grep.openRule";
+const UNSAFE_FIX_ANNOTATION: &str = "ast-grep.unsafeFix";
 const QUICKFIX_AST_GREP: &str = "quickfix.ast-grep";
 const FIX_ALL_AST_GREP: &str = "source.fixAll.ast-grep";
+const APPLICABLE_RULES_AST_GREP: &str = "source.applicableRules.ast-grep";
+const CONFIG_FILE: &str = "sgconfig.yml";
+const RULE_WATCHER_ID: &str = "ast-grep-rule-watcher";
+/// Documents with at least this many rules are scanned by rule chunks in parallel.
+const PARALLEL_SCAN_RULES: usize = 100;
+/// number of diagnostics whose code actions are sent in one partial result
+const PARTIAL_RESULT_CHUNK: usize = 100;
+
+fn to_workspace_folder(path: PathBuf) -> WorkspaceFolder {
+  WorkspaceFolder {
+    name: path.to_string_lossy().to_string(),
+    uri: Url::from_file_path(&path).expect("folder path should be absolute"),
+  }
+}
+
+/// Path of file urls, or the url path of `untitled:` and other virtual documents,
+/// e.g. `git:/repo/a.ts?ref` for a git diff view.
+fn uri_to_path(uri: &Url) -> PathBuf {
+  uri
+    .to_file_path()
+    .unwrap_or_else(|_| PathBuf::from(uri.path()))
+}
+
+/// Files and folders of any path, which may contain opened documents.
+fn file_operation_registration() -> FileOperationRegistrationOptions {
+  FileOperationRegistrationOptions {
+    filters: vec![FileOperationFilter {
+      scheme: Some("file".to_string()),
+      pattern: FileOperationPattern {
+        glob: "**/*".to_string(),
+        matches: None,
+        options: None,
+      },
+    }],
+  }
+}
 
 fn code_action_provider(
   client_capability: &ClientCapabilities,
@@ -55,6 +239,7 @@ fn code_action_provider(
     code_action_kinds: Some(vec![
       CodeActionKind::new(QUICKFIX_AST_GREP),
       CodeActionKind::new(FIX_ALL_AST_GREP),
+      CodeActionKind::new(APPLICABLE_RULES_AST_GREP),
     ]),
     work_done_progress_options: Default::default(),
     resolve_provider: Some(true),
@@ -64,18 +249,82 @@ fn code_action_provider(
 #[tower_lsp::async_trait]
 impl<L: LSPLang> LanguageServer for Backend<L> {
   async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+    let options = ServerOptions::from_value(params.initialization_options);
+    let config_path = options.config_path.clone();
+    let has_client_rules = !options.rule_dirs.is_empty() || !options.inline_rules.is_empty();
+    self.map.set_capacity(options.max_cached_documents);
+    *self.exclude.write().unwrap() = options.exclude_globs();
+    *self.options.write().unwrap() = options;
+    *self.trace.write().unwrap() = params.trace.unwrap_or(TraceValue::Off);
+    // errors are reported once initialized
+    let has_config = config_path.is_some() && self.apply_config_path(config_path);
+    if has_config || has_client_rules {
+      self.reload_rules();
+    }
+    let folders = params.workspace_folders.unwrap_or_default();
+    self.update_workspace_folders(folders, vec![]);
+    *self.client_capabilities.write().unwrap() = params.capabilities.clone();
     Ok(InitializeResult {
       server_info: Some(ServerInfo {
         name: "ast-grep language server".to_string(),
         version: None,
       }),
       capabilities: ServerCapabilities {
-        // TODO: change this to incremental
-        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+          TextDocumentSyncOptions {
+            open_close: Some(true),
+            change: Some(TextDocumentSyncKind::INCREMENTAL),
+            save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+            ..Default::default()
+          },
+        )),
         code_action_provider: code_action_provider(&params.capabilities)
           .or(FALLBACK_CODE_ACTION_PROVIDER),
+        diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
+          identifier: Some("ast-grep".into()),
+          inter_file_dependencies: false,
+          workspace_diagnostics: true,
+          work_done_progress_options: Default::default(),
+        })),
+        position_encoding: Some(PositionEncoding::negotiate(&params.capabilities).kind()),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+          trigger_characters: Some(vec![" ".to_string()]),
+          ..Default::default()
+        }),
+        code_lens_provider: Some(CodeLensOptions {
+          resolve_provider: Some(false),
+        }),
+        workspace: Some(WorkspaceServerCapabilities {
+          workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+            supported: Some(true),
+            change_notifications: Some(OneOf::Left(true)),
+          }),
+          file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+            will_rename: Some(file_operation_registration()),
+            did_rename: Some(file_operation_registration()),
+            ..Default::default()
+          }),
+        }),
         execute_command_provider: Some(ExecuteCommandOptions {
-          commands: vec![APPLY_ALL_FIXES.to_string()],
+          commands: vec![
+            APPLY_ALL_FIXES.to_string(),
+            APPLY_FIXES_RETURN_TEXT.to_string(),
+            DIAGNOSE_TEXT.to_string(),
+            DUMP_CONFIG.to_string(),
+            APPLY_RULE_FIXES.to_string(),
+            SCAN_WORKSPACE.to_string(),
+            SEARCH.to_string(),
+            RUN_RULE_TESTS.to_string(),
+            INSPECT_NODE.to_string(),
+            RELOAD_RULES.to_string(),
+            EXTRACT_RULE.to_string(),
+            OPEN_RULE.to_string(),
+          ],
           work_done_progress_options: Default::default(),
         }),
         ..ServerCapabilities::default()
@@ -90,49 +339,50 @@ impl<L: LSPLang> LanguageServer for Backend<L> {
       .await;
 
     // Report errors loading config once, upon initialization
-    if let Err(error) = &self.rules {
-      // popup message
-      self
-        .client
-        .show_message(
-          MessageType::ERROR,
-          format!("Failed to load rules: {}", error),
-        )
-        .await;
-      // log message
-      self
-        .client
-        .log_message(
-          MessageType::ERROR,
-          format!("Failed to load rules: {}", error),
-        )
-        .await;
+    let error = self.rules.read().unwrap().as_ref().as_ref().err().cloned();
+    if let Some(error) = error {
+      self.report_rule_error(error).await;
+    }
+    let folder_errors: Vec<_> = {
+      let folders = self.folders.read().unwrap();
+      folders
+        .values()
+        .filter_map(|r| r.as_ref().as_ref().err().cloned())
+        .collect()
+    };
+    for error in folder_errors {
+      self.report_rule_error(error).await;
     }
+    self.register_file_watchers().await;
+    self.register_notebook_sync().await;
   }
 
   async fn shutdown(&self) -> Result<()> {
     Ok(())
   }
 
-  async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
+  async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
     self
       .client
       .log_message(MessageType::INFO, "workspace folders changed!")
       .await;
+    self.on_workspace_folders_change(params).await;
   }
 
-  async fn did_change_configuration(&self, _: DidChangeConfigurationParams) {
+  async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
     self
       .client
       .log_message(MessageType::INFO, "configuration changed!")
       .await;
+    self.on_configuration_change(params).await;
   }
 
-  async fn did_change_watched_files(&self, _: DidChangeWatchedFilesParams) {
+  async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
     self
       .client
       .log_message(MessageType::INFO, "watched files have changed!")
       .await;
+    self.on_watched_files_change(params).await;
   }
   async fn did_open(&self, params: DidOpenTextDocumentParams) {
     self
@@ -146,11 +396,12 @@ impl<L: LSPLang> LanguageServer for Backend<L> {
     self.on_change(params).await;
   }
 
-  async fn did_save(&self, _: DidSaveTextDocumentParams) {
+  async fn did_save(&self, params: DidSaveTextDocumentParams) {
     self
       .client
       .log_message(MessageType::INFO, "file saved!")
       .await;
+    self.on_save(params).await;
   }
 
   async fn did_close(&self, params: DidCloseTextDocumentParams) {
@@ -161,13 +412,82 @@ impl<L: LSPLang> LanguageServer for Backend<L> {
       .await;
   }
 
+  async fn will_rename_files(&self, _: RenameFilesParams) -> Result<Option<WorkspaceEdit>> {
+    // cached documents are moved once files are renamed, the rename needs no edit
+    Ok(None)
+  }
+
+  async fn did_rename_files(&self, params: RenameFilesParams) {
+    self.on_rename_files(params).await;
+  }
+
   async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
     Ok(self.on_code_action(params).await)
   }
 
+  async fn code_action_resolve(&self, params: CodeAction) -> Result<CodeAction> {
+    // return the action unchanged if the fix is gone, e.g. the document has changed
+    Ok(self.resolve_code_action(params.clone()).unwrap_or(params))
+  }
+
   async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
     Ok(self.on_execute_command(params).await)
   }
+
+  async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+    Ok(self.on_hover(params))
+  }
+
+  async fn document_symbol(
+    &self,
+    params: DocumentSymbolParams,
+  ) -> Result<Option<DocumentSymbolResponse>> {
+    Ok(self.on_document_symbol(params))
+  }
+
+  async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+    Ok(self.on_formatting(params.text_document, None).await)
+  }
+
+  async fn range_formatting(
+    &self,
+    params: DocumentRangeFormattingParams,
+  ) -> Result<Option<Vec<TextEdit>>> {
+    Ok(
+      self
+        .on_formatting(params.text_document, Some(params.range))
+        .await,
+    )
+  }
+
+  async fn goto_definition(
+    &self,
+    params: GotoDefinitionParams,
+  ) -> Result<Option<GotoDefinitionResponse>> {
+    Ok(self.on_goto_definition(params))
+  }
+
+  async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+    Ok(self.on_completion(params))
+  }
+
+  async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+    Ok(self.on_code_lens(params).await)
+  }
+
+  async fn diagnostic(
+    &self,
+    params: DocumentDiagnosticParams,
+  ) -> Result<DocumentDiagnosticReportResult> {
+    Ok(self.on_diagnostic(params).await)
+  }
+
+  async fn workspace_diagnostic(
+    &self,
+    params: WorkspaceDiagnosticParams,
+  ) -> Result<WorkspaceDiagnosticReportResult> {
+    Ok(self.on_workspace_diagnostic(params).await)
+  }
 }
 
 impl<L: LSPLang> Backend<L> {
@@ -176,241 +496,2421 @@ impl<L: LSPLang> Backend<L> {
     base: PathBuf,
     rules: std::result::Result<RuleCollection<L>, String>,
   ) -> Self {
-    Self {
+    Self(Arc::new(BackendState {
       client,
-      rules,
+      rules: RwLock::new(Arc::new(rules)),
+      rule_finder: None,
+      folders: RwLock::new(HashMap::new()),
       base,
-      map: DashMap::new(),
+      map: Documents::default(),
+      options: RwLock::new(ServerOptions::default()),
+      reported_extensions: DashSet::new(),
+      scanned_uris: DashSet::new(),
+      client_capabilities: RwLock::new(ClientCapabilities::default()),
+      diagnostics: DashMap::new(),
+      progress_id: AtomicU32::new(0),
+      trace: RwLock::new(TraceValue::Off),
+      rule_docs: DashMap::new(),
+      test_docs: DashMap::new(),
+      scan_kinds: DashMap::new(),
+      rules_generation: AtomicU64::new(0),
+      exclude: RwLock::new(GlobSet::empty()),
+      debounced: DashMap::new(),
+    }))
+  }
+
+  /// Reload rules with the finder when rule files or sgconfig.yml change.
+  /// It must be called before the backend is shared with the server.
+  pub fn with_rule_finder(mut self, finder: impl RuleFinder<L>) -> Self {
+    let state = Arc::get_mut(&mut self.0).expect("backend should not be shared yet");
+    state.rule_finder = Some(Box::new(finder));
+    self
+  }
+
+  /// The innermost workspace folder with its own rules that contains the path.
+  fn folder_of(&self, path: &Path) -> Option<PathBuf> {
+    let folders = self.folders.read().unwrap();
+    folders
+      .keys()
+      .filter(|folder| path.starts_with(folder))
+      .max_by_key(|folder| folder.components().count())
+      .cloned()
+  }
+
+  /// Call `f` with rules of the workspace folder containing the uri, or the default rules.
+  fn with_rules<T>(&self, uri: &Url, f: impl FnOnce(&Rules<L>) -> T) -> T {
+    let (rules, _) = self.rules_snapshot(uri);
+    f(&rules)
+  }
+
+  /// Rules of the workspace folder containing the uri, or the default rules,
+  /// with the generation to cache their scan kinds under.
+  /// The generation is read with the lock held, so it is never newer than the rules.
+  fn rules_snapshot(&self, uri: &Url) -> (Arc<Rules<L>>, u64) {
+    let folder = uri.to_file_path().ok().and_then(|p| self.folder_of(&p));
+    if let Some(folder) = folder {
+      let folders = self.folders.read().unwrap();
+      // the folder may be removed concurrently
+      if let Some(rules) = folders.get(&folder) {
+        let generation = self.rules_generation.load(Ordering::Acquire);
+        return (rules.clone(), generation);
+      }
     }
+    let rules = self.rules.read().unwrap();
+    let generation = self.rules_generation.load(Ordering::Acquire);
+    (rules.clone(), generation)
   }
 
-  fn get_rules(&self, uri: &Url) -> Option<Vec<&RuleConfig<L>>> {
-    let absolute_path = uri.to_file_path().ok()?;
+  /// Path of the uri relative to its workspace folder or the base,
+  /// used to match rule `files` and `ignores`.
+  fn get_relative_path(&self, uri: &Url) -> Option<PathBuf> {
+    let absolute_path = uri_to_path(uri);
+    let root = self
+      .folder_of(&absolute_path)
+      .unwrap_or_else(|| self.base.clone());
     // for_path needs relative path, see https://github.com/ast-grep/ast-grep/issues/1272
     let base = Path::new("./");
-    let path = if let Ok(p) = absolute_path.strip_prefix(&self.base) {
+    let path = if let Ok(p) = absolute_path.strip_prefix(&root) {
       base.join(p)
     } else {
       absolute_path
     };
-    let rules = self.rules.as_ref().ok()?.for_path(&path);
-    Some(rules)
+    Some(path)
   }
 
-  fn get_diagnostics(
+  /// `lang` is the document language, which may be mapped by `languageMapping` instead of the path.
+  fn get_rules<'r>(
     &self,
     uri: &Url,
-    versioned: &VersionedAst<StrDoc<L>>,
-  ) -> Option<Vec<Diagnostic>> {
-    let rules = self.get_rules(uri)?;
-    let scan = CombinedScan::new(rules);
-    let pre_scan = scan.find(&versioned.root);
-    let matches = scan.scan(&versioned.root, pre_scan, false).matches;
-    let mut diagnostics = vec![];
-    for (id, ms) in matches {
-      let rule = scan.get_rule(id);
-      let to_diagnostic = |m| convert_match_to_diagnostic(m, rule, uri);
-      diagnostics.extend(ms.into_iter().map(to_diagnostic));
+    lang: &L,
+    rules: &'r RuleCollection<L>,
+  ) -> Vec<&'r RuleConfig<L>> {
+    let Some(path) = self.get_relative_path(uri) else {
+      return vec![];
+    };
+    if self.is_excluded(&path) {
+      return vec![];
     }
-    Some(diagnostics)
+    let mut rules = rules.get_rule_from_lang(&path, lang.clone());
+    rules.retain(|rule| self.is_rule_enabled(rule));
+    rules
   }
 
-  async fn publish_diagnostics(&self, uri: Url, versioned: &VersionedAst<StrDoc<L>>) -> Option<()> {
-    let diagnostics = self.get_diagnostics(&uri, versioned).unwrap_or_default();
-    self
-      .client
-      .publish_diagnostics(uri, diagnostics, Some(versioned.version))
-      .await;
-    Some(())
+  /// Whether the path relative to the workspace root matches an `exclude` glob.
+  fn is_excluded(&self, path: &Path) -> bool {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    self.exclude.read().unwrap().is_match(path)
   }
 
-  async fn on_open(&self, params: DidOpenTextDocumentParams) -> Option<()> {
-    let text_doc = params.text_document;
-    let uri = text_doc.uri.as_str().to_owned();
-    let text = text_doc.text;
-    self
-      .client
-      .log_message(MessageType::LOG, "Parsing doc.")
-      .await;
-    let lang = Self::infer_lang_from_uri(&text_doc.uri)?;
-    let root = AstGrep::new(text, lang);
-    let versioned = VersionedAst {
-      version: text_doc.version,
-      root,
-    };
-    self
-      .client
-      .log_message(MessageType::LOG, "Publishing init diagnostics.")
-      .await;
-    self.publish_diagnostics(text_doc.uri, &versioned).await;
-    self.map.insert(uri.to_owned(), versioned); // don't lock dashmap
-    Some(())
-  }
-  async fn on_change(&self, params: DidChangeTextDocumentParams) -> Option<()> {
-    let text_doc = params.text_document;
-    let uri = text_doc.uri.as_str();
-    let text = &params.content_changes[0].text;
-    self
-      .client
-      .log_message(MessageType::LOG, "Parsing changed doc.")
-      .await;
-    let lang = Self::infer_lang_from_uri(&text_doc.uri)?;
-    let root = AstGrep::new(text, lang);
-    let mut versioned = self.map.get_mut(uri)?;
-    // skip old version update
-    if versioned.version > text_doc.version {
-      return None;
+  /// Rules in `disabledRules` or turned off by `ruleSeverityOverrides` are not reported.
+  fn is_rule_enabled(&self, rule: &RuleConfig<L>) -> bool {
+    let options = self.options.read().unwrap();
+    if options.disabled_rules.contains(&rule.id) {
+      return false;
     }
-    *versioned = VersionedAst {
-      version: text_doc.version,
-      root,
-    };
-    self
-      .client
-      .log_message(MessageType::LOG, "Publishing diagnostics.")
-      .await;
-    self.publish_diagnostics(text_doc.uri, &versioned).await;
-    Some(())
+    let severity = options.rule_severity_overrides.get(&rule.id);
+    !matches!(severity, Some(Severity::Off))
   }
-  async fn on_close(&self, params: DidCloseTextDocumentParams) {
-    self.map.remove(params.text_document.uri.as_str());
+
+  fn get_diagnostics(
+    &self,
+    uri: &Url,
+    versioned: &VersionedAst<StrDoc<L>>,
+    with_fix: bool,
+    cancel: &CancelToken,
+  ) -> Option<Vec<Diagnostic>> {
+    let path = self.get_relative_path(uri)?;
+    let (rules, generation) = self.rules_snapshot(uri);
+    let rules = self.get_rules(uri, versioned.root.lang(), rules.as_ref().as_ref().ok()?);
+    let diagnostics =
+      self.scan_with_rules(uri, &path, versioned, (rules, generation), with_fix, cancel);
+    // diagnostics of a cancelled scan are incomplete
+    (!cancel.is_cancelled()).then_some(diagnostics)
   }
 
-  fn compute_all_fixes(
+  /// Diagnostics of an open document for a request, scanned in a blocking thread.
+  /// tower-lsp drops the request future on `$/cancelRequest`, whose guard of `cancel`
+  /// stops the scan, instead of blocking other requests until the scan completes.
+  async fn get_request_diagnostics(
     &self,
-    text_document: TextDocumentIdentifier,
-  ) -> std::result::Result<HashMap<Url, Vec<TextEdit>>, LspError>
-  where
-    L: ast_grep_core::Language + std::cmp::Eq,
-  {
-    let uri = text_document.uri;
+    uri: &Url,
+    with_fix: bool,
+    cancel: &CancelToken,
+  ) -> std::result::Result<Vec<Diagnostic>, LspError> {
     let versioned = self
       .map
       .get(uri.as_str())
-      .ok_or(LspError::UnsupportedFileType)?;
-    let mut diagnostics = self
-      .get_diagnostics(&uri, &versioned)
-      .ok_or(LspError::NoActionableFix)?;
-    diagnostics.sort_by_key(|d| (d.range.start, d.range.end));
-    let mut last = Position {
-      line: 0,
-      character: 0,
-    };
-    let edits: Vec<_> = diagnostics
-      .into_iter()
-      .filter_map(|d| {
-        if d.range.start < last {
-          return None;
-        }
-        let rewrite_data = RewriteData::from_value(d.data?)?;
-        let edit = TextEdit::new(d.range, rewrite_data.fixed);
-        last = d.range.end;
-        Some(edit)
+      .ok_or(LspError::UnsupportedFileType)?
+      .clone();
+    match self.scan_snapshot(uri, versioned, with_fix, cancel).await {
+      Some(diagnostics) => Ok(diagnostics),
+      None if cancel.is_cancelled() => Err(LspError::Cancelled),
+      None => Err(LspError::NoActionableFix),
+    }
+  }
+
+  /// Scan a snapshot of the document in blocking threads so the server keeps responding.
+  /// Rules are split among threads if there are many rules for the document.
+  /// Returns None if no rule applies to the document or the scan is cancelled.
+  async fn scan_snapshot(
+    &self,
+    uri: &Url,
+    versioned: VersionedAst<StrDoc<L>>,
+    with_fix: bool,
+    cancel: &CancelToken,
+  ) -> Option<Vec<Diagnostic>> {
+    let path = self.get_relative_path(uri)?;
+    // rules are selected once, so reloaded rules or options cannot shift the chunks
+    let (rules, generation) = self.rules_snapshot(uri);
+    let selected: Vec<_> = if self.is_excluded(&path) {
+      vec![]
+    } else {
+      let collection = rules.as_ref().as_ref().ok()?;
+      let all = collection.get_rule_from_lang(&path, versioned.root.lang().clone());
+      // rules are taken by position since ids are not unique among rule files
+      (0..all.len())
+        .filter(|i| self.is_rule_enabled(all[*i]))
+        .collect()
+    };
+    let count = selected.len();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = if count < PARALLEL_SCAN_RULES {
+      count.max(1)
+    } else {
+      (count + threads - 1) / threads
+    };
+    let versioned = Arc::new(versioned);
+    let tasks: Vec<_> = selected
+      .chunks(chunk_size)
+      .map(|chunk| {
+        let backend = self.clone();
+        let (uri, path, rules) = (uri.clone(), path.clone(), rules.clone());
+        let (versioned, cancel, chunk) = (versioned.clone(), cancel.clone(), chunk.to_vec());
+        tokio::task::spawn_blocking(move || {
+          let collection = rules.as_ref().as_ref().ok()?;
+          let all = collection.get_rule_from_lang(&path, versioned.root.lang().clone());
+          let chunk = chunk.into_iter().map(|i| all[i]).collect();
+          let rules = (chunk, generation);
+          let scanned = backend.scan_with_rules(&uri, &path, &versioned, rules, with_fix, &cancel);
+          Some(scanned)
+        })
       })
       .collect();
-    if edits.is_empty() {
-      return Err(LspError::NoActionableFix);
+    let mut diagnostics = vec![];
+    for task in tasks {
+      diagnostics.extend(task.await.ok()??);
     }
-    let mut changes = HashMap::new();
-    changes.insert(uri, edits);
-    Ok(changes)
+    // diagnostics of a cancelled scan are incomplete
+    (!cancel.is_cancelled()).then_some(diagnostics)
   }
 
-  async fn on_code_action(&self, params: CodeActionParams) -> Option<CodeActionResponse> {
-    if let Some(kinds) = params.context.only.as_ref() {
-      if kinds.contains(&CodeActionKind::SOURCE_FIX_ALL) {
-        return self.fix_all_code_action(params.text_document);
-      }
+  /// Reuse kinds of the previous scan with the same rules, which are computed for every rule.
+  /// Rules are identified by address since ids are not unique among rule files.
+  /// `generation` is the one the rules are snapshotted with, see `rules_snapshot`.
+  fn combined_scan<'r>(
+    &self,
+    rules: Vec<&'r RuleConfig<L>>,
+    generation: u64,
+  ) -> CombinedScan<'r, L> {
+    let mut addresses: Vec<_> = rules
+      .iter()
+      .map(|r| *r as *const RuleConfig<L> as usize)
+      .collect();
+    addresses.sort_unstable();
+    let key = (generation, addresses);
+    if let Some(kinds) = self.scan_kinds.get(&key) {
+      return CombinedScan::with_kinds(rules, kinds.clone());
     }
-    self.quickfix_code_action(params)
+    let scan = CombinedScan::new(rules);
+    self.scan_kinds.insert(key, scan.kinds());
+    scan
   }
 
-  fn fix_all_code_action(
+  /// `path` is used to resolve rule severity with `severityOverrides`,
+  /// unless the severity is set by client's `ruleSeverityOverrides`.
+  /// The document is scanned with all rules in one traversal. It blocks, see `scan_snapshot`.
+  fn scan_with_rules(
     &self,
-    text_document: TextDocumentIdentifier,
-  ) -> Option<CodeActionResponse> {
-    let fixed = self.compute_all_fixes(text_document).ok()?;
-    let edit = WorkspaceEdit::new(fixed);
-    let code_action = CodeAction {
-      title: "Fix by ast-grep".into(),
-      command: None,
-      diagnostics: None,
-      edit: Some(edit),
-      kind: Some(CodeActionKind::new(FIX_ALL_AST_GREP)),
-      is_preferred: None,
-      data: None,
-      disabled: None,
+    uri: &Url,
+    path: &Path,
+    versioned: &VersionedAst<StrDoc<L>>,
+    (rules, generation): (Vec<&RuleConfig<L>>, u64),
+    with_fix: bool,
+    cancel: &CancelToken,
+  ) -> Vec<Diagnostic> {
+    let (overrides, report_suppressed, url_template) = {
+      let options = self.options.read().unwrap();
+      let overrides = options.rule_severity_overrides.clone();
+      let url_template = options.rule_url_template.clone();
+      (overrides, options.report_suppressed, url_template)
     };
-    Some(vec![CodeActionOrCommand::CodeAction(code_action)])
+    let encoding = self.position_encoding();
+    if cancel.is_cancelled() {
+      return vec![];
+    }
+    let scan = self.combined_scan(rules, generation);
+    let pre_scan = scan.find(&versioned.root);
+    if cancel.is_cancelled() {
+      return vec![];
+    }
+    let result = scan.scan(&versioned.root, pre_scan, false);
+    let mut diagnostics = vec![];
+    for (id, ms) in result.matches {
+      let rule = scan.get_rule(id);
+      let severity = match overrides.get(&rule.id) {
+        Some(severity) => severity.clone(),
+        None => rule.get_severity(path),
+      };
+      // the rule may be turned off after the scan started
+      if matches!(severity, Severity::Off) {
+        continue;
+      }
+      let to_diagnostic =
+        |m| convert_match_to_diagnostic(m, rule, &severity, uri, with_fix, encoding);
+      diagnostics.extend(ms.into_iter().map(to_diagnostic));
+    }
+    if report_suppressed {
+      for (id, m) in result.suppressed {
+        let rule = scan.get_rule(id);
+        // suppressed findings are never fixed
+        let diagnostic =
+          convert_match_to_diagnostic(m, rule, &Severity::Hint, uri, false, encoding);
+        diagnostics.push(to_suppressed_diagnostic(diagnostic));
+      }
+    }
+    if let Some(template) = &url_template {
+      for diagnostic in diagnostics.iter_mut() {
+        if diagnostic.code_description.is_some() {
+          continue;
+        }
+        let id = get_rule_id(diagnostic).unwrap_or_default();
+        diagnostic.code_description = code_description_from_template(template, id);
+      }
+    }
+    diagnostics
   }
 
-  fn quickfix_code_action(&self, params: CodeActionParams) -> Option<CodeActionResponse> {
-    if params.context.diagnostics.is_empty() {
-      return None;
-    }
-    let text_doc = params.text_document;
-    let response = params
-      .context
+  /// Compute diagnostics of an open document and cache them for hover and pull diagnostics.
+  /// The scan stops if the future is dropped, e.g. its debounced publish is aborted.
+  async fn update_diagnostics(
+    &self,
+    uri: &Url,
+    versioned: VersionedAst<StrDoc<L>>,
+  ) -> Vec<Diagnostic> {
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    // fix conflicts can only be found with fixes
+    let with_fix = self.options.read().unwrap().report_fix_conflicts;
+    let scanned = self.scan_snapshot(uri, versioned, with_fix, &cancel).await;
+    let diagnostics = self.apply_diagnostic_options(uri, scanned.unwrap_or_default());
+    self
       .diagnostics
-      .into_iter()
-      .filter(|d| {
-        d.source
-          .as_ref()
-          .map(|s| s.contains("ast-grep"))
-          .unwrap_or(false)
-      })
-      .filter_map(|d| diagnostic_to_code_action(&text_doc, d))
-      .map(CodeActionOrCommand::from)
-      .collect();
-    Some(response)
+      .insert(uri.as_str().to_owned(), diagnostics.clone());
+    diagnostics
   }
 
-  // TODO: support other urls besides file_scheme
-  fn infer_lang_from_uri(uri: &Url) -> Option<L> {
-    let path = uri.to_file_path().ok()?;
-    L::from_path(path)
+  /// Diagnostics published to client, including fix conflicts if configured.
+  fn get_published_diagnostics(
+    &self,
+    uri: &Url,
+    versioned: &VersionedAst<StrDoc<L>>,
+    cancel: &CancelToken,
+  ) -> Vec<Diagnostic> {
+    // fix conflicts can only be found with fixes
+    let with_fix = self.options.read().unwrap().report_fix_conflicts;
+    let diagnostics = self
+      .get_diagnostics(uri, versioned, with_fix, cancel)
+      .unwrap_or_default();
+    self.apply_diagnostic_options(uri, diagnostics)
   }
 
-  async fn on_execute_command(&self, params: ExecuteCommandParams) -> Option<Value> {
-    let ExecuteCommandParams {
-      arguments,
-      command,
-      work_done_progress_params: _,
-    } = params;
+  fn supports_watched_files_registration(&self) -> bool {
+    let capabilities = self.client_capabilities.read().unwrap();
+    let workspace = capabilities.workspace.as_ref();
+    let watched_files = workspace.and_then(|w| w.did_change_watched_files.as_ref());
+    watched_files
+      .and_then(|w| w.dynamic_registration)
+      .unwrap_or(false)
+  }
 
-    match command.as_ref() {
-      APPLY_ALL_FIXES => {
-        self.on_apply_all_fix(command, arguments).await?;
-        None
-      }
-      _ => {
-        self
-          .client
-          .log_message(
-            MessageType::LOG,
-            format!("Unrecognized command: {}", command),
-          )
-          .await;
-        None
-      }
+  /// Ask the client to watch rule files so rules are reloaded when they change.
+  async fn register_file_watchers(&self) {
+    let Some(finder) = &self.rule_finder else {
+      return;
+    };
+    if !self.supports_watched_files_registration() {
+      return;
+    }
+    let watchers = finder
+      .watch_patterns()
+      .into_iter()
+      .map(|pattern| FileSystemWatcher {
+        glob_pattern: GlobPattern::String(pattern),
+        kind: None,
+      })
+      .collect();
+    let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+    let registration = Registration {
+      id: RULE_WATCHER_ID.into(),
+      method: "workspace/didChangeWatchedFiles".into(),
+      register_options: serde_json::to_value(options).ok(),
+    };
+    if let Err(error) = self.client.register_capability(vec![registration]).await {
+      self
+        .client
+        .log_message(
+          MessageType::WARNING,
+          format!("Failed to watch rule files: {error}"),
+        )
+        .await;
     }
   }
 
-  async fn on_apply_all_fix_impl(
-    &self,
-    first: Value,
+  /// Watch rule files of the new project config after `configPath` changes.
+  async fn reregister_file_watchers(&self) {
+    if self.rule_finder.is_none() || !self.supports_watched_files_registration() {
+      return;
+    }
+    let unregistration = Unregistration {
+      id: RULE_WATCHER_ID.into(),
+      method: "workspace/didChangeWatchedFiles".into(),
+    };
+    if let Err(error) = self
+      .client
+      .unregister_capability(vec![unregistration])
+      .await
+    {
+      self
+        .client
+        .log_message(
+          MessageType::WARNING,
+          format!("Failed to stop watching rule files: {error}"),
+        )
+        .await;
+    }
+    self.register_file_watchers().await;
+  }
+
+  fn supports_pull_diagnostics(&self) -> bool {
+    let capabilities = self.client_capabilities.read().unwrap();
+    let text_document = capabilities.text_document.as_ref();
+    text_document.map_or(false, |t| t.diagnostic.is_some())
+  }
+
+  fn position_encoding(&self) -> PositionEncoding {
+    PositionEncoding::negotiate(&self.client_capabilities.read().unwrap())
+  }
+
+  fn supports_diagnostic_refresh(&self) -> bool {
+    let capabilities = self.client_capabilities.read().unwrap();
+    let workspace = capabilities.workspace.as_ref();
+    let diagnostic = workspace.and_then(|w| w.diagnostic.as_ref());
+    diagnostic.and_then(|d| d.refresh_support).unwrap_or(false)
+  }
+
+  /// Outline rules, utils and test cases of rule files and rule test files.
+  fn on_document_symbol(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+    let uri = params.text_document.uri.as_str();
+    let text = self
+      .rule_docs
+      .get(uri)
+      .or_else(|| self.test_docs.get(uri))?;
+    let symbols = document_symbols(&text, self.position_encoding());
+    Some(DocumentSymbolResponse::Nested(symbols))
+  }
+
+  /// Show message, note and documentation link of rules whose diagnostics contain the position.
+  /// In rule files, show the parsed tree of the hovered pattern instead.
+  fn on_hover(&self, params: HoverParams) -> Option<Hover> {
+    let TextDocumentPositionParams {
+      text_document,
+      position,
+    } = params.text_document_position_params;
+    if let Some(text) = self.rule_docs.get(text_document.uri.as_str()) {
+      return pattern_hover::<L>(&text, position);
+    }
+    let diagnostics = self.diagnostics.get(text_document.uri.as_str())?;
+    let hovered: Vec<_> = diagnostics
+      .iter()
+      .filter(|d| d.range.start <= position && position <= d.range.end)
+      .collect();
+    let first = hovered.first()?;
+    let contents: Vec<_> = self.with_rules(&text_document.uri, |rules| {
+      let rules = rules.as_ref().ok();
+      hovered
+        .iter()
+        .map(|d| {
+          let rule = get_rule_id(d).and_then(|id| rules?.get_rule(id));
+          diagnostic_to_hover(d, rule)
+        })
+        .collect()
+    });
+    Some(Hover {
+      contents: HoverContents::Markup(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: contents.join("\n\n---\n\n"),
+      }),
+      range: Some(first.range),
+    })
+  }
+
+  /// Locations of rule ids in rule files, for diagnostics containing the position.
+  fn on_goto_definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+    let TextDocumentPositionParams {
+      text_document,
+      position,
+    } = params.text_document_position_params;
+    let mut ids: Vec<String> = {
+      let diagnostics = self.diagnostics.get(text_document.uri.as_str())?;
+      diagnostics
+        .iter()
+        .filter(|d| d.range.start <= position && position <= d.range.end)
+        .filter_map(|d| get_rule_id(d).map(String::from))
+        .collect()
+    };
+    ids.dedup();
+    let sources: Vec<_> = self.with_rules(&text_document.uri, |rules| {
+      let rules = rules.as_ref().ok()?;
+      let sources = ids
+        .iter()
+        .filter_map(|id| rules.get_rule(id)?.source().cloned())
+        .collect();
+      Some(sources)
+    })?;
+    let locations: Vec<_> = sources
+      .into_iter()
+      .filter_map(|source| self.rule_location(source))
+      .collect();
+    if locations.is_empty() {
+      return None;
+    }
+    Some(GotoDefinitionResponse::Array(locations))
+  }
+
+  /// Location of the rule id in the rule file where the rule is defined.
+  fn rule_location(&self, source: RuleSource) -> Option<Location> {
+    let path = self.base.join(source.path);
+    let text = std::fs::read_to_string(&path).unwrap_or_default();
+    let range = rule_id_line_range(&text, source.line);
+    Some(Location {
+      uri: Url::from_file_path(&path).ok()?,
+      range: encode_range(&text, range, self.position_encoding()),
+    })
+  }
+
+  /// Show the rule file of the rule applicable in the document, for the applicable rules action.
+  async fn on_open_rule(&self, arguments: Vec<Value>) -> Option<Value> {
+    let uri: Url = serde_json::from_value(arguments.first()?.clone()).ok()?;
+    let id = arguments.get(1)?.as_str()?;
+    let source = self.with_rules(&uri, |rules| {
+      rules.as_ref().ok()?.get_rule(id)?.source().cloned()
+    })?;
+    let location = self.rule_location(source)?;
+    let params = ShowDocumentParams {
+      uri: location.uri,
+      external: None,
+      take_focus: Some(true),
+      selection: Some(location.range),
+    };
+    let shown = self.client.show_document(params).await.ok()?;
+    Some(json!(shown))
+  }
+
+  /// Diagnostics of an open document for `textDocument/diagnostic`. An unchanged report is
+  /// returned if the diagnostics are the same as the ones of `previousResultId`.
+  async fn on_diagnostic(
+    &self,
+    params: DocumentDiagnosticParams,
+  ) -> DocumentDiagnosticReportResult {
+    let uri = params.text_document.uri;
+    let cached = self.diagnostics.get(uri.as_str()).map(|d| d.clone());
+    let diagnostics = match cached {
+      Some(diagnostics) => diagnostics,
+      None => {
+        let versioned = self.map.get(uri.as_str()).map(|v| v.clone());
+        match versioned {
+          Some(versioned) => self.update_diagnostics(&uri, versioned).await,
+          None => vec![],
+        }
+      }
+    };
+    let result_id = diagnostics_result_id(&diagnostics);
+    let report = if params.previous_result_id.as_ref() == Some(&result_id) {
+      DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+        related_documents: None,
+        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+      })
+    } else {
+      DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+        related_documents: None,
+        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+          result_id: Some(result_id),
+          items: diagnostics,
+        },
+      })
+    };
+    DocumentDiagnosticReportResult::Report(report)
+  }
+
+  /// Diagnostics of all files under base for `workspace/diagnostic`, including unopened files.
+  /// Reports are streamed per file if the client sends a `partialResultToken`.
+  async fn on_workspace_diagnostic(
+    &self,
+    params: WorkspaceDiagnosticParams,
+  ) -> WorkspaceDiagnosticReportResult {
+    let previous: HashMap<_, _> = params
+      .previous_result_ids
+      .into_iter()
+      .map(|p| (p.uri, p.value))
+      .collect();
+    let previous = Arc::new(previous);
+    let token = params.partial_result_params.partial_result_token;
+    let work_done_token = params.work_done_progress_params.work_done_token;
+    // walking and scanning files blocks, so they run off the server task
+    let backend = self.clone();
+    let files = tokio::task::spawn_blocking(move || backend.workspace_files())
+      .await
+      .unwrap_or_default();
+    let total = files.len();
+    let progress = self
+      .begin_progress(work_done_token, "Scanning workspace")
+      .await;
+    let mut percentage = 0;
+    let mut items = vec![];
+    for (done, path) in files.into_iter().enumerate() {
+      if let Some(progress) = &progress {
+        self
+          .report_progress(progress, done, total, &mut percentage)
+          .await;
+      }
+      let (backend, previous) = (self.clone(), previous.clone());
+      let report =
+        tokio::task::spawn_blocking(move || backend.diagnose_workspace_file(&path, &previous));
+      let Some(report) = report.await.ok().flatten() else {
+        continue;
+      };
+      let Some(token) = &token else {
+        items.push(report);
+        continue;
+      };
+      let partial = PartialResult {
+        token: token.clone(),
+        value: WorkspaceDiagnosticReportPartialResult {
+          items: vec![report],
+        },
+      };
+      self
+        .client
+        .send_notification::<WorkspaceDiagnosticProgress>(partial)
+        .await;
+    }
+    if let Some(progress) = progress {
+      self
+        .end_progress(progress, format!("Scanned {total} files"))
+        .await;
+    }
+    WorkspaceDiagnosticReportResult::Report(WorkspaceDiagnosticReport { items })
+  }
+
+  fn supports_work_done_progress(&self) -> bool {
+    let capabilities = self.client_capabilities.read().unwrap();
+    let window = capabilities.window.as_ref();
+    window.and_then(|w| w.work_done_progress).unwrap_or(false)
+  }
+
+  /// Begin progress of a long scan with the token sent by client, or a token created by server.
+  /// Returns None if the client cannot show progress.
+  async fn begin_progress(
+    &self,
+    token: Option<ProgressToken>,
+    title: &str,
+  ) -> Option<ProgressToken> {
+    let token = match token {
+      Some(token) => token,
+      None if self.supports_work_done_progress() => {
+        let id = self.progress_id.fetch_add(1, Ordering::Relaxed);
+        let token = ProgressToken::String(format!("ast-grep/progress/{id}"));
+        let params = WorkDoneProgressCreateParams {
+          token: token.clone(),
+        };
+        type Create = request::WorkDoneProgressCreate;
+        self.client.send_request::<Create>(params).await.ok()?;
+        token
+      }
+      None => return None,
+    };
+    let begin = WorkDoneProgressBegin {
+      title: title.into(),
+      cancellable: Some(false),
+      message: None,
+      percentage: Some(0),
+    };
+    self
+      .send_progress(&token, WorkDoneProgress::Begin(begin))
+      .await;
+    Some(token)
+  }
+
+  /// Report `done` of `total` files scanned. `percentage` is the last reported one,
+  /// and progress is only sent when it changes to avoid flooding the client.
+  async fn report_progress(
+    &self,
+    token: &ProgressToken,
+    done: usize,
+    total: usize,
+    percentage: &mut u32,
+  ) {
+    let current = (done * 100 / total) as u32;
+    if current <= *percentage {
+      return;
+    }
+    *percentage = current;
+    let report = WorkDoneProgressReport {
+      cancellable: Some(false),
+      message: Some(format!("{done}/{total} files")),
+      percentage: Some(current),
+    };
+    self
+      .send_progress(token, WorkDoneProgress::Report(report))
+      .await;
+  }
+
+  async fn end_progress(&self, token: ProgressToken, message: String) {
+    let end = WorkDoneProgressEnd {
+      message: Some(message),
+    };
+    self.send_progress(&token, WorkDoneProgress::End(end)).await;
+  }
+
+  async fn send_progress(&self, token: &ProgressToken, progress: WorkDoneProgress) {
+    let params = ProgressParams {
+      token: token.clone(),
+      value: ProgressParamsValue::WorkDone(progress),
+    };
+    self
+      .client
+      .send_notification::<notification::Progress>(params)
+      .await;
+  }
+
+  /// Files under base and workspace folders respecting ignore files like `.gitignore`.
+  fn workspace_files(&self) -> Vec<PathBuf> {
+    let folders: Vec<_> = self.folders.read().unwrap().keys().cloned().collect();
+    let roots = std::iter::once(self.base.clone()).chain(folders);
+    let exclude = self.exclude.read().unwrap().clone();
+    let mut files: Vec<_> = roots
+      .filter_map(|root| root.canonicalize().ok())
+      .flat_map(|root| {
+        let exclude = exclude.clone();
+        WalkBuilder::new(&root)
+          .filter_entry(move |entry| {
+            let path = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+            !exclude.is_match(path)
+          })
+          .build()
+      })
+      .filter_map(|entry| entry.ok())
+      .filter(|entry| entry.file_type().map_or(false, |t| t.is_file()))
+      .map(|entry| entry.into_path())
+      .collect();
+    // folders may be nested in base
+    files.sort();
+    files.dedup();
+    files
+  }
+
+  /// Locations matching the pattern in open documents, and in workspace files if requested.
+  async fn search(&self, first: Value) -> std::result::Result<Vec<Location>, LspError> {
+    let params: SearchParams = serde_json::from_value(first).map_err(LspError::JSONDecodeError)?;
+    let lang = match &params.language {
+      Some(lang) => Some(L::from_str(lang).map_err(|_| LspError::UnsupportedFileType)?),
+      None => None,
+    };
+    if let Some(lang) = &lang {
+      Pattern::try_new(&params.pattern, lang.clone())
+        .map_err(|e| LspError::InvalidPattern(e.to_string()))?;
+    }
+    let is_searched = |doc_lang: &L| lang.as_ref().map_or(true, |l| l == doc_lang);
+    let encoding = self.position_encoding();
+    let mut locations = vec![];
+    for uri in self.map.uris() {
+      let (Some(versioned), Ok(uri)) = (self.map.get(&uri), Url::parse(&uri)) else {
+        continue;
+      };
+      if is_searched(versioned.root.lang()) {
+        locations.extend(search_pattern(
+          &uri,
+          &versioned.root,
+          &params.pattern,
+          encoding,
+        ));
+      }
+    }
+    if params.include_workspace {
+      // walking and parsing files blocks, so they run off the server task
+      let backend = self.clone();
+      let pattern = params.pattern.clone();
+      let found = tokio::task::spawn_blocking(move || {
+        backend.search_workspace_files(&pattern, lang.as_ref(), encoding)
+      });
+      locations.extend(found.await.unwrap_or_default());
+    }
+    // documents are stored in no particular order
+    locations.sort_by(|a, b| {
+      let a_start = (a.range.start.line, a.range.start.character);
+      let b_start = (b.range.start.line, b.range.start.character);
+      (a.uri.as_str(), a_start).cmp(&(b.uri.as_str(), b_start))
+    });
+    Ok(locations)
+  }
+
+  /// Locations matching the pattern in workspace files not open in the editor.
+  fn search_workspace_files(
+    &self,
+    pattern: &str,
+    lang: Option<&L>,
+    encoding: PositionEncoding,
+  ) -> Vec<Location> {
+    let mut locations = vec![];
+    for path in self.workspace_files() {
+      let Ok(uri) = Url::from_file_path(&path) else {
+        continue;
+      };
+      if self.map.contains_key(uri.as_str()) {
+        continue;
+      }
+      let doc_lang = self.infer_lang_from_uri(&uri);
+      let Some(doc_lang) = doc_lang.filter(|l| lang.map_or(true, |lang| lang == l)) else {
+        continue;
+      };
+      let Ok(text) = std::fs::read_to_string(&path) else {
+        continue;
+      };
+      let root = AstGrep::new(text, doc_lang);
+      locations.extend(search_pattern(&uri, &root, pattern, encoding));
+    }
+    locations
+  }
+
+  async fn on_search(&self, arguments: Vec<Value>) -> Option<Value> {
+    let first = arguments.first()?.clone();
+    match self.search(first).await {
+      Ok(locations) => Some(json!(locations)),
+      Err(error) => {
+        self.report_error(error).await;
+        None
+      }
+    }
+  }
+
+  /// Dump nodes in the range of an open document, as JSON and as an S-expression,
+  /// so users can find out the `kind` to write in rules.
+  async fn on_inspect_node(&self, arguments: Vec<Value>) -> Option<Value> {
+    let first = arguments.first()?.clone();
+    let params: InspectNodeParams = match serde_json::from_value(first) {
+      Ok(params) => params,
+      Err(error) => {
+        self.report_error(LspError::JSONDecodeError(error)).await;
+        return None;
+      }
+    };
+    let versioned = self.map.get(params.uri.as_str())?;
+    let encoding = self.position_encoding();
+    let nodes = inspect_nodes(&versioned.root, params.range, encoding);
+    let sexp: Vec<_> = nodes.iter().map(|node| node.to_sexp()).collect();
+    let nodes: Vec<_> = nodes
+      .iter()
+      .map(|node| dump_node(node, None, encoding))
+      .collect();
+    Some(json!({ "nodes": nodes, "sexp": sexp.join("\n") }))
+  }
+
+  /// Turn the selected code into a rule, whose pattern generalizes identifiers to meta variables.
+  /// Returns the rule id, the YAML text and the path of the rule file if it is written.
+  async fn on_extract_rule(&self, arguments: Vec<Value>) -> Option<Value> {
+    let first = arguments.first()?.clone();
+    match self.extract_rule(first) {
+      Ok(extracted) => Some(extracted),
+      Err(error) => {
+        self.report_error(error).await;
+        None
+      }
+    }
+  }
+
+  fn extract_rule(&self, first: Value) -> std::result::Result<Value, LspError> {
+    let params: ExtractRuleParams =
+      serde_json::from_value(first).map_err(LspError::JSONDecodeError)?;
+    let (pattern, lang) = {
+      let versioned = self
+        .map
+        .get(params.uri.as_str())
+        .ok_or(LspError::UnsupportedFileType)?;
+      let encoding = self.position_encoding();
+      let pattern = extract_pattern(&versioned.root, params.range, encoding);
+      let pattern = pattern.ok_or(LspError::InvalidPattern("empty selection".into()))?;
+      let lang = self.language_name(&params.uri, versioned.root.lang());
+      (pattern, lang.ok_or(LspError::UnsupportedFileType)?)
+    };
+    let id = params.id.unwrap_or_else(|| "new-rule".to_string());
+    let yaml = rule_scaffold(&id, &lang, &pattern);
+    let Some(rule_dir) = params.rule_dir else {
+      return Ok(json!({ "id": id, "yaml": yaml }));
+    };
+    // the id is used as file name, which must stay in the rule directory
+    if id.is_empty() || id.contains(['/', '\\']) || id.contains("..") {
+      return Err(LspError::WriteRuleFile(format!("invalid rule id `{id}`")));
+    }
+    let path = self.rule_dir_of(&rule_dir)?.join(format!("{id}.yml"));
+    // never overwrite an existing rule
+    if path.exists() {
+      let message = format!("{} already exists", path.display());
+      return Err(LspError::WriteRuleFile(message));
+    }
+    let written = std::fs::write(&path, &yaml);
+    written.map_err(|e| LspError::WriteRuleFile(e.to_string()))?;
+    Ok(json!({ "id": id, "yaml": yaml, "path": path }))
+  }
+
+  /// Name of the document language accepted by rule `language`, which is the mapped language
+  /// or the extension of the file.
+  fn language_name(&self, uri: &Url, lang: &L) -> Option<String> {
+    let ext = uri_to_path(uri).extension()?.to_str()?.to_string();
+    let mapped = self
+      .options
+      .read()
+      .unwrap()
+      .language_mapping
+      .get(&ext)
+      .cloned();
+    mapped
+      .into_iter()
+      .chain(Some(ext))
+      .find(|name| L::from_str(name).ok().as_ref() == Some(lang))
+  }
+
+  /// Canonical path of an existing rule directory, which is in `ruleDirs` or the project config.
+  /// Rules are never written to other directories.
+  fn rule_dir_of(&self, rule_dir: &Path) -> std::result::Result<PathBuf, LspError> {
+    let not_rule_dir = || {
+      let message = format!("{} is not a rule directory", rule_dir.display());
+      LspError::WriteRuleFile(message)
+    };
+    let dir = self
+      .base
+      .join(rule_dir)
+      .canonicalize()
+      .map_err(|_| not_rule_dir())?;
+    let in_options = self
+      .options
+      .read()
+      .unwrap()
+      .rule_dirs
+      .iter()
+      .any(|configured| {
+        let configured = self.base.join(configured).canonicalize();
+        configured.map_or(false, |configured| dir.starts_with(configured))
+      });
+    // a file of the rule directory is a rule file of the project
+    let in_project = self
+      .rule_finder
+      .as_ref()
+      .map_or(false, |finder| finder.is_rule_file(&dir.join("rule.yml")));
+    if in_options || in_project {
+      Ok(dir)
+    } else {
+      Err(not_rule_dir())
+    }
+  }
+
+  /// Returns None if the file cannot be read or the scan is cancelled.
+  fn diagnose_unopened_file(
+    &self,
+    uri: &Url,
+    path: &Path,
+    lang: L,
+    cancel: &CancelToken,
+  ) -> Option<Vec<Diagnostic>> {
+    if cancel.is_cancelled() {
+      return None;
+    }
+    let text = std::fs::read_to_string(path).ok()?;
+    let versioned = VersionedAst {
+      version: 0,
+      root: AstGrep::new(text, lang),
+    };
+    let diagnostics = self.get_published_diagnostics(uri, &versioned, cancel);
+    // diagnostics of a cancelled scan are incomplete
+    (!cancel.is_cancelled()).then_some(diagnostics)
+  }
+
+  /// Scan every file in the workspace and publish diagnostics of files not open in the editor.
+  /// Returns counts of scanned files and findings.
+  /// Walking, parsing and scanning files block, so they run off the server task.
+  async fn on_scan_workspace(&self) -> Option<Value> {
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    let backend = self.clone();
+    let files = tokio::task::spawn_blocking(move || backend.workspace_files())
+      .await
+      .unwrap_or_default();
+    let total = files.len();
+    let progress = self.begin_progress(None, "Scanning workspace").await;
+    let mut percentage = 0;
+    let mut files_with_findings = 0;
+    let mut findings = 0;
+    let previous: HashSet<String> = self.scanned_uris.iter().map(|uri| uri.clone()).collect();
+    self.scanned_uris.clear();
+    for (done, path) in files.into_iter().enumerate() {
+      if cancel.is_cancelled() {
+        return None;
+      }
+      if let Some(progress) = &progress {
+        self
+          .report_progress(progress, done, total, &mut percentage)
+          .await;
+      }
+      let Ok(uri) = Url::from_file_path(&path) else {
+        continue;
+      };
+      // open documents are already published
+      if self.map.contains_key(uri.as_str()) {
+        continue;
+      }
+      let Some(lang) = self.infer_lang_from_uri(&uri) else {
+        continue;
+      };
+      let (backend, scanned_uri, cancel) = (self.clone(), uri.clone(), cancel.clone());
+      let diagnostics = tokio::task::spawn_blocking(move || {
+        backend.diagnose_unopened_file(&scanned_uri, &path, lang, &cancel)
+      });
+      let Some(diagnostics) = diagnostics.await.ok().flatten() else {
+        continue;
+      };
+      if diagnostics.is_empty() {
+        continue;
+      }
+      files_with_findings += 1;
+      findings += diagnostics.len();
+      self.scanned_uris.insert(uri.to_string());
+      self
+        .client
+        .publish_diagnostics(uri, diagnostics, None)
+        .await;
+    }
+    // clear findings of the previous scan that are fixed or whose files are deleted
+    for uri in previous {
+      if self.scanned_uris.contains(&uri) || self.map.contains_key(&uri) {
+        continue;
+      }
+      if let Ok(uri) = Url::parse(&uri) {
+        self.client.publish_diagnostics(uri, vec![], None).await;
+      }
+    }
+    if let Some(progress) = progress {
+      self
+        .end_progress(progress, format!("Scanned {total} files"))
+        .await;
+    }
+    Some(json!({
+      "scannedFiles": total,
+      "filesWithFindings": files_with_findings,
+      "findings": findings,
+    }))
+  }
+
+  /// Returns None if the file has no finding to report or to clear from a previous pull.
+  fn diagnose_workspace_file(
+    &self,
+    path: &Path,
+    previous: &HashMap<Url, String>,
+  ) -> Option<WorkspaceDocumentDiagnosticReport> {
+    let uri = Url::from_file_path(path).ok()?;
+    let lang = self.infer_lang_from_uri(&uri)?;
+    // open documents may have unsaved changes
+    let (diagnostics, version) = if let Some(versioned) = self.map.get(uri.as_str()) {
+      let diagnostics = self.get_published_diagnostics(&uri, &versioned, &CancelToken::default());
+      (diagnostics, Some(versioned.version as i64))
+    } else {
+      let cancel = CancelToken::default();
+      (
+        self.diagnose_unopened_file(&uri, path, lang, &cancel)?,
+        None,
+      )
+    };
+    let previous_id = previous.get(&uri);
+    if diagnostics.is_empty() && previous_id.is_none() {
+      return None;
+    }
+    let result_id = diagnostics_result_id(&diagnostics);
+    let report = if previous_id == Some(&result_id) {
+      WorkspaceDocumentDiagnosticReport::Unchanged(WorkspaceUnchangedDocumentDiagnosticReport {
+        uri,
+        version,
+        unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+      })
+    } else {
+      WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+        uri,
+        version,
+        full_document_diagnostic_report: FullDocumentDiagnosticReport {
+          result_id: Some(result_id),
+          items: diagnostics,
+        },
+      })
+    };
+    Some(report)
+  }
+
+  fn apply_diagnostic_options(
+    &self,
+    uri: &Url,
+    mut diagnostics: Vec<Diagnostic>,
+  ) -> Vec<Diagnostic> {
+    let options = self.options.read().unwrap();
+    if options.report_fix_conflicts {
+      let (_, conflicts) = collect_fixes(diagnostics.clone(), &self.rule_order(uri));
+      diagnostics.extend(conflicts.iter().map(|c| c.to_diagnostic()));
+    }
+    if options.sort_diagnostics {
+      sort_diagnostics(&mut diagnostics);
+    }
+    if let Some(max_len) = options.max_message_length {
+      for diagnostic in &mut diagnostics {
+        truncate_message(diagnostic, max_len);
+      }
+    }
+    diagnostics
+  }
+
+  async fn publish_diagnostics(
+    &self,
+    uri: Url,
+    versioned: VersionedAst<StrDoc<L>>,
+    parse_time: Duration,
+  ) -> Option<()> {
+    let start = Instant::now();
+    let version = versioned.version;
+    let diagnostics = self.update_diagnostics(&uri, versioned).await;
+    let scan_time = start.elapsed();
+    self
+      .send_diagnostics(uri, diagnostics, version, parse_time, scan_time)
+      .await
+  }
+
+  /// Publish diagnostics of an open document, whose snapshot is scanned.
+  async fn publish_document_diagnostics(&self, uri: Url, parse_time: Duration) -> Option<()> {
+    let versioned = self.map.get(uri.as_str())?.clone();
+    self.publish_diagnostics(uri, versioned, parse_time).await
+  }
+
+  async fn send_diagnostics(
+    &self,
+    uri: Url,
+    diagnostics: Vec<Diagnostic>,
+    version: i32,
+    parse_time: Duration,
+    scan_time: Duration,
+  ) -> Option<()> {
+    self
+      .report_status(&uri, version, parse_time, scan_time)
+      .await;
+    if self.supports_pull_diagnostics() {
+      // the client pulls diagnostics with `textDocument/diagnostic`, avoid duplicates
+      return None;
+    }
+    self
+      .client
+      .publish_diagnostics(uri, diagnostics, Some(version))
+      .await;
+    Some(())
+  }
+
+  /// Update the trace setting by `$/setTrace` notification.
+  pub(crate) async fn set_trace(&self, params: SetTraceParams) {
+    *self.trace.write().unwrap() = params.value;
+  }
+
+  async fn report_status(
+    &self,
+    uri: &Url,
+    version: i32,
+    parse_time: Duration,
+    scan_time: Duration,
+  ) {
+    let trace = *self.trace.read().unwrap();
+    if trace != TraceValue::Off {
+      let message =
+        format!("{uri} (version {version}) parsed in {parse_time:?}, scanned in {scan_time:?}.");
+      let params = LogTraceParams {
+        message,
+        verbose: None,
+      };
+      self
+        .client
+        .send_notification::<notification::LogTrace>(params)
+        .await;
+    }
+    if self.options.read().unwrap().report_status {
+      let status = DocumentStatus::new(uri.clone(), version, parse_time, scan_time);
+      self
+        .client
+        .send_notification::<DocumentStatusNotification>(status)
+        .await;
+    }
+  }
+
+  /// Re-scan all open documents, e.g. after rules are reloaded.
+  async fn republish_all_diagnostics(&self) {
+    let mut published = vec![];
+    for uri in self.map.uris() {
+      // snapshot to avoid holding dashmap locks across await
+      let Some(versioned) = self.map.get(&uri).map(|v| v.clone()) else {
+        continue;
+      };
+      let Ok(uri) = Url::parse(&uri) else {
+        continue;
+      };
+      let version = versioned.version;
+      let diagnostics = self.update_diagnostics(&uri, versioned).await;
+      published.push((uri, diagnostics, version));
+    }
+    if self.supports_pull_diagnostics() {
+      if self.supports_diagnostic_refresh() {
+        let _ = self.client.workspace_diagnostic_refresh().await;
+      }
+      return;
+    }
+    for (uri, diagnostics, version) in published {
+      self
+        .client
+        .publish_diagnostics(uri, diagnostics, Some(version))
+        .await;
+    }
+  }
+
+  /// Forget kinds of previous scans, whose rules are about to be dropped.
+  fn invalidate_scan_kinds(&self) {
+    self.rules_generation.fetch_add(1, Ordering::Release);
+    self.scan_kinds.clear();
+  }
+
+  /// Find all rules again with the rule finder. Returns the error if rules cannot be loaded.
+  fn reload_rules(&self) -> Option<String> {
+    let finder = self.rule_finder.as_ref()?;
+    let rules = self.add_client_rules(finder.as_ref(), finder.find_rules());
+    let error = rules.as_ref().err().cloned();
+    let mut guard = self.rules.write().unwrap();
+    *guard = Arc::new(rules);
+    // cleared with the lock held so no scan caches kinds of the old rules
+    self.invalidate_scan_kinds();
+    error
+  }
+
+  /// Add rules of `ruleDirs` and `inlineRules` options to rules found by the rule finder.
+  /// Without a project config, rules are built from the options alone.
+  fn add_client_rules(
+    &self,
+    finder: &dyn RuleFinder<L>,
+    rules: std::result::Result<RuleCollection<L>, String>,
+  ) -> std::result::Result<RuleCollection<L>, String> {
+    let options = self.options.read().unwrap();
+    if options.rule_dirs.is_empty() && options.inline_rules.is_empty() {
+      return rules;
+    }
+    let mut rules = match rules {
+      Ok(rules) => rules,
+      // the config set by client must be valid
+      Err(error) if options.config_path.is_some() => return Err(error),
+      Err(_) => RuleCollection::default(),
+    };
+    let parse = |yaml: &str| finder.parse_rules(yaml).map_err(|e| error_message(&e));
+    let files = options
+      .rule_dirs
+      .iter()
+      .flat_map(|dir| WalkBuilder::new(self.base.join(dir)).build())
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.into_path())
+      .filter(|path| {
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        matches!(ext, Some("yml" | "yaml"))
+      });
+    for path in files {
+      let yaml = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Cannot read rule file {}: {e}", path.display()))?;
+      let configs =
+        parse(&yaml).map_err(|e| format!("Cannot parse rule file {}: {e}", path.display()))?;
+      rules
+        .update_file(&path, configs)
+        .map_err(|e| e.to_string())?;
+    }
+    for rule in &options.inline_rules {
+      let yaml = match rule {
+        Value::String(yaml) => yaml.clone(),
+        rule => serde_yaml::to_string(rule).map_err(|e| e.to_string())?,
+      };
+      let configs = parse(&yaml).map_err(|e| format!("Cannot parse inline rule: {e}"))?;
+      rules.add_rules(configs).map_err(|e| e.to_string())?;
+    }
+    Ok(rules)
+  }
+
+  /// Returns false if there is no rule finder to use the config.
+  fn apply_config_path(&self, config_path: Option<PathBuf>) -> bool {
+    let Some(finder) = &self.rule_finder else {
+      return false;
+    };
+    finder.set_config_path(config_path.map(|p| self.base.join(p)));
+    true
+  }
+
+  /// Drop cached ASTs and diagnostics of documents under removed folders
+  /// which are neither in another workspace folder nor in the base directory.
+  /// Returns uris of the dropped documents.
+  fn drop_documents_under(&self, removed: &[PathBuf]) -> Vec<Url> {
+    if removed.is_empty() {
+      return vec![];
+    }
+    let base = self
+      .base
+      .canonicalize()
+      .unwrap_or_else(|_| self.base.clone());
+    let dropped: Vec<_> = self
+      .map
+      .uris()
+      .into_iter()
+      .filter_map(|uri| {
+        let uri = Url::parse(&uri).ok()?;
+        let path = uri.to_file_path().ok()?;
+        let is_removed = removed.iter().any(|folder| path.starts_with(folder));
+        let is_kept = path.starts_with(&base) || self.folder_of(&path).is_some();
+        (is_removed && !is_kept).then_some(uri)
+      })
+      .collect();
+    for uri in &dropped {
+      self.map.remove(uri.as_str());
+      self.diagnostics.remove(uri.as_str());
+    }
+    dropped
+  }
+
+  /// Load rules of added workspace folders with the rule finder and drop rules of removed ones.
+  /// Returns errors of loading rules.
+  fn update_workspace_folders(
+    &self,
+    added: Vec<WorkspaceFolder>,
+    removed: Vec<WorkspaceFolder>,
+  ) -> Vec<String> {
+    let Some(finder) = &self.rule_finder else {
+      return vec![];
+    };
+    let base = self.base.canonicalize().ok();
+    let mut folders = self.folders.write().unwrap();
+    // cleared with the lock held so no scan caches kinds of the old rules
+    self.invalidate_scan_kinds();
+    for folder in removed {
+      if let Ok(path) = folder.uri.to_file_path() {
+        folders.remove(&path);
+      }
+    }
+    let mut errors = vec![];
+    for folder in added {
+      let Ok(path) = folder.uri.to_file_path() else {
+        continue;
+      };
+      // the default rules are already found in base
+      if base.is_some() && path.canonicalize().ok() == base {
+        continue;
+      }
+      let Some(rules) = finder.find_rules_in(&path) else {
+        folders.remove(&path);
+        continue;
+      };
+      if let Err(error) = &rules {
+        errors.push(error.clone());
+      }
+      folders.insert(path, Arc::new(rules));
+    }
+    errors
+  }
+
+  async fn on_workspace_folders_change(&self, params: DidChangeWorkspaceFoldersParams) {
+    let WorkspaceFoldersChangeEvent { added, removed } = params.event;
+    let removed_paths: Vec<_> = removed
+      .iter()
+      .filter_map(|folder| folder.uri.to_file_path().ok())
+      .collect();
+    let errors = self.update_workspace_folders(added, removed);
+    for error in errors {
+      self.report_rule_error(error).await;
+    }
+    for uri in self.drop_documents_under(&removed_paths) {
+      self.client.publish_diagnostics(uri, vec![], None).await;
+    }
+    self.republish_all_diagnostics().await;
+  }
+
+  /// Apply client settings and re-scan open documents with them.
+  async fn on_configuration_change(&self, params: DidChangeConfigurationParams) {
+    let (config_path, rules_changed, errors) = {
+      let mut options = self.options.write().unwrap();
+      let (merged, errors) = options.merge_settings(params.settings);
+      let config_changed = merged.config_path != options.config_path;
+      let rules_changed = config_changed
+        || merged.rule_dirs != options.rule_dirs
+        || merged.inline_rules != options.inline_rules;
+      self.map.set_capacity(merged.max_cached_documents);
+      *self.exclude.write().unwrap() = merged.exclude_globs();
+      *options = merged;
+      let config_path = config_changed.then(|| options.config_path.clone());
+      (config_path, rules_changed, errors)
+    };
+    if !errors.is_empty() {
+      let message = format!("Invalid settings are ignored: {}", errors.join("; "));
+      self
+        .client
+        .show_message(MessageType::WARNING, &message)
+        .await;
+      self.client.log_message(MessageType::WARNING, message).await;
+    }
+    if let Some(config_path) = config_path {
+      self.apply_config_path(config_path);
+      self.reregister_file_watchers().await;
+    }
+    if rules_changed {
+      if let Some(error) = self.reload_rules() {
+        self.report_rule_error(error).await;
+      }
+    }
+    self.republish_all_diagnostics().await;
+  }
+
+  /// Find all rules again on request, e.g. after rule files are edited outside the editor.
+  async fn on_reload_rules(&self) {
+    if let Some(error) = self.reload_rules() {
+      self.report_rule_error(error).await;
+    }
+    self
+      .client
+      .log_message(MessageType::INFO, "Rules reloaded.")
+      .await;
+    self.republish_all_diagnostics().await;
+  }
+
+  async fn on_watched_files_change(&self, params: DidChangeWatchedFilesParams) -> Option<()> {
+    let finder = self.rule_finder.as_ref()?;
+    finder.clear_cache();
+    let mut full_reload = false;
+    let mut updated = false;
+    let mut changed_folders = HashSet::new();
+    for change in params.changes {
+      let Ok(path) = change.uri.to_file_path() else {
+        continue;
+      };
+      if let Some(folder) = self.folder_of(&path) {
+        changed_folders.insert(folder);
+        continue;
+      }
+      if path.file_name().map_or(false, |n| n == CONFIG_FILE) {
+        full_reload = true;
+        break;
+      }
+      let path = path.canonicalize().unwrap_or(path);
+      let configs = match finder.read_rule_file(&path) {
+        Some(Ok(configs)) => configs,
+        Some(Err(error)) => {
+          self.report_rule_error(error).await;
+          continue;
+        }
+        None => {
+          full_reload = true;
+          break;
+        }
+      };
+      let result = {
+        let mut guard = self.rules.write().unwrap();
+        // cleared with the lock held so no scan caches kinds of the old rules
+        self.invalidate_scan_kinds();
+        match Arc::get_mut(&mut *guard) {
+          Some(Ok(rules)) => Some(rules.update_file(&path, configs).map_err(|e| e.to_string())),
+          _ => None,
+        }
+      };
+      // previous rules are broken or still used by a scan, reload all
+      let Some(result) = result else {
+        full_reload = true;
+        break;
+      };
+      match result {
+        Ok(()) => updated = true,
+        Err(error) => self.report_rule_error(error).await,
+      }
+    }
+    if !changed_folders.is_empty() {
+      let folders = changed_folders
+        .into_iter()
+        .map(to_workspace_folder)
+        .collect();
+      let errors = self.update_workspace_folders(folders, vec![]);
+      for error in errors {
+        self.report_rule_error(error).await;
+      }
+      updated = true;
+    }
+    if full_reload {
+      if let Some(error) = self.reload_rules() {
+        self.report_rule_error(error).await;
+      }
+    } else if !updated {
+      return None;
+    }
+    self
+      .client
+      .log_message(MessageType::INFO, "Rules reloaded.")
+      .await;
+    self.republish_all_diagnostics().await;
+    Some(())
+  }
+
+  async fn on_open(&self, params: DidOpenTextDocumentParams) -> Option<()> {
+    let text_doc = params.text_document;
+    let uri = text_doc.uri.as_str().to_owned();
+    let text = text_doc.text;
+    if self.is_test_file(&text_doc.uri) {
+      self.test_docs.insert(uri, text);
+      return Some(());
+    }
+    if self.is_rule_file(&text_doc.uri) {
+      self.rule_docs.insert(uri, text);
+      self.publish_rule_file_diagnostics(text_doc.uri).await;
+      return Some(());
+    }
+    self
+      .client
+      .log_message(MessageType::LOG, "Parsing doc.")
+      .await;
+    let lang = self.infer_lang_from_document(&text_doc.uri, &text_doc.language_id);
+    let Some(lang) = lang else {
+      self.report_unsupported_language(&text_doc.uri).await;
+      return None;
+    };
+    let start = Instant::now();
+    let root = AstGrep::new(text, lang);
+    let parse_time = start.elapsed();
+    let versioned = VersionedAst {
+      version: text_doc.version,
+      root,
+    };
+    self
+      .client
+      .log_message(MessageType::LOG, "Publishing init diagnostics.")
+      .await;
+    self.map.insert(uri.to_owned(), versioned.clone()); // don't lock dashmap
+    self
+      .publish_diagnostics(text_doc.uri, versioned, parse_time)
+      .await;
+    Some(())
+  }
+  async fn on_change(&self, params: DidChangeTextDocumentParams) -> Option<()> {
+    let text_doc = params.text_document;
+    let uri = text_doc.uri.as_str();
+    self
+      .client
+      .log_message(MessageType::LOG, "Parsing changed doc.")
+      .await;
+    let encoding = self.position_encoding();
+    let is_rule_doc = self.rule_docs.contains_key(uri);
+    let yaml_doc = self.rule_docs.get_mut(uri);
+    if let Some(mut rule_doc) = yaml_doc.or_else(|| self.test_docs.get_mut(uri)) {
+      for change in params.content_changes {
+        *rule_doc = match change.range {
+          Some(range) => {
+            let edit = TextEdit::new(range, change.text);
+            apply_text_edits(&rule_doc, &[edit], encoding)
+          }
+          None => change.text,
+        };
+      }
+      drop(rule_doc);
+      if is_rule_doc {
+        self.publish_rule_file_diagnostics(text_doc.uri).await;
+      }
+      return Some(());
+    }
+    let parse_time = {
+      // unsupported document is reported when it is opened and never stored
+      let mut versioned = self.map.get_mut(uri)?;
+      // skip old version update
+      if versioned.version > text_doc.version {
+        return None;
+      }
+      let start = Instant::now();
+      // changes are applied in order, each range is relative to the previous change
+      for change in params.content_changes {
+        apply_content_change(&mut versioned.root, change, encoding);
+      }
+      versioned.version = text_doc.version;
+      start.elapsed()
+    };
+    let (trigger, debounce) = {
+      let options = self.options.read().unwrap();
+      (options.diagnostics_trigger, options.diagnostics_debounce_ms)
+    };
+    if trigger == DiagnosticsTrigger::OnSave {
+      return Some(()); // published when the document is saved
+    }
+    if debounce == 0 {
+      return self
+        .publish_changed_diagnostics(text_doc.uri, parse_time)
+        .await;
+    }
+    // wait in a task of its own so the server keeps handling other messages
+    let backend = self.clone();
+    let uri = text_doc.uri.to_string();
+    let task = tokio::spawn(async move {
+      tokio::time::sleep(Duration::from_millis(debounce)).await;
+      let version = backend.map.version(text_doc.uri.as_str())?;
+      // a later change will publish diagnostics of its version
+      if version != text_doc.version {
+        return None;
+      }
+      backend
+        .publish_changed_diagnostics(text_doc.uri, parse_time)
+        .await
+    });
+    if let Some(pending) = self.debounced.insert(uri, task.abort_handle()) {
+      pending.abort();
+    }
+    Some(())
+  }
+  async fn publish_changed_diagnostics(&self, uri: Url, parse_time: Duration) -> Option<()> {
+    self
+      .client
+      .log_message(MessageType::LOG, "Publishing diagnostics.")
+      .await;
+    self.publish_document_diagnostics(uri, parse_time).await
+  }
+  /// Publish diagnostics of the saved document without waiting for the debounce of changes,
+  /// or at all in `onSave` mode.
+  async fn on_save(&self, params: DidSaveTextDocumentParams) -> Option<()> {
+    let published = {
+      let options = self.options.read().unwrap();
+      options.diagnostics_trigger == DiagnosticsTrigger::OnType
+        && options.diagnostics_debounce_ms == 0
+    };
+    if published {
+      return None; // already published on change
+    }
+    // the saved version is published now, the pending debounced scan is stale
+    if let Some((_, pending)) = self.debounced.remove(params.text_document.uri.as_str()) {
+      pending.abort();
+    }
+    self
+      .publish_document_diagnostics(params.text_document.uri, Duration::ZERO)
+      .await
+  }
+
+  async fn on_close(&self, params: DidCloseTextDocumentParams) {
+    if let Some((_, pending)) = self.debounced.remove(params.text_document.uri.as_str()) {
+      pending.abort();
+    }
+    self.rule_docs.remove(params.text_document.uri.as_str());
+    self.test_docs.remove(params.text_document.uri.as_str());
+    self.map.remove(params.text_document.uri.as_str());
+    self.diagnostics.remove(params.text_document.uri.as_str());
+  }
+
+  /// Move cached documents of renamed files, or of files in renamed folders, to the new uris.
+  async fn on_rename_files(&self, params: RenameFilesParams) {
+    for file in params.files {
+      let renamed: Vec<_> = self
+        .map
+        .uris()
+        .into_iter()
+        .filter(|uri| {
+          let rest = uri.strip_prefix(&file.old_uri);
+          rest.map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .collect();
+      for old_uri in renamed {
+        let new_uri = format!("{}{}", file.new_uri, &old_uri[file.old_uri.len()..]);
+        self.rename_document(&old_uri, new_uri).await;
+      }
+    }
+  }
+
+  /// Re-key the document and its diagnostics. The language is inferred again from the new path,
+  /// and the document is dropped if the new extension is not supported.
+  async fn rename_document(&self, old_uri: &str, new_uri: String) -> Option<()> {
+    let mut versioned = self.map.take(old_uri)?;
+    self.diagnostics.remove(old_uri);
+    let old_url = Url::parse(old_uri).ok()?;
+    self.client.publish_diagnostics(old_url, vec![], None).await;
+    // the client may have opened the document with the new uri already
+    if self.map.contains_key(&new_uri) {
+      return None;
+    }
+    let new_url = Url::parse(&new_uri).ok()?;
+    let lang = match self.infer_lang_from_uri(&new_url) {
+      Some(lang) => lang,
+      // the language from `languageId` is kept for files without extension
+      None if uri_to_path(&new_url).extension().is_none() => versioned.root.lang().clone(),
+      None => {
+        self.report_unsupported_language(&new_url).await;
+        return None;
+      }
+    };
+    let start = Instant::now();
+    if &lang != versioned.root.lang() {
+      versioned.root = AstGrep::new(versioned.root.source(), lang);
+    }
+    let parse_time = start.elapsed();
+    self.map.insert(new_uri, versioned.clone());
+    self
+      .publish_diagnostics(new_url, versioned, parse_time)
+      .await;
+    Some(())
+  }
+
+  /// Declaration order of rules applied to the document, which breaks ties of overlapping fixes.
+  /// Rules are ordered by their rule file and position in it, since the collection groups rules
+  /// by language. Rules without source come last.
+  fn rule_order(&self, uri: &Url) -> HashMap<String, usize> {
+    self.with_rules(uri, |rules| {
+      let Ok(rules) = rules else {
+        return HashMap::new();
+      };
+      let mut rules: Vec<_> = rules.iter().enumerate().collect();
+      rules.sort_by_key(|(i, rule)| {
+        let source = rule.source().map(|s| (&s.path, s.line));
+        (source.is_none(), source, *i)
+      });
+      let ids = rules
+        .into_iter()
+        .enumerate()
+        .map(|(order, (_, rule))| (rule.id.clone(), order));
+      ids.collect()
+    })
+  }
+
+  /// Fixes of all findings in the document which match the filter.
+  async fn compute_all_fixes(
+    &self,
+    text_document: TextDocumentIdentifier,
+    filter: &FixFilter,
+    cancel: &CancelToken,
+  ) -> std::result::Result<AllFixes, LspError>
+  where
+    L: ast_grep_core::Language + std::cmp::Eq,
+  {
+    let uri = text_document.uri;
+    let mut diagnostics = self.get_request_diagnostics(&uri, true, cancel).await?;
+    diagnostics.retain(|d| filter.matches(d));
+    let unsafe_ranges = unsafe_fix_ranges(&diagnostics);
+    let (edits, skipped) = collect_fixes(diagnostics, &self.rule_order(&uri));
+    if edits.is_empty() {
+      return Err(LspError::NoActionableFix);
+    }
+    let mut changes = HashMap::new();
+    changes.insert(uri, edits);
+    Ok(AllFixes {
+      changes,
+      unsafe_ranges,
+      skipped,
+    })
+  }
+
+  /// Tell the user which fixes are not applied because they overlap with other fixes.
+  async fn report_skipped_fixes(&self, skipped: &[FixConflict]) {
+    if skipped.is_empty() {
+      return;
+    }
+    let fixes: Vec<_> = skipped
+      .iter()
+      .map(|c| format!("`{}` (by `{}`)", c.suppressed, c.by))
+      .collect();
+    let message = format!(
+      "Skipped {} fixes overlapping with other fixes: {}.",
+      skipped.len(),
+      fixes.join(", ")
+    );
+    self.client.show_message(MessageType::INFO, message).await;
+  }
+
+  async fn on_code_action(&self, params: CodeActionParams) -> Option<CodeActionResponse> {
+    if let Some(kinds) = params.context.only.as_ref() {
+      // e.g. `editor.codeActionsOnSave: ["source.fixAll.ast-grep"]` only asks for ours
+      let fix_all = [
+        CodeActionKind::SOURCE_FIX_ALL,
+        CodeActionKind::new(FIX_ALL_AST_GREP),
+      ];
+      if kinds.iter().any(|kind| fix_all.contains(kind)) {
+        return self.fix_all_code_action(params.text_document).await;
+      }
+    }
+    let only = params.context.only.clone();
+    let requested = |kind: CodeActionKind| {
+      let mut kinds = only.iter().flatten();
+      only.is_none() || kinds.any(|k| is_kind_requested(k, &kind))
+    };
+    // searching rules of all enclosing nodes can be turned off unless explicitly requested
+    let list_applicable = match &only {
+      Some(_) => requested(CodeActionKind::new(APPLICABLE_RULES_AST_GREP)),
+      None => self.options.read().unwrap().list_applicable_rules,
+    };
+    let mut applicable = if list_applicable {
+      self.applicable_rules_code_action(&params)
+    } else {
+      None
+    };
+    // all actions are reported as partial results if streamed, see `quickfix_code_action`
+    let token = &params.partial_result_params.partial_result_token;
+    if let (Some(token), Some(actions)) = (token, &mut applicable) {
+      let partial = PartialResult {
+        token: token.clone(),
+        value: std::mem::take(actions),
+      };
+      self
+        .client
+        .send_notification::<CodeActionProgress>(partial)
+        .await;
+    }
+    let quickfixes = if requested(CodeActionKind::QUICKFIX) {
+      self.quickfix_code_action(params).await
+    } else {
+      None
+    };
+    match (quickfixes, applicable) {
+      (Some(mut actions), Some(applicable)) => {
+        actions.extend(applicable);
+        Some(actions)
+      }
+      (quickfixes, applicable) => quickfixes.or(applicable),
+    }
+  }
+
+  async fn fix_all_code_action(
+    &self,
+    text_document: TextDocumentIdentifier,
+  ) -> Option<CodeActionResponse> {
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    // fix-all actions are applied on save without confirmation
+    let fixes = self
+      .compute_all_fixes(text_document, &FixFilter::safe(), &cancel)
+      .await
+      .ok()?;
+    self.report_skipped_fixes(&fixes.skipped).await;
+    let edit = WorkspaceEdit::new(fixes.changes);
+    let code_action = CodeAction {
+      title: "Fix by ast-grep".into(),
+      command: None,
+      diagnostics: None,
+      edit: Some(edit),
+      kind: Some(CodeActionKind::new(FIX_ALL_AST_GREP)),
+      is_preferred: None,
+      data: None,
+      disabled: None,
+    };
+    Some(vec![CodeActionOrCommand::CodeAction(code_action)])
+  }
+
+  /// Fixes of all findings as formatting edits, so format on save applies them.
+  /// Only fixes inside `range` are returned for range formatting. Unsafe fixes are left out.
+  async fn on_formatting(
+    &self,
+    text_document: TextDocumentIdentifier,
+    range: Option<Range>,
+  ) -> Option<Vec<TextEdit>> {
+    let uri = text_document.uri.clone();
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    let AllFixes {
+      mut changes,
+      mut skipped,
+      ..
+    } = match self
+      .compute_all_fixes(text_document, &FixFilter::safe(), &cancel)
+      .await
+    {
+      Ok(fixes) => fixes,
+      Err(LspError::NoActionableFix) => return Some(vec![]),
+      Err(_) => return None,
+    };
+    let mut edits = changes.remove(&uri)?;
+    if let Some(range) = range {
+      let in_range = |r: &Range| range.start <= r.start && r.end <= range.end;
+      edits.retain(|e| in_range(&e.range));
+      skipped.retain(|c| in_range(&c.range));
+    }
+    self.report_skipped_fixes(&skipped).await;
+    Some(edits)
+  }
+
+  /// Annotate the first finding of each rule with the number of findings in the document.
+  async fn on_code_lens(&self, params: CodeLensParams) -> Option<Vec<CodeLens>> {
+    let uri = params.text_document.uri;
+    if self.test_docs.contains_key(uri.as_str()) {
+      let command = Command {
+        title: "Run tests".to_string(),
+        command: RUN_RULE_TESTS.to_string(),
+        arguments: Some(vec![json!(uri)]),
+      };
+      let range = Range::default();
+      return Some(vec![CodeLens {
+        range,
+        command: Some(command),
+        data: None,
+      }]);
+    }
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    let diagnostics = self.get_request_diagnostics(&uri, false, &cancel).await;
+    let mut diagnostics = diagnostics.ok()?;
+    diagnostics.retain(|d| !is_suppressed(d));
+    let lenses = self.with_rules(&uri, |rules| {
+      let rules = rules.as_ref().ok();
+      let has_fix = |id: &str| {
+        let rule = rules.and_then(|r| r.get_rule(id));
+        rule.map_or(false, |r| r.matcher.fixer.is_some())
+      };
+      rule_code_lenses(&uri, &diagnostics, has_fix, APPLY_RULE_FIXES)
+    });
+    Some(lenses)
+  }
+
+  /// List rules that can match the node under cursor or its ancestors by kind,
+  /// even if they do not fire due to other constraints. This helps rule authoring.
+  fn applicable_rules_code_action(&self, params: &CodeActionParams) -> Option<CodeActionResponse> {
+    let uri = &params.text_document.uri;
+    let versioned = self.map.get(uri.as_str())?;
+    self.with_rules(uri, |rules| {
+      let rules = self.get_rules(uri, versioned.root.lang(), rules.as_ref().ok()?);
+      let scan = CombinedScan::new(rules);
+      let node = find_node_at(
+        &versioned.root,
+        params.range.start,
+        self.position_encoding(),
+      )?;
+      let mut seen = HashSet::new();
+      let mut response = vec![];
+      for n in std::iter::once(node.clone()).chain(node.ancestors()) {
+        for rule in scan.get_rules_for_kind(n.kind_id()) {
+          if !seen.insert(&rule.id) {
+            continue;
+          }
+          // choosing the action opens the rule file, if the rule is read from one
+          let command = rule.source().map(|_| Command {
+            title: "Open rule".to_string(),
+            command: OPEN_RULE.to_string(),
+            arguments: Some(vec![json!(uri), json!(rule.id)]),
+          });
+          let disabled = command.is_none().then(|| CodeActionDisabled {
+            reason: "The rule is not defined in a rule file".to_string(),
+          });
+          let action = CodeAction {
+            title: format!("Rule `{}` is applicable to `{}`", rule.id, n.kind()),
+            kind: Some(CodeActionKind::new(APPLICABLE_RULES_AST_GREP)),
+            command,
+            disabled,
+            ..Default::default()
+          };
+          response.push(CodeActionOrCommand::CodeAction(action));
+        }
+      }
+      (!response.is_empty()).then_some(response)
+    })
+  }
+
+  /// Quick fixes and suppressions of the diagnostics in the request. If the client sends a
+  /// `partialResultToken`, actions are streamed by chunks of diagnostics, since resolving
+  /// the fixes of thousands of findings takes a while.
+  async fn quickfix_code_action(&self, params: CodeActionParams) -> Option<CodeActionResponse> {
+    if params.context.diagnostics.is_empty() {
+      return None;
+    }
+    let token = params.partial_result_params.partial_result_token;
+    let uri = params.text_document.uri;
+    let resolve_later = self.supports_code_action_resolve();
+    let has_fix = |d: &Diagnostic| {
+      self.with_rules(&uri, |rules| {
+        let rules = rules.as_ref().ok();
+        let rule = get_rule_id(d).and_then(|id| rules?.get_rule(id));
+        rule.map_or(false, |r| r.matcher.fixer.is_some())
+      })
+    };
+    let diagnostics: Vec<_> = params
+      .context
+      .diagnostics
+      .into_iter()
+      .filter(|d| {
+        d.source
+          .as_ref()
+          .map(|s| s.contains("ast-grep"))
+          .unwrap_or(false)
+      })
+      // already suppressed findings need no action
+      .filter(|d| !is_suppressed(d))
+      .collect();
+    let mut response = vec![];
+    for chunk in diagnostics.chunks(PARTIAL_RESULT_CHUNK) {
+      let mut actions: Vec<_> = chunk
+        .iter()
+        .filter(|d| has_fix(d))
+        .filter_map(|d| {
+          let action = unresolved_code_action(&uri, d.clone())?;
+          if resolve_later {
+            Some(action)
+          } else {
+            self.resolve_code_action(action)
+          }
+        })
+        .map(CodeActionOrCommand::from)
+        .collect();
+      if let Some(versioned) = self.map.get(uri.as_str()) {
+        let text = versioned.root.source();
+        let comment = comment_syntax(&uri, versioned.root.lang());
+        let suppressions = chunk
+          .iter()
+          .filter_map(|d| suppression_code_action(&uri, text, comment, d));
+        actions.extend(suppressions.map(CodeActionOrCommand::from));
+      }
+      let Some(token) = &token else {
+        response.extend(actions);
+        continue;
+      };
+      let partial = PartialResult {
+        token: token.clone(),
+        value: actions,
+      };
+      self
+        .client
+        .send_notification::<CodeActionProgress>(partial)
+        .await;
+    }
+    // all actions are reported as partial results if streamed
+    Some(response)
+  }
+
+  fn supports_code_action_resolve(&self) -> bool {
+    let capabilities = self.client_capabilities.read().unwrap();
+    let code_action = capabilities
+      .text_document
+      .as_ref()
+      .and_then(|t| t.code_action.as_ref());
+    let resolve = code_action.and_then(|c| c.resolve_support.as_ref());
+    resolve.map_or(false, |r| r.properties.iter().any(|p| p == "edit"))
+  }
+
+  /// Compute the edit of a quick fix returned by `quickfix_code_action`.
+  /// The document is scanned again with the diagnostic's rule to render the fix.
+  fn resolve_code_action(&self, action: CodeAction) -> Option<CodeAction> {
+    let QuickFixData { uri, diagnostic } = serde_json::from_value(action.data?).ok()?;
+    let text_doc = TextDocumentIdentifier::new(uri.clone());
+    // diagnostics may already carry the fix, e.g. when fix conflicts are reported
+    if let Some(action) = diagnostic_to_code_action(&text_doc, diagnostic.clone()) {
+      return Some(action);
+    }
+    let versioned = self.map.get(uri.as_str())?;
+    let path = self.get_relative_path(&uri)?;
+    let id = get_rule_id(&diagnostic)?;
+    let (rules, generation) = self.rules_snapshot(&uri);
+    // rules turned off are never scanned, as in `get_diagnostics`
+    let rules = self.get_rules(&uri, versioned.root.lang(), rules.as_ref().as_ref().ok()?);
+    let rules: Vec<_> = rules.into_iter().filter(|rule| rule.id == id).collect();
+    if rules.is_empty() {
+      return None;
+    }
+    let fixed = self
+      .scan_with_rules(
+        &uri,
+        &path,
+        &versioned,
+        (rules, generation),
+        true,
+        &CancelToken::default(),
+      )
+      .into_iter()
+      .find(|d| d.range == diagnostic.range)?;
+    diagnostic_to_code_action(&text_doc, fixed)
+  }
+
+  fn is_test_file(&self, uri: &Url) -> bool {
+    let Some(finder) = &self.rule_finder else {
+      return false;
+    };
+    uri
+      .to_file_path()
+      .map_or(false, |path| finder.is_test_file(&path))
+  }
+
+  /// Verify cases of an open rule test file with the rules in effect for the file.
+  /// Failed cases are published as diagnostics of the test file.
+  async fn on_run_rule_tests(&self, arguments: Vec<Value>) -> Option<Value> {
+    let uri: Url = serde_json::from_value(arguments.first()?.clone()).ok()?;
+    let text = self.test_docs.get(uri.as_str())?.clone();
+    let (diagnostics, passed) = self.with_rules(&uri, |rules| {
+      let rules = rules.as_ref().ok();
+      verify_rule_tests(&text, |id| rules?.get_rule(id), self.position_encoding())
+    });
+    let failed = diagnostics.len();
+    self
+      .client
+      .publish_diagnostics(uri, diagnostics, None)
+      .await;
+    Some(json!({ "passed": passed, "failed": failed }))
+  }
+
+  fn is_rule_file(&self, uri: &Url) -> bool {
+    let Some(finder) = &self.rule_finder else {
+      return false;
+    };
+    uri
+      .to_file_path()
+      .map_or(false, |path| finder.is_rule_file(&path))
+  }
+
+  fn on_completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+    let position = params.text_document_position;
+    let text = self.rule_docs.get(position.text_document.uri.as_str())?;
+    let util_ids = self
+      .rule_finder
+      .as_ref()
+      .map(|finder| finder.util_rule_ids())
+      .unwrap_or_default();
+    let encoding = self.position_encoding();
+    let items = rule_completions::<L>(&text, position.position, util_ids, encoding);
+    Some(CompletionResponse::Array(items))
+  }
+
+  fn infer_lang_from_uri(&self, uri: &Url) -> Option<L> {
+    let path = uri_to_path(uri);
+    let mapped = path.extension().and_then(|ext| {
+      let options = self.options.read().unwrap();
+      let lang = options.language_mapping.get(ext.to_str()?)?;
+      L::from_str(lang).ok()
+    });
+    mapped.or_else(|| L::from_path(path))
+  }
+
+  /// Language of a document sent by client. `languageId` is used if the uri has no extension,
+  /// e.g. unsaved documents or scripts with shebang. Files of unknown extensions
+  /// should use `languageMapping` instead, so they are still reported as unsupported.
+  fn infer_lang_from_document(&self, uri: &Url, language_id: &str) -> Option<L> {
+    self.infer_lang_from_uri(uri).or_else(|| {
+      let has_extension = uri_to_path(uri).extension().is_some();
+      if uri.scheme() == "file" && has_extension {
+        return None;
+      }
+      L::from_str(language_id).ok()
+    })
+  }
+
+  /// Log the document whose language cannot be inferred instead of silently skipping it.
+  /// A message is also shown once per extension if `reportUnsupportedLanguage` is on.
+  async fn report_unsupported_language(&self, uri: &Url) {
+    let ext = uri_to_path(uri)
+      .extension()
+      .map(|ext| ext.to_string_lossy().to_string())
+      .unwrap_or_default();
+    let files = if ext.is_empty() {
+      "files without extension".to_string()
+    } else {
+      format!("`.{ext}` files")
+    };
+    let message = format!(
+      "Cannot infer language of {uri}. ast-grep does not support {files}, use `languageMapping` option to map them to a language."
+    );
+    self
+      .client
+      .log_message(MessageType::LOG, message.clone())
+      .await;
+    let report = self.options.read().unwrap().report_unsupported_language;
+    if report && self.reported_extensions.insert(ext) {
+      self.client.show_message(MessageType::INFO, message).await;
+    }
+  }
+
+  async fn on_execute_command(&self, params: ExecuteCommandParams) -> Option<Value> {
+    let ExecuteCommandParams {
+      arguments,
+      command,
+      work_done_progress_params: _,
+    } = params;
+
+    match command.as_ref() {
+      APPLY_ALL_FIXES => {
+        self.on_apply_all_fix(command, arguments).await?;
+        None
+      }
+      APPLY_FIXES_RETURN_TEXT => self.on_apply_fixes_return_text(arguments).await,
+      DIAGNOSE_TEXT => self.on_diagnose_text(arguments).await,
+      DUMP_CONFIG => Some(self.dump_config()),
+      SCAN_WORKSPACE => self.on_scan_workspace().await,
+      SEARCH => self.on_search(arguments).await,
+      RUN_RULE_TESTS => self.on_run_rule_tests(arguments).await,
+      INSPECT_NODE => self.on_inspect_node(arguments).await,
+      EXTRACT_RULE => self.on_extract_rule(arguments).await,
+      OPEN_RULE => self.on_open_rule(arguments).await,
+      RELOAD_RULES => {
+        self.on_reload_rules().await;
+        None
+      }
+      APPLY_RULE_FIXES => {
+        self.on_apply_rule_fixes(arguments).await?;
+        None
+      }
+      _ => {
+        self
+          .client
+          .log_message(
+            MessageType::LOG,
+            format!("Unrecognized command: {}", command),
+          )
+          .await;
+        None
+      }
+    }
+  }
+
+  async fn on_apply_all_fix_impl(
+    &self,
+    first: Value,
   ) -> std::result::Result<WorkspaceEdit, LspError> {
+    let filter: FixFilter =
+      serde_json::from_value(first.clone()).map_err(LspError::JSONDecodeError)?;
     let text_doc: TextDocumentItem =
       serde_json::from_value(first).map_err(LspError::JSONDecodeError)?;
     let uri = text_doc.uri;
-    // let version = text_doc.version;
-    let changes = self.compute_all_fixes(TextDocumentIdentifier::new(uri))?;
-    let workspace_edit = WorkspaceEdit {
-      changes: Some(changes),
-      document_changes: None,
-      change_annotations: None,
+    // read the version before computing fixes, so fixes of a newer text are rejected by client
+    let version = self.document_version(&uri)?;
+    // the document is edited after the command is issued
+    if text_doc.version < version {
+      return Err(LspError::StaleVersion);
+    }
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    let text_doc = TextDocumentIdentifier::new(uri);
+    let fixes = self.compute_all_fixes(text_doc, &filter, &cancel).await?;
+    self.report_skipped_fixes(&fixes.skipped).await;
+    Ok(self.versioned_workspace_edit(fixes.changes, version, &fixes.unsafe_ranges))
+  }
+
+  fn document_version(&self, uri: &Url) -> std::result::Result<i32, LspError> {
+    let versioned = self
+      .map
+      .get(uri.as_str())
+      .ok_or(LspError::UnsupportedFileType)?;
+    Ok(versioned.version)
+  }
+
+  /// Edits of documents at `version`, so client does not apply them if the document has
+  /// been edited since. Falls back to plain `changes` if client does not support it.
+  /// Edits of `unsafe_ranges` are annotated to be confirmed by user if client supports it,
+  /// otherwise they are left out.
+  fn versioned_workspace_edit(
+    &self,
+    mut changes: HashMap<Url, Vec<TextEdit>>,
+    version: i32,
+    unsafe_ranges: &[Range],
+  ) -> WorkspaceEdit {
+    let capabilities = self.client_capabilities.read().unwrap();
+    let workspace_edit = capabilities
+      .workspace
+      .as_ref()
+      .and_then(|w| w.workspace_edit.as_ref());
+    let document_changes = workspace_edit.and_then(|e| e.document_changes);
+    let annotation_support = workspace_edit.and_then(|e| e.change_annotation_support.as_ref());
+    let document_changes = document_changes.unwrap_or(false);
+    if !document_changes || annotation_support.is_none() {
+      for edits in changes.values_mut() {
+        edits.retain(|edit| !unsafe_ranges.contains(&edit.range));
+      }
+    }
+    if !document_changes {
+      return WorkspaceEdit::new(changes);
+    }
+    let annotate = annotation_support.is_some() && !unsafe_ranges.is_empty();
+    let to_edit = |edit: TextEdit| {
+      if !annotate || !unsafe_ranges.contains(&edit.range) {
+        return OneOf::Left(edit);
+      }
+      OneOf::Right(AnnotatedTextEdit {
+        text_edit: edit,
+        annotation_id: UNSAFE_FIX_ANNOTATION.to_string(),
+      })
+    };
+    let edits = changes
+      .into_iter()
+      .map(|(uri, edits)| TextDocumentEdit {
+        text_document: OptionalVersionedTextDocumentIdentifier {
+          uri,
+          version: Some(version),
+        },
+        edits: edits.into_iter().map(to_edit).collect(),
+      })
+      .collect();
+    let change_annotations = annotate.then(|| {
+      let annotation = ChangeAnnotation {
+        label: "Unsafe fix by ast-grep".to_string(),
+        needs_confirmation: Some(true),
+        description: Some("The fix may change the semantics of the code".to_string()),
+      };
+      HashMap::from([(UNSAFE_FIX_ANNOTATION.to_string(), annotation)])
+    });
+    WorkspaceEdit {
+      changes: None,
+      document_changes: Some(DocumentChanges::Edits(edits)),
+      change_annotations,
+    }
+  }
+
+  /// Fix all findings of one rule in an open document, run by the code lens of the rule.
+  async fn on_apply_rule_fixes(&self, arguments: Vec<Value>) -> Option<()> {
+    let first = arguments.first()?.clone();
+    let workspace_edit = match self.on_apply_rule_fixes_impl(first).await {
+      Ok(workspace_edit) => workspace_edit,
+      Err(error) => {
+        self.report_error(error).await;
+        return None;
+      }
+    };
+    self.client.apply_edit(workspace_edit).await.ok()?;
+    Some(())
+  }
+
+  async fn on_apply_rule_fixes_impl(
+    &self,
+    first: Value,
+  ) -> std::result::Result<WorkspaceEdit, LspError> {
+    let params: ApplyRuleFixesParams =
+      serde_json::from_value(first).map_err(LspError::JSONDecodeError)?;
+    let version = self.document_version(&params.uri)?;
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    let text_doc = TextDocumentIdentifier::new(params.uri);
+    let filter = FixFilter {
+      rule_ids: Some(vec![params.rule_id]),
+      ..Default::default()
+    };
+    let fixes = self.compute_all_fixes(text_doc, &filter, &cancel).await?;
+    self.report_skipped_fixes(&fixes.skipped).await;
+    Ok(self.versioned_workspace_edit(fixes.changes, version, &fixes.unsafe_ranges))
+  }
+
+  /// Scan the text in argument and return the fixed text without applying edits to editor.
+  /// This is useful to test the fix pipeline end to end.
+  async fn apply_fixes_to_text(&self, first: Value) -> std::result::Result<String, LspError> {
+    let text_doc: TextDocumentItem =
+      serde_json::from_value(first).map_err(LspError::JSONDecodeError)?;
+    let lang = self
+      .infer_lang_from_document(&text_doc.uri, &text_doc.language_id)
+      .ok_or(LspError::UnsupportedFileType)?;
+    let versioned = VersionedAst {
+      version: text_doc.version,
+      root: AstGrep::new(&text_doc.text, lang),
+    };
+    let cancel = CancelToken::default();
+    let _guard = cancel.drop_guard();
+    let mut diagnostics = self
+      .scan_snapshot(&text_doc.uri, versioned, true, &cancel)
+      .await
+      .unwrap_or_default();
+    // the text is fixed without confirmation
+    diagnostics.retain(|d| FixFilter::safe().matches(d));
+    let (edits, _) = collect_fixes(diagnostics, &self.rule_order(&text_doc.uri));
+    Ok(apply_text_edits(
+      &text_doc.text,
+      &edits,
+      self.position_encoding(),
+    ))
+  }
+
+  async fn on_apply_fixes_return_text(&self, arguments: Vec<Value>) -> Option<Value> {
+    let first = arguments.first()?.clone();
+    match self.apply_fixes_to_text(first).await {
+      Ok(text) => Some(Value::String(text)),
+      Err(error) => {
+        self.report_error(error).await;
+        None
+      }
+    }
+  }
+
+  /// Diagnostics of text not tracked by the server. The language is inferred from
+  /// the uri if present, otherwise from the languageId.
+  fn diagnose_text(&self, first: Value) -> std::result::Result<Vec<Diagnostic>, LspError> {
+    let params: DiagnoseTextParams =
+      serde_json::from_value(first).map_err(LspError::JSONDecodeError)?;
+    let lang = params
+      .uri
+      .as_ref()
+      .and_then(|uri| self.infer_lang_from_uri(uri))
+      .or_else(|| L::from_str(params.language_id.as_ref()?).ok())
+      .ok_or(LspError::UnsupportedFileType)?;
+    let path = params.uri.as_ref().and_then(|u| self.get_relative_path(u));
+    let uri = params
+      .uri
+      .unwrap_or_else(|| Url::parse("untitled:Untitled").expect("should be valid url"));
+    let versioned = VersionedAst {
+      version: 0,
+      root: AstGrep::new(&params.text, lang.clone()),
+    };
+    let (rules, generation) = self.rules_snapshot(&uri);
+    let diagnostics = match rules.as_ref() {
+      Ok(rules) => {
+        let path = path.unwrap_or_default();
+        let mut rules = rules.get_rule_from_lang(&path, lang);
+        rules.retain(|rule| self.is_rule_enabled(rule));
+        self.scan_with_rules(
+          &uri,
+          &path,
+          &versioned,
+          (rules, generation),
+          true,
+          &CancelToken::default(),
+        )
+      }
+      Err(_) => vec![],
+    };
+    Ok(self.apply_diagnostic_options(&uri, diagnostics))
+  }
+
+  async fn on_diagnose_text(&self, arguments: Vec<Value>) -> Option<Value> {
+    let first = arguments.first()?.clone();
+    match self.diagnose_text(first) {
+      Ok(diagnostics) => serde_json::to_value(diagnostics).ok(),
+      Err(error) => {
+        self.report_error(error).await;
+        None
+      }
+    }
+  }
+
+  /// Effective server options and the state they are resolved to, for debugging settings.
+  fn dump_config(&self) -> Value {
+    let options = self.options.read().unwrap().clone();
+    // a custom `configPath` replaces the config file in the base directory
+    let config_path = options.config_path.as_deref();
+    let config_file = self
+      .base
+      .join(config_path.unwrap_or(Path::new(CONFIG_FILE)));
+    let config_file = config_file.exists().then_some(config_file);
+    let (rules, rules_error) = match &**self.rules.read().unwrap() {
+      Ok(rules) => (rules.iter().map(|r| r.id.clone()).collect(), None),
+      Err(error) => (vec![], Some(error.clone())),
     };
-    Ok(workspace_edit)
+    let folders: Vec<_> = self.folders.read().unwrap().keys().cloned().collect();
+    json!({
+      "options": options,
+      "base": self.base,
+      "workspaceFolders": folders,
+      "configFile": config_file,
+      "rules": rules,
+      "rulesError": rules_error,
+      "openDocuments": self.map.len(),
+      "parsedDocuments": self.map.parsed_len(),
+    })
   }
 
   async fn on_apply_all_fix(&self, command: String, arguments: Vec<Value>) -> Option<()> {
@@ -433,6 +2933,33 @@ impl<L: LSPLang> Backend<L> {
     None
   }
 
+  /// Publish errors of rules in an opened rule file, or clear them if all rules are valid.
+  async fn publish_rule_file_diagnostics(&self, uri: Url) {
+    let Some(finder) = &self.rule_finder else {
+      return;
+    };
+    let diagnostics = match self.rule_docs.get(uri.as_str()) {
+      Some(text) => rule_file_diagnostics(
+        &text,
+        |yaml| finder.parse_rules(yaml),
+        self.position_encoding(),
+      ),
+      None => return,
+    };
+    self
+      .client
+      .publish_diagnostics(uri, diagnostics, None)
+      .await;
+  }
+
+  async fn report_rule_error(&self, error: String) {
+    let message = format!("Failed to load rules: {}", error);
+    // popup message
+    self.client.show_message(MessageType::ERROR, &message).await;
+    // log message
+    self.client.log_message(MessageType::ERROR, message).await;
+  }
+
   async fn report_error(&self, error: LspError) {
     match error {
       LspError::JSONDecodeError(e) => {
@@ -456,6 +2983,31 @@ impl<L: LSPLang> Backend<L> {
           .log_message(MessageType::LOG, "No actionable fix")
           .await;
       }
+      LspError::InvalidPattern(e) => {
+        self
+          .client
+          .log_message(MessageType::ERROR, format!("Invalid pattern: {e}"))
+          .await;
+      }
+      LspError::WriteRuleFile(e) => {
+        let message = format!("Cannot write rule file: {e}");
+        self.client.show_message(MessageType::ERROR, message).await;
+      }
+      LspError::Cancelled => {
+        self
+          .client
+          .log_message(MessageType::LOG, "Request is cancelled")
+          .await;
+      }
+      LspError::StaleVersion => {
+        self
+          .client
+          .log_message(
+            MessageType::WARNING,
+            "Document is edited after the command is issued, fixes are not applied",
+          )
+          .await;
+      }
     }
   }
 }
@@ -464,4 +3016,8 @@ enum LspError {
   JSONDecodeError(serde_json::Error),
   UnsupportedFileType,
   NoActionableFix,
+  StaleVersion,
+  InvalidPattern(String),
+  WriteRuleFile(String),
+  Cancelled,
 }
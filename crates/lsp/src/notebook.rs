@@ -0,0 +1,150 @@
+//! Notebook document sync of LSP 3.17, which `LanguageServer` and lsp-types do not cover yet.
+//! Every code cell is handled as a text document of the cell uri, so diagnostics and fixes
+//! are reported per cell. Cells of unsupported languages, e.g. markdown, are skipped.
+use crate::{Backend, LSPLang};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, ClientSocket, LspService};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidOpenNotebookDocumentParams {
+  pub cell_text_documents: Vec<TextDocumentItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidChangeNotebookDocumentParams {
+  pub change: NotebookDocumentChangeEvent,
+}
+
+#[derive(Deserialize)]
+pub struct NotebookDocumentChangeEvent {
+  pub cells: Option<NotebookCellsChange>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCellsChange {
+  pub structure: Option<NotebookCellsStructure>,
+  pub text_content: Option<Vec<NotebookCellTextContent>>,
+}
+
+/// Cells added or removed. Only the opened and closed cell documents are used.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCellsStructure {
+  pub did_open: Option<Vec<TextDocumentItem>>,
+  pub did_close: Option<Vec<TextDocumentIdentifier>>,
+}
+
+#[derive(Deserialize)]
+pub struct NotebookCellTextContent {
+  pub document: VersionedTextDocumentIdentifier,
+  pub changes: Vec<TextDocumentContentChangeEvent>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidCloseNotebookDocumentParams {
+  pub cell_text_documents: Vec<TextDocumentIdentifier>,
+}
+
+impl<L: LSPLang> Backend<L> {
+  /// Build the service with notebook notifications and `$/setTrace` besides the methods of
+  /// `LanguageServer`.
+  pub fn build_service<F>(init: F) -> (LspService<Self>, ClientSocket)
+  where
+    F: FnOnce(Client) -> Self,
+  {
+    LspService::build(init)
+      .custom_method("notebookDocument/didOpen", Self::did_open_notebook)
+      .custom_method("notebookDocument/didChange", Self::did_change_notebook)
+      .custom_method("notebookDocument/didSave", Self::did_save_notebook)
+      .custom_method("notebookDocument/didClose", Self::did_close_notebook)
+      .custom_method("$/setTrace", Self::set_trace)
+      .finish()
+  }
+
+  async fn did_open_notebook(&self, params: DidOpenNotebookDocumentParams) {
+    for cell in params.cell_text_documents {
+      self.open_cell(cell).await;
+    }
+  }
+
+  async fn did_change_notebook(&self, params: DidChangeNotebookDocumentParams) {
+    let Some(cells) = params.change.cells else {
+      return;
+    };
+    if let Some(structure) = cells.structure {
+      for cell in structure.did_open.unwrap_or_default() {
+        self.open_cell(cell).await;
+      }
+      for cell in structure.did_close.unwrap_or_default() {
+        self.close_cell(cell).await;
+      }
+    }
+    for content in cells.text_content.unwrap_or_default() {
+      let params = DidChangeTextDocumentParams {
+        text_document: content.document,
+        content_changes: content.changes,
+      };
+      self.on_change(params).await;
+    }
+  }
+
+  async fn did_save_notebook(&self, _: Value) {}
+
+  async fn did_close_notebook(&self, params: DidCloseNotebookDocumentParams) {
+    for cell in params.cell_text_documents {
+      self.close_cell(cell).await;
+    }
+  }
+
+  async fn open_cell(&self, cell: TextDocumentItem) {
+    // cells without a supported language are not reported as unsupported files
+    if self
+      .infer_lang_from_document(&cell.uri, &cell.language_id)
+      .is_none()
+    {
+      return;
+    }
+    let params = DidOpenTextDocumentParams {
+      text_document: cell,
+    };
+    self.on_open(params).await;
+  }
+
+  async fn close_cell(&self, cell: TextDocumentIdentifier) {
+    let uri = cell.uri.clone();
+    self
+      .on_close(DidCloseTextDocumentParams {
+        text_document: cell,
+      })
+      .await;
+    self.client.publish_diagnostics(uri, vec![], None).await;
+  }
+
+  /// Ask the client to sync cells of all notebooks if `notebookSync` is on.
+  pub(crate) async fn register_notebook_sync(&self) {
+    if !self.options.read().unwrap().notebook_sync {
+      return;
+    }
+    let registration = Registration {
+      id: "ast-grep-notebook-sync".into(),
+      method: "notebookDocument/sync".into(),
+      register_options: Some(json!({ "notebookSelector": [{ "notebook": "*" }] })),
+    };
+    if let Err(error) = self.client.register_capability(vec![registration]).await {
+      self
+        .client
+        .log_message(
+          MessageType::WARNING,
+          format!("Failed to sync notebooks: {error}"),
+        )
+        .await;
+    }
+  }
+}
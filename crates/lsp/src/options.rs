@@ -0,0 +1,159 @@
+//! Server options configured by client's `initializationOptions` and `didChangeConfiguration`
+use ast_grep_config::Severity;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerOptions {
+  /// Report fixes that are dropped because they overlap with another rule's fix.
+  /// Such conflicts are published as informational diagnostics.
+  pub report_fix_conflicts: bool,
+  /// Send parse and scan time of each document as `ast-grep/status` notification.
+  pub report_status: bool,
+  /// Sort published diagnostics by position and rule id. On by default.
+  pub sort_diagnostics: bool,
+  /// Truncate diagnostic messages longer than this many chars. No truncation by default.
+  pub max_message_length: Option<usize>,
+  /// Languages of file extensions without leading dot, e.g. `{"mts2": "typescript"}`.
+  /// It takes precedence over the language inferred from the extension.
+  pub language_mapping: HashMap<String, String>,
+  /// Show a message once per extension when a document's language cannot be inferred.
+  /// Such documents are always logged.
+  pub report_unsupported_language: bool,
+  /// Publish findings silenced by `ast-grep-ignore` comments as hints instead of dropping them.
+  pub report_suppressed: bool,
+  /// Severity of rules by id, taking precedence over the rule's `severity` and `severityOverrides`.
+  /// `off` disables the rule.
+  pub rule_severity_overrides: HashMap<String, Severity>,
+  /// Ids of rules that are not reported.
+  pub disabled_rules: Vec<String>,
+  /// Project config used to find rules instead of `sgconfig.yml`, relative to the workspace root.
+  /// `configFile` is accepted as well.
+  #[serde(alias = "configFile")]
+  pub config_path: Option<PathBuf>,
+  /// Directories of rule files, relative to the workspace root, used besides the project config.
+  /// Rules are built from these and `inlineRules` if there is no project config.
+  pub rule_dirs: Vec<PathBuf>,
+  /// Rules used besides the project config, as rule objects or YAML text of rules.
+  pub inline_rules: Vec<Value>,
+  /// Wait this many milliseconds after a change before scanning, e.g. 300, so only the latest
+  /// version is scanned while typing. Saved documents are scanned immediately. 0 disables it.
+  pub diagnostics_debounce_ms: u64,
+  /// Documentation link of rules without `url`, e.g. `https://example.com/rules/{id}`.
+  /// `{id}` is replaced by the rule id.
+  pub rule_url_template: Option<String>,
+  /// Keep syntax trees of at most this many open documents, e.g. 50. Trees of the least
+  /// recently used documents are dropped and parsed again when needed. No limit by default.
+  pub max_cached_documents: Option<usize>,
+  /// Register notebook document sync so code cells of notebooks are scanned as documents.
+  /// The client opts in since its notebook capability cannot be read from `initialize`.
+  pub notebook_sync: bool,
+  /// Globs of paths relative to the workspace root that are never scanned,
+  /// e.g. `["**/dist/**", "**/*.min.js"]`. Workspace scans skip excluded folders.
+  pub exclude: Vec<String>,
+  /// When documents are scanned, `onType` by default. `onSave` only publishes diagnostics
+  /// of saved documents, changes just update the syntax tree.
+  pub diagnostics_trigger: DiagnosticsTrigger,
+  /// List rules applicable to the node at the cursor in code actions not limited by `only`.
+  /// On by default. Turn it off if searching rules of enclosing nodes slows down code actions.
+  pub list_applicable_rules: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiagnosticsTrigger {
+  #[default]
+  OnType,
+  OnSave,
+}
+
+impl Default for ServerOptions {
+  fn default() -> Self {
+    Self {
+      report_fix_conflicts: false,
+      report_status: false,
+      sort_diagnostics: true,
+      max_message_length: None,
+      language_mapping: HashMap::new(),
+      report_unsupported_language: false,
+      report_suppressed: false,
+      rule_severity_overrides: HashMap::new(),
+      disabled_rules: vec![],
+      config_path: None,
+      rule_dirs: vec![],
+      inline_rules: vec![],
+      diagnostics_debounce_ms: 0,
+      rule_url_template: None,
+      max_cached_documents: None,
+      notebook_sync: false,
+      exclude: vec![],
+      diagnostics_trigger: DiagnosticsTrigger::OnType,
+      list_applicable_rules: true,
+    }
+  }
+}
+
+impl ServerOptions {
+  pub fn from_value(value: Option<Value>) -> Self {
+    value
+      .and_then(|v| serde_json::from_value(v).ok())
+      .unwrap_or_default()
+  }
+
+  /// Update options with settings sent by `didChangeConfiguration`.
+  /// Settings may be nested under `astGrep` section. Options absent in settings are kept.
+  /// Invalid settings leave their options unchanged and are returned as errors.
+  pub fn merge_settings(&self, settings: Value) -> (Self, Vec<String>) {
+    let settings = match settings {
+      Value::Object(mut map) if map.contains_key("astGrep") => map.remove("astGrep").unwrap(),
+      settings => settings,
+    };
+    let Value::Object(mut settings) = settings else {
+      return (self.clone(), vec![]);
+    };
+    // an alias cannot be merged with the field serialized by its name
+    if let Some(config_file) = settings.remove("configFile") {
+      settings.insert("configPath".into(), config_file);
+    }
+    let Ok(Value::Object(mut merged)) = serde_json::to_value(self) else {
+      return (self.clone(), vec![]);
+    };
+    let mut options = self.clone();
+    let mut errors = vec![];
+    // merge settings one by one so an invalid one does not discard the others
+    for (key, value) in settings {
+      let previous = merged.insert(key.clone(), value);
+      match serde_json::from_value(Value::Object(merged.clone())) {
+        Ok(parsed) => options = parsed,
+        Err(error) => {
+          errors.push(format!("`{key}`: {error}"));
+          match previous {
+            Some(previous) => merged.insert(key, previous),
+            None => merged.remove(&key),
+          };
+        }
+      }
+    }
+    (options, errors)
+  }
+
+  /// Compile `exclude`, where `dir/**` also matches `dir` itself so the folder is not walked.
+  /// Invalid globs are ignored.
+  pub fn exclude_globs(&self) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for glob in &self.exclude {
+      let folder = glob.strip_suffix("/**");
+      for glob in std::iter::once(glob.as_str()).chain(folder) {
+        if let Ok(glob) = Glob::new(glob) {
+          builder.add(glob);
+        }
+      }
+    }
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+  }
+}
@@ -0,0 +1,561 @@
+use crate::utils::{decode_position, encode_range, find_rule_id_range, PositionEncoding};
+
+use ast_grep_config::{RuleConfig, RuleConfigError};
+use ast_grep_core::language::Language;
+use ast_grep_core::matcher::PatternNode;
+use ast_grep_core::meta_var::MetaVariable;
+use ast_grep_core::Pattern;
+use serde::Deserialize;
+use tower_lsp::lsp_types::*;
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+const TOP_LEVEL_KEYS: &[(&str, &str)] = &[
+  ("id", "Unique, descriptive identifier of the rule"),
+  ("language", "Language of the files the rule applies to"),
+  ("rule", "The rule object to find matching AST nodes"),
+  ("message", "Main message highlighting why the rule fired"),
+  ("severity", "Severity of the rule, or off"),
+  ("note", "Additional notes in markdown to explain the rule"),
+  ("fix", "Replacement string or fix config to fix the match"),
+  ("constraints", "Additional rules for meta variables"),
+  ("utils", "Utility rules that can be used in `matches`"),
+  ("transform", "Meta variables transformed from others"),
+  ("rewriters", "Rules used in `rewrite` transformation"),
+  ("labels", "Messages of meta variables in diagnostic"),
+  ("files", "Glob patterns of files the rule applies to"),
+  ("ignores", "Glob patterns of files the rule skips"),
+  ("url", "Documentation link of the rule"),
+  ("metadata", "Extra information of the rule"),
+];
+
+const RULE_KEYS: &[(&str, &str)] = &[
+  ("pattern", "Match nodes by code pattern"),
+  ("kind", "Match nodes by tree-sitter node kind"),
+  ("regex", "Match nodes whose text matches the regex"),
+  ("nthChild", "Match nodes by their index among siblings"),
+  ("range", "Match nodes by their position in the source"),
+  ("inside", "Match nodes inside a node of the rule"),
+  ("has", "Match nodes having a node of the rule"),
+  ("precedes", "Match nodes before a node of the rule"),
+  ("follows", "Match nodes after a node of the rule"),
+  ("all", "Match nodes matching all rules in the list"),
+  ("any", "Match nodes matching any rule in the list"),
+  ("not", "Match nodes not matching the rule"),
+  ("matches", "Match nodes by a utility rule id"),
+  ("stopBy", "When to stop searching in relational rules"),
+  ("field", "Field name of the node in relational rules"),
+];
+
+const SEVERITIES: &[&str] = &["hint", "info", "warning", "error", "off"];
+
+/// Completions of a rule file at the position. Keys are completed at the start of a line,
+/// `kind` with node kinds of the rule language, and `matches` with utility rule ids.
+pub fn rule_completions<L: Language + FromStr>(
+  text: &str,
+  position: Position,
+  util_ids: Vec<String>,
+  encoding: PositionEncoding,
+) -> Vec<CompletionItem> {
+  let position = decode_position(text, position, encoding);
+  let line = text.lines().nth(position.line as usize).unwrap_or_default();
+  let mut column = (position.character as usize).min(line.len());
+  while !line.is_char_boundary(column) {
+    column -= 1;
+  }
+  let prefix = &line[..column];
+  let rest = prefix.trim_start();
+  let indent = prefix.len() - rest.len();
+  let item = rest.strip_prefix('-').map(str::trim_start);
+  if let Some((key, _)) = item.unwrap_or(rest).split_once(':') {
+    return match key.trim() {
+      "kind" => {
+        let kinds = rule_language::<L>(text).map(|l| node_kinds(&l));
+        to_items(kinds.unwrap_or_default(), CompletionItemKind::ENUM_MEMBER)
+      }
+      "matches" => {
+        let mut ids = local_util_ids(text);
+        ids.extend(util_ids);
+        to_items(ids, CompletionItemKind::REFERENCE)
+      }
+      "severity" => to_items(SEVERITIES.iter(), CompletionItemKind::ENUM_MEMBER),
+      _ => vec![],
+    };
+  }
+  let keys = if indent == 0 && item.is_none() {
+    TOP_LEVEL_KEYS
+  } else {
+    RULE_KEYS
+  };
+  keys
+    .iter()
+    .map(|(key, detail)| CompletionItem {
+      label: key.to_string(),
+      kind: Some(CompletionItemKind::PROPERTY),
+      detail: Some(detail.to_string()),
+      insert_text: Some(format!("{key}: ")),
+      ..Default::default()
+    })
+    .collect()
+}
+
+/// Show how the `pattern` value on the hovered line is parsed, with node kinds and
+/// meta variables, so rule authors can check the pattern without running it.
+pub fn pattern_hover<L: Language + FromStr>(text: &str, position: Position) -> Option<Hover> {
+  let src = pattern_at_line(text, position.line as usize)?;
+  let lang = rule_language::<L>(text)?;
+  let value = match Pattern::try_new(&src, lang.clone()) {
+    Ok(pattern) => {
+      let mut dumped = String::new();
+      // multi-node patterns, e.g. consecutive statements, match a run of siblings
+      for node in std::iter::once(&pattern.node).chain(pattern.following()) {
+        dump_pattern_node(node, &lang, 0, &mut dumped).expect("should write string");
+      }
+      format!("**Pattern AST**\n```\n{dumped}```")
+    }
+    Err(e) => format!("**Invalid pattern**: {e}"),
+  };
+  Some(Hover {
+    contents: HoverContents::Markup(MarkupContent {
+      kind: MarkupKind::Markdown,
+      value,
+    }),
+    range: None,
+  })
+}
+
+/// The `pattern` value on the line, either inline or as a block scalar on following lines.
+fn pattern_at_line(text: &str, line: usize) -> Option<String> {
+  let mut lines = text.lines().skip(line);
+  let content = lines.next()?;
+  let rest = content.trim_start();
+  let indent = content.len() - rest.len();
+  let rest = rest.strip_prefix('-').map_or(rest, str::trim_start);
+  let value = rest.strip_prefix("pattern:")?.trim();
+  if !value.is_empty() && !value.starts_with(['|', '>']) {
+    let unquoted = value
+      .strip_prefix('"')
+      .and_then(|v| v.strip_suffix('"'))
+      .or_else(|| value.strip_prefix('\'')?.strip_suffix('\''));
+    return Some(unquoted.unwrap_or(value).to_string());
+  }
+  let block: Vec<_> = lines
+    .take_while(|l| l.trim().is_empty() || l.len() - l.trim_start().len() > indent)
+    .collect();
+  let dedent = block
+    .iter()
+    .filter(|l| !l.trim().is_empty())
+    .map(|l| l.len() - l.trim_start().len())
+    .min()?;
+  let block: Vec<_> = block
+    .iter()
+    .map(|l| l.get(dedent..).unwrap_or(""))
+    .collect();
+  Some(block.join("\n").trim_end().to_string())
+}
+
+fn dump_pattern_node<L: Language>(
+  node: &PatternNode,
+  lang: &L,
+  depth: usize,
+  out: &mut String,
+) -> std::fmt::Result {
+  let indent = "  ".repeat(depth);
+  let ts_lang = lang.get_ts_language();
+  let kind = |id| ts_lang.node_kind_for_id(id).unwrap_or_default().to_string();
+  let meta_char = lang.meta_var_char();
+  match node {
+    PatternNode::MetaVar { meta_var } => {
+      let name = match meta_var {
+        MetaVariable::Capture(id, _) => format!("{meta_char}{id}"),
+        MetaVariable::Dropped(_) => format!("{meta_char}_"),
+        MetaVariable::Multiple => meta_char.to_string().repeat(3),
+        MetaVariable::MultiCapture(id) => format!("{}{id}", meta_char.to_string().repeat(3)),
+      };
+      writeln!(out, "{indent}{name} (meta variable)")
+    }
+    // unnamed nodes like punctuation are omitted
+    PatternNode::Terminal { is_named, .. } if !is_named => Ok(()),
+    PatternNode::Terminal { text, kind_id, .. } => {
+      writeln!(out, "{indent}{} `{text}`", kind(*kind_id))
+    }
+    PatternNode::Internal { kind_id, children } => {
+      writeln!(out, "{indent}{}", kind(*kind_id))?;
+      for child in children {
+        dump_pattern_node(child, lang, depth + 1, out)?;
+      }
+      Ok(())
+    }
+  }
+}
+
+fn to_items<S: ToString>(
+  labels: impl IntoIterator<Item = S>,
+  kind: CompletionItemKind,
+) -> Vec<CompletionItem> {
+  labels
+    .into_iter()
+    .map(|label| CompletionItem {
+      label: label.to_string(),
+      kind: Some(kind),
+      ..Default::default()
+    })
+    .collect()
+}
+
+fn rule_language<L: FromStr>(text: &str) -> Option<L> {
+  let lang = text.lines().find_map(|l| l.strip_prefix("language:"))?;
+  L::from_str(lang.trim()).ok()
+}
+
+/// Named node kinds of the language, which can be used in `kind` rule.
+fn node_kinds<L: Language>(lang: &L) -> Vec<String> {
+  let ts_lang = lang.get_ts_language();
+  let mut kinds: Vec<_> = (0..ts_lang.node_kind_count())
+    .filter(|&id| ts_lang.node_kind_is_named(id) && ts_lang.node_kind_is_visible(id))
+    .filter_map(|id| Some(ts_lang.node_kind_for_id(id)?.to_string()))
+    .collect();
+  kinds.sort();
+  kinds.dedup();
+  kinds
+}
+
+/// Keys of the top level `utils` in the rule file.
+fn local_util_ids(text: &str) -> Vec<String> {
+  let lines = text.lines().skip_while(|l| !l.starts_with("utils:"));
+  let mut ids = vec![];
+  let mut child_indent = None;
+  for line in lines.skip(1) {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+    let indent = line.len() - trimmed.len();
+    if indent == 0 {
+      break;
+    }
+    if indent != *child_indent.get_or_insert(indent) {
+      continue;
+    }
+    if let Some((id, _)) = trimmed.split_once(':') {
+      ids.push(id.trim().to_string());
+    }
+  }
+  ids
+}
+
+/// Test cases of a rule in a rule test file, verified by `ast-grep test`.
+#[derive(Deserialize)]
+struct RuleTest {
+  id: String,
+  #[serde(default)]
+  valid: Vec<String>,
+  #[serde(default)]
+  invalid: Vec<String>,
+}
+
+/// Verify cases in the text of a rule test file with rules found by `get_rule`.
+/// Returns diagnostics of failed cases and the number of passed cases.
+pub fn verify_rule_tests<'r, L: Language + 'r>(
+  text: &str,
+  get_rule: impl Fn(&str) -> Option<&'r RuleConfig<L>>,
+  encoding: PositionEncoding,
+) -> (Vec<Diagnostic>, usize) {
+  let mut diagnostics = vec![];
+  let mut passed = 0;
+  for document in serde_yaml::Deserializer::from_str(text) {
+    let test = match RuleTest::deserialize(document) {
+      Ok(test) => test,
+      Err(e) => {
+        let position = e.location().map_or(Position::new(0, 0), |l| {
+          Position::new(l.line().saturating_sub(1) as u32, 0)
+        });
+        let message = format!("Cannot parse rule test: {e}");
+        diagnostics.push(error_diagnostic(Range::new(position, position), message));
+        continue;
+      }
+    };
+    let Some(rule) = get_rule(&test.id) else {
+      let range = find_rule_id_range(text, &test.id).unwrap_or_default();
+      let message = format!("Rule `{}` is not found.", test.id);
+      diagnostics.push(error_diagnostic(range, message));
+      continue;
+    };
+    let is_reported = |case: &str| {
+      rule
+        .language
+        .ast_grep(case)
+        .root()
+        .find(&rule.matcher)
+        .is_some()
+    };
+    for case in &test.valid {
+      if is_reported(case) {
+        let message = format!("Valid case is reported by `{}`.", test.id);
+        diagnostics.push(error_diagnostic(find_case_range(text, case), message));
+      } else {
+        passed += 1;
+      }
+    }
+    for case in &test.invalid {
+      if is_reported(case) {
+        passed += 1;
+      } else {
+        let message = format!("Invalid case is not reported by `{}`.", test.id);
+        diagnostics.push(error_diagnostic(find_case_range(text, case), message));
+      }
+    }
+  }
+  encode_diagnostics(text, &mut diagnostics, encoding);
+  (diagnostics, passed)
+}
+
+/// Ranges in rule files are found with byte columns and converted before sent to client.
+fn encode_diagnostics(text: &str, diagnostics: &mut [Diagnostic], encoding: PositionEncoding) {
+  for diagnostic in diagnostics {
+    diagnostic.range = encode_range(text, diagnostic.range, encoding);
+  }
+}
+
+fn error_diagnostic(range: Range, message: String) -> Diagnostic {
+  Diagnostic {
+    range,
+    severity: Some(DiagnosticSeverity::ERROR),
+    source: Some(String::from("ast-grep")),
+    message,
+    ..Default::default()
+  }
+}
+
+/// Diagnostics of rules in the text of a rule file which `parse` fails to parse.
+/// Documents are parsed one by one so that every malformed rule is reported.
+pub fn rule_file_diagnostics<L: Language>(
+  text: &str,
+  parse: impl Fn(&str) -> Result<Vec<RuleConfig<L>>, RuleConfigError>,
+  encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+  let lines: Vec<_> = text.lines().collect();
+  let mut diagnostics = vec![];
+  for (start, end) in document_spans(&lines) {
+    let document = &lines[start..end];
+    let is_empty = document.iter().all(|line| {
+      let line = line.trim();
+      line.is_empty() || line.starts_with('#') || line.starts_with("---")
+    });
+    if is_empty {
+      continue;
+    }
+    // leading lines are kept blank so error locations are lines of the whole file
+    let yaml = "\n".repeat(start) + &document.join("\n");
+    let Err(error) = parse(&yaml) else {
+      continue;
+    };
+    let location = match &error {
+      RuleConfigError::Yaml(e) => e.location(),
+      _ => None,
+    };
+    let range = match location {
+      Some(l) => {
+        let line = l.line().saturating_sub(1);
+        // YAML error columns count chars
+        let chars = l.column().saturating_sub(1);
+        let content = lines.get(line).copied().unwrap_or_default();
+        let column = content
+          .char_indices()
+          .nth(chars)
+          .map_or(content.len(), |(i, _)| i);
+        line_range(&lines, line, column)
+      }
+      None => document_id_range(document, start),
+    };
+    diagnostics.push(error_diagnostic(range, error_message(&error)));
+  }
+  encode_diagnostics(text, &mut diagnostics, encoding);
+  diagnostics
+}
+
+/// Line spans of YAML documents separated by `---`.
+fn document_spans(lines: &[&str]) -> Vec<(usize, usize)> {
+  let mut starts: Vec<_> = lines
+    .iter()
+    .enumerate()
+    .filter(|(i, line)| *i > 0 && is_document_start(line))
+    .map(|(i, _)| i)
+    .collect();
+  starts.insert(0, 0);
+  let ends = starts.iter().skip(1).copied().chain([lines.len()]);
+  starts.iter().copied().zip(ends).collect()
+}
+
+fn is_document_start(line: &str) -> bool {
+  line.strip_prefix("---").map_or(false, |rest| {
+    rest.is_empty() || rest.starts_with(char::is_whitespace)
+  })
+}
+
+/// Range from the column to the end of the line.
+fn line_range(lines: &[&str], line: usize, column: usize) -> Range {
+  let end = lines
+    .get(line)
+    .map_or(column, |l| l.trim_end().len().max(column));
+  Range::new(
+    Position::new(line as u32, column as u32),
+    Position::new(line as u32, end as u32),
+  )
+}
+
+/// Range of the `id` value of a document, or its first line if the id is not found.
+fn document_id_range(document: &[&str], start: usize) -> Range {
+  let id_line = document.iter().position(|line| line.starts_with("id:"));
+  let Some(line) = id_line.or_else(|| document.iter().position(|l| !l.trim().is_empty())) else {
+    return Range::default();
+  };
+  let content = document[line];
+  let column = content
+    .strip_prefix("id:")
+    .map_or(0, |rest| content.len() - rest.trim_start().len());
+  let range = line_range(document, line, column);
+  let line = (start + line) as u32;
+  Range::new(
+    Position::new(line, range.start.character),
+    Position::new(line, range.end.character),
+  )
+}
+
+/// Message of the error with its sources, which explain the generic parse errors.
+pub fn error_message(error: &dyn std::error::Error) -> String {
+  let mut messages = vec![error.to_string()];
+  let mut source = error.source();
+  while let Some(e) = source {
+    messages.push(e.to_string());
+    source = e.source();
+  }
+  let messages: Vec<_> = messages.iter().map(|m| m.trim_end_matches('.')).collect();
+  format!("{}.", messages.join(": "))
+}
+
+/// Range of the line where a case starts. Block scalars are dedented by YAML,
+/// so the first line of the case is searched without indentation.
+fn find_case_range(text: &str, case: &str) -> Range {
+  let Some(first) = case.lines().map(str::trim).find(|l| !l.is_empty()) else {
+    return Range::default();
+  };
+  let found = text.lines().enumerate().find_map(|(line, content)| {
+    let start = content.find(first)?;
+    let start = Position::new(line as u32, start as u32);
+    let end = Position::new(line as u32, content.trim_end().len() as u32);
+    Some(Range::new(start, end))
+  });
+  found.unwrap_or_default()
+}
+
+/// Outline of a rule file or a rule test file. Every document is a symbol of its id,
+/// with utility rules and test cases as children.
+pub fn document_symbols(text: &str, encoding: PositionEncoding) -> Vec<DocumentSymbol> {
+  let lines: Vec<_> = text.lines().collect();
+  let mut symbols = vec![];
+  for (start, end) in document_spans(&lines) {
+    let document = &lines[start..end];
+    let Some(id_line) = document.iter().position(|l| l.starts_with("id:")) else {
+      continue;
+    };
+    let id = document[id_line]["id:".len()..].trim();
+    let id = id.trim_matches(|c| c == '"' || c == '\'');
+    if id.is_empty() {
+      continue;
+    }
+    let column = document[id_line].find(id).unwrap_or(0);
+    let selection = line_range(&lines, start + id_line, column);
+    let last = end - 1;
+    let range = Range::new(
+      Position::new(start as u32, 0),
+      Position::new(last as u32, lines[last].len() as u32),
+    );
+    let mut children = vec![];
+    for (line, util) in section_entries(document, "utils") {
+      let util = util.split_once(':').map_or(util, |(key, _)| key).trim();
+      let range = line_range(&lines, start + line, document[line].find(util).unwrap_or(0));
+      children.push(symbol(util, "util", SymbolKind::FUNCTION, range));
+    }
+    for key in ["valid", "invalid"] {
+      for (line, case) in section_entries(document, key) {
+        let Some(case) = case.strip_prefix('-') else {
+          continue;
+        };
+        let name = case_name(case.trim(), &document[line + 1..]);
+        let range = line_range(&lines, start + line, document[line].find('-').unwrap_or(0));
+        children.push(symbol(&name, key, SymbolKind::STRING, range));
+      }
+    }
+    let mut rule = symbol(id, "rule", SymbolKind::CLASS, range);
+    rule.selection_range = selection;
+    rule.children = Some(children);
+    symbols.push(rule);
+  }
+  for symbol in symbols.iter_mut() {
+    encode_symbol(text, symbol, encoding);
+  }
+  symbols
+}
+
+fn encode_symbol(text: &str, symbol: &mut DocumentSymbol, encoding: PositionEncoding) {
+  symbol.range = encode_range(text, symbol.range, encoding);
+  symbol.selection_range = encode_range(text, symbol.selection_range, encoding);
+  for child in symbol.children.iter_mut().flatten() {
+    encode_symbol(text, child, encoding);
+  }
+}
+
+#[allow(deprecated)]
+fn symbol(name: &str, detail: &str, kind: SymbolKind, range: Range) -> DocumentSymbol {
+  DocumentSymbol {
+    name: name.to_string(),
+    detail: Some(detail.to_string()),
+    kind,
+    tags: None,
+    deprecated: None,
+    range,
+    selection_range: range,
+    children: None,
+  }
+}
+
+/// Lines of direct entries of the top level `key`, with the entry text without indentation.
+/// Sequence entries may be at the same indentation as the key.
+fn section_entries<'a>(document: &[&'a str], key: &str) -> Vec<(usize, &'a str)> {
+  let prefix = format!("{key}:");
+  let Some(start) = document.iter().position(|l| l.starts_with(&prefix)) else {
+    return vec![];
+  };
+  let mut entries = vec![];
+  let mut child_indent = None;
+  for (i, line) in document.iter().enumerate().skip(start + 1) {
+    let trimmed = line.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+      continue;
+    }
+    let indent = line.len() - trimmed.len();
+    if indent == 0 && !trimmed.starts_with('-') {
+      break;
+    }
+    if indent == *child_indent.get_or_insert(indent) {
+      entries.push((i, trimmed));
+    }
+  }
+  entries
+}
+
+/// First line of a test case, which is on the next line if the case is a block scalar.
+fn case_name(case: &str, rest: &[&str]) -> String {
+  let is_block = case.is_empty() || case.starts_with('|') || case.starts_with('>');
+  let first = if is_block {
+    rest.iter().map(|l| l.trim()).find(|l| !l.is_empty())
+  } else {
+    Some(case.trim_matches(|c| c == '"' || c == '\''))
+  };
+  match first {
+    Some(first) if !first.is_empty() => first.to_string(),
+    _ => "case".to_string(),
+  }
+}
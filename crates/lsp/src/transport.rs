@@ -0,0 +1,133 @@
+//! Serve the language server over TCP or WebSocket besides stdio.
+//! Both transports listen on the address and serve the first client that connects.
+use crate::{Backend, LSPLang};
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{duplex, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{accept_async_with_config, WebSocketStream};
+use tower_lsp::{Client, Server};
+
+use std::io::{Error, ErrorKind, Result};
+
+/// Serve the language server to a client connecting to `addr` over TCP.
+/// Messages are framed by `Content-Length` headers as in stdio.
+pub async fn serve_tcp<L, F>(addr: impl ToSocketAddrs, init: F) -> Result<()>
+where
+  L: LSPLang,
+  F: FnOnce(Client) -> Backend<L>,
+{
+  let listener = TcpListener::bind(addr).await?;
+  let (stream, _) = listener.accept().await?;
+  let (read, write) = stream.into_split();
+  let (service, socket) = Backend::build_service(init);
+  Server::new(read, write, socket).serve(service).await;
+  Ok(())
+}
+
+/// Serve the language server to a client connecting to `addr` over WebSocket.
+/// Every text or binary message is one JSON-RPC message without `Content-Length` headers.
+pub async fn serve_ws<L, F>(addr: impl ToSocketAddrs, init: F) -> Result<()>
+where
+  L: LSPLang,
+  F: FnOnce(Client) -> Backend<L>,
+{
+  let listener = TcpListener::bind(addr).await?;
+  let (stream, _) = listener.accept().await?;
+  let config = WebSocketConfig {
+    max_message_size: Some(MAX_MESSAGE_SIZE),
+    max_frame_size: Some(MAX_MESSAGE_SIZE),
+    ..Default::default()
+  };
+  let socket = accept_async_with_config(stream, Some(config))
+    .await
+    .map_err(into_io_error)?;
+  let (write, read) = socket.split();
+  // the server reads and writes messages with headers, bridged to websocket messages
+  let (server_in, bridge_in) = duplex(BUFFER_SIZE);
+  let (server_out, bridge_out) = duplex(BUFFER_SIZE);
+  let incoming = tokio::spawn(forward_incoming(read, bridge_in));
+  let outgoing = tokio::spawn(forward_outgoing(bridge_out, write));
+  let (service, socket) = Backend::build_service(init);
+  Server::new(server_in, server_out, socket)
+    .serve(service)
+    .await;
+  // the server exits before the client closes the connection
+  incoming.abort();
+  outgoing
+    .await
+    .map_err(|e| Error::new(ErrorKind::Other, e))?
+}
+
+const BUFFER_SIZE: usize = 64 * 1024;
+/// Larger messages or frames close the connection instead of being buffered.
+const MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+type WebSocket = WebSocketStream<TcpStream>;
+
+fn into_io_error(error: WsError) -> Error {
+  match error {
+    WsError::Io(error) => error,
+    error => Error::new(ErrorKind::InvalidData, error),
+  }
+}
+
+/// Forward websocket messages to the server with `Content-Length` headers.
+/// Pings and close frames are answered by the websocket stream.
+async fn forward_incoming(
+  mut read: SplitStream<WebSocket>,
+  mut server: DuplexStream,
+) -> Result<()> {
+  // dropping `server` at return ends the server's input
+  while let Some(message) = read.next().await {
+    let payload = match message.map_err(into_io_error)? {
+      Message::Text(text) => text.into_bytes(),
+      Message::Binary(data) => data,
+      Message::Close(_) => break,
+      _ => continue,
+    };
+    let header = format!("Content-Length: {}\r\n\r\n", payload.len());
+    server.write_all(header.as_bytes()).await?;
+    server.write_all(&payload).await?;
+  }
+  Ok(())
+}
+
+/// Forward server messages to the client as text messages without headers.
+async fn forward_outgoing(
+  server: DuplexStream,
+  mut write: SplitSink<WebSocket, Message>,
+) -> Result<()> {
+  let mut server = BufReader::new(server);
+  loop {
+    let mut length = None;
+    loop {
+      let mut line = String::new();
+      if server.read_line(&mut line).await? == 0 {
+        // the server has exited, the client may have closed the connection already
+        let _ = write.close().await;
+        return Ok(());
+      }
+      let line = line.trim_end();
+      if line.is_empty() {
+        break;
+      }
+      if let Some(value) = line.strip_prefix("Content-Length:") {
+        length = value.trim().parse().ok();
+      }
+    }
+    let Some(length) = length else {
+      return Err(Error::new(ErrorKind::InvalidData, "missing Content-Length"));
+    };
+    let mut body = vec![0; length];
+    server.read_exact(&mut body).await?;
+    let text = String::from_utf8(body).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    write
+      .send(Message::Text(text))
+      .await
+      .map_err(into_io_error)?;
+  }
+}
@@ -1,17 +1,28 @@
 //! Provides utility to convert ast-grep data types to lsp data types
 use ast_grep_config::RuleConfig;
 use ast_grep_config::Severity;
-use ast_grep_core::{language::Language, Doc, Node, NodeMatch, StrDoc};
+use ast_grep_core::source::Edit;
+use ast_grep_core::{language::Language, AstGrep, Doc, Node, NodeMatch, Pattern, StrDoc};
 
 use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::notification::Notification;
 use tower_lsp::lsp_types::*;
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Serialize, Deserialize)]
 pub struct RewriteData {
   pub fixed: String,
-  // maybe we should have fixed range
+  /// range replaced by the fix if it differs from the diagnostic range, e.g. fix with expandEnd
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub range: Option<Range>,
+  /// the fix is marked `unsafe` by the rule and needs confirmation
+  #[serde(default, rename = "unsafe", skip_serializing_if = "std::ops::Not::not")]
+  pub is_unsafe: bool,
 }
 
 impl RewriteData {
@@ -22,21 +33,317 @@ impl RewriteData {
   fn from_node_match<L: Language>(
     node_match: &NodeMatch<StrDoc<L>>,
     rule: &RuleConfig<L>,
+    encoding: PositionEncoding,
   ) -> Option<Self> {
     let fixer = rule.matcher.fixer.as_ref()?;
-    let edit = node_match.replace_by(fixer);
+    let edit = node_match.make_edit(&rule.matcher, fixer);
+    let start = edit.position;
+    let end = start + edit.deleted_length;
+    let range = if (start..end) == node_match.range() {
+      None
+    } else {
+      let text = node_match.get_node().root().get_text();
+      Some(Range::new(
+        offset_to_position(text, start, encoding),
+        offset_to_position(text, end, encoding),
+      ))
+    };
     let rewrite = String::from_utf8(edit.inserted_text).ok()?;
-    Some(Self { fixed: rewrite })
+    Some(Self {
+      fixed: rewrite,
+      range,
+      is_unsafe: fixer.is_unsafe(),
+    })
   }
 }
 
+/// Data attached to each diagnostic. Rewrite fields are flattened so `RewriteData` can be read back.
+#[derive(Serialize)]
+struct DiagnosticData {
+  /// identity of the finding which is stable when edits elsewhere shift the diagnostic range
+  fingerprint: String,
+  #[serde(flatten)]
+  rewrite: Option<RewriteData>,
+}
+
+/// Rule id plus a hash of the matched text. Identical matches of one rule share a fingerprint.
+/// FNV-1a is used instead of `DefaultHasher` whose output may change across Rust releases.
+fn compute_fingerprint(rule_id: &str, text: &str) -> String {
+  format!("{rule_id}:{:016x}", fnv_hash(text))
+}
+
+fn fnv_hash(text: &str) -> u64 {
+  text.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+    (hash ^ b as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+/// `resultId` of pulled diagnostics. Same diagnostics have the same id across pulls.
+pub fn diagnostics_result_id(diagnostics: &[Diagnostic]) -> String {
+  let json = serde_json::to_string(diagnostics).unwrap_or_default();
+  format!("{:016x}", fnv_hash(&json))
+}
+
+/// Arguments of `ast-grep.diagnoseText`. Either `uri` or `languageId` is needed to infer language.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnoseTextParams {
+  pub text: String,
+  pub uri: Option<Url>,
+  pub language_id: Option<String>,
+}
+
+/// Arguments of `ast-grep.applyRuleFixes`, sent by the fix-all code lens of a rule.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyRuleFixesParams {
+  pub uri: Url,
+  pub rule_id: String,
+}
+
+/// Optional fields of the `ast-grep.applyAllFixes` argument besides the text document.
+/// Only fixes of `ruleIds` inside `range` are applied if they are given.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FixFilter {
+  pub rule_ids: Option<Vec<String>>,
+  pub range: Option<Range>,
+  /// drop fixes marked `unsafe`, for edits applied without user confirmation
+  #[serde(skip)]
+  pub skip_unsafe: bool,
+}
+
+impl FixFilter {
+  /// Fixes applied without confirmation, e.g. on save.
+  pub fn safe() -> Self {
+    Self {
+      skip_unsafe: true,
+      ..Default::default()
+    }
+  }
+
+  pub fn matches(&self, diagnostic: &Diagnostic) -> bool {
+    if self.skip_unsafe && is_unsafe_fix(diagnostic) {
+      return false;
+    }
+    if let Some(rule_ids) = &self.rule_ids {
+      let id = get_rule_id(diagnostic).unwrap_or_default();
+      if !rule_ids.iter().any(|rule_id| rule_id == id) {
+        return false;
+      }
+    }
+    let Some(range) = &self.range else {
+      return true;
+    };
+    // the fix may replace a different range than the diagnostic, e.g. with expandEnd
+    let rewrite = diagnostic.data.clone().and_then(RewriteData::from_value);
+    let fix_range = rewrite.and_then(|r| r.range).unwrap_or(diagnostic.range);
+    range.start <= fix_range.start && fix_range.end <= range.end
+  }
+}
+
+/// Arguments of `ast-grep.search`. Only documents of `language` are searched if it is given.
+/// Unopened files in the workspace are also searched if `includeWorkspace` is true.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchParams {
+  pub pattern: String,
+  pub language: Option<String>,
+  #[serde(default)]
+  pub include_workspace: bool,
+}
+
+/// Locations of nodes matching the pattern, which is parsed in the language of the document.
+/// Documents in which the pattern is invalid have no matches.
+pub fn search_pattern<L: Language>(
+  uri: &Url,
+  root: &AstGrep<StrDoc<L>>,
+  pattern: &str,
+  encoding: PositionEncoding,
+) -> Vec<Location> {
+  let Ok(pattern) = Pattern::try_new(pattern, root.lang().clone()) else {
+    return vec![];
+  };
+  root
+    .root()
+    .find_all(pattern)
+    .map(|node_match| {
+      let range = convert_match_to_range(&node_match, encoding);
+      Location::new(uri.clone(), range)
+    })
+    .collect()
+}
+
+/// Arguments of `ast-grep.inspectNode`: the document and the selected range to inspect.
+#[derive(Deserialize)]
+pub struct InspectNodeParams {
+  pub uri: Url,
+  pub range: Range,
+}
+
+/// Syntax node dumped by `ast-grep.inspectNode`. Only named children are kept,
+/// which are the nodes `kind` can match.
+#[derive(Serialize)]
+pub struct NodeDump {
+  pub kind: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub field: Option<String>,
+  pub range: Range,
+  pub children: Vec<NodeDump>,
+}
+
+/// Named nodes covered by the range, or the smallest named node containing the range start
+/// if no node is fully covered, e.g. the range is empty.
+pub fn inspect_nodes<L: Language>(
+  root: &AstGrep<StrDoc<L>>,
+  range: Range,
+  encoding: PositionEncoding,
+) -> Vec<Node<StrDoc<L>>> {
+  let start = to_byte_position(root.source(), range.start, encoding);
+  let end = to_byte_position(root.source(), range.end, encoding);
+  let mut nodes = vec![];
+  collect_covered(root.root(), start, end, &mut nodes);
+  if nodes.is_empty() {
+    nodes.extend(find_node_at(root, range.start, encoding));
+  }
+  nodes
+}
+
+fn collect_covered<'r, D: Doc>(
+  node: Node<'r, D>,
+  start: (usize, usize),
+  end: (usize, usize),
+  nodes: &mut Vec<Node<'r, D>>,
+) {
+  if node.end_pos() <= start || end <= node.start_pos() {
+    return;
+  }
+  if node.is_named() && start <= node.start_pos() && node.end_pos() <= end {
+    nodes.push(node);
+    return;
+  }
+  for child in node.children() {
+    collect_covered(child, start, end, nodes);
+  }
+}
+
+pub fn dump_node<L: Language>(
+  node: &Node<StrDoc<L>>,
+  field: Option<String>,
+  encoding: PositionEncoding,
+) -> NodeDump {
+  // field names are only known by the cursor, in the same order as children
+  let mut fields = vec![];
+  let mut cursor = node.get_ts_node().walk();
+  if cursor.goto_first_child() {
+    loop {
+      fields.push(cursor.field_name().map(|name| name.to_string()));
+      if !cursor.goto_next_sibling() {
+        break;
+      }
+    }
+  }
+  let children = node
+    .children()
+    .zip(fields)
+    .filter(|(child, _)| child.is_named())
+    .map(|(child, field)| dump_node(&child, field, encoding))
+    .collect();
+  NodeDump {
+    kind: node.kind().to_string(),
+    field,
+    range: convert_node_to_range(node, encoding),
+    children,
+  }
+}
+
+/// Checked by scans in blocking threads to stop the work of a cancelled request.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::Relaxed);
+  }
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
+  }
+  /// tower-lsp drops the future of a request on `$/cancelRequest`,
+  /// so the guard held by the future cancels the token.
+  pub fn drop_guard(&self) -> CancelGuard {
+    CancelGuard(self.clone())
+  }
+}
+
+pub struct CancelGuard(CancelToken);
+
+impl Drop for CancelGuard {
+  fn drop(&mut self) {
+    self.0.cancel();
+  }
+}
+
+/// Time spent on the last parse and scan of a document, sent as `ast-grep/status`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStatus {
+  pub uri: Url,
+  pub version: i32,
+  /// parse time in milliseconds
+  pub parse_time: f64,
+  /// scan time in milliseconds
+  pub scan_time: f64,
+}
+
+impl DocumentStatus {
+  pub fn new(uri: Url, version: i32, parse_time: Duration, scan_time: Duration) -> Self {
+    Self {
+      uri,
+      version,
+      parse_time: parse_time.as_secs_f64() * 1000.0,
+      scan_time: scan_time.as_secs_f64() * 1000.0,
+    }
+  }
+}
+
+pub enum DocumentStatusNotification {}
+
+impl Notification for DocumentStatusNotification {
+  type Params = DocumentStatus;
+  const METHOD: &'static str = "ast-grep/status";
+}
+
+/// Partial result of a request with `partialResultToken`, sent as `$/progress`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PartialResult<T> {
+  pub token: ProgressToken,
+  pub value: T,
+}
+
+/// Reports of some files in `workspace/diagnostic`, streamed before the response.
+pub enum WorkspaceDiagnosticProgress {}
+
+impl Notification for WorkspaceDiagnosticProgress {
+  type Params = PartialResult<WorkspaceDiagnosticReportPartialResult>;
+  const METHOD: &'static str = "$/progress";
+}
+
+/// Code actions of some diagnostics in `textDocument/codeAction`, streamed before the response.
+pub enum CodeActionProgress {}
+
+impl Notification for CodeActionProgress {
+  type Params = PartialResult<CodeActionResponse>;
+  const METHOD: &'static str = "$/progress";
+}
+
 pub fn diagnostic_to_code_action(
   text_doc: &TextDocumentIdentifier,
   diagnostic: Diagnostic,
 ) -> Option<CodeAction> {
   let rewrite_data = RewriteData::from_value(diagnostic.data?)?;
   let mut changes = HashMap::new();
-  let text_edit = TextEdit::new(diagnostic.range, rewrite_data.fixed);
+  let range = rewrite_data.range.unwrap_or(diagnostic.range);
+  let text_edit = TextEdit::new(range, rewrite_data.fixed);
   changes.insert(text_doc.uri.clone(), vec![text_edit]);
 
   let edit = WorkspaceEdit::new(changes);
@@ -56,34 +363,726 @@ pub fn diagnostic_to_code_action(
   Some(action)
 }
 
-fn convert_node_to_range<D: Doc>(node_match: &Node<D>) -> Range {
-  let (start_row, start_col) = node_match.start_pos();
-  let (end_row, end_col) = node_match.end_pos();
+/// Comment delimiters tried in order by `comment_syntax`.
+/// `--` goes before `#` so SQL gets its standard comment.
+const COMMENT_SYNTAXES: [(&str, &str); 5] = [
+  ("// ", ""),
+  ("-- ", ""),
+  ("# ", ""),
+  ("/* ", " */"),
+  ("<!-- ", " -->"),
+];
+
+/// Comments of another language accepted by some grammars, e.g. `//` in CSS.
+const FOREIGN_COMMENT_KINDS: [&str; 2] = ["js_comment", "html_comment"];
+
+/// Comment delimiters of the document language, the first ones parsed as a comment in it.
+/// Languages without such comments, e.g. PHP outside `<?php`, use the extension table.
+pub fn comment_syntax<L: Language>(uri: &Url, lang: &L) -> (&'static str, &'static str) {
+  let is_comment = |(start, end): &(&str, &str)| {
+    let source = format!("{start}ast-grep-ignore{end}");
+    let root = lang.ast_grep(&source);
+    let root = root.root();
+    let mut children = root.children();
+    match (children.next(), children.next()) {
+      (Some(node), None) => {
+        let kind = node.kind();
+        let is_comment = kind.contains("comment") && !FOREIGN_COMMENT_KINDS.contains(&&*kind);
+        is_comment && node.text() == source
+      }
+      _ => false,
+    }
+  };
+  let found = COMMENT_SYNTAXES.into_iter().find(is_comment);
+  found.unwrap_or_else(|| comment_syntax_of_extension(uri))
+}
+
+/// Comment delimiters of the document language inferred from extension, `//` by default.
+fn comment_syntax_of_extension(uri: &Url) -> (&'static str, &'static str) {
+  let path = Path::new(uri.path());
+  let ext = path
+    .extension()
+    .and_then(|e| e.to_str())
+    .unwrap_or_default();
+  match ext {
+    "py" | "pyi" | "rb" | "sh" | "bash" | "zsh" | "ex" | "exs" | "nix" | "yml" | "yaml" => {
+      ("# ", "")
+    }
+    "lua" | "hs" | "sql" => ("-- ", ""),
+    "html" | "htm" | "xml" => ("<!-- ", " -->"),
+    "css" => ("/* ", " */"),
+    _ => ("// ", ""),
+  }
+}
+
+/// Quick fix inserting an `ast-grep-ignore` comment for the rule above the diagnostic's line.
+/// The comment is indented like the line so it stays attached to the code.
+/// `comment` is the delimiters found by `comment_syntax`.
+pub fn suppression_code_action(
+  uri: &Url,
+  text: &str,
+  comment: (&str, &str),
+  diagnostic: &Diagnostic,
+) -> Option<CodeAction> {
+  let id = get_rule_id(diagnostic)?;
+  let line = diagnostic.range.start.line;
+  let line_text = text.lines().nth(line as usize).unwrap_or_default();
+  let indent: String = line_text
+    .chars()
+    .take_while(|c| *c == ' ' || *c == '\t')
+    .collect();
+  let (start, end) = comment;
+  let comment = format!("{indent}{start}ast-grep-ignore: {id}{end}\n");
+  let position = Position::new(line, 0);
+  let edit = TextEdit::new(Range::new(position, position), comment);
+  let changes = HashMap::from([(uri.clone(), vec![edit])]);
+  Some(CodeAction {
+    title: format!("Suppress `{id}` for this line"),
+    kind: Some(CodeActionKind::QUICKFIX),
+    diagnostics: Some(vec![diagnostic.clone()]),
+    edit: Some(WorkspaceEdit::new(changes)),
+    ..Default::default()
+  })
+}
+
+/// Data of a quick fix whose edit is computed in `codeAction/resolve`.
+#[derive(Serialize, Deserialize)]
+pub struct QuickFixData {
+  pub uri: Url,
+  pub diagnostic: Diagnostic,
+}
+
+/// Quick fix without edit. The diagnostic is kept in data to find the match when resolved.
+pub fn unresolved_code_action(uri: &Url, diagnostic: Diagnostic) -> Option<CodeAction> {
+  let id = get_rule_id(&diagnostic)?.to_string();
+  let data = QuickFixData {
+    uri: uri.clone(),
+    diagnostic,
+  };
+  Some(CodeAction {
+    title: format!("Fix `{id}` with ast-grep"),
+    kind: Some(CodeActionKind::QUICKFIX),
+    is_preferred: Some(true),
+    data: serde_json::to_value(data).ok(),
+    ..Default::default()
+  })
+}
+
+/// A fix dropped because its range overlaps with a fix of higher priority.
+pub struct FixConflict {
+  pub range: Range,
+  /// id of the rule whose fix is dropped
+  pub suppressed: String,
+  /// id of the rule whose fix is applied
+  pub by: String,
+}
+
+impl FixConflict {
+  pub fn to_diagnostic(&self) -> Diagnostic {
+    Diagnostic {
+      range: self.range,
+      severity: Some(DiagnosticSeverity::INFORMATION),
+      code: Some(NumberOrString::String(self.suppressed.clone())),
+      source: Some(String::from("ast-grep")),
+      message: format!(
+        "Fix for rule `{}` is suppressed by rule `{}` here.",
+        self.suppressed, self.by
+      ),
+      ..Default::default()
+    }
+  }
+}
+
+/// Arguments of `ast-grep.extractRule`: the selected code to turn into a rule.
+/// The rule file is written into `ruleDir` if it is given, otherwise the rule is only returned.
+/// `ruleDir` must be an existing directory of `ruleDirs` or of the project config.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractRuleParams {
+  pub uri: Url,
+  pub range: Range,
+  pub id: Option<String>,
+  pub rule_dir: Option<PathBuf>,
+}
+
+/// Pattern of the selected code whose identifiers are replaced by meta variables.
+/// The same identifier is replaced by the same variable, so the pattern keeps the references.
+pub fn extract_pattern<L: Language>(
+  root: &AstGrep<StrDoc<L>>,
+  range: Range,
+  encoding: PositionEncoding,
+) -> Option<String> {
+  let source = root.source();
+  let start = position_to_offset(source, range.start, encoding);
+  let end = position_to_offset(source, range.end, encoding).max(start);
+  let selected = &source[start..end];
+  let start = start + (selected.len() - selected.trim_start().len());
+  let end = start + selected.trim().len();
+  if start == end {
+    return None;
+  }
+  let mut names: Vec<&str> = vec![];
+  let mut pattern = String::new();
+  let mut last = start;
+  for node in root.root().dfs() {
+    let node_range = node.range();
+    let is_identifier = node.is_named_leaf() && node.kind().ends_with("identifier");
+    if !is_identifier || node_range.start < last || end < node_range.end {
+      continue;
+    }
+    let name = &source[node_range.clone()];
+    let index = match names.iter().position(|n| *n == name) {
+      Some(index) => index,
+      None => {
+        names.push(name);
+        names.len() - 1
+      }
+    };
+    pattern.push_str(&source[last..node_range.start]);
+    pattern.push_str(&meta_var_name(index));
+    last = node_range.end;
+  }
+  pattern.push_str(&source[last..end]);
+  Some(pattern)
+}
+
+/// `$A` to `$Z`, then `$VAR26` and so on.
+fn meta_var_name(index: usize) -> String {
+  match u8::try_from(index) {
+    Ok(i) if i < 26 => format!("${}", (b'A' + i) as char),
+    _ => format!("$VAR{index}"),
+  }
+}
+
+/// Rule file with the pattern, to be completed with a message and constraints by the user.
+pub fn rule_scaffold(id: &str, lang: &str, pattern: &str) -> String {
+  let mut yaml = format!(
+    "id: {id}\nlanguage: {lang}\nseverity: hint\nmessage: Describe why the code is reported\nrule:\n  pattern: |\n"
+  );
+  for line in pattern.lines() {
+    if !line.trim().is_empty() {
+      yaml.push_str("    ");
+      yaml.push_str(line);
+    }
+    yaml.push('\n');
+  }
+  yaml
+}
+
+pub fn get_rule_id(diagnostic: &Diagnostic) -> Option<&str> {
+  match diagnostic.code.as_ref()? {
+    NumberOrString::String(id) => Some(id),
+    NumberOrString::Number(_) => None,
+  }
+}
+
+/// Range of the `id: <rule_id>` value in the text of a rule file.
+/// The line is searched when requested so it is accurate after the file is edited.
+pub fn find_rule_id_range(text: &str, rule_id: &str) -> Option<Range> {
+  text.lines().enumerate().find_map(|(line, content)| {
+    let (range, value) = id_value_range(line as u32, content)?;
+    let unquoted = value.trim_matches(|c| c == '"' || c == '\'');
+    (unquoted == rule_id).then_some(range)
+  })
+}
+
+/// Range of the rule id value at the 1-based `line` of a rule file, from `RuleConfig::source`.
+/// Falls back to the line start if the line is not an `id:` line, e.g. the file has changed.
+pub fn rule_id_line_range(text: &str, line: usize) -> Range {
+  let line = line.saturating_sub(1);
+  let start = Position::new(line as u32, 0);
+  text
+    .lines()
+    .nth(line)
+    .and_then(|content| id_value_range(line as u32, content))
+    .map_or(Range::new(start, start), |(range, _)| range)
+}
+
+fn id_value_range(line: u32, content: &str) -> Option<(Range, &str)> {
+  let rest = content.trim_start().strip_prefix("id:")?;
+  let value = rest.trim();
+  let start = content.len() - rest.len() + (rest.len() - rest.trim_start().len());
+  let end = start + value.len();
+  let range = Range::new(
+    Position::new(line, start as u32),
+    Position::new(line, end as u32),
+  );
+  Some((range, value))
+}
+
+/// Report a finding silenced by an `ast-grep-ignore` comment as a hint.
+/// `data.suppressed` tells clients and other features to skip it.
+pub fn to_suppressed_diagnostic(mut diagnostic: Diagnostic) -> Diagnostic {
+  diagnostic.severity = Some(DiagnosticSeverity::HINT);
+  diagnostic.message = format!("Suppressed: {}", diagnostic.message);
+  let data = diagnostic
+    .data
+    .get_or_insert_with(|| serde_json::Value::Object(Default::default()));
+  if let Some(data) = data.as_object_mut() {
+    data.insert("suppressed".into(), true.into());
+  }
+  diagnostic
+}
+
+pub fn is_suppressed(diagnostic: &Diagnostic) -> bool {
+  let data = diagnostic.data.as_ref();
+  data.and_then(|d| d.get("suppressed")?.as_bool()) == Some(true)
+}
+
+/// Whether actions of `kind` are asked for by the `requested` kind of `only`.
+/// Kinds are hierarchical, e.g. `source` includes `source.applicableRules.ast-grep`.
+pub fn is_kind_requested(requested: &CodeActionKind, kind: &CodeActionKind) -> bool {
+  let (requested, kind) = (requested.as_str(), kind.as_str());
+  match kind.strip_prefix(requested) {
+    Some(rest) => rest.is_empty() || rest.starts_with('.'),
+    None => false,
+  }
+}
+
+/// Whether the fix of the diagnostic is marked `unsafe`.
+pub fn is_unsafe_fix(diagnostic: &Diagnostic) -> bool {
+  let rewrite_data = diagnostic.data.clone().and_then(RewriteData::from_value);
+  rewrite_data.map_or(false, |data| data.is_unsafe)
+}
+
+/// Ranges replaced by fixes marked `unsafe`, which equal ranges of their edits.
+pub fn unsafe_fix_ranges(diagnostics: &[Diagnostic]) -> Vec<Range> {
+  diagnostics
+    .iter()
+    .filter_map(|d| {
+      let rewrite_data = d.data.clone().and_then(RewriteData::from_value)?;
+      let range = rewrite_data.range.unwrap_or(d.range);
+      rewrite_data.is_unsafe.then_some(range)
+    })
+    .collect()
+}
+
+struct Fix {
+  range: Range,
+  fixed: String,
+  diagnostic: Diagnostic,
+}
+
+impl Fix {
+  fn overlaps(&self, other: &Fix) -> bool {
+    self.range.start < other.range.end && other.range.start < self.range.end
+  }
+}
+
+fn severity_rank(diagnostic: &Diagnostic) -> u8 {
+  match diagnostic.severity {
+    Some(DiagnosticSeverity::ERROR) => 0,
+    Some(DiagnosticSeverity::WARNING) => 1,
+    Some(DiagnosticSeverity::INFORMATION) => 2,
+    Some(DiagnosticSeverity::HINT) => 3,
+    _ => 4,
+  }
+}
+
+/// Non-overlapping fixes of the diagnostics sorted by position, and the fixes dropped by overlaps.
+/// Among overlapping fixes, fixes of higher severity win, then fixes of rules declared earlier
+/// in `rule_order`, then the fix starting first.
+pub fn collect_fixes(
+  diagnostics: Vec<Diagnostic>,
+  rule_order: &HashMap<String, usize>,
+) -> (Vec<TextEdit>, Vec<FixConflict>) {
+  let mut fixes: Vec<_> = diagnostics
+    .into_iter()
+    .filter_map(|diagnostic| {
+      let rewrite_data = diagnostic.data.clone().and_then(RewriteData::from_value)?;
+      let range = rewrite_data.range.unwrap_or(diagnostic.range);
+      Some(Fix {
+        range,
+        fixed: rewrite_data.fixed,
+        diagnostic,
+      })
+    })
+    .collect();
+  fixes.sort_by_key(|fix| (fix.range.start, fix.range.end));
+  // group fixes which overlap with each other transitively
+  let mut groups: Vec<Vec<Fix>> = vec![];
+  let mut group_end = Position::default();
+  for fix in fixes {
+    match groups.last_mut() {
+      Some(group) if fix.range.start < group_end => group.push(fix),
+      _ => groups.push(vec![fix]),
+    }
+    let end = groups.last().and_then(|g| g.last()).map(|f| f.range.end);
+    group_end = group_end.max(end.unwrap_or_default());
+  }
+  let declared = |fix: &Fix| {
+    let id = get_rule_id(&fix.diagnostic).unwrap_or_default();
+    rule_order.get(id).copied().unwrap_or(usize::MAX)
+  };
+  let mut applied = vec![];
+  let mut conflicts = vec![];
+  for mut group in groups {
+    group.sort_by_key(|fix| {
+      (
+        severity_rank(&fix.diagnostic),
+        declared(fix),
+        fix.range.start,
+      )
+    });
+    let mut winners: Vec<Fix> = vec![];
+    for fix in group {
+      match winners.iter().find(|winner| winner.overlaps(&fix)) {
+        Some(winner) => conflicts.push(FixConflict {
+          range: fix.diagnostic.range,
+          suppressed: get_rule_id(&fix.diagnostic).unwrap_or_default().to_string(),
+          by: get_rule_id(&winner.diagnostic)
+            .unwrap_or_default()
+            .to_string(),
+        }),
+        None => winners.push(fix),
+      }
+    }
+    applied.extend(winners);
+  }
+  applied.sort_by_key(|fix| (fix.range.start, fix.range.end));
+  let edits = applied
+    .into_iter()
+    .map(|fix| TextEdit::new(fix.range, fix.fixed))
+    .collect();
+  (edits, conflicts)
+}
+
+/// Sort diagnostics from top to bottom, then by rule id, so clients receive a stable order.
+pub fn sort_diagnostics(diagnostics: &mut [Diagnostic]) {
+  diagnostics.sort_by(|a, b| {
+    let key = |d: &Diagnostic| (d.range.start, d.range.end);
+    key(a)
+      .cmp(&key(b))
+      .then_with(|| get_rule_id(a).cmp(&get_rule_id(b)))
+  });
+}
+
+/// Truncate message longer than `max_len` chars with an ellipsis.
+/// The full message is kept in `data.message` for clients to show on demand.
+pub fn truncate_message(diagnostic: &mut Diagnostic, max_len: usize) {
+  let Some((cut, _)) = diagnostic.message.char_indices().nth(max_len) else {
+    return;
+  };
+  let end = diagnostic.message[..cut]
+    .char_indices()
+    .last()
+    .map_or(0, |(i, _)| i);
+  let mut message = diagnostic.message[..end].to_string();
+  message.push('…');
+  let full = std::mem::replace(&mut diagnostic.message, message);
+  let data = diagnostic
+    .data
+    .get_or_insert_with(|| serde_json::Value::Object(Default::default()));
+  if let Some(data) = data.as_object_mut() {
+    data.insert("message".into(), full.into());
+  }
+}
+
+/// Markdown of the rule id, full message, note and documentation link of a diagnostic.
+pub fn diagnostic_to_hover<L: Language>(
+  diagnostic: &Diagnostic,
+  rule: Option<&RuleConfig<L>>,
+) -> String {
+  // message may be truncated by `maxMessageLength`
+  let full_message = diagnostic
+    .data
+    .as_ref()
+    .and_then(|data| data.get("message")?.as_str());
+  let message = full_message.unwrap_or(&diagnostic.message);
+  let mut hover = match get_rule_id(diagnostic) {
+    Some(id) => format!("**{id}**: {message}"),
+    None => message.to_string(),
+  };
+  if let Some(note) = rule.and_then(|r| r.note.as_ref()) {
+    hover.push_str("\n\n");
+    hover.push_str(note);
+  }
+  if let Some(description) = &diagnostic.code_description {
+    hover.push_str(&format!("\n\n[Documentation]({})", description.href));
+  }
+  hover
+}
+
+/// One code lens per rule at its first finding, with the number of findings.
+/// The lens of a fixable rule runs `command` to fix all findings of the rule.
+pub fn rule_code_lenses(
+  uri: &Url,
+  diagnostics: &[Diagnostic],
+  has_fix: impl Fn(&str) -> bool,
+  command: &str,
+) -> Vec<CodeLens> {
+  let mut findings: HashMap<&str, (Range, usize)> = HashMap::new();
+  for diagnostic in diagnostics {
+    let Some(id) = get_rule_id(diagnostic) else {
+      continue;
+    };
+    let entry = findings.entry(id).or_insert((diagnostic.range, 0));
+    if diagnostic.range.start < entry.0.start {
+      entry.0 = diagnostic.range;
+    }
+    entry.1 += 1;
+  }
+  let mut findings: Vec<_> = findings.into_iter().collect();
+  findings.sort_by_key(|(id, (range, _))| (range.start, *id));
+  findings
+    .into_iter()
+    .map(|(id, (range, count))| {
+      let plural = if count == 1 { "" } else { "s" };
+      let command = if has_fix(id) {
+        let params = ApplyRuleFixesParams {
+          uri: uri.clone(),
+          rule_id: id.to_string(),
+        };
+        Command {
+          title: format!("Fix all {count} occurrence{plural} of rule `{id}`"),
+          command: command.to_string(),
+          arguments: serde_json::to_value(params).ok().map(|v| vec![v]),
+        }
+      } else {
+        // lens without command is only informational
+        Command {
+          title: format!("{count} occurrence{plural} of rule `{id}`"),
+          command: String::new(),
+          arguments: None,
+        }
+      };
+      CodeLens {
+        range,
+        command: Some(command),
+        data: None,
+      }
+    })
+    .collect()
+}
+
+/// Find the innermost named node containing the position
+pub fn find_node_at<L: Language>(
+  root: &AstGrep<StrDoc<L>>,
+  pos: Position,
+  encoding: PositionEncoding,
+) -> Option<Node<StrDoc<L>>> {
+  let pos = to_byte_position(root.source(), pos, encoding);
+  root
+    .root()
+    .dfs()
+    .filter(|n| n.is_named() && n.start_pos() <= pos && pos < n.end_pos())
+    .last()
+}
+
+/// Unit of `Position::character` negotiated with the client. Tree-sitter columns are bytes,
+/// so UTF-8 needs no conversion.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+  Utf8,
+  /// the encoding every client supports if it does not declare `positionEncodings`
+  #[default]
+  Utf16,
+  Utf32,
+}
+
+impl PositionEncoding {
+  /// The first encoding in the client's order of preference that is supported.
+  pub fn negotiate(capabilities: &ClientCapabilities) -> Self {
+    let general = capabilities.general.as_ref();
+    let encodings = general.and_then(|g| g.position_encodings.as_ref());
+    encodings
+      .into_iter()
+      .flatten()
+      .find_map(|kind| match kind.as_str() {
+        "utf-8" => Some(Self::Utf8),
+        "utf-16" => Some(Self::Utf16),
+        "utf-32" => Some(Self::Utf32),
+        _ => None,
+      })
+      .unwrap_or_default()
+  }
+
+  pub fn kind(self) -> PositionEncodingKind {
+    match self {
+      Self::Utf8 => PositionEncodingKind::UTF8,
+      Self::Utf16 => PositionEncodingKind::UTF16,
+      Self::Utf32 => PositionEncodingKind::UTF32,
+    }
+  }
+
+  /// Length of the text in code units of the encoding.
+  fn len(self, text: &str) -> usize {
+    match self {
+      Self::Utf8 => text.len(),
+      Self::Utf16 => text.encode_utf16().count(),
+      Self::Utf32 => text.chars().count(),
+    }
+  }
+
+  /// Byte length of the longest prefix of the line spanning at most `units` code units.
+  /// A column inside a char or past the line end does not split the char or the line break.
+  fn byte_len(self, line: &str, units: usize) -> usize {
+    let mut count = 0;
+    for (i, c) in line.char_indices() {
+      count += match self {
+        Self::Utf8 => c.len_utf8(),
+        Self::Utf16 => c.len_utf16(),
+        Self::Utf32 => 1,
+      };
+      if count > units {
+        return i;
+      }
+    }
+    line.len()
+  }
+}
+
+/// Byte offset of the line start and the line without its line break.
+fn line_at(text: &str, line: u32) -> (usize, &str) {
+  let mut lines = text.split_inclusive('\n');
+  let start = lines.by_ref().take(line as usize).map(str::len).sum();
+  let content = lines.next().unwrap_or_default();
+  (start, content.strip_suffix('\n').unwrap_or(content))
+}
+
+/// Convert an LSP position to byte offset in text.
+fn position_to_offset(text: &str, pos: Position, encoding: PositionEncoding) -> usize {
+  let (start, line) = line_at(text, pos.line);
+  start + encoding.byte_len(line, pos.character as usize)
+}
+
+/// Convert an LSP position to row and byte column, as positions of tree-sitter nodes.
+fn to_byte_position(text: &str, pos: Position, encoding: PositionEncoding) -> (usize, usize) {
+  let (_, line) = line_at(text, pos.line);
+  (
+    pos.line as usize,
+    encoding.byte_len(line, pos.character as usize),
+  )
+}
+
+/// Convert a range with byte columns, e.g. found by scanning lines of a rule file, to the encoding.
+pub fn encode_range(text: &str, range: Range, encoding: PositionEncoding) -> Range {
+  let encode = |pos: Position| {
+    let (_, line) = line_at(text, pos.line);
+    let mut column = (pos.character as usize).min(line.len());
+    while !line.is_char_boundary(column) {
+      column -= 1;
+    }
+    Position::new(pos.line, encoding.len(&line[..column]) as u32)
+  };
+  Range::new(encode(range.start), encode(range.end))
+}
+
+/// Convert a position in the encoding to a position with byte column, the inverse of `encode_range`.
+pub fn decode_position(text: &str, pos: Position, encoding: PositionEncoding) -> Position {
+  let (_, column) = to_byte_position(text, pos, encoding);
+  Position::new(pos.line, column as u32)
+}
+
+/// Convert a byte offset in text to an LSP position.
+fn offset_to_position(text: &str, offset: usize, encoding: PositionEncoding) -> Position {
+  let before = &text[..offset];
+  let line = before.matches('\n').count();
+  let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+  Position {
+    line: line as u32,
+    character: encoding.len(&before[line_start..]) as u32,
+  }
+}
+
+/// Apply a change of `didChange` to the document. A ranged change edits the old tree
+/// so tree-sitter reuses unchanged nodes instead of parsing the whole text again.
+pub fn apply_content_change<L: Language>(
+  root: &mut AstGrep<StrDoc<L>>,
+  change: TextDocumentContentChangeEvent,
+  encoding: PositionEncoding,
+) {
+  let Some(range) = change.range else {
+    *root = AstGrep::new(change.text, root.lang().clone());
+    return;
+  };
+  let source = root.source();
+  let position = position_to_offset(source, range.start, encoding);
+  let end = position_to_offset(source, range.end, encoding).max(position);
+  let edit = Edit {
+    position,
+    deleted_length: end - position,
+    inserted_text: change.text.into_bytes(),
+  };
+  if root.edit(edit).is_err() {
+    // source is already edited, only the tree is stale
+    *root = AstGrep::new(root.source(), root.lang().clone());
+  }
+}
+
+/// Apply non-overlapping edits to text, e.g. edits returned by `collect_fixes`.
+pub fn apply_text_edits(text: &str, edits: &[TextEdit], encoding: PositionEncoding) -> String {
+  let mut ret = String::with_capacity(text.len());
+  let mut start = 0;
+  for edit in edits {
+    let edit_start = position_to_offset(text, edit.range.start, encoding);
+    let edit_end = position_to_offset(text, edit.range.end, encoding);
+    ret.push_str(&text[start..edit_start]);
+    ret.push_str(&edit.new_text);
+    start = edit_end;
+  }
+  ret.push_str(&text[start..]);
+  ret
+}
+
+fn convert_node_to_range<L: Language>(node: &Node<StrDoc<L>>, encoding: PositionEncoding) -> Range {
+  let text = node.root().get_text();
+  let ends = (node.start_pos(), node.end_pos());
+  convert_byte_range(text, node.range(), ends, encoding)
+}
+
+/// `ends` are the start and end positions of the byte range in rows and byte columns.
+fn convert_byte_range(
+  text: &str,
+  range: std::ops::Range<usize>,
+  (start, end): ((usize, usize), (usize, usize)),
+  encoding: PositionEncoding,
+) -> Range {
+  // byte columns are converted by the text between the line start and the offset
+  let to_position = |(row, col): (usize, usize), offset: usize| Position {
+    line: row as u32,
+    character: encoding.len(&text[offset - col..offset]) as u32,
+  };
   Range {
-    start: Position {
-      line: start_row as u32,
-      character: start_col as u32,
-    },
-    end: Position {
-      line: end_row as u32,
-      character: end_col as u32,
-    },
+    start: to_position(start, range.start),
+    end: to_position(end, range.end),
   }
 }
 
+/// Range of the match, which extends to the last sibling for multi-node patterns.
+fn convert_match_to_range<L: Language>(
+  node_match: &NodeMatch<StrDoc<L>>,
+  encoding: PositionEncoding,
+) -> Range {
+  let text = node_match.root().get_text();
+  let ends = (node_match.start_pos(), node_match.end_pos());
+  convert_byte_range(text, node_match.range(), ends, encoding)
+}
+
+/// `severity` is the rule severity resolved for the document path.
+/// Fix text is only computed `with_fix`, otherwise it is resolved lazily by `codeAction/resolve`.
 pub fn convert_match_to_diagnostic<L: Language>(
   node_match: NodeMatch<StrDoc<L>>,
   rule: &RuleConfig<L>,
+  severity: &Severity,
   uri: &Url,
+  with_fix: bool,
+  encoding: PositionEncoding,
 ) -> Diagnostic {
-  // TODO
-  let rewrite_data =
-    RewriteData::from_node_match(&node_match, rule).and_then(|r| serde_json::to_value(r).ok());
+  let rewrite = if with_fix {
+    RewriteData::from_node_match(&node_match, rule, encoding)
+  } else {
+    None
+  };
+  let data = DiagnosticData {
+    fingerprint: compute_fingerprint(&rule.id, &node_match.text()),
+    rewrite,
+  };
   Diagnostic {
-    range: convert_node_to_range(&node_match),
+    range: convert_match_to_range(&node_match, encoding),
     code: Some(NumberOrString::String(rule.id.clone())),
-    code_description: url_to_code_description(&rule.url),
-    severity: Some(match rule.severity {
+    code_description: rule_code_description(rule),
+    severity: Some(match severity {
       Severity::Error => DiagnosticSeverity::ERROR,
       Severity::Warning => DiagnosticSeverity::WARNING,
       Severity::Info => DiagnosticSeverity::INFORMATION,
@@ -92,9 +1091,9 @@ pub fn convert_match_to_diagnostic<L: Language>(
     }),
     message: get_non_empty_message(rule, &node_match),
     source: Some(String::from("ast-grep")),
-    tags: None,
-    related_information: collect_labels(&node_match, uri),
-    data: rewrite_data,
+    tags: rule_diagnostic_tags(rule),
+    related_information: collect_labels(&node_match, rule, uri, encoding),
+    data: serde_json::to_value(data).ok(),
   }
 }
 
@@ -109,27 +1108,69 @@ fn get_non_empty_message<L: Language>(rule: &RuleConfig<L>, nm: &NodeMatch<StrDo
 
 fn collect_labels<L: Language>(
   node_match: &NodeMatch<StrDoc<L>>,
+  rule: &RuleConfig<L>,
   uri: &Url,
+  encoding: PositionEncoding,
 ) -> Option<Vec<DiagnosticRelatedInformation>> {
-  let secondary_nodes = node_match.get_env().get_labels("secondary")?;
-  Some(
-    secondary_nodes
-      .iter()
-      .map(|n| {
-        let location = Location {
-          uri: uri.clone(),
-          range: convert_node_to_range(n),
-        };
-        DiagnosticRelatedInformation {
-          location,
-          message: String::new(),
-        }
-      })
-      .collect(),
-  )
+  let env = node_match.get_env();
+  let related_info = |range, message: &str| DiagnosticRelatedInformation {
+    location: Location {
+      uri: uri.clone(),
+      range,
+    },
+    message: message.to_string(),
+  };
+  let mut infos: Vec<_> = env
+    .get_labels("secondary")
+    .into_iter()
+    .flatten()
+    .map(|n| related_info(convert_node_to_range(n, encoding), ""))
+    .collect();
+  // sort labels by variable name for a stable order
+  let mut labels: Vec<_> = rule.labels.iter().flatten().collect();
+  labels.sort();
+  for (var, message) in labels {
+    if let Some(node) = env.get_match(var) {
+      infos.push(related_info(convert_node_to_range(node, encoding), message));
+      continue;
+    }
+    // multi meta variable spans from its first to last node
+    let nodes = env.get_multiple_matches(var);
+    if let (Some(first), Some(last)) = (nodes.first(), nodes.last()) {
+      let range = Range::new(
+        convert_node_to_range(first, encoding).start,
+        convert_node_to_range(last, encoding).end,
+      );
+      infos.push(related_info(range, message));
+    }
+  }
+  (!infos.is_empty()).then_some(infos)
+}
+
+/// Documentation link of the rule in `url`, or in `metadata.url` if the rule has no `url`.
+fn rule_code_description<L: Language>(rule: &RuleConfig<L>) -> Option<CodeDescription> {
+  let metadata_url = || rule.metadata.as_ref()?.get("url")?.as_str();
+  let href = Url::parse(rule.url.as_deref().or_else(metadata_url)?).ok()?;
+  Some(CodeDescription { href })
+}
+
+/// Tags in `metadata.tags` of the rule, which editors render as strikethrough or faded text.
+fn rule_diagnostic_tags<L: Language>(rule: &RuleConfig<L>) -> Option<Vec<DiagnosticTag>> {
+  let tags = rule.metadata.as_ref()?.get("tags")?;
+  let tags: Vec<_> = tags
+    .items()
+    .iter()
+    .filter_map(|tag| match tag.as_str() {
+      "deprecated" => Some(DiagnosticTag::DEPRECATED),
+      "unnecessary" => Some(DiagnosticTag::UNNECESSARY),
+      _ => None,
+    })
+    .collect();
+  (!tags.is_empty()).then_some(tags)
 }
 
-fn url_to_code_description(url: &Option<String>) -> Option<CodeDescription> {
-  let href = Url::parse(url.as_ref()?).ok()?;
+/// Documentation link of a rule without its own url, by replacing `{id}` in the template.
+pub fn code_description_from_template(template: &str, rule_id: &str) -> Option<CodeDescription> {
+  let href = Url::parse(&template.replace("{id}", rule_id)).ok()?;
   Some(CodeDescription { href })
 }
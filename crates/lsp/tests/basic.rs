@@ -1,10 +1,17 @@
-use ast_grep_config::{from_yaml_string, GlobalRules, RuleCollection, RuleConfig};
+use ast_grep_config::{
+  from_yaml_string, from_yaml_string_with_source, GlobalRules, RuleCollection, RuleConfig,
+  RuleConfigError,
+};
 use ast_grep_language::SupportLang;
 use ast_grep_lsp::*;
-use serde_json::Value;
+use serde_json::{json, Value};
+use tempfile::TempDir;
 use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::time::{timeout, Duration};
 
-use std::path::Path;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 pub fn req(msg: &str) -> String {
   format!("Content-Length: {}\r\n\r\n{}", msg.len(), msg)
@@ -20,8 +27,9 @@ pub fn parse_jsonrpc(input: &mut &str) -> Option<Value> {
 
   let input_str = input_str.trim_start_matches("\r\n\r\n");
 
-  let body = &input_str[..length];
-  let value = serde_json::from_str(&body[..length]).ok()?;
+  // the message may not be fully received yet
+  let body = input_str.get(..length)?;
+  let value = serde_json::from_str(body).ok()?;
   *input = &input_str[length..];
   value
 }
@@ -55,10 +63,7 @@ fn req_resp_should_work() {
   )
 }
 
-pub fn create_lsp() -> (DuplexStream, DuplexStream) {
-  let globals = GlobalRules::default();
-  let config: RuleConfig<SupportLang> = from_yaml_string(
-    r"
+const NO_CONSOLE_RULE: &str = r"
 id: no-console-rule
 message: No console.log
 severity: warning
@@ -68,30 +73,121 @@ rule:
 note: no console.log
 fix: |
   alert($$$A)
-",
-    &globals,
-  )
-  .unwrap()
-  .pop()
-  .unwrap();
-  let base = Path::new("./").to_path_buf();
-  let rc: RuleCollection<SupportLang> = RuleCollection::try_new(vec![config]).unwrap();
+";
+
+pub fn create_lsp() -> (DuplexStream, ServerOutput) {
+  create_lsp_with_rules(NO_CONSOLE_RULE)
+}
+
+pub fn create_lsp_with_rules(rules: &str) -> (DuplexStream, ServerOutput) {
+  create_lsp_with_base(rules, Path::new("./").to_path_buf())
+}
+
+pub fn create_lsp_with_base(rules: &str, base: PathBuf) -> (DuplexStream, ServerOutput) {
+  let globals = GlobalRules::default();
+  let configs: Vec<RuleConfig<SupportLang>> = from_yaml_string(rules, &globals).unwrap();
+  let rc: RuleCollection<SupportLang> = RuleCollection::try_new(configs).unwrap();
   let rc_result: std::result::Result<_, String> = Ok(rc);
-  let (service, socket) =
-    LspService::build(|client| Backend::new(client, base, rc_result)).finish();
+  let (service, socket) = Backend::build_service(|client| Backend::new(client, base, rc_result));
   let (req_client, req_server) = duplex(1024);
   let (resp_server, resp_client) = duplex(1024);
 
   // start server as concurrent task
   tokio::spawn(Server::new(req_server, resp_server, socket).serve(service));
 
-  (req_client, resp_client)
+  (req_client, ServerOutput::new(resp_client))
+}
+
+/// Serve rule files from memory, keyed by path.
+#[derive(Clone, Default)]
+pub struct MockRuleFinder {
+  files: Arc<Mutex<HashMap<PathBuf, String>>>,
+}
+
+impl MockRuleFinder {
+  pub fn write(&self, path: &str, rules: &str) {
+    self
+      .files
+      .lock()
+      .unwrap()
+      .insert(PathBuf::from(path), rules.to_string());
+  }
+}
+
+impl MockRuleFinder {
+  fn collect_rules(
+    &self,
+    folder: &Path,
+  ) -> std::result::Result<RuleCollection<SupportLang>, String> {
+    let files = self.files.lock().unwrap();
+    let mut collection = RuleCollection::default();
+    for (path, yaml) in files.iter().filter(|(p, _)| p.starts_with(folder)) {
+      let rules: Vec<RuleConfig<SupportLang>> =
+        from_yaml_string_with_source(yaml, &Default::default(), path).map_err(|e| e.to_string())?;
+      collection
+        .add_file_rules(path.clone(), rules)
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(collection)
+  }
+}
+
+impl RuleFinder<SupportLang> for MockRuleFinder {
+  fn find_rules(&self) -> std::result::Result<RuleCollection<SupportLang>, String> {
+    self.collect_rules(Path::new("/"))
+  }
+  fn find_rules_in(
+    &self,
+    folder: &Path,
+  ) -> Option<std::result::Result<RuleCollection<SupportLang>, String>> {
+    let files = self.files.lock().unwrap();
+    if !files.keys().any(|p| p.starts_with(folder)) {
+      return None;
+    }
+    drop(files);
+    Some(self.collect_rules(folder))
+  }
+  fn is_rule_file(&self, path: &Path) -> bool {
+    self.files.lock().unwrap().contains_key(path)
+  }
+  fn is_test_file(&self, path: &Path) -> bool {
+    path.starts_with("/rule-tests")
+  }
+  fn util_rule_ids(&self) -> Vec<String> {
+    vec!["global-util".to_string()]
+  }
+  fn parse_rules(
+    &self,
+    text: &str,
+  ) -> std::result::Result<Vec<RuleConfig<SupportLang>>, RuleConfigError> {
+    from_yaml_string(text, &Default::default())
+  }
+  fn read_rule_file(
+    &self,
+    path: &Path,
+  ) -> Option<std::result::Result<Vec<RuleConfig<SupportLang>>, String>> {
+    let files = self.files.lock().unwrap();
+    let yaml = files.get(path)?;
+    let rules = from_yaml_string_with_source(yaml, &Default::default(), path);
+    Some(rules.map_err(|e| e.to_string()))
+  }
+}
+
+pub fn create_lsp_with_finder(finder: MockRuleFinder) -> (DuplexStream, ServerOutput) {
+  let base = Path::new("./").to_path_buf();
+  let rules = finder.find_rules();
+  let (service, socket) =
+    Backend::build_service(|client| Backend::new(client, base, rules).with_rule_finder(finder));
+  let (req_client, req_server) = duplex(1024);
+  let (resp_server, resp_client) = duplex(1024);
+  tokio::spawn(Server::new(req_server, resp_server, socket).serve(service));
+  (req_client, ServerOutput::new(resp_client))
 }
 
 pub async fn initialize_lsp(
   req_client: &mut DuplexStream,
-  resp_client: &mut DuplexStream,
-) -> Vec<u8> {
+  resp_client: &mut ServerOutput,
+) -> Option<Value> {
   let initialize = r#"{
       "jsonrpc":"2.0",
       "id": 1,
@@ -102,21 +198,149 @@ pub async fn initialize_lsp(
         }
       }
     }"#;
+  req_client
+    .write_all(req(initialize).as_bytes())
+    .await
+    .unwrap();
+  wait_for(resp_client, |v| v["id"] == 1).await
+}
+
+pub async fn initialize_lsp_with_options(
+  req_client: &mut DuplexStream,
+  resp_client: &mut ServerOutput,
+  options: Value,
+) -> Option<Value> {
+  let initialize = json!({
+    "jsonrpc": "2.0",
+    "id": 1,
+    "method": "initialize",
+    "params": {
+      "capabilities": {},
+      "initializationOptions": options,
+    }
+  });
+  req_client
+    .write_all(req(&initialize.to_string()).as_bytes())
+    .await
+    .unwrap();
+  wait_for(resp_client, |v| v["id"] == 1).await
+}
+
+pub async fn notify(req_client: &mut DuplexStream, method: &str, params: Value) {
+  let notification = json!({
+    "jsonrpc": "2.0",
+    "method": method,
+    "params": params,
+  });
+  req_client
+    .write_all(req(&notification.to_string()).as_bytes())
+    .await
+    .unwrap();
+}
+
+pub async fn open_document(req_client: &mut DuplexStream, uri: &str, text: &str) {
+  let params = json!({
+    "textDocument": {
+      "uri": uri,
+      "languageId": "typescript",
+      "version": 1,
+      "text": text,
+    }
+  });
+  notify(req_client, "textDocument/didOpen", params).await;
+}
+
+/// Server messages read from the stream but not yet consumed by `wait_for`.
+pub struct ServerOutput {
+  stream: DuplexStream,
+  received: Vec<u8>,
+  pending: VecDeque<Value>,
+}
+
+impl ServerOutput {
+  pub fn new(stream: DuplexStream) -> Self {
+    Self {
+      stream,
+      received: vec![],
+      pending: VecDeque::new(),
+    }
+  }
+
+  // move every complete message out of the byte buffer
+  fn parse_received(&mut self) {
+    let Ok(text) = std::str::from_utf8(&self.received) else {
+      return; // a multi-byte char may be split across reads
+    };
+    let mut rest = text;
+    while let Some(value) = parse_jsonrpc(&mut rest) {
+      self.pending.push_back(value);
+    }
+    let consumed = text.len() - rest.len();
+    self.received.drain(..consumed);
+  }
+}
+
+// read server messages until one satisfies the predicate or timeout
+// messages not matching the predicate are kept for later calls
+pub async fn wait_for(
+  resp_client: &mut ServerOutput,
+  predicate: impl Fn(&Value) -> bool,
+) -> Option<Value> {
   let mut buf = vec![0; 1024];
+  loop {
+    if let Some(index) = resp_client.pending.iter().position(&predicate) {
+      return resp_client.pending.remove(index);
+    }
+    let read = timeout(Duration::from_secs(5), resp_client.stream.read(&mut buf));
+    let len = read.await.ok()?.ok()?;
+    resp_client.received.extend_from_slice(&buf[..len]);
+    resp_client.parse_received();
+  }
+}
 
+pub async fn request(
+  req_client: &mut DuplexStream,
+  resp_client: &mut ServerOutput,
+  method: &str,
+  params: Value,
+) -> Value {
+  let request = json!({
+    "jsonrpc": "2.0",
+    "id": 42,
+    "method": method,
+    "params": params,
+  });
   req_client
-    .write_all(req(initialize).as_bytes())
+    .write_all(req(&request.to_string()).as_bytes())
     .await
     .unwrap();
-  let _ = resp_client.read(&mut buf).await.unwrap();
+  let response = wait_for(resp_client, |v| {
+    v["id"] == 42 && (v.get("result").is_some() || v.get("error").is_some())
+  });
+  response.await.expect("should respond")
+}
+
+pub async fn wait_for_diagnostics(resp_client: &mut ServerOutput) -> Vec<Value> {
+  let published = wait_for(resp_client, |v| {
+    v["method"] == "textDocument/publishDiagnostics"
+  })
+  .await
+  .expect("should publish diagnostics");
+  published["params"]["diagnostics"]
+    .as_array()
+    .cloned()
+    .unwrap_or_default()
+}
 
-  buf
+fn is_running_log(v: &Value) -> bool {
+  let message = v["params"]["message"].as_str().unwrap_or_default();
+  v["method"] == "window/logMessage" && message.starts_with("Running")
 }
 
 pub async fn request_code_action_to_lsp(
   req_client: &mut DuplexStream,
-  resp_client: &mut DuplexStream,
-) -> Vec<u8> {
+  resp_client: &mut ServerOutput,
+) -> Option<Value> {
   let code_action_request = r#"{
       "jsonrpc": "2.0",
       "id": 1,
@@ -158,20 +382,17 @@ pub async fn request_code_action_to_lsp(
       }
       }"#;
 
-  let mut buf = vec![0; 1024];
   req_client
     .write_all(req(code_action_request).as_bytes())
     .await
     .unwrap();
-  let _ = resp_client.read(&mut buf).await.unwrap();
-
-  buf
+  wait_for(resp_client, is_running_log).await
 }
 
 pub async fn request_execute_command_to_lsp(
   req_client: &mut DuplexStream,
-  resp_client: &mut DuplexStream,
-) -> Vec<u8> {
+  resp_client: &mut ServerOutput,
+) -> Option<Value> {
   let execute_command_request: &str = r#"
   {
     "jsonrpc": "2.0",
@@ -190,14 +411,11 @@ pub async fn request_execute_command_to_lsp(
     }
   }
   "#;
-  let mut buf = vec![0; 1024];
   req_client
     .write_all(req(execute_command_request).as_bytes())
     .await
     .unwrap();
-  let _ = resp_client.read(&mut buf).await.unwrap();
-
-  buf
+  wait_for(resp_client, is_running_log).await
 }
 
 #[test]
@@ -205,9 +423,9 @@ fn test_basic() {
   tokio::runtime::Runtime::new().unwrap().block_on(async {
     let (mut req_client, mut resp_client) = create_lsp();
 
-    let buf = initialize_lsp(&mut req_client, &mut resp_client).await;
+    let initialized = initialize_lsp(&mut req_client, &mut resp_client).await;
 
-    assert!(!resp(&buf).is_empty());
+    assert!(initialized.is_some());
   });
 }
 
@@ -219,14 +437,10 @@ fn test_code_action() {
 
     initialize_lsp(&mut req_client, &mut resp_client).await;
 
-    let buf = request_code_action_to_lsp(&mut req_client, &mut resp_client).await;
-    // {"jsonrpc":"2.0","method":"window/logMessage","params":{"message":"Running CodeAction source.fixAll","type":3}}
-    let resp_list = resp(&buf);
-
-    let running_code_action_resp = resp_list
-      .iter()
-      .find(|v| v["method"] == "window/logMessage")
+    let running_code_action_resp = request_code_action_to_lsp(&mut req_client, &mut resp_client)
+      .await
       .unwrap();
+    // {"jsonrpc":"2.0","method":"window/logMessage","params":{"message":"Running CodeAction source.fixAll","type":3}}
 
     assert_eq!(
       running_code_action_resp["params"]["message"],
@@ -242,19 +456,3126 @@ fn test_execute_apply_all_fixes() {
 
     initialize_lsp(&mut req_client, &mut resp_client).await;
 
-    let buf = request_execute_command_to_lsp(&mut req_client, &mut resp_client).await;
-
+    let running_command_resp = request_execute_command_to_lsp(&mut req_client, &mut resp_client)
+      .await
+      .unwrap();
     // {"jsonrpc":"2.0","method":"window/logMessage","params":{"message":"Running ExecuteCommand ast-grep.applyAllFixes","type":3}}
-    let resp_list = resp(&buf);
 
-    let running_command_resp = resp_list
+    assert_eq!(
+      running_command_resp["params"]["message"],
+      "Running ExecuteCommand ast-grep.applyAllFixes"
+    );
+  });
+}
+
+#[test]
+fn test_report_fix_conflicts() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+---
+id: no-console-member
+message: Use logger
+severity: hint
+language: TypeScript
+rule:
+  pattern: console.log
+fix: logger.log
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    let options = json!({ "reportFixConflicts": true });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(123)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 3);
+    let conflict = diagnostics
       .iter()
-      .find(|v| v["method"] == "window/logMessage")
+      .find(|d| d["severity"] == 3)
+      .expect("should report conflict");
+    // the fix of higher severity wins
+    assert_eq!(
+      conflict["message"],
+      "Fix for rule `no-console-member` is suppressed by rule `no-console-rule` here."
+    );
+  });
+}
+
+#[test]
+fn test_no_fix_conflicts_by_default() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+---
+id: no-console-member
+message: Use logger
+severity: hint
+language: TypeScript
+rule:
+  pattern: console.log
+fix: logger.log
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(123)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 2);
+  });
+}
+
+#[test]
+fn test_applicable_rules_code_action() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(a, b)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let position = json!({ "line": 0, "character": 13 });
+    let params = |only: Option<&str>| {
+      json!({
+        "textDocument": { "uri": "file:///test.ts" },
+        "range": { "start": position, "end": position },
+        "context": { "diagnostics": [], "only": only.map(|kind| vec![kind]) },
+      })
+    };
+    // parent kinds and requests without `only` list applicable rules as well
+    for only in [
+      Some("source.applicableRules.ast-grep"),
+      Some("source"),
+      None,
+    ] {
+      let response = request(
+        &mut req_client,
+        &mut resp_client,
+        "textDocument/codeAction",
+        params(only),
+      )
+      .await;
+      let actions = response["result"].as_array().expect("should have actions");
+      assert_eq!(actions.len(), 1);
+      assert_eq!(
+        actions[0]["title"],
+        "Rule `no-console-rule` is applicable to `call_expression`"
+      );
+      assert_eq!(actions[0]["kind"], "source.applicableRules.ast-grep");
+      // the rule is not read from a rule file
+      assert!(actions[0]["disabled"]["reason"].is_string());
+    }
+    for only in [Some("quickfix"), Some("source.applicable")] {
+      let response = request(
+        &mut req_client,
+        &mut resp_client,
+        "textDocument/codeAction",
+        params(only),
+      )
+      .await;
+      assert_eq!(response["result"], json!(null));
+    }
+    notify(
+      &mut req_client,
+      "workspace/didChangeConfiguration",
+      json!({ "settings": { "listApplicableRules": false } }),
+    )
+    .await;
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params(None),
+    )
+    .await;
+    assert_eq!(response["result"], json!(null));
+  });
+}
+
+#[test]
+fn test_applicable_rule_opens_rule_file() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let rule_path = dir.path().join("no-alert.yml");
+    let rule = "id: no-alert\nlanguage: TypeScript\nrule:\n  pattern: alert($A)";
+    std::fs::write(&rule_path, rule).unwrap();
+    let finder = MockRuleFinder::default();
+    finder.write(rule_path.to_str().unwrap(), rule);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "alert(a)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let position = json!({ "line": 0, "character": 1 });
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": { "start": position, "end": position },
+      "context": { "diagnostics": [], "only": ["source.applicableRules.ast-grep"] },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params,
+    )
+    .await;
+    let command = response["result"][0]["command"].clone();
+    assert_eq!(command["command"], "ast-grep.openRule");
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 43,
+      "method": "workspace/executeCommand",
+      "params": { "command": command["command"], "arguments": command["arguments"] },
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
       .unwrap();
+    let show = wait_for(&mut resp_client, |v| v["method"] == "window/showDocument")
+      .await
+      .expect("should show rule file");
+    assert!(show["params"]["uri"]
+      .as_str()
+      .unwrap()
+      .ends_with("/no-alert.yml"));
+    let selection = json!({
+      "start": { "line": 0, "character": 4 },
+      "end": { "line": 0, "character": 12 },
+    });
+    assert_eq!(show["params"]["selection"], selection);
+  });
+}
+
+#[test]
+fn test_code_action_partial_results() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "console.log(1)\n".repeat(150);
+    open_document(&mut req_client, "file:///test.ts", &text).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 150);
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": diagnostics[0]["range"],
+      "context": { "diagnostics": diagnostics },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params.clone(),
+    )
+    .await;
+    let expected = response["result"].as_array().unwrap().len();
+    let mut params = params;
+    params["partialResultToken"] = json!("partial");
+    let code_action = json!({
+      "jsonrpc": "2.0",
+      "id": 43,
+      "method": "textDocument/codeAction",
+      "params": params,
+    });
+    req_client
+      .write_all(req(&code_action.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let response = wait_for(&mut resp_client, |v| v["id"] == 43)
+      .await
+      .expect("should respond");
+    // streamed actions are not repeated in the response
+    assert_eq!(response["result"], json!([]));
+    // partial results are not ordered with the response, so wait until all actions arrive.
+    // Messages read so far are checked again after every read.
+    let partials = std::sync::Mutex::new(HashSet::new());
+    let count = |partials: &HashSet<String>| -> usize {
+      partials
+        .iter()
+        .map(|p| serde_json::from_str::<Vec<Value>>(p).unwrap().len())
+        .sum()
+    };
+    wait_for(&mut resp_client, |v| {
+      let mut partials = partials.lock().unwrap();
+      if v["method"] == "$/progress" && v["params"]["token"] == "partial" {
+        partials.insert(v["params"]["value"].to_string());
+      }
+      count(&partials) == expected
+    })
+    .await
+    .expect("should stream all actions");
+    // applicable rules and two chunks of quick fixes
+    assert_eq!(partials.into_inner().unwrap().len(), 3);
+  });
+}
+
+const NO_ALERT_RULE: &str = r"
+id: no-alert
+message: No alert
+severity: warning
+language: TypeScript
+rule:
+  pattern: alert($$$A)
+";
+
+#[test]
+fn test_reload_single_rule_file() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/console.yml", NO_CONSOLE_RULE);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder.clone());
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(
+      &mut req_client,
+      "file:///test.ts",
+      "console.log(1); alert(1)",
+    )
+    .await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-console-rule");
+    // only the changed rule file is re-read
+    finder.write("/rules/console.yml", NO_ALERT_RULE);
+    let changes = json!({ "changes": [{ "uri": "file:///rules/console.yml", "type": 2 }] });
+    notify(&mut req_client, "workspace/didChangeWatchedFiles", changes).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-alert");
+    // sgconfig.yml triggers a full reload
+    finder.write("/rules/console2.yml", NO_CONSOLE_RULE);
+    let changes = json!({ "changes": [{ "uri": "file:///sgconfig.yml", "type": 2 }] });
+    notify(&mut req_client, "workspace/didChangeWatchedFiles", changes).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 2);
+  });
+}
+
+#[test]
+fn test_reload_rule_with_same_id() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/console.yml", NO_CONSOLE_RULE);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder.clone());
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(1); 123").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics[0]["range"]["end"]["character"], 14);
+    // kinds of the previous scan must not be reused for the changed rule
+    let rule = "id: no-console-rule\nlanguage: TypeScript\nrule: { kind: number }";
+    finder.write("/rules/console.yml", rule);
+    let changes = json!({ "changes": [{ "uri": "file:///rules/console.yml", "type": 2 }] });
+    notify(&mut req_client, "workspace/didChangeWatchedFiles", changes).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    let numbers: Vec<_> = diagnostics
+      .iter()
+      .map(|d| d["range"]["start"]["character"].clone())
+      .collect();
+    assert_eq!(numbers, [12, 16]);
+  });
+}
+
+#[test]
+fn test_report_status() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "reportStatus": true });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    let status = wait_for(&mut resp_client, |v| v["method"] == "ast-grep/status")
+      .await
+      .expect("should report status");
+    assert_eq!(status["params"]["uri"], "file:///test.ts");
+    assert_eq!(status["params"]["version"], 1);
+    assert!(status["params"]["parseTime"].as_f64().is_some());
+    assert!(status["params"]["scanTime"].as_f64().is_some());
+  });
+}
+
+#[test]
+fn test_trace_timing() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///a.ts", "console.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    notify(&mut req_client, "$/setTrace", json!({ "value": "verbose" })).await;
+    open_document(&mut req_client, "file:///b.ts", "console.log(2)").await;
+    // timing of a.ts is not traced before the trace is on
+    let trace = wait_for(&mut resp_client, |v| v["method"] == "$/logTrace")
+      .await
+      .expect("should trace timing");
+    let message = trace["params"]["message"].as_str().unwrap();
+    assert!(
+      message.starts_with("file:///b.ts (version 1) parsed in"),
+      "{message}"
+    );
+  });
+}
+
+#[test]
+fn test_apply_fixes_return_text() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "let a = 1\nconsole.log(a)\nconsole.log(a, 2)\n";
+    let params = json!({
+      "command": "ast-grep.applyFixesReturnText",
+      "arguments": [{
+        "uri": "file:///test.ts",
+        "languageId": "typescript",
+        "version": 1,
+        "text": text,
+      }],
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      params,
+    )
+    .await;
+    // fix in NO_CONSOLE_RULE is a block scalar with a trailing newline
+    let expected = "let a = 1\nalert(a)\n\nalert(a, 2)\n\n";
+    assert_eq!(response["result"], expected);
+  });
+}
+
+#[test]
+fn test_fix_include_trivia() {
+  let rules = r"
+id: replace-function
+message: Replace function
+severity: warning
+language: TypeScript
+rule:
+  kind: function_declaration
+fix:
+  template: function replaced() {}
+  includeTrivia: true
+";
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "let a = 1\n/** doc */\nfunction test() {} // trailing\n";
+    let params = json!({
+      "command": "ast-grep.applyFixesReturnText",
+      "arguments": [{
+        "uri": "file:///test.ts",
+        "languageId": "typescript",
+        "version": 1,
+        "text": text,
+      }],
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      params,
+    )
+    .await;
+    assert_eq!(response["result"], "let a = 1\nfunction replaced() {}\n");
+  });
+}
+
+#[test]
+fn test_sort_diagnostics() {
+  let rules = r"
+id: no-foo
+message: No foo
+severity: warning
+language: TypeScript
+rule:
+  pattern: foo()
+---
+id: no-bar
+message: No bar
+severity: warning
+language: TypeScript
+rule:
+  pattern: bar()
+";
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "foo()\nbar()\nfoo()").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    let lines: Vec<_> = diagnostics
+      .iter()
+      .map(|d| d["range"]["start"]["line"].clone())
+      .collect();
+    assert_eq!(lines, vec![json!(0), json!(1), json!(2)]);
+  });
+}
+
+#[test]
+fn test_diagnose_text() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let params = json!({
+      "command": "ast-grep.diagnoseText",
+      "arguments": [{
+        "languageId": "typescript",
+        "text": "let a = 1\nconsole.log(a)",
+      }],
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      params,
+    )
+    .await;
+    let diagnostics = response["result"].as_array().expect("should be array");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-console-rule");
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+    // the document is not tracked by server
+    let params = json!({
+      "command": "ast-grep.diagnoseText",
+      "arguments": [{
+        "uri": "file:///unopened.ts",
+        "text": "console.log(1)",
+      }],
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      params,
+    )
+    .await;
+    assert_eq!(response["result"].as_array().map(Vec::len), Some(1));
+  });
+}
+
+#[test]
+fn test_severity_overrides() {
+  let rules = r#"
+id: no-console-rule
+message: No console.log
+severity: error
+language: TypeScript
+rule:
+  pattern: console.log($$$A)
+severityOverrides:
+  - files: ["**/legacy/**"]
+    severity: warning
+"#;
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///src/a.ts", "console.log(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    // DiagnosticSeverity::ERROR
+    assert_eq!(diagnostics[0]["severity"], 1);
+    open_document(&mut req_client, "file:///legacy/a.ts", "console.log(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    // DiagnosticSeverity::WARNING
+    assert_eq!(diagnostics[0]["severity"], 2);
+  });
+}
+
+#[test]
+fn test_same_id_rules_of_different_files() {
+  let rules = r#"
+id: dup
+message: m
+language: TypeScript
+files: ["**/a/**"]
+rule:
+  pattern: foo()
+---
+id: dup
+message: m
+language: TypeScript
+files: ["**/b/**"]
+rule:
+  kind: number
+"#;
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///a/x.ts", "foo()").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    // kinds of the scan with the other `dup` rule are not reused
+    open_document(&mut req_client, "file:///b/x.ts", "foo(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["character"], 4);
+  });
+}
 
+#[test]
+fn test_diagnostic_fingerprint() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///a.ts", "console.log(1)").await;
+    let before = wait_for_diagnostics(&mut resp_client).await;
+    let text = "let a = 1\n\nconsole.log(1)\nconsole.log(2)";
+    open_document(&mut req_client, "file:///b.ts", text).await;
+    let after = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(after.len(), 2);
+    // the same finding moved
+    assert_ne!(before[0]["range"], after[0]["range"]);
     assert_eq!(
-      running_command_resp["params"]["message"],
-      "Running ExecuteCommand ast-grep.applyAllFixes"
+      before[0]["data"]["fingerprint"],
+      after[0]["data"]["fingerprint"]
+    );
+    // a new finding
+    assert_ne!(
+      after[0]["data"]["fingerprint"],
+      after[1]["data"]["fingerprint"]
+    );
+    let fingerprint = after[0]["data"]["fingerprint"].as_str().unwrap();
+    assert!(fingerprint.starts_with("no-console-rule:"));
+  });
+}
+
+#[test]
+fn test_dump_config() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "reportStatus": true });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", "let a = 1").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let params = json!({
+      "command": "ast-grep.dumpConfig",
+      "arguments": [],
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      params,
+    )
+    .await;
+    let config = &response["result"];
+    assert_eq!(config["options"]["reportStatus"], true);
+    assert_eq!(config["options"]["sortDiagnostics"], true);
+    assert_eq!(config["rules"], json!(["no-console-rule"]));
+    assert_eq!(config["rulesError"], Value::Null);
+    assert_eq!(config["openDocuments"], 1);
+  });
+}
+
+#[test]
+fn test_dump_custom_config_path() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("sgconfig.yml"), "ruleDirs: []").unwrap();
+    std::fs::write(dir.path().join("custom.yml"), "ruleDirs: []").unwrap();
+    let base = dir.path().to_path_buf();
+    let (mut req_client, mut resp_client) = create_lsp_with_base(NO_CONSOLE_RULE, base);
+    let options = json!({ "configPath": "custom.yml" });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    let params = json!({ "command": "ast-grep.dumpConfig", "arguments": [] });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      params,
+    )
+    .await;
+    let config_file = response["result"]["configFile"].as_str().unwrap();
+    assert_eq!(Path::new(config_file), dir.path().join("custom.yml"));
+  });
+}
+
+#[test]
+fn test_multi_statement_pattern() {
+  let rules = r"
+id: parallel-await
+message: Use Promise.all
+severity: warning
+language: TypeScript
+rule:
+  pattern: const $A = await $X; const $B = await $Y;
+fix: const [$A, $B] = await Promise.all([$X, $Y]);
+";
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "const a = await foo();\nconst b = await bar();\n";
+    open_document(&mut req_client, "file:///test.ts", text).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    let range = &diagnostics[0]["range"];
+    assert_eq!(range["start"], json!({"line": 0, "character": 0}));
+    assert_eq!(range["end"], json!({"line": 1, "character": 22}));
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": range,
+      "context": { "diagnostics": diagnostics },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params,
+    )
+    .await;
+    let edit = &response["result"][0]["edit"]["changes"]["file:///test.ts"][0];
+    assert_eq!(
+      edit["newText"],
+      "const [a, b] = await Promise.all([foo(), bar()]);"
     );
   });
 }
+
+#[test]
+fn test_max_message_length() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "maxMessageLength": 5 });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics[0]["message"], "No c…");
+    assert_eq!(diagnostics[0]["data"]["message"], "No console.log");
+  });
+}
+
+#[test]
+fn test_language_mapping() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "languageMapping": { "ts2": "typescript" } });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts2", "console.log(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-console-rule");
+  });
+}
+
+#[test]
+fn test_report_unsupported_language() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "reportUnsupportedLanguage": true });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.unknown", "console.log(1)").await;
+    let shown = wait_for(&mut resp_client, |v| v["method"] == "window/showMessage")
+      .await
+      .expect("should show message");
+    let message = shown["params"]["message"].as_str().unwrap();
+    assert!(message.contains("`.unknown` files"), "{message}");
+  });
+}
+
+#[test]
+fn test_incremental_change() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let result = initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let result = result.expect("should initialize");
+    assert_eq!(
+      result["result"]["capabilities"]["textDocumentSync"]["change"],
+      2
+    );
+    open_document(&mut req_client, "file:///test.ts", "let a = 1\nfoo(a)").await;
+    assert!(wait_for_diagnostics(&mut resp_client).await.is_empty());
+    let range = |line, start, end| {
+      json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+      })
+    };
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts", "version": 2 },
+      "contentChanges": [
+        { "range": range(1, 0, 3), "text": "console.log" },
+        { "range": range(0, 8, 9), "text": "2" },
+      ],
+    });
+    notify(&mut req_client, "textDocument/didChange", params).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"], range(1, 0, 14));
+  });
+}
+
+#[test]
+fn test_position_encoding() {
+  // "😀" is 4 UTF-8 bytes, 2 UTF-16 code units and 1 UTF-32 char
+  let cases = [
+    (json!(null), "utf-16", 14),
+    (json!(["utf-32", "utf-8"]), "utf-32", 13),
+    (json!(["utf-8"]), "utf-8", 16),
+  ];
+  for (encodings, expected, column) in cases {
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+      let (mut req_client, mut resp_client) = create_lsp();
+      let initialize = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": { "capabilities": { "general": { "positionEncodings": encodings } } },
+      });
+      req_client
+        .write_all(req(&initialize.to_string()).as_bytes())
+        .await
+        .unwrap();
+      let result = wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+      let encoding = &result["result"]["capabilities"]["positionEncoding"];
+      assert_eq!(encoding, expected);
+      let text = "let s = \"😀\"; foo(1)";
+      open_document(&mut req_client, "file:///test.ts", text).await;
+      assert!(wait_for_diagnostics(&mut resp_client).await.is_empty());
+      let range = |start, end| {
+        json!({
+          "start": { "line": 0, "character": start },
+          "end": { "line": 0, "character": end },
+        })
+      };
+      let params = json!({
+        "textDocument": { "uri": "file:///test.ts", "version": 2 },
+        "contentChanges": [{ "range": range(column, column + 3), "text": "console.log" }],
+      });
+      notify(&mut req_client, "textDocument/didChange", params).await;
+      let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+      assert_eq!(diagnostics.len(), 1);
+      assert_eq!(diagnostics[0]["range"], range(column, column + 14));
+    });
+  }
+}
+
+#[test]
+fn test_notebook_cells() {
+  let rules =
+    "id: no-print\nmessage: No print\nlanguage: Python\nrule:\n  pattern: print($A)\nfix: log($A)";
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    let options = json!({ "notebookSync": true });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    notify(&mut req_client, "initialized", json!({})).await;
+    let registration = wait_for(&mut resp_client, |v| {
+      v["method"] == "client/registerCapability"
+    })
+    .await
+    .expect("should register notebook sync");
+    let registration = &registration["params"]["registrations"][0];
+    assert_eq!(registration["method"], "notebookDocument/sync");
+    let cell = |id: &str| format!("vscode-notebook-cell:/test.ipynb#{id}");
+    let params = json!({
+      "notebookDocument": {
+        "uri": "file:///test.ipynb",
+        "notebookType": "jupyter-notebook",
+        "version": 1,
+        "cells": [
+          { "kind": 1, "document": cell("md") },
+          { "kind": 2, "document": cell("code") },
+        ],
+      },
+      "cellTextDocuments": [
+        { "uri": cell("md"), "languageId": "markdown", "version": 1, "text": "# print(1)" },
+        { "uri": cell("code"), "languageId": "python", "version": 1, "text": "print(1)" },
+      ],
+    });
+    notify(&mut req_client, "notebookDocument/didOpen", params).await;
+    let published = wait_for(&mut resp_client, |v| {
+      v["method"] == "textDocument/publishDiagnostics"
+    })
+    .await
+    .expect("should publish diagnostics of the code cell");
+    assert_eq!(published["params"]["uri"], cell("code"));
+    assert_eq!(published["params"]["diagnostics"][0]["code"], "no-print");
+    let range = &published["params"]["diagnostics"][0]["range"];
+    let params = json!({
+      "notebookDocument": { "uri": "file:///test.ipynb", "version": 2 },
+      "change": {
+        "cells": {
+          "textContent": [{
+            "document": { "uri": cell("code"), "version": 2 },
+            "changes": [{ "range": range, "text": "x = 1" }],
+          }],
+        },
+      },
+    });
+    notify(&mut req_client, "notebookDocument/didChange", params).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert!(diagnostics.is_empty());
+  });
+}
+
+#[test]
+fn test_refresh_pull_diagnostics_on_configuration_change() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": {
+          "textDocument": { "diagnostic": {} },
+          "workspace": { "diagnostic": { "refreshSupport": true } },
+        },
+        "initializationOptions": { "reportStatus": true },
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    wait_for(&mut resp_client, |v| v["method"] == "ast-grep/status").await;
+    let settings = json!({ "settings": { "ruleSeverityOverrides": { "no-console-rule": "off" } } });
+    notify(
+      &mut req_client,
+      "workspace/didChangeConfiguration",
+      settings,
+    )
+    .await;
+    let refresh = wait_for(&mut resp_client, |v| {
+      v["method"] == "workspace/diagnostic/refresh"
+    })
+    .await
+    .expect("should ask client to pull diagnostics again");
+    let response = json!({ "jsonrpc": "2.0", "id": refresh["id"], "result": null });
+    req_client
+      .write_all(req(&response.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let params = json!({ "textDocument": { "uri": "file:///test.ts" } });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/diagnostic",
+      params,
+    )
+    .await;
+    assert_eq!(response["result"]["items"], json!([]));
+  });
+}
+
+#[test]
+fn test_pull_diagnostics() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": { "textDocument": { "diagnostic": {} } },
+        "initializationOptions": { "reportStatus": true },
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let result = wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    let provider = &result["result"]["capabilities"]["diagnosticProvider"];
+    assert_eq!(provider["identifier"], "ast-grep");
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    // diagnostics are not published to pull clients, wait until the document is scanned
+    wait_for(&mut resp_client, |v| v["method"] == "ast-grep/status").await;
+    let params = json!({ "textDocument": { "uri": "file:///test.ts" } });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/diagnostic",
+      params,
+    )
+    .await;
+    let report = &response["result"];
+    assert_eq!(report["kind"], "full");
+    assert_eq!(report["items"][0]["code"], "no-console-rule");
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "previousResultId": report["resultId"],
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/diagnostic",
+      params,
+    )
+    .await;
+    assert_eq!(response["result"]["kind"], "unchanged");
+    assert_eq!(response["result"]["resultId"], report["resultId"]);
+  });
+}
+
+#[test]
+fn test_exclude_globs() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().to_path_buf();
+    std::fs::create_dir_all(base.join("src")).unwrap();
+    std::fs::create_dir_all(base.join("dist")).unwrap();
+    std::fs::write(base.join("src/a.ts"), "console.log(1)").unwrap();
+    std::fs::write(base.join("src/a.min.ts"), "console.log(1)").unwrap();
+    std::fs::write(base.join("dist/a.ts"), "console.log(1)").unwrap();
+    let (mut req_client, mut resp_client) = create_lsp_with_base(NO_CONSOLE_RULE, base.clone());
+    let options = json!({ "exclude": ["dist/**", "**/*.min.ts"] });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    let params = json!({ "previousResultIds": [] });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/diagnostic",
+      params,
+    )
+    .await;
+    let items = response["result"]["items"].as_array().unwrap().clone();
+    assert_eq!(items.len(), 1);
+    assert!(items[0]["uri"].as_str().unwrap().ends_with("src/a.ts"));
+    // open documents are not scanned either
+    let uri = format!("file://{}", base.join("dist/a.ts").display());
+    open_document(&mut req_client, &uri, "console.log(1)").await;
+    assert!(wait_for_diagnostics(&mut resp_client).await.is_empty());
+  });
+}
+
+#[test]
+fn test_workspace_diagnostic() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().to_path_buf();
+    std::fs::write(base.join("a.ts"), "console.log(1)").unwrap();
+    std::fs::write(base.join("b.ts"), "let a = 1").unwrap();
+    std::fs::write(base.join("c.txt"), "console.log(1)").unwrap();
+    let (mut req_client, mut resp_client) = create_lsp_with_base(NO_CONSOLE_RULE, base);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let params = json!({ "previousResultIds": [] });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/diagnostic",
+      params,
+    )
+    .await;
+    let items = response["result"]["items"].as_array().unwrap().clone();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["kind"], "full");
+    assert!(items[0]["uri"].as_str().unwrap().ends_with("a.ts"));
+    assert_eq!(items[0]["items"][0]["code"], "no-console-rule");
+    // stream reports with partial result token
+    let params = json!({
+      "previousResultIds": [{ "uri": items[0]["uri"], "value": items[0]["resultId"] }],
+      "partialResultToken": "partial",
+    });
+    let request = json!({
+      "jsonrpc": "2.0",
+      "id": 43,
+      "method": "workspace/diagnostic",
+      "params": params,
+    });
+    req_client
+      .write_all(req(&request.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let progress = wait_for(&mut resp_client, |v| v["method"] == "$/progress")
+      .await
+      .expect("should stream partial result");
+    assert_eq!(progress["params"]["token"], "partial");
+    assert_eq!(progress["params"]["value"]["items"][0]["kind"], "unchanged");
+  });
+}
+
+#[test]
+fn test_hover() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let rules = format!("{NO_CONSOLE_RULE}url: https://ast-grep.github.io/\n");
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "let a = 1\nconsole.log(a)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let hover_at = |line, character| {
+      json!({
+        "textDocument": { "uri": "file:///test.ts" },
+        "position": { "line": line, "character": character },
+      })
+    };
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/hover",
+      hover_at(1, 3),
+    )
+    .await;
+    let hover = &response["result"];
+    assert_eq!(hover["contents"]["kind"], "markdown");
+    assert_eq!(
+      hover["contents"]["value"],
+      "**no-console-rule**: No console.log\n\nno console.log\n\n[Documentation](https://ast-grep.github.io/)"
+    );
+    assert_eq!(hover["range"]["start"]["line"], 1);
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/hover",
+      hover_at(0, 3),
+    )
+    .await;
+    assert!(response["result"].is_null());
+  });
+}
+
+#[test]
+fn test_code_action_resolve() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": {
+          "textDocument": {
+            "codeAction": { "resolveSupport": { "properties": ["edit"] } },
+          },
+        },
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert!(diagnostics[0]["data"].get("fixed").is_none());
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": diagnostics[0]["range"],
+      "context": { "diagnostics": diagnostics },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params,
+    )
+    .await;
+    let action = response["result"][0].clone();
+    assert_eq!(action["title"], "Fix `no-console-rule` with ast-grep");
+    assert!(action.get("edit").is_none());
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "codeAction/resolve",
+      action.clone(),
+    )
+    .await;
+    let edit = &response["result"]["edit"]["changes"]["file:///test.ts"][0];
+    assert_eq!(edit["newText"], "alert(1)\n");
+    // the rule is turned off after the action is listed
+    let settings = json!({ "ruleSeverityOverrides": { "no-console-rule": "off" } });
+    let params = json!({ "settings": settings });
+    notify(&mut req_client, "workspace/didChangeConfiguration", params).await;
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "codeAction/resolve",
+      action,
+    )
+    .await;
+    assert!(response["result"].get("edit").is_none());
+  });
+}
+
+#[test]
+fn test_register_file_watchers() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("rules/a.yml", NO_CONSOLE_RULE);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": {
+          "workspace": { "didChangeWatchedFiles": { "dynamicRegistration": true } },
+        },
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    notify(&mut req_client, "initialized", json!({})).await;
+    let registration = wait_for(&mut resp_client, |v| {
+      v["method"] == "client/registerCapability"
+    })
+    .await
+    .expect("should register watchers");
+    let registration = &registration["params"]["registrations"][0];
+    assert_eq!(registration["method"], "workspace/didChangeWatchedFiles");
+    let watchers = &registration["registerOptions"]["watchers"];
+    assert_eq!(watchers[0]["globPattern"], "**/sgconfig.yml");
+  });
+}
+
+#[test]
+fn test_did_change_configuration() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    // DiagnosticSeverity::WARNING
+    assert_eq!(diagnostics[0]["severity"], 2);
+    let settings = json!({
+      "astGrep": { "ruleSeverityOverrides": { "no-console-rule": "error" } }
+    });
+    let params = json!({ "settings": settings });
+    notify(&mut req_client, "workspace/didChangeConfiguration", params).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    // DiagnosticSeverity::ERROR
+    assert_eq!(diagnostics[0]["severity"], 1);
+    let params = json!({ "settings": { "disabledRules": ["no-console-rule"] } });
+    notify(&mut req_client, "workspace/didChangeConfiguration", params).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert!(diagnostics.is_empty());
+  });
+}
+
+#[test]
+fn test_invalid_settings() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let settings = json!({
+      "maxMessageLength": "long",
+      "ruleSeverityOverrides": { "no-console-rule": "error" },
+    });
+    let params = json!({ "settings": settings });
+    notify(&mut req_client, "workspace/didChangeConfiguration", params).await;
+    let message = wait_for(&mut resp_client, |v| v["method"] == "window/showMessage")
+      .await
+      .expect("should report invalid settings");
+    // MessageType::WARNING
+    assert_eq!(message["params"]["type"], 2);
+    let text = message["params"]["message"].as_str().unwrap();
+    assert!(text.contains("maxMessageLength"));
+    assert!(!text.contains("ruleSeverityOverrides"));
+    // the valid setting is still applied
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics[0]["severity"], 1);
+  });
+}
+
+#[test]
+fn test_reregister_file_watchers() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("rules/a.yml", NO_CONSOLE_RULE);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": {
+          "workspace": { "didChangeWatchedFiles": { "dynamicRegistration": true } },
+        },
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    notify(&mut req_client, "initialized", json!({})).await;
+    let registration = wait_for(&mut resp_client, |v| {
+      v["method"] == "client/registerCapability"
+    })
+    .await
+    .expect("should register watchers");
+    let response = json!({ "jsonrpc": "2.0", "id": registration["id"], "result": null });
+    req_client
+      .write_all(req(&response.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let params = json!({ "settings": { "configPath": "custom.yml" } });
+    notify(&mut req_client, "workspace/didChangeConfiguration", params).await;
+    let unregistration = wait_for(&mut resp_client, |v| {
+      v["method"] == "client/unregisterCapability"
+    })
+    .await
+    .expect("should stop watching the old config");
+    let removed = &unregistration["params"]["unregisterations"][0];
+    assert_eq!(removed["id"], "ast-grep-rule-watcher");
+    let response = json!({ "jsonrpc": "2.0", "id": unregistration["id"], "result": null });
+    req_client
+      .write_all(req(&response.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let registration = wait_for(&mut resp_client, |v| {
+      v["method"] == "client/registerCapability"
+    })
+    .await
+    .expect("should watch the new config");
+    let registration = &registration["params"]["registrations"][0];
+    assert_eq!(registration["id"], "ast-grep-rule-watcher");
+  });
+}
+
+#[test]
+fn test_workspace_folders() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/console.yml", NO_CONSOLE_RULE);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder.clone());
+    // rules of the folder are found after the default rules
+    finder.write("/project/rules/alert.yml", NO_ALERT_RULE);
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": {},
+        "workspaceFolders": [{ "uri": "file:///project", "name": "project" }],
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    let source = "console.log(1); alert(1)";
+    open_document(&mut req_client, "file:///project/test.ts", source).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-alert");
+    let removed = json!({
+      "event": { "added": [], "removed": [{ "uri": "file:///project", "name": "project" }] }
+    });
+    notify(
+      &mut req_client,
+      "workspace/didChangeWorkspaceFolders",
+      removed,
+    )
+    .await;
+    // documents of the removed folder are dropped
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert!(diagnostics.is_empty());
+    let added = json!({
+      "event": { "added": [{ "uri": "file:///project", "name": "project" }], "removed": [] }
+    });
+    notify(
+      &mut req_client,
+      "workspace/didChangeWorkspaceFolders",
+      added,
+    )
+    .await;
+    open_document(&mut req_client, "file:///project/test.ts", source).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-alert");
+  });
+}
+
+#[test]
+fn test_code_lens() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let source = "let a = 1\nconsole.log(1); console.log(2)";
+    open_document(&mut req_client, "file:///test.ts", source).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let params = json!({ "textDocument": { "uri": "file:///test.ts" } });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeLens",
+      params,
+    )
+    .await;
+    let lenses = response["result"].as_array().expect("should return lenses");
+    assert_eq!(lenses.len(), 1);
+    assert_eq!(lenses[0]["range"]["start"]["line"], 1);
+    let command = &lenses[0]["command"];
+    assert_eq!(
+      command["title"],
+      "Fix all 2 occurrences of rule `no-console-rule`"
+    );
+    assert_eq!(command["command"], "ast-grep.applyRuleFixes");
+    let params = json!({
+      "command": command["command"],
+      "arguments": command["arguments"],
+    });
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 2,
+      "method": "workspace/executeCommand",
+      "params": params,
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let apply_edit = wait_for(&mut resp_client, |v| v["method"] == "workspace/applyEdit")
+      .await
+      .expect("should apply edit");
+    let edits = &apply_edit["params"]["edit"]["changes"]["file:///test.ts"];
+    assert_eq!(edits.as_array().map(Vec::len), Some(2));
+    assert_eq!(edits[0]["newText"], "alert(1)\n");
+  });
+}
+
+#[test]
+fn test_fix_all_ast_grep_kind() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let position = json!({ "line": 0, "character": 0 });
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": { "start": position, "end": position },
+      "context": { "diagnostics": [], "only": ["source.fixAll.ast-grep"] },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params,
+    )
+    .await;
+    let actions = response["result"].as_array().expect("should have actions");
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0]["kind"], "source.fixAll.ast-grep");
+    let edits = &actions[0]["edit"]["changes"]["file:///test.ts"];
+    assert_eq!(edits[0]["newText"], "alert(1)\n");
+  });
+}
+
+#[test]
+fn test_suppression_code_action() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let source = "function f() {\n  console.log(1)\n}";
+    open_document(&mut req_client, "file:///test.ts", source).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": diagnostics[0]["range"],
+      "context": { "diagnostics": diagnostics },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params,
+    )
+    .await;
+    let actions = response["result"].as_array().expect("should have actions");
+    let suppression = actions
+      .iter()
+      .find(|a| a["title"] == "Suppress `no-console-rule` for this line")
+      .expect("should suppress");
+    let edit = &suppression["edit"]["changes"]["file:///test.ts"][0];
+    assert_eq!(edit["range"]["start"], json!({ "line": 1, "character": 0 }));
+    assert_eq!(edit["newText"], "  // ast-grep-ignore: no-console-rule\n");
+  });
+}
+
+#[test]
+fn test_suppression_code_action_block_comment() {
+  let rules = "id: no-red\nmessage: m\nlanguage: css\nrule: {kind: declaration}\n---\nid: no-div\nmessage: m\nlanguage: html\nrule: {kind: element}\n---\nid: no-print\nmessage: m\nlanguage: python\nrule: {pattern: print($A)}";
+  let cases = [
+    (
+      "file:///test.css",
+      "a {\n  color: red;\n}",
+      "  /* ast-grep-ignore: no-red */\n",
+    ),
+    (
+      "file:///test.html",
+      "<div></div>",
+      "<!-- ast-grep-ignore: no-div -->\n",
+    ),
+    // the comment syntax follows the mapped language, not the extension
+    (
+      "file:///test.star",
+      "print(1)",
+      "# ast-grep-ignore: no-print\n",
+    ),
+  ];
+  let options = json!({ "languageMapping": { "star": "python" } });
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    for (uri, source, comment) in cases {
+      let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+      initialize_lsp_with_options(&mut req_client, &mut resp_client, options.clone()).await;
+      open_document(&mut req_client, uri, source).await;
+      let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+      assert_eq!(diagnostics.len(), 1);
+      let params = json!({
+        "textDocument": { "uri": uri },
+        "range": diagnostics[0]["range"],
+        "context": { "diagnostics": diagnostics },
+      });
+      let response = request(
+        &mut req_client,
+        &mut resp_client,
+        "textDocument/codeAction",
+        params,
+      )
+      .await;
+      let actions = response["result"].as_array().expect("should have actions");
+      let suppression = actions
+        .iter()
+        .find(|a| a["title"].as_str().unwrap().starts_with("Suppress"))
+        .expect("should suppress");
+      let edit = &suppression["edit"]["changes"][uri][0];
+      assert_eq!(edit["newText"], comment);
+      // the inserted comment suppresses the finding
+      let line = edit["range"]["start"]["line"].as_u64().unwrap() as usize;
+      let mut lines: Vec<_> = source.lines().map(|l| format!("{l}\n")).collect();
+      lines.insert(line, comment.to_string());
+      let change = json!({
+        "textDocument": { "uri": uri, "version": 2 },
+        "contentChanges": [{ "text": lines.concat() }],
+      });
+      notify(&mut req_client, "textDocument/didChange", change).await;
+      let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+      assert!(diagnostics.is_empty(), "{uri}: {diagnostics:?}");
+    }
+  });
+}
+
+#[test]
+fn test_report_suppressed() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let source = "// ast-grep-ignore\nconsole.log(1)\nconsole.log(2)";
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", source).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 2);
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "reportSuppressed": true });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", source).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 2);
+    // DiagnosticSeverity::HINT
+    assert_eq!(diagnostics[0]["severity"], 4);
+    assert_eq!(diagnostics[0]["message"], "Suppressed: No console.log");
+    assert_eq!(diagnostics[0]["data"]["suppressed"], true);
+    assert_eq!(diagnostics[1]["severity"], 2);
+  });
+}
+
+#[test]
+fn test_formatting() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let source = "console.log(1)\nconsole.log(2)";
+    open_document(&mut req_client, "file:///test.ts", source).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let options = json!({ "tabSize": 2, "insertSpaces": true });
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "options": options,
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/formatting",
+      params,
+    )
+    .await;
+    let edits = response["result"].as_array().expect("should have edits");
+    assert_eq!(edits.len(), 2);
+    let range = json!({
+      "start": { "line": 1, "character": 0 },
+      "end": { "line": 1, "character": 14 },
+    });
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": range,
+      "options": options,
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/rangeFormatting",
+      params,
+    )
+    .await;
+    let edits = response["result"].as_array().expect("should have edits");
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0]["range"]["start"]["line"], 1);
+  });
+}
+
+#[test]
+fn test_rule_severity() {
+  let rules = r"
+id: info-rule
+message: info
+severity: info
+language: TypeScript
+rule:
+  pattern: console.log($$$A)
+---
+id: hint-rule
+message: hint
+severity: hint
+language: TypeScript
+rule:
+  pattern: alert($$$A)
+---
+id: off-rule
+message: off
+severity: off
+language: TypeScript
+rule:
+  pattern: debugger
+";
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let source = "console.log(1)\nalert(1)\ndebugger";
+    open_document(&mut req_client, "file:///test.ts", source).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    // rules turned off are not reported
+    assert_eq!(diagnostics.len(), 2);
+    // DiagnosticSeverity::INFORMATION
+    assert_eq!(diagnostics[0]["severity"], 3);
+    // DiagnosticSeverity::HINT
+    assert_eq!(diagnostics[1]["severity"], 4);
+  });
+}
+
+#[test]
+fn test_label_related_information() {
+  let rules = r"
+id: no-reassign
+message: Reassigned variable
+severity: warning
+language: TypeScript
+rule:
+  pattern: $A = $B
+  inside:
+    stopBy: end
+    kind: program
+    has:
+      stopBy: end
+      pattern: let $A = $C
+labels:
+  B: assigned here
+";
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "let a = 1\na = 2").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    let related = diagnostics[0]["relatedInformation"]
+      .as_array()
+      .expect("should have related information");
+    let labeled = related
+      .iter()
+      .find(|r| r["message"] == "assigned here")
+      .expect("should have label");
+    let range = &labeled["location"]["range"];
+    assert_eq!(range["start"], json!({ "line": 1, "character": 4 }));
+    assert_eq!(range["end"], json!({ "line": 1, "character": 5 }));
+  });
+}
+
+#[test]
+fn test_work_done_progress() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    std::fs::write(dir.path().join("a.ts"), "console.log(1)").unwrap();
+    let base = dir.path().to_path_buf();
+    let (mut req_client, mut resp_client) = create_lsp_with_base(NO_CONSOLE_RULE, base);
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": { "capabilities": { "window": { "workDoneProgress": true } } }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    let request = json!({
+      "jsonrpc": "2.0",
+      "id": 43,
+      "method": "workspace/diagnostic",
+      "params": { "previousResultIds": [] },
+    });
+    req_client
+      .write_all(req(&request.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let create = wait_for(&mut resp_client, |v| {
+      v["method"] == "window/workDoneProgress/create"
+    })
+    .await
+    .expect("should create progress token");
+    let created = json!({ "jsonrpc": "2.0", "id": create["id"], "result": null });
+    req_client
+      .write_all(req(&created.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 43)
+      .await
+      .expect("should respond");
+    // the end notification may arrive after the response
+    for kind in ["begin", "end"] {
+      let progress = wait_for(&mut resp_client, |v| {
+        v["method"] == "$/progress" && v["params"]["value"]["kind"] == kind
+      })
+      .await
+      .expect("should report progress");
+      assert_eq!(progress["params"]["token"], create["params"]["token"]);
+    }
+  });
+}
+
+/// Run `ast-grep.scanWorkspace` and wait for its response and the diagnostics of `file`.
+/// Notifications may be sent after the response.
+async fn scan_workspace(
+  req_client: &mut DuplexStream,
+  resp_client: &mut ServerOutput,
+  id: i64,
+  file: &str,
+) -> (Value, Value) {
+  let params = json!({ "command": "ast-grep.scanWorkspace", "arguments": [] });
+  let execute = json!({
+    "jsonrpc": "2.0",
+    "id": id,
+    "method": "workspace/executeCommand",
+    "params": params,
+  });
+  req_client
+    .write_all(req(&execute.to_string()).as_bytes())
+    .await
+    .unwrap();
+  // wait for each separately, a publish seen while waiting for both would be replayed later
+  let response = wait_for(resp_client, |v| v["id"] == id)
+    .await
+    .expect("should respond");
+  let published = wait_for(resp_client, |v| {
+    v["method"] == "textDocument/publishDiagnostics"
+      && v["params"]["uri"].as_str().unwrap().ends_with(file)
+  })
+  .await
+  .expect("should publish");
+  let published = published["params"].clone();
+  (response, published)
+}
+
+#[test]
+fn test_scan_workspace() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().to_path_buf();
+    std::fs::write(base.join("a.ts"), "console.log(1); console.log(2)").unwrap();
+    std::fs::write(base.join("b.ts"), "let a = 1").unwrap();
+    let (mut req_client, mut resp_client) = create_lsp_with_base(NO_CONSOLE_RULE, base.clone());
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let (response, published) = scan_workspace(&mut req_client, &mut resp_client, 42, "a.ts").await;
+    assert_eq!(response["result"]["scannedFiles"], 2);
+    assert_eq!(response["result"]["filesWithFindings"], 1);
+    assert_eq!(response["result"]["findings"], 2);
+    assert_eq!(published["diagnostics"].as_array().unwrap().len(), 2);
+    // findings fixed since the last scan are cleared
+    std::fs::write(base.join("a.ts"), "let b = 2").unwrap();
+    let (response, published) = scan_workspace(&mut req_client, &mut resp_client, 43, "a.ts").await;
+    assert_eq!(response["result"]["findings"], 0);
+    assert_eq!(published["diagnostics"], json!([]));
+  });
+}
+
+#[test]
+fn test_untitled_document() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "untitled:Untitled-1", "console.log(1)").await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-console-rule");
+  });
+}
+
+#[test]
+fn test_rule_completion() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    let rule = "id: a\nlanguage: TypeScript\nutils:\n  local-util:\n    kind: number\nrule:\n  kind: \n  matches: \n  ";
+    finder.write("/rules/a.yml", "id: a\nlanguage: TypeScript\nrule: {pattern: a}");
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///rules/a.yml", rule).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let complete = |line, character| {
+      json!({
+        "textDocument": { "uri": "file:///rules/a.yml" },
+        "position": { "line": line, "character": character },
+      })
+    };
+    let labels = |v: Value| -> Vec<String> {
+      v["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["label"].as_str().unwrap().to_string())
+        .collect()
+    };
+    let kinds = request(&mut req_client, &mut resp_client, "textDocument/completion", complete(6, 8)).await;
+    let kinds = labels(kinds);
+    assert!(kinds.contains(&"call_expression".to_string()));
+    assert!(!kinds.contains(&"(".to_string()));
+    let ids = request(&mut req_client, &mut resp_client, "textDocument/completion", complete(7, 11)).await;
+    assert_eq!(labels(ids), ["local-util", "global-util"]);
+    let keys = request(&mut req_client, &mut resp_client, "textDocument/completion", complete(8, 2)).await;
+    assert!(labels(keys).contains(&"pattern".to_string()));
+    let keys = request(&mut req_client, &mut resp_client, "textDocument/completion", complete(0, 0)).await;
+    assert!(labels(keys).contains(&"severity".to_string()));
+  });
+}
+
+#[test]
+fn test_document_symbols() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/a.yml", "id: a\nlanguage: TypeScript\nrule: {pattern: a}");
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let rule = "id: a\nlanguage: TypeScript\nutils:\n  is-num:\n    kind: number\nrule: {matches: is-num}\n---\nid: b\nlanguage: TypeScript\nrule: {pattern: b}\n";
+    open_document(&mut req_client, "file:///rules/a.yml", rule).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let symbols = |uri| json!({ "textDocument": { "uri": uri } });
+    let outline = request(&mut req_client, &mut resp_client, "textDocument/documentSymbol", symbols("file:///rules/a.yml")).await;
+    let outline = outline["result"].as_array().unwrap().clone();
+    assert_eq!(outline.len(), 2);
+    assert_eq!(outline[0]["name"], "a");
+    assert_eq!(outline[0]["selectionRange"]["start"], json!({ "line": 0, "character": 4 }));
+    assert_eq!(outline[0]["children"][0]["name"], "is-num");
+    assert_eq!(outline[1]["name"], "b");
+    assert_eq!(outline[1]["range"]["start"]["line"], 6);
+    let test = "id: a\nvalid:\n- '1'\ninvalid:\n  - |\n    let a = 1\n  - a(2)\n";
+    open_document(&mut req_client, "file:///rule-tests/a-test.yml", test).await;
+    let outline = request(&mut req_client, &mut resp_client, "textDocument/documentSymbol", symbols("file:///rule-tests/a-test.yml")).await;
+    let cases: Vec<_> = outline["result"][0]["children"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|c| (c["name"].as_str().unwrap(), c["detail"].as_str().unwrap()))
+      .collect();
+    assert_eq!(cases, [("1", "valid"), ("let a = 1", "invalid"), ("a(2)", "invalid")]);
+  });
+}
+
+#[test]
+fn test_goto_rule_definition() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let rule_path = dir.path().join("no-alert.yml");
+    let rule = "# avoid alert\nid: no-alert\nlanguage: TypeScript\nrule:\n  pattern: alert($A)";
+    std::fs::write(&rule_path, rule).unwrap();
+    let finder = MockRuleFinder::default();
+    finder.write(rule_path.to_str().unwrap(), rule);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "let a = 1\nalert(a)").await;
+    assert_eq!(wait_for_diagnostics(&mut resp_client).await.len(), 1);
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "position": { "line": 1, "character": 2 },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/definition",
+      params,
+    )
+    .await;
+    let locations = response["result"].as_array().expect("should find rule");
+    assert_eq!(locations.len(), 1);
+    let uri = locations[0]["uri"].as_str().unwrap();
+    assert!(uri.ends_with("/no-alert.yml"), "{uri}");
+    let range = json!({
+      "start": { "line": 1, "character": 4 },
+      "end": { "line": 1, "character": 12 },
+    });
+    assert_eq!(locations[0]["range"], range);
+  });
+}
+
+#[test]
+fn test_pattern_hover() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/a.yml", "id: a\nlanguage: TypeScript\nrule: {pattern: a}");
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let rule = "id: a\nlanguage: TypeScript\nrule:\n  any:\n    - pattern: console.log($A)\n    - pattern: |\n        let $_ = 1\n        foo($B)\n";
+    open_document(&mut req_client, "file:///rules/a.yml", rule).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let hover = |line| {
+      json!({
+        "textDocument": { "uri": "file:///rules/a.yml" },
+        "position": { "line": line, "character": 8 },
+      })
+    };
+    let response = request(&mut req_client, &mut resp_client, "textDocument/hover", hover(4)).await;
+    let value = response["result"]["contents"]["value"].as_str().unwrap();
+    assert!(value.contains("call_expression\n  member_expression"), "{value}");
+    assert!(value.contains("property_identifier `log`"), "{value}");
+    assert!(value.contains("$A (meta variable)"), "{value}");
+    let response = request(&mut req_client, &mut resp_client, "textDocument/hover", hover(5)).await;
+    let value = response["result"]["contents"]["value"].as_str().unwrap();
+    assert!(value.contains("lexical_declaration"), "{value}");
+    assert!(value.contains("$_ (meta variable)"), "{value}");
+    // every statement of a multi-statement pattern is shown
+    assert!(value.contains("expression_statement\n  call_expression"), "{value}");
+    assert!(value.contains("$B (meta variable)"), "{value}");
+    let response = request(&mut req_client, &mut resp_client, "textDocument/hover", hover(0)).await;
+    assert!(response["result"].is_null());
+  });
+}
+
+#[test]
+fn test_apply_all_fixes_versioned() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": { "workspace": { "workspaceEdit": { "documentChanges": true } } },
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let execute = |version| {
+      let document = json!({
+        "uri": "file:///test.ts",
+        "languageId": "typescript",
+        "version": version,
+        "text": "console.log(1)",
+      });
+      let params = json!({ "command": "ast-grep.applyAllFixes", "arguments": [document] });
+      json!({
+        "jsonrpc": "2.0",
+        "id": 42,
+        "method": "workspace/executeCommand",
+        "params": params,
+      })
+    };
+    req_client
+      .write_all(req(&execute(1).to_string()).as_bytes())
+      .await
+      .unwrap();
+    let apply = wait_for(&mut resp_client, |v| v["method"] == "workspace/applyEdit")
+      .await
+      .expect("should apply edit");
+    let edit = &apply["params"]["edit"];
+    assert!(edit["changes"].is_null());
+    let document_change = &edit["documentChanges"][0];
+    assert_eq!(document_change["textDocument"]["uri"], "file:///test.ts");
+    assert_eq!(document_change["textDocument"]["version"], 1);
+    assert_eq!(document_change["edits"][0]["newText"], "alert(1)\n");
+    let applied = json!({ "jsonrpc": "2.0", "id": apply["id"], "result": { "applied": true } });
+    req_client
+      .write_all(req(&applied.to_string()).as_bytes())
+      .await
+      .unwrap();
+    // the document is edited after the command is issued
+    let change = json!({
+      "textDocument": { "uri": "file:///test.ts", "version": 2 },
+      "contentChanges": [{ "text": "console.log(2)" }],
+    });
+    notify(&mut req_client, "textDocument/didChange", change).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    req_client
+      .write_all(req(&execute(1).to_string()).as_bytes())
+      .await
+      .unwrap();
+    let log = wait_for(&mut resp_client, |v| {
+      v["method"] == "workspace/applyEdit"
+        || v["params"]["message"]
+          .as_str()
+          .map_or(false, |m| m.contains("not applied"))
+    })
+    .await
+    .expect("should reject stale version");
+    assert_eq!(log["method"], "window/logMessage");
+  });
+}
+
+#[test]
+fn test_search() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().to_path_buf();
+    std::fs::write(base.join("c.ts"), "console.log(3)").unwrap();
+    let (mut req_client, mut resp_client) = create_lsp_with_base(NO_CONSOLE_RULE, base.clone());
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///a.ts", "let a = 1\nconsole.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let search = |args: Value| json!({ "command": "ast-grep.search", "arguments": [args] });
+    let method = "workspace/executeCommand";
+    let params = search(json!({ "pattern": "console.log($A)" }));
+    let response = request(&mut req_client, &mut resp_client, method, params).await;
+    let locations = response["result"].as_array().unwrap();
+    assert_eq!(locations.len(), 1);
+    assert_eq!(locations[0]["uri"], "file:///a.ts");
+    assert_eq!(locations[0]["range"]["start"]["line"], 1);
+    // the range covers all statements of a multi-statement match
+    let params = search(json!({ "pattern": "let $A = 1\nconsole.log($B)" }));
+    let response = request(&mut req_client, &mut resp_client, method, params).await;
+    let locations = response["result"].as_array().unwrap();
+    assert_eq!(locations.len(), 1);
+    assert_eq!(
+      locations[0]["range"]["start"],
+      json!({ "line": 0, "character": 0 })
+    );
+    assert_eq!(
+      locations[0]["range"]["end"],
+      json!({ "line": 1, "character": 14 })
+    );
+    let params = search(json!({ "pattern": "console.log($A)", "includeWorkspace": true }));
+    let response = request(&mut req_client, &mut resp_client, method, params).await;
+    let locations = response["result"].as_array().unwrap();
+    assert_eq!(locations.len(), 2);
+    assert!(locations[1]["uri"].as_str().unwrap().ends_with("c.ts"));
+    let params = search(json!({ "pattern": "console.log($A)", "language": "rust" }));
+    let response = request(&mut req_client, &mut resp_client, method, params).await;
+    assert_eq!(response["result"], json!([]));
+  });
+}
+
+#[test]
+fn test_debounce_diagnostics() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "diagnosticsDebounceMs": 500 });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", "let a = 1").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let change = |version, text| {
+      json!({
+        "textDocument": { "uri": "file:///test.ts", "version": version },
+        "contentChanges": [{ "text": text }],
+      })
+    };
+    notify(
+      &mut req_client,
+      "textDocument/didChange",
+      change(2, "console.log(1)"),
+    )
+    .await;
+    notify(
+      &mut req_client,
+      "textDocument/didChange",
+      change(3, "let a = 2"),
+    )
+    .await;
+    let versions = Mutex::new(vec![]);
+    wait_for(&mut resp_client, |v| {
+      if v["method"] != "textDocument/publishDiagnostics" {
+        return false;
+      }
+      let mut versions = versions.lock().unwrap();
+      versions.push(v["params"]["version"].clone());
+      versions.last() == Some(&json!(3))
+    })
+    .await
+    .expect("should publish latest version");
+    assert_eq!(versions.into_inner().unwrap(), [3]);
+    // saved document is published without debounce
+    notify(
+      &mut req_client,
+      "textDocument/didChange",
+      change(4, "console.log(2)"),
+    )
+    .await;
+    let start = std::time::Instant::now();
+    let save = json!({ "textDocument": { "uri": "file:///test.ts" } });
+    notify(&mut req_client, "textDocument/didSave", save).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert!(start.elapsed() < Duration::from_millis(500));
+    // the debounced scan of the saved version is aborted instead of published again
+    let published = wait_for(&mut resp_client, |v| {
+      v["method"] == "textDocument/publishDiagnostics"
+    });
+    let republished = timeout(Duration::from_millis(800), published).await;
+    assert!(republished.is_err(), "should not publish twice");
+  });
+}
+
+#[test]
+fn test_rule_file_utf16_positions() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write(
+      "/rules/a.yml",
+      "id: a\nlanguage: TypeScript\nrule: {pattern: a}",
+    );
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let rule = "id: 规则\nlanguage: TypeScript\nrule: {pattern: a}\nmessage: 信息: [\n";
+    open_document(&mut req_client, "file:///rules/a.yml", rule).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    let range = json!({
+      "start": { "line": 3, "character": 11 },
+      "end": { "line": 3, "character": 14 },
+    });
+    assert_eq!(diagnostics[0]["range"], range);
+    let params = json!({ "textDocument": { "uri": "file:///rules/a.yml" } });
+    let outline = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/documentSymbol",
+      params,
+    )
+    .await;
+    // columns count UTF-16 code units, not bytes
+    let selection = json!({
+      "start": { "line": 0, "character": 4 },
+      "end": { "line": 0, "character": 6 },
+    });
+    assert_eq!(outline["result"][0]["selectionRange"], selection);
+  });
+}
+
+#[test]
+fn test_debounce_does_not_block_requests() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "diagnosticsDebounceMs": 3000 });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    let start = std::time::Instant::now();
+    // more pending debounces than the server handles concurrently
+    for i in 0..6 {
+      let uri = format!("file:///test{i}.ts");
+      open_document(&mut req_client, &uri, "let a = 1").await;
+      wait_for_diagnostics(&mut resp_client).await;
+      let change = json!({
+        "textDocument": { "uri": uri, "version": 2 },
+        "contentChanges": [{ "text": "console.log(1)" }],
+      });
+      notify(&mut req_client, "textDocument/didChange", change).await;
+    }
+    let params = json!({ "textDocument": { "uri": "file:///test0.ts" } });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/documentSymbol",
+      params,
+    )
+    .await;
+    assert!(response.get("error").is_none());
+    assert!(start.elapsed() < Duration::from_millis(3000));
+  });
+}
+
+#[test]
+fn test_diagnostics_on_save() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "diagnosticsTrigger": "onSave" });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", "let a = 1").await;
+    assert!(wait_for_diagnostics(&mut resp_client).await.is_empty());
+    let change = json!({
+      "textDocument": { "uri": "file:///test.ts", "version": 2 },
+      "contentChanges": [{ "text": "console.log(1)" }],
+    });
+    notify(&mut req_client, "textDocument/didChange", change).await;
+    let published = wait_for(&mut resp_client, |v| {
+      v["method"] == "textDocument/publishDiagnostics"
+    })
+    .await;
+    assert!(
+      published.is_none(),
+      "changes should not publish diagnostics"
+    );
+    let save = json!({ "textDocument": { "uri": "file:///test.ts" } });
+    notify(&mut req_client, "textDocument/didSave", save).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+  });
+}
+
+#[test]
+fn test_scan_many_rules() {
+  let rules: Vec<_> = (0..120)
+    .map(|i| format!("id: rule-{i}\nmessage: m\nlanguage: TypeScript\nrule: {{pattern: foo{i}()}}"))
+    .collect();
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules.join("\n---\n"));
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(
+      &mut req_client,
+      "file:///test.ts",
+      "foo7(); bar(); foo119()",
+    )
+    .await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    let codes: Vec<_> = diagnostics.iter().map(|d| d["code"].clone()).collect();
+    assert_eq!(codes, ["rule-7", "rule-119"]);
+  });
+}
+
+fn free_port() -> u16 {
+  let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+  listener.local_addr().unwrap().port()
+}
+
+fn create_backend(client: tower_lsp::Client) -> Backend<SupportLang> {
+  let globals = GlobalRules::default();
+  let configs = from_yaml_string(NO_CONSOLE_RULE, &globals).unwrap();
+  let rules = RuleCollection::try_new(configs).map_err(|e| e.to_string());
+  Backend::new(client, Path::new("./").to_path_buf(), rules)
+}
+
+async fn connect(port: u16) -> tokio::net::TcpStream {
+  for _ in 0..50 {
+    if let Ok(stream) = tokio::net::TcpStream::connect(("127.0.0.1", port)).await {
+      return stream;
+    }
+    tokio::time::sleep(Duration::from_millis(20)).await;
+  }
+  panic!("server should listen on port {port}");
+}
+
+const INITIALIZE: &str =
+  r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#;
+
+#[test]
+fn test_serve_tcp() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let port = free_port();
+    tokio::spawn(serve_tcp(("127.0.0.1", port), create_backend));
+    let mut stream = connect(port).await;
+    stream.write_all(req(INITIALIZE).as_bytes()).await.unwrap();
+    let mut buf = vec![0; 4096];
+    let len = timeout(Duration::from_secs(5), stream.read(&mut buf))
+      .await
+      .expect("should respond")
+      .unwrap();
+    let response = resp(&buf[..len]);
+    assert!(response[0]["result"]["capabilities"].is_object());
+  });
+}
+
+#[test]
+fn test_serve_ws() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let port = free_port();
+    tokio::spawn(serve_ws(("127.0.0.1", port), create_backend));
+    let mut stream = connect(port).await;
+    let handshake = "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+    let mut buf = vec![0; 4096];
+    let len = stream.read(&mut buf).await.unwrap();
+    let response = std::str::from_utf8(&buf[..len]).unwrap();
+    assert!(response.starts_with("HTTP/1.1 101"));
+    let accept = response.lines().find_map(|line| {
+      let (name, value) = line.split_once(':')?;
+      name.eq_ignore_ascii_case("sec-websocket-accept").then(|| value.trim())
+    });
+    assert_eq!(accept, Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+    // clients must mask their frames
+    let mask = [1, 2, 3, 4];
+    let mut frame = vec![0x81, 0x80 | 126];
+    frame.extend((INITIALIZE.len() as u16).to_be_bytes());
+    frame.extend(mask);
+    frame.extend(INITIALIZE.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    stream.write_all(&frame).await.unwrap();
+    let mut header = [0; 2];
+    timeout(Duration::from_secs(5), stream.read_exact(&mut header))
+      .await
+      .expect("should respond")
+      .unwrap();
+    assert_eq!(header[0], 0x81);
+    let len = match header[1] {
+      126 => stream.read_u16().await.unwrap() as usize,
+      127 => stream.read_u64().await.unwrap() as usize,
+      len => len as usize,
+    };
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).await.unwrap();
+    let response: Value = serde_json::from_slice(&payload).unwrap();
+    assert!(response["result"]["capabilities"].is_object());
+  });
+}
+
+#[test]
+fn test_serve_ws_oversized_frame() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let port = free_port();
+    let server = tokio::spawn(serve_ws(("127.0.0.1", port), create_backend));
+    let mut stream = connect(port).await;
+    let handshake = "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+    let mut buf = vec![0; 4096];
+    let len = stream.read(&mut buf).await.unwrap();
+    assert!(std::str::from_utf8(&buf[..len]).unwrap().starts_with("HTTP/1.1 101"));
+    // the frame claims a huge payload that is never allocated
+    let mut frame = vec![0x81, 0x80 | 127];
+    frame.extend((1u64 << 62).to_be_bytes());
+    frame.extend([1, 2, 3, 4]);
+    stream.write_all(&frame).await.unwrap();
+    // the server stops reading the client and exits
+    timeout(Duration::from_secs(5), server)
+      .await
+      .expect("should close the connection")
+      .unwrap()
+      .unwrap();
+  });
+}
+
+#[test]
+fn test_cancel_request() {
+  let rules: Vec<_> = (0..300)
+    .map(|i| {
+      format!(
+        "id: rule-{i}\nmessage: m\nlanguage: TypeScript\nrule: {{pattern: foo{i}()}}\nfix: bar()"
+      )
+    })
+    .collect();
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules.join("\n---\n"));
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = format!("{}foo1();", "let a = 1;\n".repeat(5000));
+    open_document(&mut req_client, "file:///test.ts", &text).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let formatting = json!({
+      "jsonrpc": "2.0",
+      "id": 42,
+      "method": "textDocument/formatting",
+      "params": {
+        "textDocument": { "uri": "file:///test.ts" },
+        "options": { "tabSize": 2, "insertSpaces": true },
+      },
+    });
+    let cancel = json!({
+      "jsonrpc": "2.0",
+      "method": "$/cancelRequest",
+      "params": { "id": 42 },
+    });
+    // the scan of the request is pending in a blocking thread when the cancel is read
+    let messages = req(&formatting.to_string()) + &req(&cancel.to_string());
+    req_client.write_all(messages.as_bytes()).await.unwrap();
+    let response = wait_for(&mut resp_client, |v| v["id"] == 42)
+      .await
+      .expect("should respond");
+    assert_eq!(response["error"]["code"], -32800);
+  });
+}
+
+#[test]
+fn test_apply_all_fixes_with_filter() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+---
+id: no-foo
+message: Use bar
+language: TypeScript
+rule:
+  pattern: foo($A)
+fix: bar($A)
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "console.log(1)\nfoo(2)\nfoo(3)";
+    open_document(&mut req_client, "file:///test.ts", text).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let document = json!({
+      "uri": "file:///test.ts",
+      "languageId": "typescript",
+      "version": 1,
+      "text": text,
+      "ruleIds": ["no-foo"],
+      "range": { "start": { "line": 2, "character": 0 }, "end": { "line": 2, "character": 6 } },
+    });
+    let params = json!({ "command": "ast-grep.applyAllFixes", "arguments": [document] });
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 42,
+      "method": "workspace/executeCommand",
+      "params": params,
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let apply = wait_for(&mut resp_client, |v| v["method"] == "workspace/applyEdit")
+      .await
+      .expect("should apply edit");
+    let edits = &apply["params"]["edit"]["changes"]["file:///test.ts"];
+    assert_eq!(edits.as_array().unwrap().len(), 1);
+    assert_eq!(edits[0]["newText"], "bar(3)");
+    assert_eq!(edits[0]["range"]["start"]["line"], 2);
+  });
+}
+
+#[test]
+fn test_run_rule_tests() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write(
+      "/rules/no-foo.yml",
+      "id: no-foo\nmessage: m\nlanguage: TypeScript\nrule: {pattern: foo($A)}",
+    );
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let test = "id: no-foo\nvalid:\n  - bar(1)\n  - foo(2)\ninvalid:\n  - foo(3)\n  - baz(4)\n";
+    let uri = "file:///rule-tests/no-foo-test.yml";
+    open_document(&mut req_client, uri, test).await;
+    let params = json!({ "textDocument": { "uri": uri } });
+    let lenses = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeLens",
+      params,
+    )
+    .await;
+    let command = &lenses["result"][0]["command"];
+    assert_eq!(command["title"], "Run tests");
+    let params = json!({ "command": command["command"], "arguments": command["arguments"] });
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 43,
+      "method": "workspace/executeCommand",
+      "params": params,
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let response = std::sync::Mutex::new(None);
+    let published = std::sync::Mutex::new(None);
+    wait_for(&mut resp_client, |v| {
+      if v["id"] == 43 {
+        *response.lock().unwrap() = Some(v.clone());
+      } else if v["method"] == "textDocument/publishDiagnostics" {
+        *published.lock().unwrap() = Some(v["params"].clone());
+      }
+      response.lock().unwrap().is_some() && published.lock().unwrap().is_some()
+    })
+    .await
+    .expect("should respond and publish");
+    let response = response.into_inner().unwrap().unwrap();
+    assert_eq!(response["result"], json!({ "passed": 2, "failed": 2 }));
+    let published = published.into_inner().unwrap().unwrap();
+    assert_eq!(published["uri"], uri);
+    let lines: Vec<_> = published["diagnostics"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|d| d["range"]["start"]["line"].clone())
+      .collect();
+    assert_eq!(lines, [3, 6]);
+  });
+}
+
+#[test]
+fn test_rule_code_description() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+url: https://example.com/console
+---
+id: no-alert
+message: No alert
+language: TypeScript
+rule:
+  pattern: alert($$$A)
+metadata:
+  url: https://example.com/alert
+---
+id: no-debugger
+message: No debugger
+language: TypeScript
+rule:
+  kind: debugger_statement
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    let options = json!({ "ruleUrlTemplate": "https://example.com/rules/{id}" });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    let text = "console.log(1)\nalert(2)\ndebugger";
+    open_document(&mut req_client, "file:///test.ts", text).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    let hrefs: Vec<_> = diagnostics
+      .iter()
+      .map(|d| d["codeDescription"]["href"].clone())
+      .collect();
+    assert_eq!(
+      hrefs,
+      [
+        "https://example.com/console",
+        "https://example.com/alert",
+        "https://example.com/rules/no-debugger",
+      ]
+    );
+  });
+}
+
+#[test]
+fn test_rule_diagnostic_tags() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+metadata:
+  tags: [deprecated, unnecessary, other]
+---
+id: no-alert
+message: No alert
+language: TypeScript
+rule:
+  pattern: alert($$$A)
+metadata:
+  tags: unnecessary
+  url: https://example.com/alert
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    initialize_lsp(&mut req_client, &mut resp_client).await;
+    let text = "console.log(1)\nalert(2)\ndebugger";
+    open_document(&mut req_client, "file:///test.ts", text).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics[0]["tags"], json!([2, 1]));
+    assert_eq!(diagnostics[1]["tags"], json!([1]));
+    let href = &diagnostics[1]["codeDescription"]["href"];
+    assert_eq!(href, "https://example.com/alert");
+  });
+}
+
+#[test]
+fn test_unsafe_fix_annotation() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+---
+id: no-foo
+message: Use bar
+language: TypeScript
+rule:
+  pattern: foo($A)
+fix:
+  template: bar($A)
+  unsafe: true
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    let initialize = json!({
+      "jsonrpc": "2.0",
+      "id": 1,
+      "method": "initialize",
+      "params": {
+        "capabilities": {
+          "workspace": {
+            "workspaceEdit": { "documentChanges": true, "changeAnnotationSupport": {} },
+          },
+        },
+      }
+    });
+    req_client
+      .write_all(req(&initialize.to_string()).as_bytes())
+      .await
+      .unwrap();
+    wait_for(&mut resp_client, |v| v["id"] == 1).await.unwrap();
+    let text = "console.log(1)\nfoo(2)";
+    open_document(&mut req_client, "file:///test.ts", text).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let document = json!({
+      "uri": "file:///test.ts",
+      "languageId": "typescript",
+      "version": 1,
+      "text": text,
+    });
+    let params = json!({ "command": "ast-grep.applyAllFixes", "arguments": [document] });
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 42,
+      "method": "workspace/executeCommand",
+      "params": params,
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let apply = wait_for(&mut resp_client, |v| v["method"] == "workspace/applyEdit")
+      .await
+      .expect("should apply edit");
+    let edit = &apply["params"]["edit"];
+    let edits = &edit["documentChanges"][0]["edits"];
+    assert!(edits[0]["annotationId"].is_null());
+    assert_eq!(edits[1]["newText"], "bar(2)");
+    let annotation_id = edits[1]["annotationId"].as_str().unwrap();
+    let annotation = &edit["changeAnnotations"][annotation_id];
+    assert_eq!(annotation["needsConfirmation"], true);
+  });
+}
+
+#[test]
+fn test_unsafe_fix_not_applied_without_confirmation() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+---
+id: no-foo
+message: Use bar
+language: TypeScript
+rule:
+  pattern: foo($A)
+fix:
+  template: bar($A)
+  unsafe: true
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "console.log(1)\nfoo(2)";
+    open_document(&mut req_client, "file:///test.ts", text).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "options": { "tabSize": 2, "insertSpaces": true },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/formatting",
+      params,
+    )
+    .await;
+    let edits = response["result"].as_array().unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0]["newText"], "alert(1)\n");
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 1, "character": 6 } },
+      "context": { "diagnostics": [], "only": ["source.fixAll.ast-grep"] },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/codeAction",
+      params,
+    )
+    .await;
+    let edits = &response["result"][0]["edit"]["changes"]["file:///test.ts"];
+    assert_eq!(edits.as_array().unwrap().len(), 1);
+    let document = json!({
+      "uri": "file:///test.ts",
+      "languageId": "typescript",
+      "version": 1,
+      "text": text,
+    });
+    let params = json!({ "command": "ast-grep.applyFixesReturnText", "arguments": [document] });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      params,
+    )
+    .await;
+    assert_eq!(response["result"], "alert(1)\n\nfoo(2)");
+  });
+}
+
+#[test]
+fn test_extensionless_file_language_id() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let script = "#!/usr/bin/env ts-node\nconsole.log(1)";
+    open_document(&mut req_client, "file:///bin/script", script).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+  });
+}
+
+#[test]
+fn test_malformed_rule_file() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/console.yml", NO_CONSOLE_RULE);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "id: valid\nlanguage: TypeScript\nrule:\n  pattern: a\n---\nid: bad-severity\nlanguage: TypeScript\nseverity: [1]\nrule:\n  pattern: a\n---\nid: undefined-util\nlanguage: TypeScript\nrule:\n  matches: not-found\n";
+    open_document(&mut req_client, "file:///rules/console.yml", text).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 7);
+    assert_eq!(diagnostics[0]["range"]["start"]["character"], 10);
+    assert_eq!(diagnostics[1]["range"]["start"]["line"], 11);
+    assert_eq!(diagnostics[1]["range"]["start"]["character"], 4);
+    let message = diagnostics[1]["message"].as_str().unwrap();
+    assert!(message.contains("Rule `not-found` is not defined."));
+    // errors are cleared once the rule file is fixed
+    let change = json!({
+      "textDocument": { "uri": "file:///rules/console.yml", "version": 2 },
+      "contentChanges": [{ "text": NO_CONSOLE_RULE }],
+    });
+    notify(&mut req_client, "textDocument/didChange", change).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert!(diagnostics.is_empty());
+  });
+}
+
+#[test]
+fn test_rename_files() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///src/test.ts", "console.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let params = json!({ "files": [{ "oldUri": "file:///src", "newUri": "file:///lib" }] });
+    notify(&mut req_client, "workspace/didRenameFiles", params).await;
+    let published = Mutex::new(HashMap::new());
+    wait_for(&mut resp_client, |v| {
+      if v["method"] == "textDocument/publishDiagnostics" {
+        let params = &v["params"];
+        let uri = params["uri"].as_str().unwrap().to_string();
+        let count = params["diagnostics"].as_array().unwrap().len();
+        published.lock().unwrap().insert(uri, count);
+      }
+      published.lock().unwrap().len() == 2
+    })
+    .await
+    .expect("should publish diagnostics of both uris");
+    let published = published.into_inner().unwrap();
+    assert_eq!(published["file:///src/test.ts"], 0);
+    assert_eq!(published["file:///lib/test.ts"], 1);
+    // the document is found with the new uri
+    let params = json!({ "textDocument": { "uri": "file:///lib/test.ts" } });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/diagnostic",
+      params,
+    )
+    .await;
+    assert_eq!(response["result"]["items"].as_array().unwrap().len(), 1);
+  });
+}
+
+#[test]
+fn test_max_cached_documents() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "maxCachedDocuments": 1 });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///a.ts", "console.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    open_document(&mut req_client, "file:///b.ts", "console.log(2)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let dump = json!({ "command": "ast-grep.dumpConfig", "arguments": [] });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      dump.clone(),
+    )
+    .await;
+    assert_eq!(response["result"]["openDocuments"], 2);
+    assert_eq!(response["result"]["parsedDocuments"], 1);
+    // the evicted document is parsed again
+    let params = json!({
+      "textDocument": { "uri": "file:///a.ts" },
+      "options": { "tabSize": 2, "insertSpaces": true },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/formatting",
+      params,
+    )
+    .await;
+    let edits = response["result"].as_array().expect("should have edits");
+    assert_eq!(edits.len(), 1);
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      dump,
+    )
+    .await;
+    assert_eq!(response["result"]["openDocuments"], 2);
+    assert_eq!(response["result"]["parsedDocuments"], 1);
+  });
+}
+
+#[test]
+fn test_client_rules() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().to_path_buf();
+    std::fs::create_dir_all(base.join("rules")).unwrap();
+    std::fs::write(base.join("rules/console.yml"), NO_CONSOLE_RULE).unwrap();
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(MockRuleFinder::default());
+    let inline_rule = json!({
+      "id": "no-debugger",
+      "language": "TypeScript",
+      "rule": { "pattern": "debugger" },
+    });
+    let options = json!({
+      "ruleDirs": [base.join("rules")],
+      "inlineRules": [NO_ALERT_RULE, inline_rule],
+    });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    let source = "console.log(1)\nalert(1)\ndebugger";
+    open_document(&mut req_client, "file:///test.ts", source).await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    let codes: Vec<_> = diagnostics.iter().map(|d| d["code"].clone()).collect();
+    assert_eq!(codes, ["no-console-rule", "no-alert", "no-debugger"]);
+    // rules are built again when the options change
+    let settings = json!({ "settings": { "inlineRules": [] } });
+    notify(
+      &mut req_client,
+      "workspace/didChangeConfiguration",
+      settings,
+    )
+    .await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-console-rule");
+  });
+}
+
+#[test]
+fn test_reload_rules_command() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/alert.yml", NO_ALERT_RULE);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder.clone());
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(
+      &mut req_client,
+      "file:///test.ts",
+      "console.log(1)\nalert(1)",
+    )
+    .await;
+    let diagnostics = wait_for_diagnostics(&mut resp_client).await;
+    assert_eq!(diagnostics[0]["code"], "no-alert");
+    finder.write("/rules/alert.yml", NO_CONSOLE_RULE);
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 43,
+      "method": "workspace/executeCommand",
+      "params": { "command": "ast-grep.reloadRules", "arguments": [] },
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let responded = std::sync::Mutex::new(false);
+    let published = std::sync::Mutex::new(None);
+    wait_for(&mut resp_client, |v| {
+      if v["id"] == 43 {
+        *responded.lock().unwrap() = true;
+      } else if v["method"] == "textDocument/publishDiagnostics" {
+        *published.lock().unwrap() = Some(v["params"]["diagnostics"].clone());
+      }
+      *responded.lock().unwrap() && published.lock().unwrap().is_some()
+    })
+    .await
+    .expect("should respond and republish");
+    let diagnostics = published.into_inner().unwrap().unwrap();
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+    assert_eq!(diagnostics[0]["code"], "no-console-rule");
+  });
+}
+
+#[test]
+fn test_extract_rule() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let dir = TempDir::new().unwrap();
+    let base = dir.path().to_path_buf();
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "ruleDirs": [base] });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    open_document(&mut req_client, "file:///test.ts", "const x = foo(bar, bar.baz)\n").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let range = json!({
+      "start": { "line": 0, "character": 9 },
+      "end": { "line": 0, "character": 27 },
+    });
+    let extract = |arguments| json!({ "command": "ast-grep.extractRule", "arguments": [arguments] });
+    let arguments = json!({ "uri": "file:///test.ts", "range": range });
+    let response = request(&mut req_client, &mut resp_client, "workspace/executeCommand", extract(arguments)).await;
+    let expected = "id: new-rule\nlanguage: ts\nseverity: hint\nmessage: Describe why the code is reported\nrule:\n  pattern: |\n    $A($B, $B.$C)\n";
+    assert_eq!(response["result"]["yaml"], expected);
+    assert!(response["result"].get("path").is_none());
+    let arguments = json!({ "uri": "file:///test.ts", "range": range, "id": "no-foo", "ruleDir": base });
+    let response = request(&mut req_client, &mut resp_client, "workspace/executeCommand", extract(arguments.clone())).await;
+    let written = std::fs::read_to_string(base.join("no-foo.yml")).unwrap();
+    assert_eq!(response["result"]["yaml"], written);
+    assert!(written.starts_with("id: no-foo\n"));
+    // existing rule files are not overwritten
+    std::fs::write(base.join("no-foo.yml"), "edited").unwrap();
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 43,
+      "method": "workspace/executeCommand",
+      "params": extract(arguments),
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let shown = wait_for(&mut resp_client, |v| v["method"] == "window/showMessage").await;
+    let message = shown.expect("should show error")["params"]["message"].clone();
+    assert!(message.as_str().unwrap().contains("already exists"));
+    assert_eq!(std::fs::read_to_string(base.join("no-foo.yml")).unwrap(), "edited");
+    // rules are only written into configured rule directories
+    let outside = std::env::temp_dir();
+    let escapes = [
+      json!({ "uri": "file:///test.ts", "range": range, "id": "../escaped", "ruleDir": base }),
+      json!({ "uri": "file:///test.ts", "range": range, "id": "escaped", "ruleDir": outside }),
+    ];
+    for (id, arguments) in escapes.into_iter().enumerate() {
+      let execute = json!({
+        "jsonrpc": "2.0",
+        "id": 44 + id,
+        "method": "workspace/executeCommand",
+        "params": extract(arguments),
+      });
+      req_client
+        .write_all(req(&execute.to_string()).as_bytes())
+        .await
+        .unwrap();
+      let shown = wait_for(&mut resp_client, |v| v["method"] == "window/showMessage").await;
+      let message = shown.expect("should show error")["params"]["message"].clone();
+      assert!(message.as_str().unwrap().starts_with("Cannot write rule file"));
+    }
+    assert!(!outside.join("escaped.yml").exists());
+  });
+}
+
+#[test]
+fn test_inspect_node() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(
+      &mut req_client,
+      "file:///test.ts",
+      "let a = 1\nconsole.log(a)",
+    )
+    .await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let inspect = |start: (u32, u32), end: (u32, u32)| {
+      let range = json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+      });
+      let arguments = json!([{ "uri": "file:///test.ts", "range": range }]);
+      json!({ "command": "ast-grep.inspectNode", "arguments": arguments })
+    };
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      inspect((1, 0), (1, 14)),
+    )
+    .await;
+    let result = &response["result"];
+    let node = &result["nodes"][0];
+    assert_eq!(node["kind"], "expression_statement");
+    assert_eq!(node["range"]["start"]["line"], 1);
+    let call = &node["children"][0];
+    assert_eq!(call["kind"], "call_expression");
+    assert_eq!(call["children"][0]["field"], "function");
+    assert_eq!(call["children"][0]["kind"], "member_expression");
+    let sexp = result["sexp"].as_str().unwrap();
+    assert!(
+      sexp.contains("(call_expression function: (member_expression"),
+      "{sexp}"
+    );
+    // the smallest node at an empty range
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "workspace/executeCommand",
+      inspect((0, 4), (0, 4)),
+    )
+    .await;
+    let nodes = response["result"]["nodes"].as_array().unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0]["kind"], "identifier");
+  });
+}
+
+#[test]
+fn test_report_skipped_fixes() {
+  let rules = format!(
+    "{NO_CONSOLE_RULE}
+---
+id: no-console-member
+message: Use logger
+severity: warning
+language: TypeScript
+rule:
+  pattern: console.log
+fix: logger.log
+"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp_with_rules(&rules);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    let text = "console.log(1)";
+    open_document(&mut req_client, "file:///test.ts", text).await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let document = json!({
+      "uri": "file:///test.ts",
+      "languageId": "typescript",
+      "version": 1,
+      "text": text,
+    });
+    let params = json!({ "command": "ast-grep.applyAllFixes", "arguments": [document] });
+    let execute = json!({
+      "jsonrpc": "2.0",
+      "id": 42,
+      "method": "workspace/executeCommand",
+      "params": params,
+    });
+    req_client
+      .write_all(req(&execute.to_string()).as_bytes())
+      .await
+      .unwrap();
+    let message = Mutex::new(None);
+    let apply = Mutex::new(None);
+    wait_for(&mut resp_client, |v| {
+      if v["method"] == "window/showMessage" {
+        *message.lock().unwrap() = Some(v["params"]["message"].clone());
+      } else if v["method"] == "workspace/applyEdit" {
+        *apply.lock().unwrap() = Some(v["params"].clone());
+      }
+      message.lock().unwrap().is_some() && apply.lock().unwrap().is_some()
+    })
+    .await
+    .expect("should report skipped fixes and apply edit");
+    // rules of the same severity are applied in declaration order
+    let message = message.into_inner().unwrap().unwrap();
+    assert_eq!(
+      message,
+      "Skipped 1 fixes overlapping with other fixes: `no-console-member` (by `no-console-rule`)."
+    );
+    let apply = apply.into_inner().unwrap().unwrap();
+    let edits = &apply["edit"]["changes"]["file:///test.ts"];
+    assert_eq!(edits.as_array().unwrap().len(), 1);
+    assert_eq!(edits[0]["newText"], "alert(1)\n");
+  });
+}
+
+#[test]
+fn test_skipped_fixes_in_declaration_order() {
+  // the collection puts rules with `ignores` after other rules, but the first rule is declared first
+  let rules = format!(
+    "id: no-console-member
+message: Use logger
+severity: warning
+language: TypeScript
+ignores: ['**/*.js']
+rule:
+  pattern: console.log
+fix: logger.log
+---{NO_CONSOLE_RULE}"
+  );
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let finder = MockRuleFinder::default();
+    finder.write("/rules/console.yml", &rules);
+    let (mut req_client, mut resp_client) = create_lsp_with_finder(finder);
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, json!({})).await;
+    open_document(&mut req_client, "file:///test.ts", "console.log(1)").await;
+    wait_for_diagnostics(&mut resp_client).await;
+    let params = json!({
+      "textDocument": { "uri": "file:///test.ts" },
+      "options": { "tabSize": 2, "insertSpaces": true },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/formatting",
+      params,
+    )
+    .await;
+    let edits = response["result"].as_array().expect("should have edits");
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0]["newText"], "logger.log");
+    let message = wait_for(&mut resp_client, |v| v["method"] == "window/showMessage")
+      .await
+      .expect("should report skipped fixes");
+    assert_eq!(
+      message["params"]["message"],
+      "Skipped 1 fixes overlapping with other fixes: `no-console-rule` (by `no-console-member`)."
+    );
+  });
+}
+
+#[test]
+fn test_max_cached_documents_concurrent_changes() {
+  tokio::runtime::Runtime::new().unwrap().block_on(async {
+    let (mut req_client, mut resp_client) = create_lsp();
+    let options = json!({ "maxCachedDocuments": 1, "diagnosticsDebounceMs": 50 });
+    initialize_lsp_with_options(&mut req_client, &mut resp_client, options).await;
+    for uri in ["file:///a.ts", "file:///b.ts"] {
+      open_document(&mut req_client, uri, "let a = 1").await;
+      wait_for_diagnostics(&mut resp_client).await;
+    }
+    // both documents are edited before either is scanned, each evicting the other
+    for version in 2..5 {
+      for uri in ["file:///a.ts", "file:///b.ts"] {
+        let change = json!({
+          "textDocument": { "uri": uri, "version": version },
+          "contentChanges": [{ "text": format!("console.log({version})") }],
+        });
+        notify(&mut req_client, "textDocument/didChange", change).await;
+      }
+    }
+    // unread messages fill the output, so publishing waits while documents are scanned
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let published = Mutex::new(HashSet::new());
+    wait_for(&mut resp_client, |v| {
+      if v["method"] == "textDocument/publishDiagnostics" && v["params"]["version"] == 4 {
+        let uri = v["params"]["uri"].as_str().unwrap().to_string();
+        published.lock().unwrap().insert(uri);
+      }
+      published.lock().unwrap().len() == 2
+    })
+    .await
+    .expect("should publish diagnostics of both documents");
+    // the server still responds after both documents are scanned
+    let params = json!({
+      "textDocument": { "uri": "file:///a.ts" },
+      "options": { "tabSize": 2, "insertSpaces": true },
+    });
+    let response = request(
+      &mut req_client,
+      &mut resp_client,
+      "textDocument/formatting",
+      params,
+    )
+    .await;
+    assert_eq!(response["result"].as_array().unwrap().len(), 1);
+  });
+}
@@ -0,0 +1,3 @@
+t('outer', t('inner'), 'after')
+t('single')
+const skipped = 'not translated'
@@ -1,10 +1,19 @@
 import test from 'ava'
 
 import {
-  js, ts, tsx, html, Lang,
-  parseFiles, parseAsync, findInFiles,
+  js, ts, tsx, html, python, go, rust, java, c, Lang,
+  parseFiles, parseAsync, findInFiles, findInFilesIter, findInFilesAsNdjson, extractStrings,
+  countMatches, fixInPlace, findRedundantRules, saveRuleCache, loadRuleCache, ruleSources,
+  validateConfigDir, registerDynamicLanguage, parseBuffer, nodeKinds, fields, compileRule,
+  openSharedRoot, releaseSharedRoot, SourceMap, findInVirtualFiles,
   parse as parseWithLang,
 } from '../index'
+import type { Matcher, NapiConfig, SgNode } from '../index'
+import { getEventListeners } from 'events'
+import { mkdtempSync, readFileSync, writeFileSync } from 'fs'
+import { tmpdir } from 'os'
+import { join } from 'path'
+import { Worker } from 'worker_threads'
 const { parse, kind } = js
 let parseMulti = countedPromise(parseFiles)
 
@@ -88,6 +97,20 @@ test('find with transformation', t => {
   t.deepEqual(match.getMatch('A')?.text(), '"Hello, 世界"')
 })
 
+test('find with multi-statement pattern', t => {
+  const sg = parse('const a = await foo()\nconst b = await bar()\nlog(a, b)')
+  const matches = sg.root().findAll({
+    rule: {
+      pattern: 'const $A = await $X\nconst $B = await $Y',
+    },
+  })
+  t.is(matches.length, 1)
+  t.is(matches[0].getMatch('A')?.text(), 'a')
+  t.is(matches[0].getMatch('Y')?.text(), 'bar()')
+  // the range covers both statements
+  t.deepEqual(matches[0].range().end, { line: 1, column: 21, index: 43 })
+})
+
 
 test('test code fix', t => {
   const sg = parse('a = console.log(123)')
@@ -122,6 +145,206 @@ test('test code fix with user defined range', t => {
   t.deepEqual(newCode, 'いいよ = log514')
 })
 
+test('test code fix with max edits', t => {
+  const sg = parse('log(1) + log(2) + log(3)')
+  const fixes = sg.root().findAll(js.kind('number')).map(n => n.replace('0'))
+  t.deepEqual(sg.root().commitEdits(fixes, 3), 'log(0) + log(0) + log(0)')
+  const error = t.throws(() => sg.root().commitEdits(fixes, 2))
+  t.regex(error!.message, /3 edits to `anonymous` exceed maxEdits 2/)
+})
+
+test('commit edits of root', t => {
+  const sg = parse('let a = 1; let b = 2; let c = 3')
+  const edits = [
+    sg.root().find('let b = $B')!.replace('const b = 2;'),
+    sg.root().find('1')!.replace('10'),
+    sg.root().find('let $C = 3')!.replace('/* c */'),
+  ]
+  // overlapping edit is skipped
+  edits.push(sg.root().find('2')!.replace('20'))
+  const expected = 'let a = 10; const b = 2; /* c */'
+  t.is(sg.root().commitEdits(edits), expected)
+  const error = t.throws(() => sg.root().commitEdits(edits, 3))
+  t.regex(error!.message, /4 edits to `anonymous` exceed maxEdits 3/)
+  const node = sg.root().find('let a = $A')!
+  const edited = sg.commitEditsAndParse(edits)
+  t.is(edited.root().text(), expected)
+  t.is(edited.root().find('const b = $B')!.getMatch('B')!.text(), '2')
+  t.is(node.text(), 'let a = 1;')
+  t.is(sg.root().text(), 'let a = 1; let b = 2; let c = 3')
+  t.throws(() => sg.commitEditsAndParse(edits, 3), { message: /4 edits to `anonymous` exceed maxEdits 3/ })
+})
+
+test('fix in config', t => {
+  const sg = parse('let a = 1; var b = 2')
+  const nodes = sg.root().findAll({
+    rule: { pattern: 'var $A = $B' },
+    fix: 'let $A = $B',
+  })
+  t.is(nodes.length, 1)
+  t.is(nodes[0].getFixedText(), 'let b = 2')
+  const fix = nodes[0].fix()!
+  t.is(sg.root().commitEdits([fix]), 'let a = 1; let b = 2')
+  const expanded = sg.root().find({
+    rule: { pattern: 'let $A = 1' },
+    fix: { template: '', expandEnd: { regex: ';' } },
+  })!
+  t.is(sg.root().commitEdits([expanded.fix()!]), ' var b = 2')
+  t.is(sg.root().find('var $A = $B')!.fix(), null)
+})
+
+test('rewriters in config', t => {
+  const sg = parse('log(1, 2)')
+  const node = sg.root().find({
+    rule: { pattern: 'log($$$ARGS)' },
+    transform: {
+      NEW: { rewrite: { rewriters: ['double'], source: '$$$ARGS', joinBy: ', ' } },
+    },
+    fix: 'print($NEW)',
+    rewriters: [
+      { id: 'double', rule: { kind: 'number', pattern: '$N' }, fix: '2 * $N' },
+    ],
+  })!
+  t.is(node.getFixedText(), 'print(2 * 1, 2 * 2)')
+})
+
+test('parse buffer with byte offsets', t => {
+  const sg = parseBuffer(Lang.JavaScript, Buffer.from('let 你好 = "世界"; log(你好)'))
+  const node = sg.root().find('log($A)')!
+  t.is(node.getMatch('A')!.text(), '你好')
+  // each CJK character is three bytes in UTF-8
+  t.deepEqual(node.range().start, { line: 0, column: 23, index: 23 })
+  t.is(sg.root().findAll({ rule: { kind: 'identifier' } }).length, 3)
+  t.throws(() => parseBuffer(Lang.JavaScript, Buffer.from([0xff, 0xfe])), {
+    message: /source is not UTF-8/,
+  })
+})
+
+test('get transformed of buffer node', t => {
+  const sg = parseBuffer(Lang.JavaScript, Buffer.from('log("你好, world")'))
+  const node = sg.root().find({
+    rule: { pattern: 'log($A)' },
+    transform: {
+      INNER: { substring: { source: '$A', startChar: 1, endChar: -1 } },
+    },
+  })!
+  t.is(node.getTransformed('INNER'), '你好, world')
+  t.is(node.getTransformed('NOT_EXIST'), null)
+})
+
+test('node kinds and fields', t => {
+  const kinds = nodeKinds(Lang.TypeScript)
+  const identifier = kinds.find(k => k.name === 'identifier' && k.named)!
+  t.is(identifier.id, ts.kind('identifier'))
+  t.true(kinds.some(k => k.name === '(' && !k.named))
+  t.false(kinds.some(k => k.name.startsWith('_')))
+  t.deepEqual(ts.nodeKinds(), kinds)
+  t.true(fields('TypeScript').includes('body'))
+  t.true(python.fields().includes('parameters'))
+})
+
+test('run tree-sitter query', t => {
+  const sg = parse('foo(1); bar(a, b)')
+  const captures = sg.queryTS('((call_expression function: (identifier) @f) (#eq? @f "bar")) (arguments (identifier) @arg)')
+  t.deepEqual(captures.map(c => [c.name, c.text]), [['f', 'bar'], ['arg', 'a'], ['arg', 'b']])
+  t.deepEqual(captures[0].range.start, { line: 0, column: 8, index: 8 })
+  t.throws(() => sg.queryTS('(not_a_kind) @a'), {
+    message: /invalid tree-sitter query/
+  })
+})
+
+test('dump AST', t => {
+  const sg = parse('a = 12')
+  const assignment = sg.dumpAST().children[0].children[0]
+  t.is(assignment.kind, 'assignment_expression')
+  t.deepEqual(assignment.children.map(c => [c.field, c.text]), [['left', 'a'], ['right', '12']])
+  t.deepEqual(assignment.children[1].range.start, { line: 0, column: 4, index: 4 })
+  const unnamed = sg.dumpAST({ includeUnnamed: true }).children[0].children[0]
+  t.deepEqual(unnamed.children.map(c => c.kind), ['identifier', '=', 'number'])
+  const shallow = sg.dumpAST({ maxDepth: 1 })
+  t.deepEqual(shallow.children[0].children, [])
+  t.is(shallow.children[0].text, 'a = 12')
+  // the dump is plain JSON
+  t.deepEqual(JSON.parse(JSON.stringify(shallow)), shallow)
+})
+
+test('share root with worker threads', async t => {
+  const sg = parse('let a = 123')
+  const handle = sg.share()
+  const code = `
+    const { parentPort, workerData } = require('worker_threads')
+    const { openSharedRoot } = require(workerData.binding)
+    const root = openSharedRoot(workerData.handle)
+    parentPort.postMessage(root.root().find('let $A = $B').getMatch('B').text())
+  `
+  const worker = new Worker(code, {
+    eval: true,
+    workerData: { handle, binding: require.resolve('../index') },
+  })
+  const found = await new Promise(resolve => worker.once('message', resolve))
+  await worker.terminate()
+  t.is(found, '123')
+  t.is(openSharedRoot(handle).filename(), 'anonymous')
+  t.true(releaseSharedRoot(handle))
+  t.throws(() => openSharedRoot(handle), { message: /not found or already released/ })
+})
+
+test('apply edit incrementally', t => {
+  const sg = parse('let a = 1; let b = 2')
+  const old = sg.root().find('let b = $B')!
+  const range = old.getMatch('B')!.range()
+  sg.applyEdit({ startByte: range.start.index * 2, endByte: range.end.index * 2, text: '42' })
+  t.is(sg.root().text(), 'let a = 1; let b = 42')
+  t.is(sg.root().find('let b = $B')!.getMatch('B')!.text(), '42')
+  // nodes before the edit keep the old source
+  t.is(old.text(), 'let b = 2')
+  sg.applyEdit({ startByte: 0, endByte: 0, text: '// a\n' })
+  t.is(sg.root().find('let a = $A')!.range().start.line, 1)
+  t.throws(() => sg.applyEdit({ startByte: 1, endByte: 4, text: '' }), {
+    message: /invalid edit range/,
+  })
+})
+
+test('original range by source map', t => {
+  // `let x = 1` in app.ts is compiled to `var x = 1` after a header line
+  const sourceMap = new SourceMap(JSON.stringify({
+    version: 3,
+    sources: ['app.ts'],
+    sourceRoot: 'src',
+    mappings: ';AAAA,IAAI',
+  }))
+  const sg = parse('"use strict";\nvar x = 1')
+  const node = sg.root().find('var $A = 1')!
+  const id = node.getMatch('A')!.originalRange(sourceMap)!
+  t.is(id.source, 'src/app.ts')
+  t.deepEqual(id.start, { line: 0, column: 4 })
+  t.deepEqual(id.end, { line: 0, column: 5 })
+  t.deepEqual(sourceMap.originalRange(node.range())!.start, { line: 0, column: 0 })
+  t.is(sg.root().find('"use strict"')!.originalRange(sourceMap), null)
+  t.throws(() => new SourceMap('{"version":3,"sources":[],"mappings":"AAAA"}'), {
+    message: /invalid mapping segment/,
+  })
+})
+
+test('test code fix with trivia', t => {
+  const sg = parse('a()\n/** doc */\nfunction b() {} // b\n')
+  const match = sg.root().find(js.kind('function_declaration'))!
+  const edit = match.replace('function c() {}', true)
+  t.deepEqual(edit.startPos, 4)
+  const newCode = sg.root().commitEdits([edit])
+  t.deepEqual(newCode, 'a()\nfunction c() {}\n')
+})
+
+test('find definition of captured identifier', t => {
+  const sg = parse('import { a } from "mod"\nfunction f(a) {\n  return a\n}\nlog(a)')
+  const inner = sg.root().find('return $A')!.getMatch('A')!
+  t.deepEqual(inner.findDefinition()!.range().start.line, 1)
+  const outer = sg.root().find('log($A)')!.getMatch('A')!
+  t.deepEqual(outer.findDefinition()!.parent()!.kind(), 'import_specifier')
+  const unknown = parse('log(b)').root().find('log($A)')!.getMatch('A')!
+  t.is(unknown.findDefinition(), null)
+})
+
 test('findAll from native code', t => {
   const sg = parse('console.log(123); let a = console.log.bind(console);')
   const match = sg.root().findAll('console.log')
@@ -136,6 +359,20 @@ test('findAll from native code', t => {
   })
 })
 
+test('find with pattern object', t => {
+  const sg = parse('const a = { b: 1, c: 2 }; foo(/* x */ b)')
+  const pairs = sg.root().findAll({ context: '({ $K: $V })', selector: 'pair' })
+  t.deepEqual(pairs.map(p => p.text()), ['b: 1', 'c: 2'])
+  t.true(pairs[0].inside({ pattern: 'const $A = $B' }))
+  t.is(sg.root().find({ pattern: 'foo(b)' }), null)
+  t.truthy(sg.root().find({ pattern: 'foo(b)', strictness: 'relaxed' }))
+  const config = js.pattern({ context: '({ $K: $V })', selector: 'pair' })
+  t.is(sg.root().findAll(config).length, 2)
+  t.throws(() => sg.root().find({ selector: 'pair' }), {
+    message: /must have `pattern` or `context`/
+  })
+})
+
 test('find not match', t => {
   const sg = parse('console.log(123)')
   const match = sg.root().find('notExist')
@@ -168,6 +405,54 @@ test('find by config', t => {
   })
 })
 
+test('scan with multiple rules', t => {
+  const sg = parse('var a = 1; console.log(a); let b = 2')
+  const result = sg.scan([
+    { id: 'no-var', rule: { pattern: 'var $A = $B' } },
+    { id: 'no-console', rule: { pattern: 'console.log($$$)' } },
+    { rule: { kind: 'number' } },
+  ])
+  t.deepEqual(Object.keys(result).sort(), ['2', 'no-console', 'no-var'])
+  t.is(result['no-var'][0].text(), 'var a = 1;')
+  t.is(result['no-console'][0].text(), 'console.log(a)')
+  t.deepEqual(result['2'].map(n => n.text()), ['1', '2'])
+})
+
+test('scan merges rules sharing an id', t => {
+  const sg = parse('var a = 1; let b = 2')
+  const result = sg.scan([
+    { id: 'decl', rule: { pattern: 'var $A = $B' } },
+    { id: 'decl', rule: { pattern: 'let $A = $B' } },
+  ])
+  t.deepEqual(result['decl'].map(n => n.text()), ['var a = 1;', 'let b = 2'])
+})
+
+test('scan with ignored kinds', t => {
+  const sg = parse('foo(foo, bar)')
+  const result = sg.scan([
+    { id: 'pruned', rule: { kind: 'identifier' }, ignoreKinds: ['arguments'] },
+    { id: 'all', rule: { kind: 'identifier' } },
+  ])
+  t.deepEqual(result['pruned'].map(n => n.text()), ['foo'])
+  t.deepEqual(result['all'].map(n => n.text()), ['foo', 'foo', 'bar'])
+})
+
+test('findAll with ignored kinds', t => {
+  const sg = parse('foo(foo, bar)')
+  const nodes = sg.root().findAll({
+    rule: { kind: 'identifier' },
+    ignoreKinds: ['arguments'],
+  })
+  t.deepEqual(nodes.map(n => n.text()), ['foo'])
+})
+
+test('scan requires rule with kind', t => {
+  const sg = parse('var a = 1')
+  t.throws(() => sg.scan([{ rule: { regex: 'a' } }]), {
+    message: /must specify a kind/
+  })
+})
+
 test('test find files', async t => {
   await parseMulti(['./__test__/index.spec.ts'], (err, tree) => {
     t.is(err, null)
@@ -236,6 +521,104 @@ test('find in files', async t => {
   })
 })
 
+test('find in files with batch size and pause', async t => {
+  let calls = 0
+  let matchCount = 0
+  let delivered = () => {}
+  const done = new Promise<void>(r => delivered = r)
+  const found = ts.findInFiles({
+    paths: ['./'],
+    matcher: {
+      rule: {kind: 'member_expression'}
+    },
+  }, (err, n) => {
+    t.is(err, null)
+    calls++
+    matchCount += n.length
+    delivered()
+  }, { batchSize: 1e9, highWaterMark: 1 })
+  found.pause()
+  setTimeout(() => found.resume(), 10)
+  const fileCount = await found
+  await done
+  // all matches are sent in the last batch
+  t.is(calls, 1)
+  t.true(fileCount > 1)
+  t.true(matchCount > fileCount)
+})
+
+test('abort or time out find in files', async t => {
+  const config = {
+    paths: ['./'],
+    matcher: { rule: { kind: 'member_expression' } },
+  }
+  // a paused search cannot finish before it is aborted or timed out
+  const controller = new AbortController()
+  const aborted = ts.findInFiles(config, () => {}, { signal: controller.signal })
+  aborted.pause()
+  controller.abort()
+  await t.throwsAsync(aborted, { code: 'Cancelled', message: /^AbortError/ })
+  const timedOut = ts.findInFiles(config, () => {}, { timeoutMs: 10 })
+  timedOut.pause()
+  await t.throwsAsync(timedOut, { code: 'Cancelled', message: /^TimeoutError/ })
+  // already aborted signal
+  const parsed = parseFiles(['./'], () => {}, { signal: controller.signal })
+  await t.throwsAsync(parsed, { code: 'Cancelled', message: /^AbortError/ })
+})
+
+test('remove abort listener and drop queued files after abort', async t => {
+  const controller = new AbortController()
+  const found = ts.findInFiles({
+    paths: ['./'],
+    matcher: { rule: { kind: 'member_expression' } },
+  }, () => {}, { signal: controller.signal })
+  await found
+  t.is(getEventListeners(controller.signal, 'abort').length, 0)
+  let calls = 0
+  const parsed = parseFiles(['./'], () => {
+    calls += 1
+    controller.abort()
+  }, { signal: controller.signal })
+  await parsed.catch(() => {})
+  await new Promise(r => setTimeout(r, 50))
+  // roots queued before abort are not passed to callback
+  t.is(calls, 1)
+  t.is(getEventListeners(controller.signal, 'abort').length, 0)
+})
+
+test('compiled rule', async t => {
+  const matcher = compileRule(Lang.TypeScript, {
+    rule: { pattern: 'console.log($A)' },
+    fix: 'logger.log($A)',
+  })
+  t.is(matcher.language(), 'TypeScript')
+  const sg = ts.parse('console.log(1); console.log(2)')
+  t.is(sg.root().find(matcher)!.getFixedText(), 'logger.log(1)')
+  t.is(sg.root().findAll(matcher).length, 2)
+  t.is(ts.compileRule({ rule: { kind: 'number' } }).language(), 'TypeScript')
+  t.throws(() => js.parse('console.log(1)').root().find(matcher), {
+    message: /Matcher is compiled for TypeScript, but used for JavaScript/,
+  })
+  let findInFiles = countedPromise(ts.findInFiles)
+  const fileCount = await findInFiles({
+    paths: ['./__test__/index.spec.ts'],
+    matcher: compileRule(Lang.TypeScript, { rule: { kind: 'member_expression' } }),
+  }, (err, n) => {
+    t.is(err, null)
+    t.true(n.length > 0)
+  })
+  t.is(fileCount, 1)
+
+  const fixed = await findInFiles({
+    paths: ['./__test__/index.spec.ts'],
+    matcher,
+  }, (err, n) => {
+    t.is(err, null)
+    t.true(n.every(node => node.fix()!.insertedText.startsWith('logger.log(')))
+  })
+  t.is(fixed, 1)
+})
+
 test('find in files with filename', async t => {
   let findInFiles = countedPromise(ts.findInFiles)
   await findInFiles({
@@ -294,6 +677,97 @@ test('find with language globs can parse with correct language', async t => {
   })
 })
 
+test('find in script block of single-file component', async t => {
+  let findInFiles = countedPromise(js.findInFiles)
+  await findInFiles({
+    paths: ['./__test__/'],
+    matcher: {
+      rule: {pattern: 'greeting: $A'},
+    },
+    languageGlobs: ['*.vue'],
+  }, (err, n) => {
+    t.is(err, null)
+    t.is(n.length, 1)
+    t.is(n[0].text(), 'greeting: "Hello"')
+    // range is relative to the vue file
+    const { start } = n[0].range()
+    t.is(start.line, 11)
+    t.is(start.column, 6)
+  })
+})
+
+test('parse single-file component as html by default', async t => {
+  const kinds: string[] = []
+  const count = await parseMulti({
+    paths: ['./__test__/test.vue'],
+    languageGlobs: { html: ['*.vue'] },
+  }, (err, root) => {
+    t.is(err, null)
+    kinds.push(root.root().kind())
+  })
+  t.is(count, 1)
+  t.deepEqual(kinds, ['document'])
+})
+
+test('parse blocks of single-file component', async t => {
+  const kinds: string[] = []
+  let done = () => {}
+  const received = new Promise<void>(r => done = r)
+  const count = await parseFiles({
+    paths: ['./__test__/test.vue'],
+    languageGlobs: { html: ['*.vue'] },
+  }, (err, root) => {
+    t.is(err, null)
+    kinds.push(root.root().kind())
+    if (kinds.length === 3) done()
+  }, { splitBlocks: true })
+  await received
+  t.is(count, 3)
+  t.deepEqual(kinds.sort(), ['document', 'program', 'stylesheet'])
+})
+
+
+test('find in virtual files', async t => {
+  const files = {
+    'src/a.ts': 'console.log(a)',
+    'src/b.js': 'console.log(b); console.log(c)',
+    'src/App.vue': '<script lang="ts">console.log(d)</script>',
+    'README.md': 'console.log(e)',
+  }
+  const options = { languageGlobs: { html: ['*.vue'] } }
+  const find = (matcher: NapiConfig | Matcher) => countedPromise(
+    (files: Record<string, string>, cb: (err: null | Error, result: SgNode[]) => void) =>
+      findInVirtualFiles(files, matcher, cb, options)
+  )
+  const found: string[] = []
+  const count = await find({ rule: { pattern: 'console.log($A)' } })(files, (err, nodes) => {
+    t.is(err, null)
+    found.push(...nodes.map(n => `${n.getRoot().filename()}:${n.text()}`))
+  })
+  t.is(count, 3)
+  t.deepEqual(found.sort(), [
+    'src/App.vue:console.log(d)',
+    'src/a.ts:console.log(a)',
+    'src/b.js:console.log(b)',
+    'src/b.js:console.log(c)',
+  ])
+  // a compiled matcher only matches files in its language
+  const inTs: string[] = []
+  const matcher = compileRule(Lang.TypeScript, { rule: { pattern: 'console.log($A)' } })
+  await find(matcher)(files, (err, nodes) => {
+    t.is(err, null)
+    inTs.push(...nodes.map(n => n.getRoot().filename()))
+  })
+  t.deepEqual(inTs.sort(), ['src/App.vue', 'src/a.ts'])
+
+  // nodes carry the fix of the rule
+  const fixed: string[] = []
+  await find({ rule: { pattern: 'console.log($A)' }, fix: 'logger.log($A)' })(files, (err, nodes) => {
+    t.is(err, null)
+    fixed.push(...nodes.map(n => n.fix()!.insertedText))
+  })
+  t.deepEqual(fixed.sort(), ['logger.log(a)', 'logger.log(b)', 'logger.log(c)', 'logger.log(d)'])
+})
 
 function countedPromise<F extends (t: any, cb: any) => Promise<number>>(func: F) {
   type P = Parameters<F>
@@ -324,6 +798,106 @@ test('parse python', t => {
   })
 })
 
+test('find in files as ndjson', async t => {
+  const lines: any[] = []
+  const summary = new Promise<any>(resolve => {
+    findInFilesAsNdjson(Lang.Rust, {
+      paths: ['./src'],
+      matcher: {
+        rule: {pattern: 'ast_grep_core'},
+      },
+    }, (err, line) => {
+      t.is(err, null)
+      t.true(line.endsWith('\n'))
+      const json = JSON.parse(line)
+      if (json.type === 'summary') {
+        resolve(json)
+      } else {
+        lines.push(json)
+      }
+    })
+  })
+  const { fileCount, matchCount } = await summary
+  t.assert(fileCount > 0)
+  t.is(matchCount, lines.length)
+  t.is(lines[0].type, 'match')
+  t.is(lines[0].text, 'ast_grep_core')
+  t.truthy(lines[0].range.start)
+})
+
+test('count matches in files', async t => {
+  const config = {
+    paths: ['./src'],
+    matcher: {
+      rule: {pattern: 'ast_grep_core'},
+    },
+  }
+  const { total, files } = await countMatches(Lang.Rust, config)
+  const sum = Object.values(files).reduce((a, b) => a + b, 0)
+  t.assert(total > 0)
+  t.is(total, sum)
+  let found = 0
+  const counted = countedPromise((t, cb) => findInFiles(Lang.Rust, t, cb))
+  const fileCount = await counted(config, (err: null | Error, nodes: any[]) => {
+    t.is(err, null)
+    found += nodes.length
+  })
+  t.is(Object.keys(files).length, fileCount)
+  t.is(total, found)
+})
+
+test('fix files in place', async t => {
+  const dir = mkdtempSync(join(tmpdir(), 'ast-grep-'))
+  const file = join(dir, 'a.ts')
+  writeFileSync(file, 'console.log(!!!!a)\nconsole.log(b)')
+  writeFileSync(join(dir, 'b.ts'), 'let b = 1')
+  const config = {
+    paths: [dir],
+    matcher: { rule: { pattern: '!!$A' } },
+  }
+  const dryRun = await fixInPlace(Lang.TypeScript, config, { fix: '$A', dryRun: true })
+  t.deepEqual(dryRun.changed, [{ file, edits: 1 }])
+  t.is(readFileSync(file, 'utf8'), 'console.log(!!!!a)\nconsole.log(b)')
+  const capped = await fixInPlace(Lang.TypeScript, config, { fix: '$A', maxEdits: 1, maxPasses: 5 })
+  t.deepEqual(capped.skipped, [file])
+  t.is(capped.totalEdits, 0)
+  const summary = await fixInPlace(Lang.TypeScript, config, { fix: '$A', maxPasses: 5 })
+  t.deepEqual(summary.changed, [{ file, edits: 2 }])
+  t.is(summary.totalEdits, 2)
+  t.deepEqual(summary.failed, [])
+  t.is(readFileSync(file, 'utf8'), 'console.log(a)\nconsole.log(b)')
+})
+
+test('extract strings', async t => {
+  const strings = await extractStrings(['./__test__/index.spec.ts'], {
+    regex: '^extract \\w+ strings$',
+  })
+  t.deepEqual(strings.map(s => s.value), ['extract decoded strings'])
+  t.is(strings[0].file, './__test__/index.spec.ts')
+  const decoded = await extractStrings(['./__test__/index.spec.ts'], {
+    regex: 'decoded',
+    pattern: 'const fixture = $A',
+  })
+  t.deepEqual(decoded.map(s => s.value), ['it\'s decoded\n', 'extract decoded strings'])
+  // fixture strings for the test above
+  const fixture = { text: 'it\'s decoded\n', title: 'extract decoded strings' }
+  t.truthy(fixture)
+})
+
+test('extract strings of nested matches once', async t => {
+  const fixture = './__test__/fixtures/extract.js'
+  const strings = await extractStrings([fixture], { pattern: 't($$$)' })
+  t.deepEqual(strings.map(s => s.value), ['outer', 'inner', 'after', 'single'])
+  const all = await extractStrings([fixture])
+  t.deepEqual(all.map(s => s.value), ['outer', 'inner', 'after', 'single', 'not translated'])
+})
+
+test('extract strings rejects invalid pattern', async t => {
+  await t.throwsAsync(extractStrings(['./__test__/fixtures/extract.js'], { pattern: '12  3344' }), {
+    message: /invalid pattern for JavaScript/,
+  })
+})
+
 test('parse python async', async t => {
   const sg = await parseAsync(Lang.Python, 'print("hello world")')
   const node = sg.root().find('print')
@@ -346,4 +920,146 @@ test('find rust', async t => {
   })
   t.assert(changed)
   t.assert(num > 0)
-})
\ No newline at end of file
+})
+test('find redundant rules', t => {
+  const found = findRedundantRules(Lang.TypeScript, [
+    { id: 'log', rule: { pattern: 'console.log(123)' } },
+    { id: 'any-log', rule: { pattern: 'console.log($A)' } },
+    { id: 'dup', rule: { pattern: 'console.log($A)' } },
+    { id: 'error', rule: { pattern: 'console.error($A)' } },
+  ])
+  t.deepEqual(found, [
+    { general: 'any-log', specific: 'log', confidence: 'medium' },
+    { general: 'dup', specific: 'log', confidence: 'medium' },
+    { general: 'any-log', specific: 'dup', confidence: 'exact' },
+  ])
+})
+
+test('save and load rule cache', t => {
+  const cachePath = join(mkdtempSync(join(tmpdir(), 'ast-grep-')), 'rules.json')
+  const source = 'id: no-log\nrule: { pattern: console.log($A) }'
+  t.is(loadRuleCache(cachePath, source, Lang.TypeScript), null)
+  saveRuleCache(cachePath, source, Lang.TypeScript, [
+    { id: 'no-log', rule: { pattern: 'console.log($A)' } },
+  ])
+  const rules = loadRuleCache(cachePath, source, Lang.TypeScript)!
+  t.is(rules.length, 1)
+  t.is(rules[0].language(), 'TypeScript')
+  const sg = ts.parse('console.log(1)')
+  t.is(sg.root().findAll(rules[0]).length, 1)
+  t.is(loadRuleCache(cachePath, source + '\n', Lang.TypeScript), null)
+  t.is(loadRuleCache(cachePath, source, Lang.JavaScript), null)
+  t.throws(() => saveRuleCache(cachePath, source, Lang.TypeScript, [{ rule: { kind: 'not-a-kind' } }]))
+})
+
+test('locate rule sources', t => {
+  const rulePath = join(mkdtempSync(join(tmpdir(), 'ast-grep-')), 'rules.yml')
+  writeFileSync(rulePath, [
+    'id: no-log',
+    'language: TypeScript',
+    'rule: { kind: call_expression, pattern: console.log($A) }',
+    '---',
+    'id: no-var',
+    'language: TypeScript',
+    'rule: { kind: variable_declaration }',
+  ].join('\n'))
+  const sources = ruleSources([rulePath])
+  const sg = ts.parse('var a = console.log(1)')
+  const found = sg.root().scan([
+    { id: 'no-var', rule: { kind: 'variable_declaration' } },
+  ])
+  t.is(found['no-var'].length, 1)
+  t.deepEqual(sources['no-var'], { file: rulePath, line: 5 })
+  t.deepEqual(sources['no-log'], { file: rulePath, line: 1 })
+})
+
+test('validate config dir', t => {
+  const dir = mkdtempSync(join(tmpdir(), 'ast-grep-'))
+  writeFileSync(join(dir, 'a.yml'), 'id: a\nlanguage: TypeScript\nrule: { pattern: console.log($A) }')
+  writeFileSync(join(dir, 'b.yml'), 'id: a\nlanguage: TypeScript\nrule: { matches: missing }')
+  writeFileSync(join(dir, 'c.yml'), 'id: c\nlanguage: TypeScript\nrule: { kind: not-a-kind }')
+  const report = validateConfigDir(dir)
+  t.false(report.valid)
+  t.deepEqual(report.ruleIds, ['a'])
+  t.is(report.undefinedUtils[0].util, 'missing')
+  t.is(report.errors.length, 1)
+  t.deepEqual(report.unusedCaptures[0].captures, ['A'])
+})
+
+test('parse backend languages', t => {
+  t.is(python.parse('print(1)').root().find('print($A)')!.text(), 'print(1)')
+  t.is(go.parse('func a() { b() }').root().find('b()')!.text(), 'b()')
+  t.is(rust.parse('fn a() { b!(1); }').root().find('b!($A)')!.text(), 'b!(1)')
+  t.is(java.parse('class A { void a() { b(); } }').root().find('b()')!.text(), 'b()')
+  t.is(c.parse('int a() { return b(); }').root().find('b()')!.text(), 'b()')
+})
+
+test('infer backend languages in parseFiles', async t => {
+  const dir = mkdtempSync(join(tmpdir(), 'ast-grep-'))
+  writeFileSync(join(dir, 'a.py'), 'print(1)')
+  writeFileSync(join(dir, 'b.go'), 'package main')
+  writeFileSync(join(dir, 'c.rs'), 'fn main() {}')
+  writeFileSync(join(dir, 'd.java'), 'class D {}')
+  const langs: string[] = []
+  await parseMulti([dir], (err, root) => {
+    t.is(err, null)
+    langs.push(root.root().kind())
+  })
+  t.deepEqual(langs.sort(), ['module', 'program', 'source_file', 'source_file'])
+})
+
+test.serial('register dynamic language', async t => {
+  if (process.platform !== 'linux' && process.platform !== 'darwin') {
+    t.pass()
+    return
+  }
+  const suffix = process.platform === 'linux' ? 'linux' : 'mac'
+  registerDynamicLanguage({
+    name: 'myjson',
+    libraryPath: join('..', '..', 'benches', 'fixtures', `json-${suffix}.so`),
+    extensions: ['myjson'],
+    languageSymbol: 'tree_sitter_json',
+  })
+  const sg = parseWithLang('myjson', '{"a": 123}')
+  t.is(sg.root().find('123')!.kind(), 'number')
+  const found = sg.root().find({ rule: { kind: 'pair' }, language: 'myjson' })
+  t.is(found!.text(), '"a": 123')
+  t.throws(() => registerDynamicLanguage({ name: 'myjson', libraryPath: '', extensions: [] }), {
+    message: /already registered/
+  })
+  const dir = mkdtempSync(join(tmpdir(), 'ast-grep-'))
+  writeFileSync(join(dir, 'a.myjson'), '{"b": 1}')
+  const counted = countedPromise((t, cb) => findInFiles('myjson', t, cb))
+  const fileCount = await counted({
+    paths: [dir],
+    matcher: { rule: { kind: 'number' } },
+  }, (err: null | Error, nodes: any[]) => {
+    t.is(err, null)
+    t.is(nodes[0].text(), '1')
+  })
+  t.is(fileCount, 1)
+})
+
+test('find in files iter', async t => {
+  const dir = mkdtempSync(join(tmpdir(), 'ast-grep-'))
+  writeFileSync(join(dir, 'a.ts'), 'console.log(1); console.log(2)')
+  writeFileSync(join(dir, 'b.ts'), 'console.log(3)')
+  const texts: string[] = []
+  for await (const node of findInFilesIter(Lang.TypeScript, {
+    paths: [dir],
+    matcher: { rule: { pattern: 'console.log($A)' } },
+  })) {
+    texts.push(node.text())
+    t.true(node.getRoot().filename().endsWith('.ts'))
+  }
+  t.deepEqual(texts.sort(), ['console.log(1)', 'console.log(2)', 'console.log(3)'])
+  let count = 0
+  for await (const _ of ts.findInFilesIter({
+    paths: [dir],
+    matcher: { rule: { pattern: 'console.log($A)' } },
+  })) {
+    count++
+    break
+  }
+  t.is(count, 1)
+})
@@ -8,34 +8,85 @@
  * See https://ast-grep.github.io/reference/yaml.html
  */
 export interface NapiConfig {
+  /** Unique rule id, used to group matches when scanning with multiple rules */
+  id?: string
   /** The rule object, see https://ast-grep.github.io/reference/rule.html */
   rule: any
   /** See https://ast-grep.github.io/guide/rule-config.html#constraints */
   constraints?: any
-  /** Available languages: html, css, js, jsx, ts, tsx */
-  language?: Lang
+  /**
+   * Available languages: html, css, js, jsx, ts, tsx, python, go, rust, java, c, other `Lang`s
+   * and languages registered by `registerDynamicLanguage`
+   */
+  language?: Lang | string
   /** https://ast-grep.github.io/reference/yaml.html#transform */
   transform?: any
   /** https://ast-grep.github.io/guide/rule-config/utility-rule.html */
   utils?: any
+  /**
+   * Node kinds that `scan` will not descend into for this rule, e.g. comments or strings.
+   * Other APIs like `findAll` skip matches of, or inside, these kinds.
+   */
+  ignoreKinds?: Array<string>
+  /**
+   * A string or FixConfig object, see https://ast-grep.github.io/reference/yaml/fix.html
+   * The fix of a match is available in `SgNode.fix`.
+   */
+  fix?: any
+  /**
+   * Rules with `id` and `fix` used by the `rewrite` transformation,
+   * see https://ast-grep.github.io/reference/yaml/rewriter.html
+   */
+  rewriters?: any
 }
 export interface FileOption {
   paths: Array<string>
   languageGlobs: Record<string, Array<string>>
 }
-export function parseFiles(paths: Array<string> | FileOption, callback: (err: null | Error, result: SgRoot) => void): Promise<number>
+export interface ParseFilesOptions {
+  /** Reject with a `TimeoutError` if files are not all parsed in the time. */
+  timeoutMs?: number
+  /** Reject with an `AbortError` when the signal is aborted. */
+  signal?: AbortSignal
+  /**
+   * Pass a root for each `<script>` and `<style>` block of Vue and Svelte files
+   * to `callback` after the HTML root of the file. Off by default.
+   */
+  splitBlocks?: boolean
+}
+/**
+ * Parse files in parallel.
+ * Resolves to the number of roots passed to `callback`, one per file unless `splitBlocks` is set.
+ * If aborted or timed out, stops parsing more files and rejects with code `Cancelled`.
+ */
+export function parseFiles(paths: Array<string> | FileOption, callback: (err: null | Error, result: SgRoot) => void, options?: ParseFilesOptions | undefined | null): Promise<number>
 export interface FindConfig {
   /** specify the file paths to recursively find files */
   paths: Array<string>
-  /** a Rule object to find what nodes will match */
-  matcher: NapiConfig
+  /** a Rule object, or a `Matcher` from `compileRule`, to find what nodes will match */
+  matcher: NapiConfig | Matcher
   /**
    * An list of pattern globs to treat of certain files in the specified language.
    * eg. ['*.vue', '*.svelte'] for html.findFiles, or ['*.ts'] for tsx.findFiles.
+   * Only `<script>` or `<style>` blocks in the language are matched in Vue and Svelte files,
+   * e.g. ['*.vue'] for ts.findFiles matches `<script lang="ts">`.
    * It is slightly different from https://ast-grep.github.io/reference/sgconfig.html#languageglobs
    */
   languageGlobs?: Array<string>
 }
+export interface FindInFilesOptions {
+  /**
+   * Minimal number of matches passed to one callback call, default 1.
+   * Matches of several files are batched in one call, except the last batch.
+   */
+  batchSize?: number
+  /** Maximal number of batches waiting for the JS thread before file walking blocks. */
+  highWaterMark?: number
+  /** Reject with a `TimeoutError` if files are not all searched in the time. */
+  timeoutMs?: number
+  /** Reject with an `AbortError` when the signal is aborted. */
+  signal?: AbortSignal
+}
 export const enum Lang {
   Html = 'Html',
   JavaScript = 'JavaScript',
@@ -61,6 +112,81 @@ export const enum Lang {
   Scala = 'Scala',
   Swift = 'Swift'
 }
+/** A tree-sitter grammar compiled as a dynamic library. */
+export interface DynamicLangOption {
+  /** Name of the language used as `lang`, e.g. `'kotlin'` */
+  name: string
+  /** Path of the compiled grammar, e.g. `kotlin.so`, `kotlin.dylib` or `kotlin.dll` */
+  libraryPath: string
+  /** File extensions without leading dot, used to infer the language of files, e.g. `['kt']` */
+  extensions: Array<string>
+  /** Char replacing `$` in patterns if `$` is not valid in identifiers of the language */
+  expandoChar?: string
+  /** Char of meta variables, `$` by default */
+  metaVarChar?: string
+  /** Symbol of the function returning the grammar, `tree_sitter_{name}` by default */
+  languageSymbol?: string
+}
+/** A node kind in the grammar of a language. */
+export interface NodeKind {
+  /** The kind id, same as `kind(lang, name)` for named kinds */
+  id: number
+  /** The name used in `kind` rules */
+  name: string
+  /** Named kinds are grammar rules like `identifier`, others are tokens like `(` or `if` */
+  named: boolean
+}
+/** An edit of the source in byte offsets, used by `SgRoot.applyEdit`. */
+export interface ByteEdit {
+  /** Byte offset where the replaced text starts */
+  startByte: number
+  /** Byte offset where the replaced text ends */
+  endByte: number
+  /** The new text */
+  text: string
+}
+/** A position in an original source file. */
+export interface OriginalPos {
+  /** line number starting from 0 */
+  line: number
+  /** column number starting from 0 */
+  column: number
+}
+/** A range in an original source file. */
+export interface OriginalRange {
+  /** The source file of the range, joined with `sourceRoot` of the map */
+  source: string
+  /** starting position in the source */
+  start: OriginalPos
+  /** ending position in the source, same as `start` if the end is mapped to another source */
+  end: OriginalPos
+}
+/** A node captured by a tree-sitter query. */
+export interface QueryCapture {
+  /** Name of the capture without `@`, e.g. `name` for `@name` */
+  name: string
+  /** Index of the pattern in the query that captured the node */
+  patternIndex: number
+  text: string
+  range: Range
+}
+export interface DumpOptions {
+  /** Include unnamed nodes like `(` or `if`, default false */
+  includeUnnamed?: boolean
+  /** Depth of the deepest dumped nodes, the root being 0. No limit by default */
+  maxDepth?: number
+}
+/** A syntax node in the tree returned by `SgRoot.dumpAST`. */
+export interface DumpNode {
+  kind: string
+  /** Field of the node in its parent, e.g. `name` */
+  field?: string
+  named: boolean
+  range: Range
+  /** Source text, only for nodes without dumped children to keep the dump small */
+  text?: string
+  children: Array<DumpNode>
+}
 export interface Edit {
   /** The start position of the edit */
   startPos: number
@@ -84,7 +210,7 @@ export interface Range {
   end: Pos
 }
 /** Parse a string to an ast-grep instance */
-export function parse(lang: Lang, src: string): SgRoot
+export function parse(lang: Lang | string, src: string): SgRoot
 /**
  * Parse a string to an ast-grep instance asynchronously in threads.
  * It utilize multiple CPU cores when **concurrent processing sources**.
@@ -92,39 +218,291 @@ export function parse(lang: Lang, src: string): SgRoot
  * Please refer to libuv doc, nodejs' underlying runtime
  * for its default behavior and performance tuning tricks.
  */
-export function parseAsync(lang: Lang, src: string): Promise<SgRoot>
+export function parseAsync(lang: Lang | string, src: string): Promise<SgRoot>
+/**
+ * Parse UTF-8 bytes to a tree without converting them to UTF-16 like `parse`.
+ * It saves memory and time for large files, and ranges of nodes are in byte offsets.
+ */
+export function parseBuffer(lang: Lang | string, src: Buffer): Utf8Root
+/**
+ * Open a tree shared by `SgRoot.share`, e.g. in a worker thread.
+ * The returned root has its own copy of the tree, so the source is not read or parsed again.
+ */
+export function openSharedRoot(handle: number): SgRoot
+/**
+ * Release a tree shared by `SgRoot.share`. Roots opened before are still valid.
+ * Returns false if the handle is not found.
+ */
+export function releaseSharedRoot(handle: number): boolean
 /** Get the `kind` number from its string name. */
-export function kind(lang: Lang, kindName: string): number
-/** Compile a string to ast-grep Pattern. */
-export function pattern(lang: Lang, pattern: string): NapiConfig
+export function kind(lang: Lang | string, kindName: string): number
+/**
+ * List visible node kinds of the language, e.g. to validate `kind` in rules or for autocompletion.
+ * Hidden kinds like `_expression` are omitted since they never appear in trees.
+ */
+export function nodeKinds(lang: Lang | string): Array<NodeKind>
+/** List field names of the language, e.g. to validate `field` in rules or for autocompletion. */
+export function fields(lang: Lang | string): Array<string>
+export interface PatternObject {
+  /** The pattern code. Same as `context`, only one of them can be set. */
+  pattern?: string
+  /** The surrounding code that helps to resolve any ambiguity in the syntax. */
+  context?: string
+  /** The sub-syntax node kind that is the actual matcher of the pattern. */
+  selector?: string
+  /** Strictness of the pattern. More strict pattern matches fewer nodes. Default `smart`. */
+  strictness?: 'cst' | 'smart' | 'ast' | 'relaxed' | 'signature'
+}
+/** Compile a pattern string or object to ast-grep Pattern. */
+export function pattern(lang: Lang | string, pattern: string | PatternObject): NapiConfig
+/**
+ * Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`,
+ * instead of parsing and building the rule config on every call.
+ * `lang` is used if the config has no `language`.
+ */
+export function compileRule(lang: Lang | string, config: NapiConfig): Matcher
+/**
+ * Register a tree-sitter grammar compiled as a dynamic library, e.g. `kotlin.so`.
+ * The language can then be used by its name in `parse`, `findInFiles` and `NapiConfig.language`,
+ * and files of its extensions are parsed by `parseFiles`.
+ * It is safe to register while other files are being parsed in the background.
+ */
+export function registerDynamicLanguage(option: DynamicLangOption): void
 /**
  * Discover and parse multiple files in Rust.
  * `lang` specifies the language.
  * `config` specifies the file path and matcher.
  * `callback` will receive matching nodes found in a file.
+ * `options` batches matches and limits the ones waiting for callback.
+ * The returned promise resolves to the file count, and its `pause` and `resume` throttle file walking.
+ */
+export function findInFiles(lang: Lang | string, config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+/**
+ * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+ * `lang` specifies the language and `config` is the same as `findInFiles`.
+ * Files are walked lazily so `break` in `for await` stops the search.
+ */
+export function findInFilesIter(lang: Lang | string, config: FindConfig): AsyncIterableIterator<SgNode>
+/**
+ * Discover and scan multiple files in Rust, streaming matches as NDJSON.
+ * `callback` receives one JSON line per match as soon as it is found,
+ * `{"type":"match","file":...,"text":...,"range":...}`,
+ * followed by a terminal `{"type":"summary","fileCount":...,"matchCount":...}` line.
+ * Every line, including the summary, ends with a newline.
+ */
+export function findInFilesAsNdjson(lang: Lang | string, config: FindConfig, callback: (err: null | Error, line: string) => void): Promise<number>
+export interface MatchCount {
+  /** total number of matches in all files */
+  total: number
+  /** number of matches keyed by file path, files without match are omitted */
+  files: Record<string, number>
+}
+/**
+ * Count matches in files without creating nodes for them, e.g. for metrics over a large codebase.
+ * `config` is the same as `findInFiles`.
+ */
+export function countMatches(lang: Lang | string, config: FindConfig): Promise<MatchCount>
+export interface FixInPlaceOptions {
+  /**
+   * The fix applied to each match, a string template or a FixConfig object.
+   * See https://ast-grep.github.io/reference/yaml.html#fix
+   */
+  fix: string | Record<string, unknown>
+  /** Compute the summary without writing files */
+  dryRun?: boolean
+  /** Skip files that would receive more edits than the cap, in all passes */
+  maxEdits?: number
+  /** Rescan fixed code and fix again until no match is left or the limit is reached. Default is 1. */
+  maxPasses?: number
+}
+export interface FixedFile {
+  file: string
+  /** number of edits applied to the file */
+  edits: number
+}
+export interface FixSummary {
+  /** files changed, or to be changed in `dryRun`, sorted by path */
+  changed: Array<FixedFile>
+  totalEdits: number
+  /** files skipped for exceeding `maxEdits`, sorted by path */
+  skipped: Array<string>
+  /** files whose fixes could not be written, sorted by path */
+  failed: Array<FailedFile>
+}
+export interface FailedFile {
+  file: string
+  error: string
+}
+/**
+ * Apply fixes of matches in files and write changed files back to disk.
+ * `config` is the same as `findInFiles` and `options.fix` is the rewrite applied to matches.
+ * Overlapping fixes are skipped in one pass, use `options.maxPasses` to fix them iteratively.
+ * Files are replaced atomically by renaming a temporary file in the same directory.
+ * Files that cannot be written are reported in `failed` and left unchanged.
+ * Fixes marked `unsafe` are rejected since nobody confirms them.
+ */
+export function fixInPlace(lang: Lang | string, config: FindConfig, options: FixInPlaceOptions): Promise<FixSummary>
+export interface ExtractStringOption {
+  /** Only return strings whose decoded value matches the regex */
+  regex?: string
+  /**
+   * Only return strings inside nodes matching the pattern.
+   * The pattern is parsed once in the language of each file,
+   * and the promise is rejected if it is invalid in any of them.
+   */
+  pattern?: string
+  /** customize additional file globs for languages */
+  languageGlobs?: Record<string, Array<string>>
+}
+export interface ExtractedString {
+  file: string
+  range: Range
+  /** The string content with quotes removed and escape sequences decoded */
+  value: string
+}
+/**
+ * Extract string literals in files, e.g. to find user-facing strings for i18n.
+ * Language is inferred from file extension. `value` of each result is the decoded string.
+ * `option.regex` filters the decoded value and `option.pattern` limits strings inside matches.
+ */
+export function extractStrings(paths: Array<string>, option?: ExtractStringOption | undefined | null): Promise<Array<ExtractedString>>
+export interface RedundantRule {
+  /** Id of the rule that matches everything matched by `specific` */
+  general: string
+  /** Id of the rule made redundant by `general` */
+  specific: string
+  /**
+   * `exact`: the two rules are identical.
+   * `high`: `general` has a subset of the rule fields and constraints of `specific`.
+   * `medium`: `general` pattern matches `specific` pattern, which is parsed as code.
+   */
+  confidence: string
+}
+/**
+ * Find rules that match a subset of what another rule matches, e.g. duplicates across rule files.
+ * `lang` is the default language for rules without `language`.
+ * A rule without `id` is identified by its index in `rules`. It is heuristic and may miss redundancy.
+ */
+export function findRedundantRules(lang: Lang, rules: Array<NapiConfig>): Array<RedundantRule>
+/**
+ * Save rules to a cache file after checking that every rule compiles.
+ * `source` is the rule config the rules are read from, e.g. the YAML text, and its hash is stored.
+ * `lang` is the default language for rules without `language`.
+ */
+export function saveRuleCache(cachePath: string, source: string, lang: Lang, rules: Array<NapiConfig>): void
+/**
+ * Load rules saved by `saveRuleCache` as compiled matchers, without reading the rule config again.
+ * Returns null if the cache is missing, or stale because `source`, `lang` or ast-grep version changed.
+ */
+export function loadRuleCache(cachePath: string, source: string, lang: Lang): Array<Matcher> | null
+export interface RuleLocation {
+  /** Path of the rule file, as passed to `ruleSources` */
+  file: string
+  /** 1-based line of the rule `id` */
+  line: number
+}
+/**
+ * Locate rules defined in YAML rule files, keyed by rule id like the result of `SgRoot.scan`.
+ * It lets tools report where the rule of a finding is defined, e.g. `rules/no-var.yml:12`.
+ * Rules with the same id in later files override earlier ones.
+ * Rules are skipped if their `id` lines cannot be located, e.g. in flow style YAML.
  */
-export function findInFiles(lang: Lang, config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void): Promise<number>
+export function ruleSources(paths: Array<string>): Record<string, RuleLocation>
+export interface ValidateConfigOptions {
+  /**
+   * Directories of global utility rules, relative to the validated directory.
+   * Files in them are registered as utils instead of validated as rules.
+   */
+  utilDirs?: Array<string>
+}
+export interface RuleFileError {
+  file: string
+  error: string
+}
+export interface DuplicateRuleId {
+  id: string
+  /** files defining the id, one entry per definition */
+  files: Array<string>
+}
+export interface UndefinedUtil {
+  file: string
+  /** id used in `matches` but not defined in `utils` or global utility rules */
+  util: string
+}
+export interface UnusedCapture {
+  id: string
+  file: string
+  /** meta variables captured by the rule but never referenced elsewhere in it */
+  captures: Array<string>
+}
+export interface ConfigDirReport {
+  /** false if any rule file fails to compile, references undefined utils or has duplicate ids */
+  valid: boolean
+  /** ids of compiled rules, in file path order */
+  ruleIds: Array<string>
+  errors: Array<RuleFileError>
+  duplicateIds: Array<DuplicateRuleId>
+  undefinedUtils: Array<UndefinedUtil>
+  /** unused captures are reported but do not fail the validation */
+  unusedCaptures: Array<UnusedCapture>
+}
+/**
+ * Validate every YAML rule file in a directory, e.g. as a CI gate for a rule library.
+ * The report lists compiled rule ids, files failing to compile, duplicate ids,
+ * references to undefined utility rules and captures never used by a rule.
+ */
+export function validateConfigDir(path: string, options?: ValidateConfigOptions | undefined | null): ConfigDirReport
+export interface VirtualFilesOptions {
+  /** Globs of files to treat as a language, e.g. `{ html: ['*.vue'] }`, like `FileOption`. */
+  languageGlobs?: Record<string, Array<string>>
+}
+/**
+ * Find nodes in sources held in memory, keyed by their paths, without reading files from disk.
+ * Languages are inferred from paths, and Vue or Svelte files treated as html by `languageGlobs`
+ * are matched in their blocks.
+ * A `Matcher`, or a config with `language`, only matches files in the language.
+ * A config without `language` skips files in languages it cannot compile for.
+ * `callback` is called with the matches of each root, and the promise resolves to the number of calls.
+ */
+export function findInVirtualFiles(files: Record<string, string>, matcher: NapiConfig | Matcher, callback: (err: null | Error, result: SgNode[]) => void, options?: VirtualFilesOptions): Promise<number>
+/** Async iterator returned by `findInFilesIter`. */
+/** A rule compiled by `compileRule` for one language. */
+export class Matcher {
+  /** The language the rule is compiled for. */
+  language(): Lang | string
+}
+export class FindInFilesIter {
+  /** Resolve the next match, or `done` after all files are walked. */
+  next(): Promise<IteratorResult<SgNode, undefined>>
+  /** Stop walking files, called when `for await` exits early. */
+  return(): IteratorResult<SgNode, undefined>
+}
 export class SgNode {
   range(): Range
+  /**
+   * Range of the node in the original source of transpiled code, e.g. `.tsx` of compiled `.js`.
+   * Returns null if the node is not mapped by the source map.
+   */
+  originalRange(sourceMap: SourceMap): OriginalRange | null
   isLeaf(): boolean
   isNamed(): boolean
   isNamedLeaf(): boolean
   /** Returns the string name of the node kind */
   kind(): string
   text(): string
-  matches(m: string): boolean
-  inside(m: string): boolean
-  has(m: string): boolean
-  precedes(m: string): boolean
-  follows(m: string): boolean
+  matches(m: string | PatternObject): boolean
+  inside(m: string | PatternObject): boolean
+  has(m: string | PatternObject): boolean
+  precedes(m: string | PatternObject): boolean
+  follows(m: string | PatternObject): boolean
   getMatch(m: string): SgNode | null
   getMultipleMatches(m: string): Array<SgNode>
   getTransformed(m: string): string | null
   /** Returns the node's SgRoot */
   getRoot(): SgRoot
   children(): Array<SgNode>
-  find(matcher: string | number | NapiConfig): SgNode | null
-  findAll(matcher: string | number | NapiConfig): Array<SgNode>
+  find(matcher: string | number | NapiConfig | Matcher | PatternObject): SgNode | null
+  findAll(matcher: string | number | NapiConfig | Matcher | PatternObject): Array<SgNode>
   /** Finds the child node in the `field` */
   field(name: string): SgNode | null
   parent(): SgNode | null
@@ -134,8 +512,29 @@ export class SgNode {
   nextAll(): Array<SgNode>
   prev(): SgNode | null
   prevAll(): Array<SgNode>
-  replace(text: string): Edit
-  commitEdits(edits: Array<Edit>): string
+  /**
+   * Finds the declaration of the identifier in the same file, e.g. a variable, function or import.
+   * This is best-effort and scope-unaware: the nearest preceding declaration in enclosing nodes is returned.
+   */
+  findDefinition(): SgNode | null
+  /**
+   * Create an edit replacing the node.
+   * If `includeTrivia` is true, leading comments and a trailing comment on the same line are also replaced.
+   */
+  replace(text: string, includeTrivia?: boolean | undefined | null): Edit
+  /**
+   * The edit of the `fix` in the rule that found the node, e.g. by `findAll` or `SgRoot.scan`.
+   * Returns null if the node is not found by a rule with `fix`.
+   */
+  fix(): Edit | null
+  /** The text replacing the node by the rule's `fix`, i.e. `fix()?.insertedText`. */
+  getFixedText(): string | null
+  /**
+   * Apply edits to the node's text. Edits overlapping with a preceding one are skipped.
+   * If `maxEdits` is set and more edits are passed, an error naming the file is thrown
+   * instead of applying any edit, so the file can be reviewed manually.
+   */
+  commitEdits(edits: Array<Edit>, maxEdits?: number | undefined | null): string
 }
 /** Represents the parsed tree of code. */
 export class SgRoot {
@@ -146,6 +545,83 @@ export class SgRoot {
    * Returns `"anonymous"` if the instance is created by `lang.parse(source)`.
    */
   filename(): string
+  /**
+   * Share the tree with worker threads, e.g. by `postMessage`, and return its handle.
+   * Workers call `openSharedRoot(handle)` to get the tree without parsing the file again.
+   * Call `releaseSharedRoot(handle)` when no worker needs it anymore.
+   */
+  share(): number
+  /**
+   * Run a tree-sitter query, e.g. from a `.scm` file, and return the captured nodes.
+   * Predicates like `#eq?` and `#match?` are supported.
+   */
+  queryTS(query: string): Array<QueryCapture>
+  /**
+   * Dump the tree as a JSON-compatible object of kinds, fields, ranges and text.
+   * Only named nodes are dumped by default, like the nodes `kind` rules can match.
+   */
+  dumpAST(options?: DumpOptions | undefined | null): DumpNode
+  /**
+   * Apply edits from `replace` of any nodes in the tree and return the new source.
+   * Edits overlapping with a preceding one are skipped. `maxEdits` works as in `SgNode.commitEdits`.
+   */
+  commitEdits(edits: Array<Edit>, maxEdits?: number | undefined | null): string
+  /**
+   * Apply edits like `commitEdits` and parse the new source incrementally from this tree.
+   * A new root is returned so nodes of this root stay valid. `maxEdits` works as in `commitEdits`.
+   */
+  commitEditsAndParse(edits: Array<Edit>, maxEdits?: number | undefined | null): SgRoot
+  /**
+   * Apply an edit to the source and parse it incrementally from the current tree,
+   * e.g. to keep the tree of a file open in an editor in sync.
+   * Offsets are in bytes of the UTF-16 source that tree-sitter parses, i.e. twice `Pos.index`.
+   * Nodes got before the edit still see the old source, whose tree is copied for the edit.
+   * Otherwise the tree is edited in place.
+   */
+  applyEdit(edit: ByteEdit): void
+  /**
+   * Scan the tree with multiple rules in a single traversal.
+   * Returns matched nodes grouped by rule id.
+   * Matches of rules sharing an id are merged under that id.
+   * A rule without `id` is keyed by its index in `rules`.
+   */
+  scan(rules: Array<NapiConfig>): Record<string, Array<SgNode>>
+}
+/** A decoded source map, passed with the generated code to translate positions back. */
+export class SourceMap {
+  /** Decode a source map in JSON. Index maps with `sections` are not supported. */
+  constructor(json: string)
+  /**
+   * Translate a range in the generated code, e.g. `node.range()`, to the original source.
+   * Returns null if the start of the range is not mapped.
+   */
+  originalRange(range: Range): OriginalRange | null
+}
+/** Parsed tree of UTF-8 source from `parseBuffer`. */
+export class Utf8Root {
+  /** Returns the root node of the tree. */
+  root(): Utf8Node
+}
+/** Node of a `Utf8Root`. `column` and `index` of its range are in bytes. */
+export class Utf8Node {
+  /** Range of the node in byte offsets of the UTF-8 source. */
+  range(): Range
+  isLeaf(): boolean
+  isNamed(): boolean
+  /** Returns the string name of the node kind */
+  kind(): string
+  text(): string
+  matches(m: string | PatternObject): boolean
+  getMatch(m: string): Utf8Node | null
+  getMultipleMatches(m: string): Array<Utf8Node>
+  /** Returns the value of a metavariable computed by `transform` of the rule that found the node. */
+  getTransformed(m: string): string | null
+  find(matcher: string | number | NapiConfig | Matcher | PatternObject): Utf8Node | null
+  findAll(matcher: string | number | NapiConfig | Matcher | PatternObject): Array<Utf8Node>
+  children(): Array<Utf8Node>
+  /** Finds the child node in the `field` */
+  field(name: string): Utf8Node | null
+  parent(): Utf8Node | null
 }
 export namespace html {
   /** Parse a string to an ast-grep instance */
@@ -158,16 +634,30 @@ export namespace html {
    * for its default behavior and performance tuning tricks.
    */
   export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
   /** Get the `kind` number from its string name. */
   export function kind(kindName: string): number
-  /** Compile a string to ast-grep Pattern. */
-  export function pattern(pattern: string): NapiConfig
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
   /**
    * Discover and parse multiple files in Rust.
    * `config` specifies the file path and matcher.
    * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
    */
-  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void): Promise<number>
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
 }
 export namespace js {
   /** Parse a string to an ast-grep instance */
@@ -180,16 +670,30 @@ export namespace js {
    * for its default behavior and performance tuning tricks.
    */
   export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
   /** Get the `kind` number from its string name. */
   export function kind(kindName: string): number
-  /** Compile a string to ast-grep Pattern. */
-  export function pattern(pattern: string): NapiConfig
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
   /**
    * Discover and parse multiple files in Rust.
    * `config` specifies the file path and matcher.
    * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
    */
-  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void): Promise<number>
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
 }
 export namespace jsx {
   /** Parse a string to an ast-grep instance */
@@ -202,16 +706,30 @@ export namespace jsx {
    * for its default behavior and performance tuning tricks.
    */
   export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
   /** Get the `kind` number from its string name. */
   export function kind(kindName: string): number
-  /** Compile a string to ast-grep Pattern. */
-  export function pattern(pattern: string): NapiConfig
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
   /**
    * Discover and parse multiple files in Rust.
    * `config` specifies the file path and matcher.
    * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
    */
-  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void): Promise<number>
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
 }
 export namespace ts {
   /** Parse a string to an ast-grep instance */
@@ -224,16 +742,30 @@ export namespace ts {
    * for its default behavior and performance tuning tricks.
    */
   export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
   /** Get the `kind` number from its string name. */
   export function kind(kindName: string): number
-  /** Compile a string to ast-grep Pattern. */
-  export function pattern(pattern: string): NapiConfig
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
   /**
    * Discover and parse multiple files in Rust.
    * `config` specifies the file path and matcher.
    * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
    */
-  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void): Promise<number>
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
 }
 export namespace tsx {
   /** Parse a string to an ast-grep instance */
@@ -246,16 +778,30 @@ export namespace tsx {
    * for its default behavior and performance tuning tricks.
    */
   export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
   /** Get the `kind` number from its string name. */
   export function kind(kindName: string): number
-  /** Compile a string to ast-grep Pattern. */
-  export function pattern(pattern: string): NapiConfig
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
   /**
    * Discover and parse multiple files in Rust.
    * `config` specifies the file path and matcher.
    * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
    */
-  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void): Promise<number>
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
 }
 export namespace css {
   /** Parse a string to an ast-grep instance */
@@ -268,14 +814,208 @@ export namespace css {
    * for its default behavior and performance tuning tricks.
    */
   export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
+  /** Get the `kind` number from its string name. */
+  export function kind(kindName: string): number
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
+  /**
+   * Discover and parse multiple files in Rust.
+   * `config` specifies the file path and matcher.
+   * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
+}
+export namespace python {
+  /** Parse a string to an ast-grep instance */
+  export function parse(src: string): SgRoot
+  /**
+   * Parse a string to an ast-grep instance asynchronously in threads.
+   * It utilize multiple CPU cores when **concurrent processing sources**.
+   * However, spawning excessive many threads may backfire.
+   * Please refer to libuv doc, nodejs' underlying runtime
+   * for its default behavior and performance tuning tricks.
+   */
+  export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
+  /** Get the `kind` number from its string name. */
+  export function kind(kindName: string): number
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
+  /**
+   * Discover and parse multiple files in Rust.
+   * `config` specifies the file path and matcher.
+   * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
+}
+export namespace go {
+  /** Parse a string to an ast-grep instance */
+  export function parse(src: string): SgRoot
+  /**
+   * Parse a string to an ast-grep instance asynchronously in threads.
+   * It utilize multiple CPU cores when **concurrent processing sources**.
+   * However, spawning excessive many threads may backfire.
+   * Please refer to libuv doc, nodejs' underlying runtime
+   * for its default behavior and performance tuning tricks.
+   */
+  export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
   /** Get the `kind` number from its string name. */
   export function kind(kindName: string): number
-  /** Compile a string to ast-grep Pattern. */
-  export function pattern(pattern: string): NapiConfig
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
   /**
    * Discover and parse multiple files in Rust.
    * `config` specifies the file path and matcher.
    * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
+}
+export namespace rust {
+  /** Parse a string to an ast-grep instance */
+  export function parse(src: string): SgRoot
+  /**
+   * Parse a string to an ast-grep instance asynchronously in threads.
+   * It utilize multiple CPU cores when **concurrent processing sources**.
+   * However, spawning excessive many threads may backfire.
+   * Please refer to libuv doc, nodejs' underlying runtime
+   * for its default behavior and performance tuning tricks.
+   */
+  export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
+  /** Get the `kind` number from its string name. */
+  export function kind(kindName: string): number
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
+  /**
+   * Discover and parse multiple files in Rust.
+   * `config` specifies the file path and matcher.
+   * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
+}
+export namespace java {
+  /** Parse a string to an ast-grep instance */
+  export function parse(src: string): SgRoot
+  /**
+   * Parse a string to an ast-grep instance asynchronously in threads.
+   * It utilize multiple CPU cores when **concurrent processing sources**.
+   * However, spawning excessive many threads may backfire.
+   * Please refer to libuv doc, nodejs' underlying runtime
+   * for its default behavior and performance tuning tricks.
+   */
+  export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
+  /** Get the `kind` number from its string name. */
+  export function kind(kindName: string): number
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
+  /**
+   * Discover and parse multiple files in Rust.
+   * `config` specifies the file path and matcher.
+   * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
+   */
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
+}
+export namespace c {
+  /** Parse a string to an ast-grep instance */
+  export function parse(src: string): SgRoot
+  /**
+   * Parse a string to an ast-grep instance asynchronously in threads.
+   * It utilize multiple CPU cores when **concurrent processing sources**.
+   * However, spawning excessive many threads may backfire.
+   * Please refer to libuv doc, nodejs' underlying runtime
+   * for its default behavior and performance tuning tricks.
+   */
+  export function parseAsync(src: string): Promise<SgRoot>
+  /** Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16. */
+  export function parseBuffer(src: Buffer): Utf8Root
+  /** Get the `kind` number from its string name. */
+  export function kind(kindName: string): number
+  /** List visible node kinds of the language, e.g. to validate `kind` in rules. */
+  export function nodeKinds(): Array<NodeKind>
+  /** List field names of the language, e.g. to validate `field` in rules. */
+  export function fields(): Array<string>
+  /** Compile a pattern string or object to ast-grep Pattern. */
+  export function pattern(pattern: string | PatternObject): NapiConfig
+  /** Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`. */
+  export function compileRule(config: NapiConfig): Matcher
+  /**
+   * Discover and parse multiple files in Rust.
+   * `config` specifies the file path and matcher.
+   * `callback` will receive matching nodes found in a file.
+   * `options` batches matches and limits the ones waiting for callback.
+   */
+  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions): Promise<number> & { pause(): void; resume(): void }
+  /**
+   * Discover and parse multiple files in Rust, yielding matches as an async iterator.
+   * Files are walked lazily so `break` in `for await` stops the search.
    */
-  export function findInFiles(config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void): Promise<number>
+  export function findInFilesIter(config: FindConfig): AsyncIterableIterator<SgNode>
 }
@@ -281,20 +281,48 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { parseFiles, Lang, SgNode, SgRoot, parse, parseAsync, kind, pattern, findInFiles, html, js, jsx, ts, tsx, css } = nativeBinding
+const { Matcher, parseFiles, FindInFilesIter, Lang, SgNode, SgRoot, SourceMap, Utf8Root, Utf8Node, parse, parseAsync, parseBuffer, openSharedRoot, releaseSharedRoot, kind, nodeKinds, fields, pattern, compileRule, registerDynamicLanguage, findInFiles, findInFilesIter, findInFilesAsNdjson, countMatches, fixInPlace, extractStrings, findRedundantRules, saveRuleCache, loadRuleCache, ruleSources, validateConfigDir, findInVirtualFiles, html, js, jsx, ts, tsx, css, python, go, rust, java, c } = nativeBinding
 
+module.exports.Matcher = Matcher
 module.exports.parseFiles = parseFiles
+module.exports.FindInFilesIter = FindInFilesIter
 module.exports.Lang = Lang
 module.exports.SgNode = SgNode
 module.exports.SgRoot = SgRoot
+module.exports.SourceMap = SourceMap
+module.exports.Utf8Root = Utf8Root
+module.exports.Utf8Node = Utf8Node
 module.exports.parse = parse
 module.exports.parseAsync = parseAsync
+module.exports.parseBuffer = parseBuffer
+module.exports.openSharedRoot = openSharedRoot
+module.exports.releaseSharedRoot = releaseSharedRoot
 module.exports.kind = kind
+module.exports.nodeKinds = nodeKinds
+module.exports.fields = fields
 module.exports.pattern = pattern
+module.exports.compileRule = compileRule
+module.exports.registerDynamicLanguage = registerDynamicLanguage
 module.exports.findInFiles = findInFiles
+module.exports.findInFilesIter = findInFilesIter
+module.exports.findInFilesAsNdjson = findInFilesAsNdjson
+module.exports.countMatches = countMatches
+module.exports.fixInPlace = fixInPlace
+module.exports.extractStrings = extractStrings
+module.exports.findRedundantRules = findRedundantRules
+module.exports.saveRuleCache = saveRuleCache
+module.exports.loadRuleCache = loadRuleCache
+module.exports.ruleSources = ruleSources
+module.exports.validateConfigDir = validateConfigDir
+module.exports.findInVirtualFiles = findInVirtualFiles
 module.exports.html = html
 module.exports.js = js
 module.exports.jsx = jsx
 module.exports.ts = ts
 module.exports.tsx = tsx
 module.exports.css = css
+module.exports.python = python
+module.exports.go = go
+module.exports.rust = rust
+module.exports.java = java
+module.exports.c = c
@@ -0,0 +1,78 @@
+//! `compileRule`, a rule compiled once and reused by `find`, `findAll` and `findInFiles`
+//! without deserializing and building the rule on every call.
+use ast_grep_config::RuleCore;
+use napi::anyhow::anyhow;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use std::sync::Arc;
+
+use crate::doc::NapiConfig;
+use crate::napi_lang::NapiLang;
+
+/// A rule compiled by `compileRule` for one language.
+#[napi(js_name = "Matcher")]
+pub struct CompiledRule {
+  rule: Arc<RuleCore<NapiLang>>,
+  /// kept for APIs overriding the rule, e.g. `fixInPlace`
+  config: NapiConfig,
+  lang: NapiLang,
+}
+
+#[napi]
+impl CompiledRule {
+  /// The language the rule is compiled for.
+  #[napi(ts_return_type = "Lang | string")]
+  pub fn language(&self) -> String {
+    self.lang.to_string()
+  }
+}
+
+impl CompiledRule {
+  pub(crate) fn lang(&self) -> NapiLang {
+    self.lang
+  }
+}
+
+/// A rule config or a compiled `Matcher`.
+pub type RuleMatcher = Either<ClassInstance<CompiledRule>, NapiConfig>;
+
+pub fn compile_rule_impl(lang: NapiLang, config: NapiConfig) -> Result<CompiledRule> {
+  let lang = config.language.unwrap_or(lang);
+  let rule = config.clone().parse_with(lang)?;
+  Ok(CompiledRule {
+    rule: Arc::new(rule),
+    config,
+    lang,
+  })
+}
+
+/// Get the compiled rule for `lang`, or compile the rule config.
+pub fn compile_matcher(matcher: RuleMatcher, lang: NapiLang) -> Result<Arc<RuleCore<NapiLang>>> {
+  match matcher {
+    Either::A(compiled) => {
+      check_lang(&compiled, lang)?;
+      Ok(compiled.rule.clone())
+    }
+    Either::B(config) => Ok(Arc::new(config.parse_with(lang)?)),
+  }
+}
+
+/// Get the rule config of the matcher, e.g. to compile it with another `fix`.
+pub fn matcher_config(matcher: RuleMatcher, lang: NapiLang) -> Result<NapiConfig> {
+  match matcher {
+    Either::A(compiled) => {
+      check_lang(&compiled, lang)?;
+      Ok(compiled.config.clone())
+    }
+    Either::B(config) => Ok(config),
+  }
+}
+
+fn check_lang(compiled: &CompiledRule, lang: NapiLang) -> Result<()> {
+  if compiled.lang == lang {
+    return Ok(());
+  }
+  let expected = compiled.lang;
+  Err(anyhow!("Matcher is compiled for {expected}, but used for {lang}").into())
+}
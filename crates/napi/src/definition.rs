@@ -0,0 +1,132 @@
+//! Best-effort lookup of the declaration of an identifier.
+//! It is scope-unaware: enclosing nodes are treated as scopes and the nearest
+//! preceding binding of the same name wins. No type or module information is used.
+use ast_grep_core::{Doc, Node};
+
+/// Fields that hold the bound name in declaration-like nodes across grammars.
+const BINDING_FIELDS: &[&str] = &["name", "left", "pattern", "alias"];
+
+/// Find the declaration site of the identifier, searching enclosing scopes from inside out.
+/// Returns the identifier node in the declaration, e.g. `a` in `const a = 1`.
+pub fn find_definition<'r, D: Doc>(ident: &Node<'r, D>) -> Option<Node<'r, D>> {
+  let name = ident.text();
+  let start = ident.range().start;
+  for scope in ident.ancestors() {
+    let mut found = None;
+    // children containing the identifier are already visited as inner scopes
+    for child in scope.children().take_while(|c| c.range().end <= start) {
+      let binding = if child.kind().contains("parameter") {
+        // parameters of the function enclosing the identifier
+        child.dfs().filter(|n| is_binding(n, &name)).last()
+      } else {
+        find_binding(&child, &name)
+      };
+      if binding.is_some() {
+        found = binding;
+      }
+    }
+    if found.is_some() {
+      return found;
+    }
+  }
+  None
+}
+
+/// Find the last binding of `name` in node, not descending into nested blocks or parameters.
+fn find_binding<'r, D: Doc>(node: &Node<'r, D>, name: &str) -> Option<Node<'r, D>> {
+  if is_block(node) || node.kind().contains("parameter") {
+    return None;
+  }
+  if is_binding(node, name) {
+    return Some(node.clone());
+  }
+  node
+    .children()
+    .filter_map(|c| find_binding(&c, name))
+    .last()
+}
+
+fn is_block<D: Doc>(node: &Node<D>) -> bool {
+  let kind = node.kind();
+  kind.contains("block") || kind.contains("body")
+}
+
+/// Check if the node is an identifier bound by its parent declaration.
+fn is_binding<D: Doc>(node: &Node<D>, name: &str) -> bool {
+  if !node.kind().contains("identifier") || node.text() != name {
+    return false;
+  }
+  let Some(parent) = node.parent() else {
+    return false;
+  };
+  let kind = parent.kind();
+  if kind.contains("parameter") || kind.contains("import") {
+    return true;
+  }
+  let is_declaration = [
+    "declarat",
+    "definition",
+    "assignment",
+    "specifier",
+    "import",
+  ]
+  .iter()
+  .any(|k| kind.contains(k));
+  is_declaration
+    && BINDING_FIELDS
+      .iter()
+      .filter_map(|f| parent.field(f))
+      .any(|n| n.range() == node.range())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_core::language::Language;
+  use ast_grep_core::matcher::KindMatcher;
+  use ast_grep_language::SupportLang;
+
+  fn definition_line(src: &str, lang: SupportLang, nth_usage: usize) -> Option<usize> {
+    let root = lang.ast_grep(src);
+    let matcher = KindMatcher::new("identifier", lang);
+    let usages: Vec<_> = root
+      .root()
+      .find_all(matcher)
+      .filter(|n| n.text() == "a")
+      .collect();
+    let def = find_definition(&usages[nth_usage])?;
+    assert_eq!(def.text(), "a");
+    Some(def.start_pos().0)
+  }
+
+  #[test]
+  fn test_variable_definition() {
+    let src = "const a = 1\nfunction f() {\n  const a = 2\n  use(a)\n}\nuse(a)";
+    let ts = SupportLang::TypeScript;
+    // the inner usage resolves to the shadowing declaration
+    assert_eq!(definition_line(src, ts, 2), Some(2));
+    assert_eq!(definition_line(src, ts, 3), Some(0));
+  }
+
+  #[test]
+  fn test_function_and_parameter() {
+    let src = "function a() {}\nfunction f(a) {\n  return a\n}\na()";
+    let js = SupportLang::JavaScript;
+    assert_eq!(definition_line(src, js, 2), Some(1));
+    assert_eq!(definition_line(src, js, 3), Some(0));
+  }
+
+  #[test]
+  fn test_import_definition() {
+    let src = "import { a } from 'mod'\na()";
+    assert_eq!(definition_line(src, SupportLang::TypeScript, 1), Some(0));
+    let src = "from mod import a\na = 1\nprint(a)";
+    assert_eq!(definition_line(src, SupportLang::Python, 2), Some(1));
+  }
+
+  #[test]
+  fn test_no_definition() {
+    let src = "use(a)";
+    assert_eq!(definition_line(src, SupportLang::TypeScript, 0), None);
+  }
+}
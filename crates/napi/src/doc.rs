@@ -1,12 +1,14 @@
-use crate::Lang;
+use crate::napi_lang::NapiLang;
 
-use ast_grep_config::{DeserializeEnv, RuleCore, SerializableRuleCore};
+use ast_grep_config::{
+  GlobalRules, RuleConfig, RuleCore, SerializableRuleConfig, SerializableRuleCore,
+};
 use ast_grep_core::source::{Content, Doc, Edit, TSParseError};
 use ast_grep_core::Language;
-use ast_grep_language::SupportLang;
 use napi::anyhow::Error;
 use napi::bindgen_prelude::Result as NapiResult;
 use napi_derive::napi;
+use serde_json::json;
 use tree_sitter::{InputEdit, Node, Parser, ParserError, Point, Tree};
 
 use std::borrow::Cow;
@@ -15,39 +17,144 @@ use std::ops::Range;
 /// Rule configuration similar to YAML
 /// See https://ast-grep.github.io/reference/yaml.html
 #[napi(object)]
+#[derive(Clone)]
 pub struct NapiConfig {
+  /// Unique rule id, used to group matches when scanning with multiple rules
+  pub id: Option<String>,
   /// The rule object, see https://ast-grep.github.io/reference/rule.html
   pub rule: serde_json::Value,
   /// See https://ast-grep.github.io/guide/rule-config.html#constraints
   pub constraints: Option<serde_json::Value>,
-  /// Available languages: html, css, js, jsx, ts, tsx
-  pub language: Option<Lang>,
+  /// Available languages: html, css, js, jsx, ts, tsx, python, go, rust, java, c, other `Lang`s
+  /// and languages registered by `registerDynamicLanguage`
+  #[napi(ts_type = "Lang | string")]
+  pub language: Option<NapiLang>,
   /// https://ast-grep.github.io/reference/yaml.html#transform
   pub transform: Option<serde_json::Value>,
   /// https://ast-grep.github.io/guide/rule-config/utility-rule.html
   pub utils: Option<serde_json::Value>,
+  /// Node kinds that `scan` will not descend into for this rule, e.g. comments or strings.
+  /// Other APIs like `findAll` skip matches of, or inside, these kinds.
+  pub ignore_kinds: Option<Vec<String>>,
+  /// A string or FixConfig object, see https://ast-grep.github.io/reference/yaml/fix.html
+  /// The fix of a match is available in `SgNode.fix`.
+  pub fix: Option<serde_json::Value>,
+  /// Rules with `id` and `fix` used by the `rewrite` transformation,
+  /// see https://ast-grep.github.io/reference/yaml/rewriter.html
+  pub rewriters: Option<serde_json::Value>,
 }
 
+#[cfg(test)]
 impl NapiConfig {
-  pub fn parse_with(self, language: Lang) -> NapiResult<RuleCore<SupportLang>> {
-    let lang = self.language.unwrap_or(language);
-    let lang: SupportLang = lang.into();
-    let rule = SerializableRuleCore {
+  /// Config of the rule object with all other fields unset.
+  pub fn from_rule(rule: serde_json::Value) -> Self {
+    Self {
+      id: None,
+      rule,
+      constraints: None,
+      language: None,
+      transform: None,
+      utils: None,
+      ignore_kinds: None,
+      fix: None,
+      rewriters: None,
+    }
+  }
+}
+
+impl NapiConfig {
+  fn into_core(self) -> NapiResult<SerializableRuleCore> {
+    Ok(SerializableRuleCore {
       rule: serde_json::from_value(self.rule)?,
       constraints: self.constraints.map(serde_json::from_value).transpose()?,
       transform: self.transform.map(serde_json::from_value).transpose()?,
       utils: self.utils.map(serde_json::from_value).transpose()?,
-      fix: None,
-    };
-    let env = DeserializeEnv::new(lang);
-    rule.get_matcher(env).map_err(|e| {
-      let error = Error::from(e)
-        .chain()
-        .map(ToString::to_string)
-        .collect::<Vec<_>>();
-      napi::Error::new(napi::Status::InvalidArg, error.join("\n |->"))
+      fix: self.fix.map(serde_json::from_value).transpose()?,
     })
   }
+
+  /// Rewriters are only registered via a full rule config, so every rule is parsed as one.
+  fn into_rule_config(
+    mut self,
+    language: NapiLang,
+    default_id: String,
+  ) -> NapiResult<SerializableRuleConfig<NapiLang>> {
+    let lang = self.language.unwrap_or(language);
+    let id = self.id.take().unwrap_or(default_id);
+    let ignore_kinds = self.ignore_kinds.take();
+    let rewriters = self.rewriters.take();
+    Ok(SerializableRuleConfig {
+      core: self.into_core()?,
+      id,
+      language: lang,
+      rewriters: rewriters.map(serde_json::from_value).transpose()?,
+      message: String::new(),
+      note: None,
+      severity: Default::default(),
+      files: None,
+      ignores: None,
+      ignore_kinds,
+      severity_overrides: None,
+      labels: None,
+      url: None,
+      metadata: None,
+    })
+  }
+
+  /// `ignoreKinds` is pruned by `CombinedScan` when scanning. A standalone matcher
+  /// instead excludes nodes that are of, or inside, an ignored kind.
+  fn into_matcher_config(
+    mut self,
+    language: NapiLang,
+  ) -> NapiResult<SerializableRuleConfig<NapiLang>> {
+    if let Some(kinds) = self.ignore_kinds.take().filter(|k| !k.is_empty()) {
+      let ignored: Vec<_> = kinds.iter().map(|kind| json!({ "kind": kind })).collect();
+      let excluded = json!({
+        "any": [{ "any": ignored }, { "inside": { "any": ignored, "stopBy": "end" } }]
+      });
+      self.rule = json!({ "all": [self.rule, { "not": excluded }] });
+    }
+    self.into_rule_config(language, String::new())
+  }
+
+  pub fn parse_with(self, language: NapiLang) -> NapiResult<RuleCore<NapiLang>> {
+    let config = self.into_matcher_config(language)?;
+    config
+      .get_matcher(&GlobalRules::default())
+      .map_err(to_napi_error)
+  }
+
+  /// Same as `parse_with` but the `fix` overrides the config's, e.g. for `fixInPlace`.
+  pub fn parse_with_fix(
+    self,
+    language: NapiLang,
+    fix: serde_json::Value,
+  ) -> NapiResult<RuleCore<NapiLang>> {
+    let mut config = self.into_matcher_config(language)?;
+    config.core.fix = Some(serde_json::from_value(fix)?);
+    config
+      .get_matcher(&GlobalRules::default())
+      .map_err(to_napi_error)
+  }
+
+  /// Build a full RuleConfig for scanning. `default_id` is used if the config has no id.
+  pub fn parse_rule_config(
+    self,
+    language: NapiLang,
+    default_id: String,
+  ) -> NapiResult<RuleConfig<NapiLang>> {
+    let inner = self.into_rule_config(language, default_id)?;
+    RuleConfig::try_from(inner, &GlobalRules::default()).map_err(to_napi_error)
+  }
+}
+
+fn to_napi_error<E: Into<Error>>(e: E) -> napi::Error {
+  let error = e
+    .into()
+    .chain()
+    .map(ToString::to_string)
+    .collect::<Vec<_>>();
+  napi::Error::new(napi::Status::InvalidArg, error.join("\n |->"))
 }
 
 #[derive(Clone)]
@@ -55,6 +162,12 @@ pub struct Wrapper {
   inner: Vec<u16>,
 }
 
+impl Wrapper {
+  pub fn as_slice(&self) -> &[u16] {
+    &self.inner
+  }
+}
+
 impl Content for Wrapper {
   type Underlying = u16;
   fn parse_tree_sitter(
@@ -123,12 +236,12 @@ fn pos_for_byte_offset(input: &[u16], byte_offset: usize) -> Point {
 
 #[derive(Clone)]
 pub struct JsDoc {
-  lang: SupportLang,
+  lang: NapiLang,
   source: Wrapper,
 }
 
 impl JsDoc {
-  pub fn new(src: String, lang: SupportLang) -> Self {
+  pub fn new(src: String, lang: NapiLang) -> Self {
     let source = Wrapper {
       inner: src.encode_utf16().collect(),
     };
@@ -137,7 +250,7 @@ impl JsDoc {
 }
 
 impl Doc for JsDoc {
-  type Lang = SupportLang;
+  type Lang = NapiLang;
   type Source = Wrapper;
   fn parse(&self, old_tree: Option<&Tree>) -> std::result::Result<Tree, TSParseError> {
     let mut parser = Parser::new()?;
@@ -173,9 +286,10 @@ impl Doc for JsDoc {
 mod test {
   use super::*;
   use ast_grep_core::AstGrep;
+  use ast_grep_language::SupportLang;
   #[test]
   fn test_js_doc() {
-    let doc = JsDoc::new("console.log(123)".into(), SupportLang::JavaScript);
+    let doc = JsDoc::new("console.log(123)".into(), SupportLang::JavaScript.into());
     let grep = AstGrep::doc(doc);
     assert_eq!(grep.root().text(), "console.log(123)");
     let node = grep.root().find("console");
@@ -184,7 +298,10 @@ mod test {
 
   #[test]
   fn test_js_doc_single_node_replace() {
-    let doc = JsDoc::new("console.log(1 + 2 + 3)".into(), SupportLang::JavaScript);
+    let doc = JsDoc::new(
+      "console.log(1 + 2 + 3)".into(),
+      SupportLang::JavaScript.into(),
+    );
     let mut grep = AstGrep::doc(doc);
     let edit = grep
       .root()
@@ -196,7 +313,10 @@ mod test {
 
   #[test]
   fn test_js_doc_multiple_node_replace() {
-    let doc = JsDoc::new("console.log(1 + 2 + 3)".into(), SupportLang::JavaScript);
+    let doc = JsDoc::new(
+      "console.log(1 + 2 + 3)".into(),
+      SupportLang::JavaScript.into(),
+    );
     let mut grep = AstGrep::doc(doc);
     let edit = grep
       .root()
@@ -205,4 +325,67 @@ mod test {
     grep.edit(edit).expect("should work");
     assert_eq!(grep.root().text(), "log(1 + 2 + 3)");
   }
+
+  fn rewrite_config(rewriter: &str) -> NapiConfig {
+    NapiConfig {
+      id: None,
+      rule: serde_json::json!({ "pattern": "log($$$ARGS)" }),
+      constraints: None,
+      language: None,
+      transform: Some(serde_json::json!({
+        "NEW": { "rewrite": { "rewriters": [rewriter], "source": "$$$ARGS", "joinBy": ", " } }
+      })),
+      utils: None,
+      ignore_kinds: None,
+      fix: Some(serde_json::json!("print($NEW)")),
+      rewriters: Some(serde_json::json!([
+        { "id": "double", "rule": { "kind": "number", "pattern": "$N" }, "fix": "2 * $N" }
+      ])),
+    }
+  }
+
+  #[test]
+  fn test_rewriters() {
+    let rule = rewrite_config("double")
+      .parse_with(SupportLang::JavaScript.into())
+      .expect("should parse");
+    let doc = JsDoc::new("log(1, 2)".into(), SupportLang::JavaScript.into());
+    let grep = AstGrep::doc(doc);
+    let node = grep.root().find(&rule).expect("should match");
+    let fixer = rule.fixer.as_ref().expect("should have fix");
+    let edit = node.make_edit(&rule, fixer);
+    assert_eq!(
+      Wrapper::encode_bytes(&edit.inserted_text),
+      "print(2 * 1, 2 * 2)"
+    );
+  }
+
+  #[test]
+  fn test_ignore_kinds_in_matcher() {
+    let config = NapiConfig {
+      rule: serde_json::json!({ "kind": "identifier" }),
+      ignore_kinds: Some(vec!["arguments".into()]),
+      transform: None,
+      fix: None,
+      rewriters: None,
+      ..rewrite_config("double")
+    };
+    let rule = config
+      .parse_with(SupportLang::JavaScript.into())
+      .expect("should parse");
+    let doc = JsDoc::new("foo(foo, bar)".into(), SupportLang::JavaScript.into());
+    let grep = AstGrep::doc(doc);
+    let found: Vec<_> = grep
+      .root()
+      .find_all(&rule)
+      .map(|n| n.text().to_string())
+      .collect();
+    assert_eq!(found, ["foo"]);
+  }
+
+  #[test]
+  fn test_undefined_rewriter() {
+    let ret = rewrite_config("not-defined").parse_with(SupportLang::JavaScript.into());
+    assert!(ret.is_err());
+  }
 }
@@ -0,0 +1,126 @@
+//! JSON dump of syntax trees, e.g. for playgrounds to visualize exactly what ast-grep sees.
+use ast_grep_core::Node;
+use napi_derive::napi;
+
+use crate::doc::JsDoc;
+use crate::sg_node::{to_pos, Range};
+
+#[napi(object)]
+pub struct DumpOptions {
+  /// Include unnamed nodes like `(` or `if`, default false
+  pub include_unnamed: Option<bool>,
+  /// Depth of the deepest dumped nodes, the root being 0. No limit by default
+  pub max_depth: Option<u32>,
+}
+
+/// A syntax node in the tree returned by `SgRoot.dumpAST`.
+#[napi(object)]
+pub struct DumpNode {
+  pub kind: String,
+  /// Field of the node in its parent, e.g. `name`
+  pub field: Option<String>,
+  pub named: bool,
+  pub range: Range,
+  /// Source text, only for nodes without dumped children to keep the dump small
+  pub text: Option<String>,
+  pub children: Vec<DumpNode>,
+}
+
+pub fn dump_ast(root: Node<JsDoc>, options: Option<DumpOptions>) -> DumpNode {
+  let options = options.unwrap_or(DumpOptions {
+    include_unnamed: None,
+    max_depth: None,
+  });
+  dump_node(root, None, &options, 0)
+}
+
+fn dump_node(
+  node: Node<JsDoc>,
+  field: Option<String>,
+  options: &DumpOptions,
+  depth: u32,
+) -> DumpNode {
+  let include_unnamed = options.include_unnamed.unwrap_or(false);
+  let mut children = vec![];
+  if options.max_depth.map_or(true, |max| depth < max) {
+    // field names are only known by the cursor, in the same order as children
+    let mut fields = vec![];
+    let mut cursor = node.get_ts_node().walk();
+    if cursor.goto_first_child() {
+      loop {
+        fields.push(cursor.field_name().map(|name| name.to_string()));
+        if !cursor.goto_next_sibling() {
+          break;
+        }
+      }
+    }
+    children = node
+      .children()
+      .zip(fields)
+      .filter(|(child, _)| include_unnamed || child.is_named())
+      .map(|(child, field)| dump_node(child, field, options, depth + 1))
+      .collect();
+  }
+  let range = node.range();
+  DumpNode {
+    kind: node.kind().to_string(),
+    field,
+    named: node.is_named(),
+    range: Range {
+      start: to_pos(node.start_pos(), range.start),
+      end: to_pos(node.end_pos(), range.end),
+    },
+    text: children.is_empty().then(|| node.text().to_string()),
+    children,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_core::AstGrep;
+  use ast_grep_language::SupportLang;
+
+  fn dump(src: &str, include_unnamed: bool, max_depth: Option<u32>) -> DumpNode {
+    let doc = JsDoc::new(src.into(), SupportLang::JavaScript.into());
+    let root = AstGrep::doc(doc);
+    let options = DumpOptions {
+      include_unnamed: Some(include_unnamed),
+      max_depth,
+    };
+    dump_ast(root.root(), Some(options))
+  }
+
+  #[test]
+  fn test_dump_ast() {
+    let program = dump("a = 12", false, None);
+    assert_eq!(program.kind, "program");
+    assert_eq!(program.text, None);
+    let assignment = &program.children[0].children[0];
+    assert_eq!(assignment.kind, "assignment_expression");
+    let fields: Vec<_> = assignment
+      .children
+      .iter()
+      .map(|c| (c.field.as_deref(), c.text.as_deref()))
+      .collect();
+    assert_eq!(
+      fields,
+      vec![(Some("left"), Some("a")), (Some("right"), Some("12"))]
+    );
+    let number = &assignment.children[1];
+    assert_eq!(number.range.start.index, 4);
+    assert_eq!(number.range.end.column, 6);
+  }
+
+  #[test]
+  fn test_dump_options() {
+    let assignment = &dump("a = 12", true, None).children[0].children[0];
+    let kinds: Vec<_> = assignment.children.iter().map(|c| &c.kind).collect();
+    assert_eq!(kinds, vec!["identifier", "=", "number"]);
+    assert!(!assignment.children[1].named);
+    let program = dump("a = 12", false, Some(1));
+    let statement = &program.children[0];
+    assert!(statement.children.is_empty());
+    assert_eq!(statement.text.as_deref(), Some("a = 12"));
+  }
+}
@@ -0,0 +1,394 @@
+use ast_grep_core::matcher::KindMatcher;
+use ast_grep_core::{Matcher, Node, Pattern};
+use ast_grep_language::SupportLang;
+use ignore::{WalkParallel, WalkState};
+use napi::anyhow::anyhow;
+use napi::bindgen_prelude::*;
+use napi::Task;
+use napi_derive::napi;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::doc::JsDoc;
+use crate::find_files::get_root;
+use crate::napi_lang::{build_files, Lang, LangOption, NapiLang};
+use crate::sg_node::{to_pos, Range};
+
+#[napi(object)]
+pub struct ExtractStringOption {
+  /// Only return strings whose decoded value matches the regex
+  pub regex: Option<String>,
+  /// Only return strings inside nodes matching the pattern.
+  /// The pattern is parsed once in the language of each file,
+  /// and the promise is rejected if it is invalid in any of them.
+  pub pattern: Option<String>,
+  /// customize additional file globs for languages
+  pub language_globs: Option<HashMap<String, Vec<String>>>,
+}
+
+#[napi(object)]
+pub struct ExtractedString {
+  pub file: String,
+  pub range: Range,
+  /// The string content with quotes removed and escape sequences decoded
+  pub value: String,
+}
+
+pub struct ExtractStrings {
+  walk: Option<WalkParallel>,
+  lang_option: LangOption,
+  regex: Option<Regex>,
+  pattern: Option<String>,
+  /// patterns compiled once per language of the walked files
+  compiled: Mutex<HashMap<NapiLang, Arc<Pattern<NapiLang>>>>,
+}
+
+impl Task for ExtractStrings {
+  type Output = Vec<ExtractedString>;
+  type JsValue = Vec<ExtractedString>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let Some(walk) = self.walk.take() else {
+      return Err(anyhow!("cannot extract strings twice").into());
+    };
+    let extracted = Mutex::new(vec![]);
+    let error = Mutex::new(None);
+    let this = &*self;
+    walk.run(|| {
+      let extracted = &extracted;
+      let error = &error;
+      Box::new(move |entry| {
+        let Ok(entry) = entry else {
+          return WalkState::Continue;
+        };
+        if !entry.file_type().map_or(false, |t| t.is_file()) {
+          return WalkState::Continue;
+        }
+        let Some(lang) = this.lang_option.get_lang(entry.path()) else {
+          return WalkState::Continue;
+        };
+        let pattern = match this.compile_pattern(lang) {
+          Ok(pattern) => pattern,
+          Err(e) => {
+            error.lock().unwrap().get_or_insert(e);
+            return WalkState::Quit;
+          }
+        };
+        // unreadable files are skipped like in findInFiles
+        if let Ok(strings) = this.extract_file(entry, lang, pattern.as_deref()) {
+          extracted.lock().unwrap().extend(strings);
+        }
+        WalkState::Continue
+      })
+    });
+    if let Some(e) = error.into_inner().unwrap() {
+      return Err(Error::new(Status::InvalidArg, e));
+    }
+    let mut extracted = extracted.into_inner().unwrap();
+    // walker is parallel, sort for deterministic output
+    extracted.sort_by(|a, b| (&a.file, a.range.start.index).cmp(&(&b.file, b.range.start.index)));
+    Ok(extracted)
+  }
+
+  fn resolve(&mut self, _: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+}
+
+impl ExtractStrings {
+  /// Compile the pattern for the language, or reuse the one compiled for an earlier file.
+  fn compile_pattern(
+    &self,
+    lang: NapiLang,
+  ) -> std::result::Result<Option<Arc<Pattern<NapiLang>>>, String> {
+    let Some(pattern) = &self.pattern else {
+      return Ok(None);
+    };
+    let mut compiled = self.compiled.lock().unwrap();
+    if let Some(compiled) = compiled.get(&lang) {
+      return Ok(Some(compiled.clone()));
+    }
+    let compiled_pattern = Pattern::try_new(pattern, lang)
+      .map(Arc::new)
+      .map_err(|e| format!("invalid pattern for {lang}: {e}"))?;
+    compiled.insert(lang, compiled_pattern.clone());
+    Ok(Some(compiled_pattern))
+  }
+
+  fn extract_file(
+    &self,
+    entry: ignore::DirEntry,
+    lang: NapiLang,
+    pattern: Option<&Pattern<NapiLang>>,
+  ) -> napi::anyhow::Result<Vec<ExtractedString>> {
+    let (root, file) = get_root(entry, &self.lang_option)?;
+    let kinds = string_kinds(lang);
+    let mut ret = vec![];
+    let mut collect = |node: Node<JsDoc>| {
+      for n in node.dfs() {
+        if !kinds.iter().any(|k| k.match_node(n.clone()).is_some()) {
+          continue;
+        }
+        if let Some(s) = self.to_extracted(&n, &file) {
+          ret.push(s);
+        }
+      }
+    };
+    if let Some(pattern) = pattern {
+      for m in root.root().find_all(pattern) {
+        collect(m.get_node().clone());
+      }
+    } else {
+      collect(root.root());
+    }
+    // strings inside nested matches are collected more than once
+    ret.sort_by_key(|s| s.range.start.index);
+    ret.dedup_by_key(|s| s.range.start.index);
+    Ok(ret)
+  }
+
+  fn to_extracted(&self, node: &Node<JsDoc>, file: &str) -> Option<ExtractedString> {
+    let value = decode_string_literal(&node.text());
+    if let Some(regex) = &self.regex {
+      if !regex.is_match(&value) {
+        return None;
+      }
+    }
+    let byte_range = node.range();
+    let range = Range {
+      start: to_pos(node.start_pos(), byte_range.start),
+      end: to_pos(node.end_pos(), byte_range.end),
+    };
+    Some(ExtractedString {
+      file: file.to_string(),
+      range,
+      value,
+    })
+  }
+}
+
+pub fn extract_strings_impl(
+  paths: Vec<String>,
+  option: Option<ExtractStringOption>,
+) -> Result<AsyncTask<ExtractStrings>> {
+  let ExtractStringOption {
+    regex,
+    pattern,
+    language_globs,
+  } = option.unwrap_or(ExtractStringOption {
+    regex: None,
+    pattern: None,
+    language_globs: None,
+  });
+  let regex = regex
+    .map(|r| Regex::new(&r))
+    .transpose()
+    .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+  let globs = Lang::lang_globs(language_globs.unwrap_or_default());
+  let walk = build_files(paths, &globs)?;
+  Ok(AsyncTask::new(ExtractStrings {
+    walk: Some(walk),
+    lang_option: LangOption::infer(&globs),
+    regex,
+    pattern,
+    compiled: Mutex::default(),
+  }))
+}
+
+/// Node kinds of string literals in each language. Kinds include the quotes.
+fn string_kind_names(lang: SupportLang) -> &'static [&'static str] {
+  use SupportLang as S;
+  match lang {
+    S::JavaScript | S::TypeScript | S::Tsx | S::Json => &["string"],
+    S::Python | S::Ruby | S::Lua | S::Elixir | S::Haskell | S::Scala | S::Sql => &["string"],
+    S::Php => &["string", "encapsed_string"],
+    S::Bash => &["string", "raw_string"],
+    S::Go => &["interpreted_string_literal", "raw_string_literal"],
+    S::Rust | S::Cpp => &["string_literal", "raw_string_literal"],
+    S::C | S::Java | S::Kotlin | S::Dart => &["string_literal"],
+    S::CSharp => &["string_literal", "verbatim_string_literal"],
+    S::Swift => &["line_string_literal"],
+    S::Css => &["string_value"],
+    S::Html => &["text", "attribute_value"],
+  }
+}
+
+fn string_kinds(lang: NapiLang) -> Vec<KindMatcher<NapiLang>> {
+  // string literals of dynamic languages are unknown
+  let NapiLang::Builtin(builtin) = lang else {
+    return vec![];
+  };
+  string_kind_names(builtin)
+    .iter()
+    .map(|kind| KindMatcher::new(kind, lang))
+    .collect()
+}
+
+/// Remove prefixes and quotes of a string literal, and decode escape sequences.
+/// Raw strings like `r"..."` in Rust/Python or `` `...` `` in Go are not decoded.
+/// Text that is not quoted, e.g. HTML text, is returned as is.
+pub fn decode_string_literal(text: &str) -> String {
+  let Some(quote_start) = text.find(['"', '\'', '`']) else {
+    return text.to_string();
+  };
+  let prefix = &text[..quote_start];
+  if !prefix
+    .chars()
+    .all(|c| c.is_ascii_alphabetic() || c == '#' || c == '@')
+  {
+    return text.to_string();
+  }
+  let hashes = prefix.matches('#').count();
+  let quoted = &text[quote_start..text.len().saturating_sub(hashes).max(quote_start)];
+  let quote = &quoted[..1];
+  let triple = quote.repeat(3);
+  let quote_len = if quoted.len() >= 6 && quoted.starts_with(&triple) && quoted.ends_with(&triple) {
+    3
+  } else {
+    1
+  };
+  if quoted.len() < quote_len * 2 || !quoted.ends_with(quote) {
+    return text.to_string();
+  }
+  let content = &quoted[quote_len..quoted.len() - quote_len];
+  let is_raw = quote == "`" || prefix.contains(['r', 'R', '@']);
+  if is_raw {
+    content.to_string()
+  } else {
+    unescape(content)
+  }
+}
+
+fn unescape(content: &str) -> String {
+  let mut ret = String::with_capacity(content.len());
+  let mut chars = content.chars().peekable();
+  // pending high surrogate from \uXXXX
+  let mut high_surrogate = None;
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      ret.push(c);
+      continue;
+    }
+    let Some(escaped) = chars.next() else {
+      ret.push(c);
+      break;
+    };
+    let decoded = match escaped {
+      'n' => '\n',
+      'r' => '\r',
+      't' => '\t',
+      'b' => '\u{8}',
+      'f' => '\u{c}',
+      'v' => '\u{b}',
+      '0' => '\0',
+      // line continuation
+      '\n' => continue,
+      'x' => {
+        let hex: String = chars.by_ref().take(2).collect();
+        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+          Some(ch) => ch,
+          None => {
+            ret.push_str("\\x");
+            ret.push_str(&hex);
+            continue;
+          }
+        }
+      }
+      'u' | 'U' => {
+        let hex: String = if chars.peek() == Some(&'{') {
+          chars.next();
+          chars.by_ref().take_while(|c| *c != '}').collect()
+        } else {
+          let len = if escaped == 'u' { 4 } else { 8 };
+          chars.by_ref().take(len).collect()
+        };
+        let Ok(code) = u32::from_str_radix(&hex, 16) else {
+          ret.push('\\');
+          ret.push(escaped);
+          ret.push_str(&hex);
+          continue;
+        };
+        match (high_surrogate.take(), code) {
+          (None, 0xD800..=0xDBFF) => {
+            high_surrogate = Some(code);
+            continue;
+          }
+          (Some(high), 0xDC00..=0xDFFF) => {
+            let combined = 0x10000 + ((high - 0xD800) << 10) + (code - 0xDC00);
+            char::from_u32(combined).unwrap_or(char::REPLACEMENT_CHARACTER)
+          }
+          (_, code) => char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+        }
+      }
+      other => other,
+    };
+    ret.push(decoded);
+  }
+  ret
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_core::language::Language;
+
+  #[test]
+  fn test_decode_string_literal() {
+    assert_eq!(decode_string_literal(r#""hello""#), "hello");
+    assert_eq!(decode_string_literal(r#"'it\'s'"#), "it's");
+    assert_eq!(decode_string_literal(r#""a\nb\t\\""#), "a\nb\t\\");
+    assert_eq!(decode_string_literal(r#""\x41B\u{43}""#), "ABC");
+    assert_eq!(decode_string_literal(r#""\uD83D\uDE00""#), "😀");
+    assert_eq!(decode_string_literal(r#"r"a\nb""#), r"a\nb");
+    assert_eq!(decode_string_literal(r##"r#"a"b"#"##), r#"a"b"#);
+    assert_eq!(decode_string_literal("`raw\\n`"), "raw\\n");
+    assert_eq!(decode_string_literal(r#"f"""doc""""#), "doc");
+    assert_eq!(decode_string_literal("Hello world"), "Hello world");
+  }
+
+  const FIXTURE: &str = "__test__/fixtures/extract.js";
+
+  fn extract(pattern: Option<&str>) -> Result<Vec<ExtractedString>> {
+    let globs = Lang::lang_globs(HashMap::new());
+    let mut task = ExtractStrings {
+      walk: Some(build_files(vec![FIXTURE.into()], &globs)?),
+      lang_option: LangOption::infer(&globs),
+      regex: None,
+      pattern: pattern.map(String::from),
+      compiled: Mutex::default(),
+    };
+    task.compute()
+  }
+
+  fn values(strings: Vec<ExtractedString>) -> Vec<String> {
+    strings.into_iter().map(|s| s.value).collect()
+  }
+
+  #[test]
+  fn test_extract_nested_matches() {
+    let strings = extract(Some("t($$$)")).expect("should extract");
+    assert_eq!(values(strings), ["outer", "inner", "after", "single"]);
+    let strings = extract(None).expect("should extract");
+    assert_eq!(
+      values(strings),
+      ["outer", "inner", "after", "single", "not translated"]
+    );
+  }
+
+  #[test]
+  fn test_extract_invalid_pattern() {
+    let err = extract(Some("12  3344")).err().expect("should fail");
+    assert!(err.reason.contains("invalid pattern for JavaScript"));
+  }
+
+  #[test]
+  fn test_string_kinds() {
+    for lang in SupportLang::all_langs() {
+      for kind in string_kind_names(*lang) {
+        let id = lang.get_ts_language().id_for_node_kind(kind, true);
+        assert_ne!(id, 0, "{kind} is not a valid kind in {lang}");
+      }
+    }
+  }
+}
@@ -1,23 +1,28 @@
 use ast_grep_config::RuleCore;
+use ast_grep_core::meta_var::MetaVarEnv;
 use ast_grep_core::pinned::{NodeData, PinnedNodeData};
-use ast_grep_core::{AstGrep, NodeMatch};
-use ast_grep_language::SupportLang;
+use ast_grep_core::{AstGrep, Doc, Matcher, Node, NodeMatch};
 use ignore::{WalkBuilder, WalkParallel, WalkState};
 use napi::anyhow::{anyhow, Context, Result as Ret};
 use napi::bindgen_prelude::*;
 use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
-use napi::{JsNumber, Task};
-use napi_derive::napi;
+use napi::{CallContext, JsNumber, JsObject, JsUndefined, JsUnknown, Ref, Task};
+use napi_derive::{js_function, napi};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::doc::{JsDoc, NapiConfig};
-use crate::napi_lang::{build_files, Lang, LangOption};
-use crate::sg_node::{SgNode, SgRoot};
+use crate::compiled_rule::{compile_matcher, RuleMatcher};
+use crate::doc::JsDoc;
+use crate::napi_lang::{build_files, Lang, LangOption, NapiLang};
+use crate::sfc::{is_sfc, sfc_roots};
+use crate::sg_node::{fix_edit, to_pos, Edit, Pos, SgNode, SgRoot};
 
 pub struct ParseAsync {
   pub src: String,
-  pub lang: Lang,
+  pub lang: NapiLang,
 }
 
 impl Task for ParseAsync {
@@ -26,21 +31,28 @@ impl Task for ParseAsync {
 
   fn compute(&mut self) -> Result<Self::Output> {
     let src = std::mem::take(&mut self.src);
-    let doc = JsDoc::new(src, self.lang.into());
-    Ok(SgRoot(AstGrep::doc(doc), "anonymous".into()))
+    let doc = JsDoc::new(src, self.lang);
+    Ok(SgRoot::new(AstGrep::doc(doc), "anonymous".into()))
   }
   fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
     Ok(output)
   }
 }
 
-type Entry = std::result::Result<ignore::DirEntry, ignore::Error>;
+pub(crate) type Entry = std::result::Result<ignore::DirEntry, ignore::Error>;
 
 pub struct IterateFiles<D> {
   walk: WalkParallel,
   lang_option: LangOption,
   tsfn: D,
-  producer: fn(&D, Entry, &LangOption) -> Ret<bool>,
+  /// walking stops if aborted or timed out
+  flow: Arc<FlowControl>,
+  /// returns the number of roots sent to JavaScript
+  producer: fn(&D, Entry, &LangOption) -> Ret<u32>,
+  /// called with the file count after all files are processed
+  finisher: Option<fn(&D, u32)>,
+  /// removed from the `AbortSignal` when the task finishes
+  abort_listener: Option<AbortListener>,
 }
 
 impl<T: 'static + Send + Sync> Task for IterateFiles<T> {
@@ -49,36 +61,58 @@ impl<T: 'static + Send + Sync> Task for IterateFiles<T> {
 
   fn compute(&mut self) -> Result<Self::Output> {
     let tsfn = &self.tsfn;
+    let flow = &self.flow;
     let file_count = AtomicU32::new(0);
     let producer = self.producer;
     let walker = std::mem::replace(&mut self.walk, WalkBuilder::new(".").build_parallel());
     walker.run(|| {
       let file_count = &file_count;
       let lang_option = &self.lang_option;
-      Box::new(move |entry| match producer(tsfn, entry, lang_option) {
-        Ok(succeed) => {
-          if succeed {
-            // file is sent to JS thread, increment file count
-            file_count.fetch_add(1, Ordering::AcqRel);
+      Box::new(move |entry| {
+        // stop walking, files not parsed yet are dropped
+        if flow.is_cancelled() {
+          return WalkState::Quit;
+        }
+        match producer(tsfn, entry, lang_option) {
+          Ok(sent) => {
+            // roots are sent to JS thread, increment file count
+            // a single-file component counts once for each of its blocks
+            file_count.fetch_add(sent, Ordering::AcqRel);
+            WalkState::Continue
           }
-          WalkState::Continue
+          Err(_) => WalkState::Skip,
         }
-        Err(_) => WalkState::Skip,
       })
     });
-    Ok(file_count.load(Ordering::Acquire))
+    if let Some(error) = flow.cancel_error() {
+      flow.reject();
+      return Err(error);
+    }
+    let file_count = file_count.load(Ordering::Acquire);
+    if let Some(finisher) = self.finisher {
+      finisher(tsfn, file_count);
+    }
+    Ok(file_count)
   }
   fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
     env.create_uint32(output)
   }
+  fn finally(&mut self, env: Env) -> Result<()> {
+    match self.abort_listener.take() {
+      Some(listener) => listener.remove(env),
+      None => Ok(()),
+    }
+  }
 }
 
 // See https://github.com/ast-grep/ast-grep/issues/206
 // NodeJS has a 1000 file limitation on sync iteration count.
 // https://github.com/nodejs/node/blob/8ba54e50496a6a5c21d93133df60a9f7cb6c46ce/src/node_api.cc#L336
-const THREAD_FUNC_QUEUE_SIZE: usize = 1000;
+pub(crate) const THREAD_FUNC_QUEUE_SIZE: usize = 1000;
 
-type ParseFiles = IterateFiles<ThreadsafeFunction<SgRoot, ErrorStrategy::CalleeHandled>>;
+type ParseCallback = ThreadsafeFunction<SgRoot, ErrorStrategy::CalleeHandled>;
+/// the callback and whether blocks of single-file components are split
+type ParseFiles = IterateFiles<(ParseCallback, bool)>;
 
 #[napi(object)]
 pub struct FileOption {
@@ -86,13 +120,28 @@ pub struct FileOption {
   pub language_globs: HashMap<String, Vec<String>>,
 }
 
+#[napi(object, object_to_js = false)]
+pub struct ParseFilesOptions {
+  /// Reject with a `TimeoutError` if files are not all parsed in the time.
+  pub timeout_ms: Option<u32>,
+  /// Reject with an `AbortError` when the signal is aborted.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<JsObject>,
+  /// Pass a root for each `<script>` and `<style>` block of Vue and Svelte files
+  /// to `callback` after the HTML root of the file. Off by default.
+  pub split_blocks: Option<bool>,
+}
+
+/// Parse files in parallel.
+/// Resolves to the number of roots passed to `callback`, one per file unless `splitBlocks` is set.
+/// If aborted or timed out, stops parsing more files and rejects with code `Cancelled`.
 #[napi(ts_return_type = "Promise<number>")]
 pub fn parse_files(
+  env: Env,
   paths: Either<Vec<String>, FileOption>,
   #[napi(ts_arg_type = "(err: null | Error, result: SgRoot) => void")] callback: JsFunction,
-) -> Result<AsyncTask<ParseFiles>> {
-  let tsfn: ThreadsafeFunction<SgRoot, ErrorStrategy::CalleeHandled> =
-    callback.create_threadsafe_function(THREAD_FUNC_QUEUE_SIZE, |ctx| Ok(vec![ctx.value]))?;
+  options: Option<ParseFilesOptions>,
+) -> Result<JsObject> {
   let (paths, globs) = match paths {
     Either::A(v) => (v, HashMap::new()),
     Either::B(FileOption {
@@ -101,35 +150,67 @@ pub fn parse_files(
     }) => (paths, Lang::lang_globs(language_globs)),
   };
   let walk = build_files(paths, &globs)?;
-  Ok(AsyncTask::new(ParseFiles {
+  let (timeout_ms, signal, split_blocks) = match options {
+    Some(ParseFilesOptions {
+      timeout_ms,
+      signal,
+      split_blocks,
+    }) => (timeout_ms, signal, split_blocks.unwrap_or(false)),
+    None => (None, None, false),
+  };
+  let flow = Arc::new(FlowControl::with_timeout(timeout_ms));
+  let abort_listener = match signal {
+    Some(signal) => listen_abort(&env, signal, &flow)?,
+    None => None,
+  };
+  let callback = skip_when_cancelled(&env, callback, &flow)?;
+  let tsfn: ParseCallback =
+    callback.create_threadsafe_function(THREAD_FUNC_QUEUE_SIZE, |ctx| Ok(vec![ctx.value]))?;
+  let task = ParseFiles {
     walk,
-    tsfn,
+    tsfn: (tsfn, split_blocks),
+    flow,
     lang_option: LangOption::infer(&globs),
     producer: call_sg_root,
-  }))
+    finisher: None,
+    abort_listener,
+  };
+  Ok(env.spawn(task)?.promise_object())
 }
 
-// returns if the entry is a file and sent to JavaScript queue
+// returns the number of roots in the entry sent to JavaScript queue
 fn call_sg_root(
-  tsfn: &ThreadsafeFunction<SgRoot, ErrorStrategy::CalleeHandled>,
+  (tsfn, split_blocks): &(ParseCallback, bool),
   entry: std::result::Result<ignore::DirEntry, ignore::Error>,
   lang_option: &LangOption,
-) -> Ret<bool> {
+) -> Ret<u32> {
   let entry = entry?;
   if !entry
     .file_type()
     .context("could not use stdin as file")?
     .is_file()
   {
-    return Ok(false);
+    return Ok(0);
   }
-  let (root, path) = get_root(entry, lang_option)?;
-  let sg = SgRoot(root, path);
-  tsfn.call(Ok(sg), ThreadsafeFunctionCallMode::Blocking);
-  Ok(true)
+  // each block of a single-file component is sent as its own root if asked
+  let (roots, path) = if *split_blocks {
+    get_roots(entry, lang_option)?
+  } else {
+    let (root, path) = get_root(entry, lang_option)?;
+    (vec![root], path)
+  };
+  let sent = roots.len() as u32;
+  for root in roots {
+    let sg = SgRoot::new(root, path.clone());
+    tsfn.call(Ok(sg), ThreadsafeFunctionCallMode::Blocking);
+  }
+  Ok(sent)
 }
 
-fn get_root(entry: ignore::DirEntry, lang_option: &LangOption) -> Ret<(AstGrep<JsDoc>, String)> {
+pub(crate) fn get_root(
+  entry: ignore::DirEntry,
+  lang_option: &LangOption,
+) -> Ret<(AstGrep<JsDoc>, String)> {
   let path = entry.into_path();
   let file_content = std::fs::read_to_string(&path)?;
   let lang = lang_option
@@ -139,36 +220,327 @@ fn get_root(entry: ignore::DirEntry, lang_option: &LangOption) -> Ret<(AstGrep<J
   Ok((AstGrep::doc(doc), path.to_string_lossy().into()))
 }
 
-pub type FindInFiles = IterateFiles<(
-  ThreadsafeFunction<PinnedNodes, ErrorStrategy::CalleeHandled>,
-  RuleCore<SupportLang>,
-)>;
+/// Same as `get_root`, but Vue and Svelte files are split into roots of their blocks.
+/// If the language is specified, only blocks in the language are returned.
+pub(crate) fn get_roots(
+  entry: ignore::DirEntry,
+  lang_option: &LangOption,
+) -> Ret<(Vec<AstGrep<JsDoc>>, String)> {
+  if !is_sfc(entry.path()) {
+    let (root, path) = get_root(entry, lang_option)?;
+    return Ok((vec![root], path));
+  }
+  let path = entry.into_path();
+  lang_option
+    .get_lang(&path)
+    .context(anyhow!("file not recognized"))?;
+  let mut roots = sfc_roots(std::fs::read_to_string(&path)?);
+  if let LangOption::Specified(lang) = lang_option {
+    roots.retain(|root| root.lang() == lang);
+  }
+  Ok((roots, path.to_string_lossy().into()))
+}
+
+pub type FindInFiles = IterateFiles<FindState>;
+
+type BatchCallback = ThreadsafeFunction<Vec<PinnedNodes>, ErrorStrategy::CalleeHandled>;
+
+pub struct FindState {
+  tsfn: BatchCallback,
+  rule: Arc<RuleCore<NapiLang>>,
+  flow: Arc<FlowControl>,
+  /// files with matches not sent yet, and the number of their matches
+  batch: Mutex<(Vec<PinnedNodes>, u32)>,
+  batch_size: u32,
+  high_water_mark: u32,
+}
+
+impl FindState {
+  /// Add the file to the batch and take the batch if it is full.
+  fn push(&self, pinned: PinnedNodes, match_count: u32) -> Option<Vec<PinnedNodes>> {
+    let mut batch = self.batch.lock().expect("should not poison");
+    batch.0.push(pinned);
+    batch.1 += match_count;
+    if batch.1 < self.batch_size {
+      return None;
+    }
+    batch.1 = 0;
+    Some(std::mem::take(&mut batch.0))
+  }
+
+  fn send(&self, batch: Vec<PinnedNodes>) {
+    self.flow.acquire(self.high_water_mark);
+    // drop the batch if cancelled while waiting
+    if self.flow.is_cancelled() {
+      self.flow.release();
+      return;
+    }
+    self
+      .tsfn
+      .call(Ok(batch), ThreadsafeFunctionCallMode::Blocking);
+  }
+}
+
+/// Pause state and batches queued for the JS thread, shared by walker threads and `findInFiles`' handle.
+/// Also tracks if walking is aborted by a signal or timed out.
+#[derive(Default)]
+pub struct FlowControl {
+  state: Mutex<FlowState>,
+  changed: Condvar,
+  /// deadline and the timeout in ms for the error message
+  timeout: Option<(Instant, u32)>,
+}
+
+#[derive(Default)]
+struct FlowState {
+  paused: bool,
+  /// batches sent to the JS thread but not passed to callback yet
+  in_flight: u32,
+  aborted: bool,
+  /// the promise is rejected because walking was cancelled
+  rejected: bool,
+}
+
+impl FlowControl {
+  fn with_timeout(timeout_ms: Option<u32>) -> Self {
+    let timeout = timeout_ms.map(|ms| (Instant::now() + Duration::from_millis(ms.into()), ms));
+    Self {
+      timeout,
+      ..Default::default()
+    }
+  }
+
+  fn timed_out(&self) -> bool {
+    self
+      .timeout
+      .map_or(false, |(deadline, _)| Instant::now() >= deadline)
+  }
+
+  fn is_cancelled(&self) -> bool {
+    self.state.lock().expect("should not poison").aborted || self.timed_out()
+  }
+
+  /// The error to reject the promise with if walking is cancelled.
+  fn cancel_error(&self) -> Option<Error> {
+    let reason = if self.state.lock().expect("should not poison").aborted {
+      "AbortError: the operation was aborted".to_string()
+    } else if let Some((_, ms)) = self.timeout.filter(|_| self.timed_out()) {
+      format!("TimeoutError: the operation timed out after {ms}ms")
+    } else {
+      return None;
+    };
+    Some(Error::new(Status::Cancelled, reason))
+  }
+
+  /// Block while `blocked` returns true, unless cancelled.
+  fn wait_while(&self, blocked: impl Fn(&FlowState) -> bool) -> std::sync::MutexGuard<FlowState> {
+    let state = self.state.lock().expect("should not poison");
+    let blocked = |s: &mut FlowState| !s.aborted && blocked(s);
+    match self.timeout {
+      Some((deadline, _)) => {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let waited = self.changed.wait_timeout_while(state, timeout, blocked);
+        waited.expect("should not poison").0
+      }
+      None => self
+        .changed
+        .wait_while(state, blocked)
+        .expect("should not poison"),
+    }
+  }
+
+  /// Block the walker thread until resumed.
+  fn wait_resumed(&self) {
+    drop(self.wait_while(|s| s.paused));
+  }
+
+  /// Block until resumed and fewer than `high_water_mark` batches are in flight.
+  fn acquire(&self, high_water_mark: u32) {
+    let mut state = self.wait_while(|s| s.paused || s.in_flight >= high_water_mark);
+    state.in_flight += 1;
+  }
+
+  /// Called in the JS thread when a batch is delivered.
+  fn release(&self) {
+    let mut state = self.state.lock().expect("should not poison");
+    state.in_flight -= 1;
+    self.changed.notify_all();
+  }
+
+  fn set_paused(&self, paused: bool) {
+    let mut state = self.state.lock().expect("should not poison");
+    state.paused = paused;
+    self.changed.notify_all();
+  }
+
+  fn abort(&self) {
+    let mut state = self.state.lock().expect("should not poison");
+    state.aborted = true;
+    self.changed.notify_all();
+  }
+
+  fn reject(&self) {
+    self.state.lock().expect("should not poison").rejected = true;
+  }
+
+  /// Values still queued for the JS thread are dropped after abort or rejection.
+  fn drops_queued(&self) -> bool {
+    let state = self.state.lock().expect("should not poison");
+    state.aborted || state.rejected
+  }
+}
+
+/// The listener added to an `AbortSignal`. It is removed when the task finishes,
+/// otherwise a long-lived signal keeps the task's `FlowControl` alive.
+struct AbortListener {
+  signal: Ref<()>,
+  listener: Ref<()>,
+}
+
+impl AbortListener {
+  fn remove(mut self, env: Env) -> Result<()> {
+    let signal: JsObject = env.get_reference_value(&self.signal)?;
+    let listener: JsObject = env.get_reference_value(&self.listener)?;
+    self.signal.unref(env)?;
+    self.listener.unref(env)?;
+    let remove_listener: JsFunction = signal.get_named_property("removeEventListener")?;
+    let event = env.create_string("abort")?.into_unknown();
+    remove_listener.call(Some(&signal), &[event, listener.into_unknown()])?;
+    Ok(())
+  }
+}
+
+/// Abort walking when the `AbortSignal` is aborted.
+/// Returns the listener to remove, or None if the signal is already aborted.
+fn listen_abort(
+  env: &Env,
+  signal: JsObject,
+  flow: &Arc<FlowControl>,
+) -> Result<Option<AbortListener>> {
+  if signal.get_named_property::<bool>("aborted")? {
+    flow.abort();
+    return Ok(None);
+  }
+  // js_function cannot capture the flow, but `this` of `handleEvent` is the listener object
+  let mut listener = env.create_object()?;
+  env.wrap(&mut listener, flow.clone())?;
+  listener.set_named_property(
+    "handleEvent",
+    env.create_function("handleEvent", abort_walk)?,
+  )?;
+  let mut options = env.create_object()?;
+  options.set_named_property("once", true)?;
+  let add_listener: JsFunction = signal.get_named_property("addEventListener")?;
+  let event = env.create_string("abort")?.into_unknown();
+  let args = [event, listener.into_unknown(), options.into_unknown()];
+  add_listener.call(Some(&signal), &args)?;
+  Ok(Some(AbortListener {
+    signal: env.create_reference(signal)?,
+    listener: env.create_reference(&args[1])?,
+  }))
+}
+
+#[js_function]
+fn abort_walk(ctx: CallContext) -> Result<JsUndefined> {
+  let this: JsObject = ctx.this()?;
+  let flow: &mut Arc<FlowControl> = ctx.env.unwrap(&this)?;
+  flow.abort();
+  ctx.env.get_undefined()
+}
+
+/// Wrap `callback` so values still queued in the threadsafe function are not passed to it
+/// once walking is aborted or the promise is rejected.
+fn skip_when_cancelled(
+  env: &Env,
+  callback: JsFunction,
+  flow: &Arc<FlowControl>,
+) -> Result<JsFunction> {
+  // like the abort listener, `this` of the bound function carries the flow and the callback
+  let mut this = env.create_object()?;
+  env.wrap(&mut this, flow.clone())?;
+  this.set_named_property("callback", callback)?;
+  let deliver = env
+    .create_function("deliver", deliver_unless_cancelled)?
+    .coerce_to_object()?;
+  let bind: JsFunction = deliver.get_named_property("bind")?;
+  JsFunction::try_from(bind.call(Some(&deliver), &[this])?)
+}
+
+#[js_function(2)]
+fn deliver_unless_cancelled(ctx: CallContext) -> Result<JsUndefined> {
+  let this: JsObject = ctx.this()?;
+  let flow: &mut Arc<FlowControl> = ctx.env.unwrap(&this)?;
+  if flow.drops_queued() {
+    return ctx.env.get_undefined();
+  }
+  let callback: JsFunction = this.get_named_property("callback")?;
+  let args = (0..ctx.length)
+    .map(|i| ctx.get::<JsUnknown>(i))
+    .collect::<Result<Vec<_>>>()?;
+  callback.call(None, &args)?;
+  ctx.env.get_undefined()
+}
 
 pub struct PinnedNodes(
-  PinnedNodeData<JsDoc, Vec<NodeMatch<'static, JsDoc>>>,
-  String,
+  pub(crate) PinnedNodeData<JsDoc, Vec<NodeMatch<'static, JsDoc>>>,
+  pub(crate) String,
+  /// fixes of the rule on each match, computed while the rule is at hand
+  pub(crate) Vec<Option<Edit>>,
 );
 unsafe impl Send for PinnedNodes {}
 unsafe impl Sync for PinnedNodes {}
 
-#[napi(object)]
+#[napi(object, object_to_js = false)]
 pub struct FindConfig {
   /// specify the file paths to recursively find files
   pub paths: Vec<String>,
-  /// a Rule object to find what nodes will match
-  pub matcher: NapiConfig,
+  /// a Rule object, or a `Matcher` from `compileRule`, to find what nodes will match
+  #[napi(ts_type = "NapiConfig | Matcher")]
+  pub matcher: RuleMatcher,
   /// An list of pattern globs to treat of certain files in the specified language.
   /// eg. ['*.vue', '*.svelte'] for html.findFiles, or ['*.ts'] for tsx.findFiles.
+  /// Only `<script>` or `<style>` blocks in the language are matched in Vue and Svelte files,
+  /// e.g. ['*.vue'] for ts.findFiles matches `<script lang="ts">`.
   /// It is slightly different from https://ast-grep.github.io/reference/sgconfig.html#languageglobs
   pub language_globs: Option<Vec<String>>,
 }
 
+#[napi(object, object_to_js = false)]
+pub struct FindInFilesOptions {
+  /// Minimal number of matches passed to one callback call, default 1.
+  /// Matches of several files are batched in one call, except the last batch.
+  pub batch_size: Option<u32>,
+  /// Maximal number of batches waiting for the JS thread before file walking blocks.
+  pub high_water_mark: Option<u32>,
+  /// Reject with a `TimeoutError` if files are not all searched in the time.
+  pub timeout_ms: Option<u32>,
+  /// Reject with an `AbortError` when the signal is aborted.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<JsObject>,
+}
+
 pub fn find_in_files_impl(
-  lang: Lang,
+  env: Env,
+  lang: NapiLang,
   config: FindConfig,
   callback: JsFunction,
-) -> Result<AsyncTask<FindInFiles>> {
-  let tsfn = callback.create_threadsafe_function(THREAD_FUNC_QUEUE_SIZE, |ctx| {
+  options: Option<FindInFilesOptions>,
+) -> Result<JsObject> {
+  let options = options.unwrap_or(FindInFilesOptions {
+    batch_size: None,
+    high_water_mark: None,
+    timeout_ms: None,
+    signal: None,
+  });
+  let flow = Arc::new(FlowControl::with_timeout(options.timeout_ms));
+  let abort_listener = match options.signal {
+    Some(signal) => listen_abort(&env, signal, &flow)?,
+    None => None,
+  };
+  let callback = skip_when_cancelled(&env, callback, &flow)?;
+  let delivered = flow.clone();
+  let tsfn = callback.create_threadsafe_function(THREAD_FUNC_QUEUE_SIZE, move |ctx| {
+    delivered.release();
     from_pinned_data(ctx.value, ctx.env)
   })?;
   let FindConfig {
@@ -176,25 +548,74 @@ pub fn find_in_files_impl(
     matcher,
     language_globs,
   } = config;
-  let rule = matcher.parse_with(lang)?;
+  let rule = compile_matcher(matcher, lang)?;
   let walk = lang.find_files(paths, language_globs)?;
-  Ok(AsyncTask::new(FindInFiles {
+  let state = FindState {
+    tsfn,
+    rule,
+    flow: flow.clone(),
+    batch: Mutex::default(),
+    batch_size: options.batch_size.unwrap_or(1),
+    high_water_mark: options
+      .high_water_mark
+      .unwrap_or(THREAD_FUNC_QUEUE_SIZE as u32)
+      .max(1),
+  };
+  let task = FindInFiles {
     walk,
-    tsfn: (tsfn, rule),
+    tsfn: state,
+    flow: flow.clone(),
     lang_option: LangOption::Specified(lang),
     producer: call_sg_node,
-  }))
+    finisher: Some(flush_batch),
+    abort_listener,
+  };
+  let mut promise = env.spawn(task)?.promise_object();
+  // the returned promise is also the handle to pause and resume matching
+  env.wrap(&mut promise, flow)?;
+  promise.set_named_property("pause", env.create_function("pause", pause_find)?)?;
+  promise.set_named_property("resume", env.create_function("resume", resume_find)?)?;
+  Ok(promise)
+}
+
+#[js_function]
+fn pause_find(ctx: CallContext) -> Result<JsUndefined> {
+  let this: JsObject = ctx.this()?;
+  let flow: &mut Arc<FlowControl> = ctx.env.unwrap(&this)?;
+  flow.set_paused(true);
+  ctx.env.get_undefined()
+}
+
+#[js_function]
+fn resume_find(ctx: CallContext) -> Result<JsUndefined> {
+  let this: JsObject = ctx.this()?;
+  let flow: &mut Arc<FlowControl> = ctx.env.unwrap(&this)?;
+  flow.set_paused(false);
+  ctx.env.get_undefined()
+}
+
+pub(crate) fn from_pinned_data(
+  batch: Vec<PinnedNodes>,
+  env: napi::Env,
+) -> Result<Vec<Vec<SgNode>>> {
+  let mut nodes = vec![];
+  for pinned in batch {
+    nodes.extend(pinned_to_nodes(pinned, env)?);
+  }
+  Ok(vec![nodes])
 }
 
-// TODO: optimize
-fn from_pinned_data(pinned: PinnedNodes, env: napi::Env) -> Result<Vec<Vec<SgNode>>> {
+pub(crate) fn pinned_to_nodes(pinned: PinnedNodes, env: napi::Env) -> Result<Vec<SgNode>> {
   let (root, nodes) = pinned.0.into_raw();
-  let sg_root = SgRoot(AstGrep { inner: root }, pinned.1);
+  let sg_root = SgRoot::new(AstGrep { inner: root }, pinned.1);
+  let tree = sg_root.0.clone();
   let reference = SgRoot::into_reference(sg_root, env)?;
   let mut v = vec![];
-  for mut node in nodes {
+  for (mut node, fix) in nodes.into_iter().zip(pinned.2) {
     let root_ref = reference.clone(env)?;
     let sg_node = SgNode {
+      fix,
+      tree: tree.clone(),
       inner: root_ref.share_with(env, |root| {
         let r = &root.0.inner;
         node.visit_nodes(|n| unsafe { r.readopt(n) });
@@ -203,32 +624,231 @@ fn from_pinned_data(pinned: PinnedNodes, env: napi::Env) -> Result<Vec<Vec<SgNod
     };
     v.push(sg_node);
   }
-  Ok(vec![v])
+  Ok(v)
 }
 
 fn call_sg_node(
-  (tsfn, rule): &(
-    ThreadsafeFunction<PinnedNodes, ErrorStrategy::CalleeHandled>,
-    RuleCore<SupportLang>,
-  ),
+  state: &FindState,
   entry: std::result::Result<ignore::DirEntry, ignore::Error>,
   lang_option: &LangOption,
-) -> Ret<bool> {
+) -> Ret<u32> {
+  // do not walk more files while paused
+  state.flow.wait_resumed();
+  let pinned_roots = find_pinned(entry, lang_option, &state.rule)?;
+  let sent = pinned_roots.len() as u32;
+  for mut pinned in pinned_roots {
+    let match_count = pinned.0.get_data().len() as u32;
+    if let Some(batch) = state.push(pinned, match_count) {
+      state.send(batch);
+    }
+  }
+  Ok(sent)
+}
+
+// send the last batch smaller than batch size
+fn flush_batch(state: &FindState, _file_count: u32) {
+  let batch = std::mem::take(&mut state.batch.lock().expect("should not poison").0);
+  if !batch.is_empty() {
+    state.send(batch);
+  }
+}
+
+/// Matches of the rule in each root of the file with matches,
+/// empty if the entry is not a file or has no match.
+pub(crate) fn find_pinned(
+  entry: Entry,
+  lang_option: &LangOption,
+  rule: &RuleCore<NapiLang>,
+) -> Ret<Vec<PinnedNodes>> {
   let entry = entry?;
   if !entry
     .file_type()
     .context("could not use stdin as file")?
     .is_file()
   {
-    return Ok(false);
+    return Ok(vec![]);
   }
-  let (root, path) = get_root(entry, lang_option)?;
-  let mut pinned = PinnedNodeData::new(root.inner, |r| r.root().find_all(rule).collect());
-  let hits: &Vec<_> = pinned.get_data();
-  if hits.is_empty() {
-    return Ok(false);
+  let (roots, path) = get_roots(entry, lang_option)?;
+  let mut ret = vec![];
+  for root in roots {
+    let mut pinned = PinnedNodeData::new(root.inner, |r| r.root().find_all(rule).collect());
+    let hits: &Vec<_> = pinned.get_data();
+    if !hits.is_empty() {
+      let fixes = hits.iter().map(|n| fix_edit(n, rule)).collect();
+      ret.push(PinnedNodes(pinned, path.clone(), fixes));
+    }
   }
-  let pinned = PinnedNodes(pinned, path);
-  tsfn.call(Ok(pinned), ThreadsafeFunctionCallMode::Blocking);
-  Ok(true)
+  Ok(ret)
+}
+
+type NdjsonCallback = ThreadsafeFunction<String, ErrorStrategy::CalleeHandled>;
+
+pub type FindInFilesNdjson = IterateFiles<(NdjsonCallback, Arc<RuleCore<NapiLang>>, AtomicU32)>;
+
+pub fn find_in_files_ndjson_impl(
+  lang: NapiLang,
+  config: FindConfig,
+  callback: JsFunction,
+) -> Result<AsyncTask<FindInFilesNdjson>> {
+  let tsfn =
+    callback.create_threadsafe_function(THREAD_FUNC_QUEUE_SIZE, |ctx| Ok(vec![ctx.value]))?;
+  let FindConfig {
+    paths,
+    matcher,
+    language_globs,
+  } = config;
+  let rule = compile_matcher(matcher, lang)?;
+  let walk = lang.find_files(paths, language_globs)?;
+  Ok(AsyncTask::new(FindInFilesNdjson {
+    walk,
+    tsfn: (tsfn, rule, AtomicU32::new(0)),
+    flow: Arc::default(),
+    lang_option: LangOption::Specified(lang),
+    producer: call_ndjson_line,
+    finisher: Some(call_ndjson_summary),
+    abort_listener: None,
+  }))
+}
+
+fn match_to_json(node: &NodeMatch<JsDoc>, path: &str) -> serde_json::Value {
+  let byte_range = node.range();
+  let start = to_pos(node.start_pos(), byte_range.start);
+  let end = to_pos(node.end_pos(), byte_range.end);
+  let pos = |p: Pos| serde_json::json!({ "line": p.line, "column": p.column, "index": p.index });
+  serde_json::json!({
+    "type": "match",
+    "file": path,
+    "text": node.text(),
+    "range": { "start": pos(start), "end": pos(end) },
+  })
+}
+
+// send each match as one JSON line as soon as the file is scanned
+fn call_ndjson_line(
+  (tsfn, rule, match_count): &(NdjsonCallback, Arc<RuleCore<NapiLang>>, AtomicU32),
+  entry: Entry,
+  lang_option: &LangOption,
+) -> Ret<u32> {
+  let entry = entry?;
+  if !entry
+    .file_type()
+    .context("could not use stdin as file")?
+    .is_file()
+  {
+    return Ok(0);
+  }
+  let (roots, path) = get_roots(entry, lang_option)?;
+  let mut found = false;
+  for node in roots.iter().flat_map(|root| root.root().find_all(&**rule)) {
+    found = true;
+    match_count.fetch_add(1, Ordering::AcqRel);
+    let line = format!("{}\n", match_to_json(&node, &path));
+    tsfn.call(Ok(line), ThreadsafeFunctionCallMode::Blocking);
+  }
+  Ok(found as u32)
+}
+
+fn call_ndjson_summary(
+  (tsfn, _, match_count): &(NdjsonCallback, Arc<RuleCore<NapiLang>>, AtomicU32),
+  file_count: u32,
+) {
+  let summary = serde_json::json!({
+    "type": "summary",
+    "fileCount": file_count,
+    "matchCount": match_count.load(Ordering::Acquire),
+  });
+  tsfn.call(
+    Ok(format!("{summary}\n")),
+    ThreadsafeFunctionCallMode::Blocking,
+  );
+}
+
+#[napi(object)]
+pub struct MatchCount {
+  /// total number of matches in all files
+  pub total: u32,
+  /// number of matches keyed by file path, files without match are omitted
+  pub files: HashMap<String, u32>,
+}
+
+pub struct CountMatches {
+  walk: Option<WalkParallel>,
+  lang_option: LangOption,
+  rule: Arc<RuleCore<NapiLang>>,
+}
+
+impl Task for CountMatches {
+  type Output = MatchCount;
+  type JsValue = MatchCount;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let Some(walk) = self.walk.take() else {
+      return Err(anyhow!("cannot count matches twice").into());
+    };
+    let files = Mutex::new(HashMap::new());
+    let this = &*self;
+    walk.run(|| {
+      let files = &files;
+      Box::new(move |entry| {
+        let Ok(entry) = entry else {
+          return WalkState::Continue;
+        };
+        if !entry.file_type().map_or(false, |t| t.is_file()) {
+          return WalkState::Continue;
+        }
+        let Ok((roots, path)) = get_roots(entry, &this.lang_option) else {
+          return WalkState::Continue;
+        };
+        let count = roots
+          .iter()
+          .map(|root| count_in_node(root.root(), &this.rule))
+          .sum();
+        if count > 0 {
+          files.lock().unwrap().insert(path, count);
+        }
+        WalkState::Continue
+      })
+    });
+    let files = files.into_inner().unwrap();
+    let total = files.values().sum();
+    Ok(MatchCount { total, files })
+  }
+
+  fn resolve(&mut self, _: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+}
+
+/// Count matches without creating NodeMatch. Captures are only allocated if the rule needs them.
+fn count_in_node<D: Doc<Lang = NapiLang>>(node: Node<D>, rule: &RuleCore<NapiLang>) -> u32 {
+  let kinds = rule.potential_kinds();
+  let empty = MetaVarEnv::new();
+  let mut count = 0;
+  for cand in node.dfs() {
+    if let Some(k) = &kinds {
+      if !k.contains(cand.kind_id().into()) {
+        continue;
+      }
+    }
+    let mut env = Cow::Borrowed(&empty);
+    if rule.match_node_with_env(cand, &mut env).is_some() {
+      count += 1;
+    }
+  }
+  count
+}
+
+pub fn count_matches_impl(lang: NapiLang, config: FindConfig) -> Result<AsyncTask<CountMatches>> {
+  let FindConfig {
+    paths,
+    matcher,
+    language_globs,
+  } = config;
+  let rule = compile_matcher(matcher, lang)?;
+  let walk = lang.find_files(paths, language_globs)?;
+  Ok(AsyncTask::new(CountMatches {
+    walk: Some(walk),
+    lang_option: LangOption::Specified(lang),
+    rule,
+  }))
 }
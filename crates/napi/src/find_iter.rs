@@ -0,0 +1,154 @@
+//! `findInFilesIter`, an async iterator of matches in files.
+//! Files are walked in a background thread that blocks when the consumer falls behind,
+//! and stops once the iterator is closed, e.g. by `break` in `for await`.
+use ast_grep_config::RuleCore;
+use ignore::{WalkParallel, WalkState};
+use napi::bindgen_prelude::*;
+use napi::{CallContext, JsObject, JsUnknown, Task};
+use napi_derive::{js_function, napi};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+
+use crate::compiled_rule::compile_matcher;
+use crate::find_files::{find_pinned, pinned_to_nodes, FindConfig, PinnedNodes};
+use crate::napi_lang::{LangOption, NapiLang};
+use crate::sg_node::SgNode;
+
+/// Files with matches found ahead of the consumer before the walker waits.
+const PREFETCH_FILES: usize = 16;
+
+type SharedReceiver = Arc<Mutex<Receiver<PinnedNodes>>>;
+
+/// Matches of a file that are not yielded yet. They are only accessed in the JS thread.
+#[derive(Clone, Default)]
+struct Pending(Rc<RefCell<VecDeque<SgNode>>>);
+unsafe impl Send for Pending {}
+
+/// Async iterator returned by `findInFilesIter`.
+#[napi]
+pub struct FindInFilesIter {
+  receiver: Option<SharedReceiver>,
+  pending: Pending,
+}
+
+#[napi]
+impl FindInFilesIter {
+  /// Resolve the next match, or `done` after all files are walked.
+  #[napi(ts_return_type = "Promise<IteratorResult<SgNode, undefined>>")]
+  pub fn next(&mut self, env: Env) -> Result<JsObject> {
+    let fetch = self.pending.0.borrow().is_empty();
+    let task = NextMatch {
+      receiver: if fetch { self.receiver.clone() } else { None },
+      pending: self.pending.clone(),
+    };
+    Ok(env.spawn(task)?.promise_object())
+  }
+
+  /// Stop walking files, called when `for await` exits early.
+  #[napi(
+    js_name = "return",
+    ts_return_type = "IteratorResult<SgNode, undefined>"
+  )]
+  pub fn close(&mut self, env: Env) -> Result<JsObject> {
+    self.receiver = None;
+    self.pending.0.borrow_mut().clear();
+    iter_result(env, None)
+  }
+}
+
+struct NextMatch {
+  receiver: Option<SharedReceiver>,
+  pending: Pending,
+}
+
+impl Task for NextMatch {
+  type Output = Option<PinnedNodes>;
+  type JsValue = JsObject;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let Some(receiver) = &self.receiver else {
+      return Ok(None);
+    };
+    // the walker has finished if all senders are dropped
+    let receiver = receiver.lock().expect("should not poison");
+    Ok(receiver.recv().ok())
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    let mut pending = self.pending.0.borrow_mut();
+    if let Some(pinned) = output {
+      pending.extend(pinned_to_nodes(pinned, env)?);
+    }
+    iter_result(env, pending.pop_front())
+  }
+}
+
+fn iter_result(env: Env, node: Option<SgNode>) -> Result<JsObject> {
+  let mut result = env.create_object()?;
+  result.set("done", node.is_none())?;
+  match node {
+    Some(node) => result.set("value", node)?,
+    None => result.set("value", ())?,
+  }
+  Ok(result)
+}
+
+#[js_function]
+fn return_this(ctx: CallContext) -> Result<JsObject> {
+  ctx.this()
+}
+
+fn walk_in_background(
+  walk: WalkParallel,
+  lang_option: LangOption,
+  rule: Arc<RuleCore<NapiLang>>,
+  sender: SyncSender<PinnedNodes>,
+) {
+  std::thread::spawn(move || {
+    walk.run(|| {
+      let sender = sender.clone();
+      let (lang_option, rule) = (&lang_option, &rule);
+      Box::new(move |entry| match find_pinned(entry, lang_option, rule) {
+        Ok(pinned_roots) => {
+          for pinned in pinned_roots {
+            if sender.send(pinned).is_err() {
+              // the iterator is closed
+              return WalkState::Quit;
+            }
+          }
+          WalkState::Continue
+        }
+        Err(_) => WalkState::Skip,
+      })
+    });
+  });
+}
+
+pub fn find_in_files_iter_impl(env: Env, lang: NapiLang, config: FindConfig) -> Result<JsObject> {
+  let FindConfig {
+    paths,
+    matcher,
+    language_globs,
+  } = config;
+  let rule = compile_matcher(matcher, lang)?;
+  let walk = lang.find_files(paths, language_globs)?;
+  let (sender, receiver) = sync_channel(PREFETCH_FILES);
+  walk_in_background(walk, LangOption::Specified(lang), rule, sender);
+  let iter = FindInFilesIter {
+    receiver: Some(Arc::new(Mutex::new(receiver))),
+    pending: Pending::default(),
+  };
+  let mut iter = iter.into_instance(env)?.as_object(env);
+  // `for await` gets the iterator by `Symbol.asyncIterator`
+  let symbol = env
+    .get_global()?
+    .get_named_property_unchecked::<JsObject>("Symbol")?;
+  let key = symbol.get_named_property_unchecked::<JsUnknown>("asyncIterator")?;
+  let get_iter = env.create_function("asyncIterator", return_this)?;
+  iter.set_property(key, get_iter)?;
+  Ok(iter)
+}
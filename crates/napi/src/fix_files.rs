@@ -0,0 +1,319 @@
+//! Apply rule fixes to files on disk, the codemod counterpart of `findInFiles`.
+use ast_grep_config::RuleCore;
+use ast_grep_core::language::Language;
+use ignore::{WalkParallel, WalkState};
+use napi::anyhow::{anyhow, Context, Result as Ret};
+use napi::bindgen_prelude::*;
+use napi::Task;
+use napi_derive::napi;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::compiled_rule::matcher_config;
+use crate::find_files::FindConfig;
+use crate::napi_lang::{LangOption, NapiLang};
+
+#[napi(object)]
+pub struct FixInPlaceOptions {
+  /// The fix applied to each match, a string template or a FixConfig object.
+  /// See https://ast-grep.github.io/reference/yaml.html#fix
+  #[napi(ts_type = "string | Record<string, unknown>")]
+  pub fix: serde_json::Value,
+  /// Compute the summary without writing files
+  pub dry_run: Option<bool>,
+  /// Skip files that would receive more edits than the cap, in all passes
+  pub max_edits: Option<u32>,
+  /// Rescan fixed code and fix again until no match is left or the limit is reached. Default is 1.
+  pub max_passes: Option<u32>,
+}
+
+#[napi(object)]
+pub struct FixedFile {
+  pub file: String,
+  /// number of edits applied to the file
+  pub edits: u32,
+}
+
+#[napi(object)]
+pub struct FixSummary {
+  /// files changed, or to be changed in `dryRun`, sorted by path
+  pub changed: Vec<FixedFile>,
+  pub total_edits: u32,
+  /// files skipped for exceeding `maxEdits`, sorted by path
+  pub skipped: Vec<String>,
+  /// files whose fixes could not be written, sorted by path
+  pub failed: Vec<FailedFile>,
+}
+
+#[napi(object)]
+pub struct FailedFile {
+  pub file: String,
+  pub error: String,
+}
+
+enum FileResult {
+  Changed(FixedFile),
+  Skipped(String),
+  Failed(FailedFile),
+}
+
+pub struct FixInPlace {
+  walk: Option<WalkParallel>,
+  lang_option: LangOption,
+  rule: RuleCore<NapiLang>,
+  dry_run: bool,
+  max_edits: Option<u32>,
+  max_passes: u32,
+}
+
+impl Task for FixInPlace {
+  type Output = FixSummary;
+  type JsValue = FixSummary;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let Some(walk) = self.walk.take() else {
+      return Err(anyhow!("cannot fix files twice").into());
+    };
+    let results = Mutex::new(vec![]);
+    let this = &*self;
+    walk.run(|| {
+      let results = &results;
+      Box::new(move |entry| {
+        let Ok(entry) = entry else {
+          return WalkState::Continue;
+        };
+        if !entry.file_type().map_or(false, |t| t.is_file()) {
+          return WalkState::Continue;
+        }
+        if let Some(result) = this.fix_file(entry.path()) {
+          results.lock().unwrap().push(result);
+        }
+        WalkState::Continue
+      })
+    });
+    let mut changed = vec![];
+    let mut skipped = vec![];
+    let mut failed = vec![];
+    for result in results.into_inner().unwrap() {
+      match result {
+        FileResult::Changed(file) => changed.push(file),
+        FileResult::Skipped(file) => skipped.push(file),
+        FileResult::Failed(file) => failed.push(file),
+      }
+    }
+    // walker is parallel, sort for deterministic output
+    changed.sort_by(|a, b| a.file.cmp(&b.file));
+    skipped.sort();
+    failed.sort_by(|a, b| a.file.cmp(&b.file));
+    let total_edits = changed.iter().map(|f| f.edits).sum();
+    Ok(FixSummary {
+      changed,
+      total_edits,
+      skipped,
+      failed,
+    })
+  }
+
+  fn resolve(&mut self, _: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+}
+
+impl FixInPlace {
+  /// Unreadable or unknown files are skipped like in findInFiles,
+  /// but a failed write is reported since the file is left unfixed.
+  fn fix_file(&self, path: &Path) -> Option<FileResult> {
+    let lang = self.lang_option.get_lang(path)?;
+    let file = path.to_string_lossy().to_string();
+    let mut text = fs::read_to_string(path).ok()?;
+    let mut edits = 0;
+    for _ in 0..self.max_passes {
+      let (fixed, count) = fix_source(&text, lang, &self.rule);
+      if count == 0 {
+        break;
+      }
+      edits += count;
+      if self.max_edits.map_or(false, |max| edits > max) {
+        return Some(FileResult::Skipped(file));
+      }
+      text = fixed;
+    }
+    if edits == 0 {
+      return None;
+    }
+    if !self.dry_run {
+      if let Err(error) = write_atomic(path, &text) {
+        let error = error.to_string();
+        return Some(FileResult::Failed(FailedFile { file, error }));
+      }
+    }
+    Some(FileResult::Changed(FixedFile { file, edits }))
+  }
+}
+
+/// Apply fixes of all matches in one pass. Fixes overlapping with a preceding one are skipped.
+fn fix_source(text: &str, lang: NapiLang, rule: &RuleCore<NapiLang>) -> (String, u32) {
+  let Some(fixer) = &rule.fixer else {
+    return (text.to_string(), 0);
+  };
+  let root = lang.ast_grep(text);
+  let mut new_content = String::new();
+  let mut start = 0;
+  let mut count = 0;
+  for node_match in root.root().find_all(rule) {
+    let edit = node_match.make_edit(rule, fixer);
+    if start > edit.position {
+      continue;
+    }
+    new_content.push_str(&text[start..edit.position]);
+    new_content.push_str(&String::from_utf8_lossy(&edit.inserted_text));
+    start = edit.position + edit.deleted_length;
+    count += 1;
+  }
+  new_content.push_str(&text[start..]);
+  (new_content, count)
+}
+
+/// Write to a temporary file in the same directory then rename it,
+/// so readers never see a partially written file.
+fn write_atomic(path: &Path, content: &str) -> Ret<()> {
+  let name = path.file_name().context("invalid file path")?;
+  let tmp = path.with_file_name(format!(".{}.ast-grep-tmp", name.to_string_lossy()));
+  let permissions = fs::metadata(path)?.permissions();
+  fs::write(&tmp, content)?;
+  let renamed = fs::set_permissions(&tmp, permissions).and_then(|_| fs::rename(&tmp, path));
+  if renamed.is_err() {
+    let _ = fs::remove_file(&tmp);
+  }
+  Ok(renamed?)
+}
+
+/// Fixes marked `unsafe` need confirmation, which files fixed in place never get.
+fn ensure_safe_fix(rule: &RuleCore<NapiLang>) -> Ret<()> {
+  if rule.fixer.as_ref().map_or(false, |fixer| fixer.is_unsafe()) {
+    return Err(anyhow!("unsafe fix cannot be applied in place"));
+  }
+  Ok(())
+}
+
+pub fn fix_in_place_impl(
+  lang: NapiLang,
+  config: FindConfig,
+  options: FixInPlaceOptions,
+) -> Result<AsyncTask<FixInPlace>> {
+  let FindConfig {
+    paths,
+    matcher,
+    language_globs,
+  } = config;
+  let FixInPlaceOptions {
+    fix,
+    dry_run,
+    max_edits,
+    max_passes,
+  } = options;
+  let rule = matcher_config(matcher, lang)?.parse_with_fix(lang, fix)?;
+  ensure_safe_fix(&rule)?;
+  let walk = lang.find_files(paths, language_globs)?;
+  Ok(AsyncTask::new(FixInPlace {
+    walk: Some(walk),
+    lang_option: LangOption::Specified(lang),
+    rule,
+    dry_run: dry_run.unwrap_or(false),
+    max_edits,
+    max_passes: max_passes.unwrap_or(1),
+  }))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::doc::NapiConfig;
+  use ast_grep_language::SupportLang;
+  use serde_json::json;
+  use tempfile::TempDir;
+
+  fn make_rule(pattern: &str, fix: &str) -> RuleCore<NapiLang> {
+    make_rule_with_fix(pattern, json!(fix))
+  }
+
+  fn make_rule_with_fix(pattern: &str, fix: serde_json::Value) -> RuleCore<NapiLang> {
+    NapiConfig::from_rule(json!({ "pattern": pattern }))
+      .parse_with_fix(SupportLang::TypeScript.into(), fix)
+      .expect("should parse")
+  }
+
+  #[test]
+  fn test_fix_source() {
+    let rule = make_rule("console.log($A)", "logger.log($A)");
+    let lang = NapiLang::Builtin(SupportLang::TypeScript);
+    let (fixed, count) = fix_source("console.log(1); console.log(2)", lang, &rule);
+    assert_eq!(fixed, "logger.log(1); logger.log(2)");
+    assert_eq!(count, 2);
+    let (fixed, count) = fix_source("let a = 1", lang, &rule);
+    assert_eq!(fixed, "let a = 1");
+    assert_eq!(count, 0);
+  }
+
+  #[test]
+  fn test_unsafe_fix() {
+    let fix = json!({ "template": "bar($A)", "unsafe": true });
+    let rule = make_rule_with_fix("foo($A)", fix);
+    assert!(ensure_safe_fix(&rule).is_err());
+    assert!(ensure_safe_fix(&make_rule("foo($A)", "bar($A)")).is_ok());
+  }
+
+  #[test]
+  fn test_fix_passes() {
+    let rule = make_rule("!!$A", "$A");
+    let lang = NapiLang::Builtin(SupportLang::TypeScript);
+    // nested matches are fixed in later passes
+    let (fixed, count) = fix_source("!!!!a", lang, &rule);
+    assert_eq!((fixed.as_str(), count), ("!!a", 1));
+    let (fixed, count) = fix_source(&fixed, lang, &rule);
+    assert_eq!((fixed.as_str(), count), ("a", 1));
+  }
+
+  #[test]
+  fn test_write_atomic() {
+    let dir = TempDir::new().expect("should create dir");
+    let path = dir.path().join("a.ts");
+    fs::write(&path, "before").expect("should write");
+    write_atomic(&path, "after").expect("should write atomically");
+    assert_eq!(fs::read_to_string(&path).expect("should read"), "after");
+    let entries = fs::read_dir(dir.path()).expect("should read dir").count();
+    assert_eq!(entries, 1, "temporary file should be renamed");
+  }
+
+  #[test]
+  fn test_fix_file_failed() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = TempDir::new().expect("should create dir");
+    let path = dir.path().join("a.ts");
+    fs::write(&path, "console.log(1)").expect("should write");
+    // root ignores permissions, a directory at the temporary path fails the write anyway
+    fs::create_dir(dir.path().join(".a.ts.ast-grep-tmp")).expect("should create dir");
+    let lang = NapiLang::Builtin(SupportLang::TypeScript);
+    let fix_in_place = FixInPlace {
+      walk: None,
+      lang_option: LangOption::Specified(lang),
+      rule: make_rule("console.log($A)", "logger.log($A)"),
+      dry_run: false,
+      max_edits: None,
+      max_passes: 1,
+    };
+    let read_only = fs::Permissions::from_mode(0o555);
+    fs::set_permissions(dir.path(), read_only).expect("should set permissions");
+    let result = fix_in_place.fix_file(&path);
+    let writable = fs::Permissions::from_mode(0o755);
+    fs::set_permissions(dir.path(), writable).expect("should set permissions");
+    let Some(FileResult::Failed(failed)) = result else {
+      panic!("write failure should be reported");
+    };
+    assert_eq!(failed.file, path.to_string_lossy());
+    assert!(!failed.error.is_empty());
+    let content = fs::read_to_string(&path).expect("should read");
+    assert_eq!(content, "console.log(1)");
+  }
+}
@@ -1,22 +1,55 @@
 #![cfg(not(feature = "napi-noop-in-unit-test"))]
 
+mod compiled_rule;
+mod definition;
 mod doc;
+mod dump_ast;
+mod extract;
 mod find_files;
+mod find_iter;
+mod fix_files;
 mod napi_lang;
+mod pattern_object;
+mod redundant;
+mod rule_cache;
+mod rule_source;
+mod sfc;
 mod sg_node;
+mod shared_root;
+mod source_map;
+mod ts_query;
+mod utf8_root;
+mod validate;
+mod virtual_files;
 
 use ast_grep_core::language::Language;
 use ast_grep_core::AstGrep;
-use ast_grep_language::SupportLang;
 use napi::bindgen_prelude::*;
+use napi::JsObject;
 use napi_derive::napi;
+use std::collections::HashMap;
 
+use compiled_rule::{compile_rule_impl, CompiledRule};
 use doc::{JsDoc, NapiConfig};
-use find_files::{find_in_files_impl, FindConfig, FindInFiles, ParseAsync};
-use napi_lang::Lang;
+use extract::{extract_strings_impl, ExtractStringOption, ExtractStrings};
+use find_files::{
+  count_matches_impl, find_in_files_impl, find_in_files_ndjson_impl, CountMatches, FindConfig,
+  FindInFilesNdjson, FindInFilesOptions, ParseAsync,
+};
+use find_iter::find_in_files_iter_impl;
+use fix_files::{fix_in_place_impl, FixInPlace, FixInPlaceOptions};
+use napi_lang::{register_dynamic_language_impl, DynamicLangOption, Lang, NapiLang, NodeKind};
+use pattern_object::{rule_pattern, NapiPattern};
+use redundant::{find_redundant_rules_impl, RedundantRule};
+use rule_cache::{load_rule_cache_impl, save_rule_cache_impl};
+use rule_source::{rule_sources_impl, RuleLocation};
 use sg_node::SgRoot;
+use shared_root::{open_shared_root_impl, release_shared_root_impl};
+use utf8_root::{parse_buffer_impl, Utf8Root};
+use validate::{validate_config_dir_impl, ConfigDirReport, ValidateConfigOptions};
 
 pub use find_files::parse_files;
+pub use virtual_files::find_in_virtual_files;
 
 macro_rules! impl_lang_mod {
     ($name: ident, $lang: ident) =>  {
@@ -28,7 +61,7 @@ macro_rules! impl_lang_mod {
         /// Parse a string to an ast-grep instance
         #[napi]
         pub fn parse(src: String) -> SgRoot {
-          parse_with_lang($lang, src)
+          parse_with_lang($lang.into(), src)
         }
 
         /// Parse a string to an ast-grep instance asynchronously in threads.
@@ -38,37 +71,72 @@ macro_rules! impl_lang_mod {
         /// for its default behavior and performance tuning tricks.
         #[napi(ts_return_type = "Promise<SgRoot>")]
         pub fn parse_async(src: String) -> AsyncTask<ParseAsync> {
-          parse_async_with_lang($lang, src)
+          parse_async_with_lang($lang.into(), src)
+        }
+        /// Parse UTF-8 bytes to a tree with ranges in byte offsets, without converting to UTF-16.
+        #[napi]
+        pub fn parse_buffer(src: Buffer) -> Result<Utf8Root> {
+          parse_buffer_with_lang($lang.into(), src)
         }
         /// Get the `kind` number from its string name.
         #[napi]
         pub fn kind(kind_name: String) -> u16 {
-          kind_with_lang($lang, kind_name)
+          kind_with_lang($lang.into(), kind_name)
         }
-        /// Compile a string to ast-grep Pattern.
+        /// List visible node kinds of the language, e.g. to validate `kind` in rules.
         #[napi]
-        pub fn pattern(pattern: String) -> NapiConfig {
-          pattern_with_lang($lang, pattern)
+        pub fn node_kinds() -> Vec<NodeKind> {
+          NapiLang::from($lang).node_kinds()
+        }
+        /// List field names of the language, e.g. to validate `field` in rules.
+        #[napi]
+        pub fn fields() -> Vec<String> {
+          NapiLang::from($lang).field_names()
+        }
+        /// Compile a pattern string or object to ast-grep Pattern.
+        #[napi(ts_args_type = "pattern: string | PatternObject")]
+        pub fn pattern(pattern: NapiPattern) -> Result<NapiConfig> {
+          pattern_with_lang($lang.into(), pattern)
+        }
+        /// Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`.
+        #[napi]
+        pub fn compile_rule(config: NapiConfig) -> Result<CompiledRule> {
+          compile_rule_with_lang($lang.into(), config)
         }
 
         /// Discover and parse multiple files in Rust.
         /// `config` specifies the file path and matcher.
         /// `callback` will receive matching nodes found in a file.
+        /// `options` batches matches and limits the ones waiting for callback.
         #[napi(
-          ts_args_type = "config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void",
-          ts_return_type = "Promise<number>"
+          ts_args_type = "config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions",
+          ts_return_type = "Promise<number> & { pause(): void; resume(): void }"
         )]
-        pub fn find_in_files(config: FindConfig, callback: JsFunction) -> Result<AsyncTask<FindInFiles>> {
-          find_in_files_impl($lang, config, callback)
+        pub fn find_in_files(
+          env: Env,
+          config: FindConfig,
+          callback: JsFunction,
+          options: Option<FindInFilesOptions>,
+        ) -> Result<JsObject> {
+          find_in_files_impl(env, $lang.into(), config, callback, options)
+        }
+
+        /// Discover and parse multiple files in Rust, yielding matches as an async iterator.
+        /// Files are walked lazily so `break` in `for await` stops the search.
+        #[napi(ts_return_type = "AsyncIterableIterator<SgNode>")]
+        pub fn find_in_files_iter(env: Env, config: FindConfig) -> Result<JsObject> {
+          find_in_files_iter_impl(env, $lang.into(), config)
         }
       }
     }
 }
 
 // for name conflict in mod
+use compile_rule as compile_rule_with_lang;
 use kind as kind_with_lang;
 use parse as parse_with_lang;
 use parse_async as parse_async_with_lang;
+use parse_buffer as parse_buffer_with_lang;
 use pattern as pattern_with_lang;
 impl_lang_mod!(html, Html);
 impl_lang_mod!(js, JavaScript);
@@ -76,12 +144,17 @@ impl_lang_mod!(jsx, JavaScript);
 impl_lang_mod!(ts, TypeScript);
 impl_lang_mod!(tsx, Tsx);
 impl_lang_mod!(css, Css);
+impl_lang_mod!(python, Python);
+impl_lang_mod!(go, Go);
+impl_lang_mod!(rust, Rust);
+impl_lang_mod!(java, Java);
+impl_lang_mod!(c, C);
 
 /// Parse a string to an ast-grep instance
-#[napi]
-pub fn parse(lang: Lang, src: String) -> SgRoot {
-  let doc = JsDoc::new(src, lang.into());
-  SgRoot(AstGrep::doc(doc), "anonymous".into())
+#[napi(ts_args_type = "lang: Lang | string, src: string")]
+pub fn parse(lang: NapiLang, src: String) -> SgRoot {
+  let doc = JsDoc::new(src, lang);
+  SgRoot::new(AstGrep::doc(doc), "anonymous".into())
 }
 
 /// Parse a string to an ast-grep instance asynchronously in threads.
@@ -89,46 +162,226 @@ pub fn parse(lang: Lang, src: String) -> SgRoot {
 /// However, spawning excessive many threads may backfire.
 /// Please refer to libuv doc, nodejs' underlying runtime
 /// for its default behavior and performance tuning tricks.
-#[napi(ts_return_type = "Promise<SgRoot>")]
-pub fn parse_async(lang: Lang, src: String) -> AsyncTask<ParseAsync> {
+#[napi(
+  ts_args_type = "lang: Lang | string, src: string",
+  ts_return_type = "Promise<SgRoot>"
+)]
+pub fn parse_async(lang: NapiLang, src: String) -> AsyncTask<ParseAsync> {
   AsyncTask::new(ParseAsync { src, lang })
 }
 
-/// Get the `kind` number from its string name.
+/// Parse UTF-8 bytes to a tree without converting them to UTF-16 like `parse`.
+/// It saves memory and time for large files, and ranges of nodes are in byte offsets.
+#[napi(ts_args_type = "lang: Lang | string, src: Buffer")]
+pub fn parse_buffer(lang: NapiLang, src: Buffer) -> Result<Utf8Root> {
+  parse_buffer_impl(lang, src)
+}
+
+/// Open a tree shared by `SgRoot.share`, e.g. in a worker thread.
+/// The returned root has its own copy of the tree, so the source is not read or parsed again.
+#[napi]
+pub fn open_shared_root(handle: u32) -> Result<SgRoot> {
+  open_shared_root_impl(handle)
+}
+
+/// Release a tree shared by `SgRoot.share`. Roots opened before are still valid.
+/// Returns false if the handle is not found.
 #[napi]
-pub fn kind(lang: Lang, kind_name: String) -> u16 {
-  let lang: SupportLang = lang.into();
+pub fn release_shared_root(handle: u32) -> bool {
+  release_shared_root_impl(handle)
+}
+
+/// Get the `kind` number from its string name.
+#[napi(ts_args_type = "lang: Lang | string, kindName: string")]
+pub fn kind(lang: NapiLang, kind_name: String) -> u16 {
   lang
     .get_ts_language()
     .id_for_node_kind(&kind_name, /* named */ true)
 }
 
-/// Compile a string to ast-grep Pattern.
-#[napi]
-pub fn pattern(lang: Lang, pattern: String) -> NapiConfig {
-  NapiConfig {
+/// List visible node kinds of the language, e.g. to validate `kind` in rules or for autocompletion.
+/// Hidden kinds like `_expression` are omitted since they never appear in trees.
+#[napi(ts_args_type = "lang: Lang | string")]
+pub fn node_kinds(lang: NapiLang) -> Vec<NodeKind> {
+  lang.node_kinds()
+}
+
+/// List field names of the language, e.g. to validate `field` in rules or for autocompletion.
+#[napi(ts_args_type = "lang: Lang | string")]
+pub fn fields(lang: NapiLang) -> Vec<String> {
+  lang.field_names()
+}
+
+/// Compile a pattern string or object to ast-grep Pattern.
+#[napi(ts_args_type = "lang: Lang | string, pattern: string | PatternObject")]
+pub fn pattern(lang: NapiLang, pattern: NapiPattern) -> Result<NapiConfig> {
+  Ok(NapiConfig {
+    id: None,
     rule: serde_json::json!({
-      "pattern": pattern,
+      "pattern": rule_pattern(pattern)?,
     }),
     constraints: None,
     language: Some(lang),
     utils: None,
     transform: None,
-  }
+    ignore_kinds: None,
+    fix: None,
+    rewriters: None,
+  })
+}
+
+/// Compile a rule once to reuse it in `find`, `findAll` and `findInFiles`,
+/// instead of parsing and building the rule config on every call.
+/// `lang` is used if the config has no `language`.
+#[napi(ts_args_type = "lang: Lang | string, config: NapiConfig")]
+pub fn compile_rule(lang: NapiLang, config: NapiConfig) -> Result<CompiledRule> {
+  compile_rule_impl(lang, config)
+}
+
+/// Register a tree-sitter grammar compiled as a dynamic library, e.g. `kotlin.so`.
+/// The language can then be used by its name in `parse`, `findInFiles` and `NapiConfig.language`,
+/// and files of its extensions are parsed by `parseFiles`.
+/// It is safe to register while other files are being parsed in the background.
+#[napi]
+pub fn register_dynamic_language(option: DynamicLangOption) -> Result<()> {
+  register_dynamic_language_impl(option)
 }
 
 /// Discover and parse multiple files in Rust.
 /// `lang` specifies the language.
 /// `config` specifies the file path and matcher.
 /// `callback` will receive matching nodes found in a file.
+/// `options` batches matches and limits the ones waiting for callback.
+/// The returned promise resolves to the file count, and its `pause` and `resume` throttle file walking.
 #[napi(
-  ts_args_type = "lang: Lang, config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void",
-  ts_return_type = "Promise<number>"
+  ts_args_type = "lang: Lang | string, config: FindConfig, callback: (err: null | Error, result: SgNode[]) => void, options?: FindInFilesOptions",
+  ts_return_type = "Promise<number> & { pause(): void; resume(): void }"
 )]
 pub fn find_in_files(
-  lang: Lang,
+  env: Env,
+  lang: NapiLang,
+  config: FindConfig,
+  callback: JsFunction,
+  options: Option<FindInFilesOptions>,
+) -> Result<JsObject> {
+  find_in_files_impl(env, lang, config, callback, options)
+}
+
+/// Discover and parse multiple files in Rust, yielding matches as an async iterator.
+/// `lang` specifies the language and `config` is the same as `findInFiles`.
+/// Files are walked lazily so `break` in `for await` stops the search.
+#[napi(
+  ts_args_type = "lang: Lang | string, config: FindConfig",
+  ts_return_type = "AsyncIterableIterator<SgNode>"
+)]
+pub fn find_in_files_iter(env: Env, lang: NapiLang, config: FindConfig) -> Result<JsObject> {
+  find_in_files_iter_impl(env, lang, config)
+}
+
+/// Discover and scan multiple files in Rust, streaming matches as NDJSON.
+/// `callback` receives one JSON line per match as soon as it is found,
+/// `{"type":"match","file":...,"text":...,"range":...}`,
+/// followed by a terminal `{"type":"summary","fileCount":...,"matchCount":...}` line.
+/// Every line, including the summary, ends with a newline.
+#[napi(
+  ts_args_type = "lang: Lang | string, config: FindConfig, callback: (err: null | Error, line: string) => void",
+  ts_return_type = "Promise<number>"
+)]
+pub fn find_in_files_as_ndjson(
+  lang: NapiLang,
   config: FindConfig,
   callback: JsFunction,
-) -> Result<AsyncTask<FindInFiles>> {
-  find_in_files_impl(lang, config, callback)
+) -> Result<AsyncTask<FindInFilesNdjson>> {
+  find_in_files_ndjson_impl(lang, config, callback)
+}
+
+/// Count matches in files without creating nodes for them, e.g. for metrics over a large codebase.
+/// `config` is the same as `findInFiles`.
+#[napi(
+  ts_args_type = "lang: Lang | string, config: FindConfig",
+  ts_return_type = "Promise<MatchCount>"
+)]
+pub fn count_matches(lang: NapiLang, config: FindConfig) -> Result<AsyncTask<CountMatches>> {
+  count_matches_impl(lang, config)
+}
+
+/// Apply fixes of matches in files and write changed files back to disk.
+/// `config` is the same as `findInFiles` and `options.fix` is the rewrite applied to matches.
+/// Overlapping fixes are skipped in one pass, use `options.maxPasses` to fix them iteratively.
+/// Files are replaced atomically by renaming a temporary file in the same directory.
+/// Files that cannot be written are reported in `failed` and left unchanged.
+/// Fixes marked `unsafe` are rejected since nobody confirms them.
+#[napi(
+  ts_args_type = "lang: Lang | string, config: FindConfig, options: FixInPlaceOptions",
+  ts_return_type = "Promise<FixSummary>"
+)]
+pub fn fix_in_place(
+  lang: NapiLang,
+  config: FindConfig,
+  options: FixInPlaceOptions,
+) -> Result<AsyncTask<FixInPlace>> {
+  fix_in_place_impl(lang, config, options)
+}
+
+/// Extract string literals in files, e.g. to find user-facing strings for i18n.
+/// Language is inferred from file extension. `value` of each result is the decoded string.
+/// `option.regex` filters the decoded value and `option.pattern` limits strings inside matches.
+#[napi(ts_return_type = "Promise<Array<ExtractedString>>")]
+pub fn extract_strings(
+  paths: Vec<String>,
+  option: Option<ExtractStringOption>,
+) -> Result<AsyncTask<ExtractStrings>> {
+  extract_strings_impl(paths, option)
+}
+
+/// Find rules that match a subset of what another rule matches, e.g. duplicates across rule files.
+/// `lang` is the default language for rules without `language`.
+/// A rule without `id` is identified by its index in `rules`. It is heuristic and may miss redundancy.
+#[napi]
+pub fn find_redundant_rules(lang: Lang, rules: Vec<NapiConfig>) -> Result<Vec<RedundantRule>> {
+  find_redundant_rules_impl(lang.into(), rules)
+}
+
+/// Save rules to a cache file after checking that every rule compiles.
+/// `source` is the rule config the rules are read from, e.g. the YAML text, and its hash is stored.
+/// `lang` is the default language for rules without `language`.
+#[napi]
+pub fn save_rule_cache(
+  cache_path: String,
+  source: String,
+  lang: Lang,
+  rules: Vec<NapiConfig>,
+) -> Result<()> {
+  save_rule_cache_impl(cache_path, source, lang.into(), rules)
+}
+
+/// Load rules saved by `saveRuleCache` as compiled matchers, without reading the rule config again.
+/// Returns null if the cache is missing, or stale because `source`, `lang` or ast-grep version changed.
+#[napi]
+pub fn load_rule_cache(
+  cache_path: String,
+  source: String,
+  lang: Lang,
+) -> Result<Option<Vec<CompiledRule>>> {
+  load_rule_cache_impl(cache_path, source, lang.into())
+}
+
+/// Locate rules defined in YAML rule files, keyed by rule id like the result of `SgRoot.scan`.
+/// It lets tools report where the rule of a finding is defined, e.g. `rules/no-var.yml:12`.
+/// Rules with the same id in later files override earlier ones.
+/// Rules are skipped if their `id` lines cannot be located, e.g. in flow style YAML.
+#[napi]
+pub fn rule_sources(paths: Vec<String>) -> Result<HashMap<String, RuleLocation>> {
+  rule_sources_impl(paths)
+}
+
+/// Validate every YAML rule file in a directory, e.g. as a CI gate for a rule library.
+/// The report lists compiled rule ids, files failing to compile, duplicate ids,
+/// references to undefined utility rules and captures never used by a rule.
+#[napi]
+pub fn validate_config_dir(
+  path: String,
+  options: Option<ValidateConfigOptions>,
+) -> Result<ConfigDirReport> {
+  validate_config_dir_impl(path, options)
 }
@@ -1,13 +1,21 @@
-use ast_grep_language::SupportLang;
+use ast_grep_core::language::{TSLanguage, TSRange};
+use ast_grep_core::{Doc, Node};
+use ast_grep_dynamic::{DynamicLang, Registration};
+use ast_grep_language::{Language, SupportLang};
 use ignore::types::{Types, TypesBuilder};
 use ignore::{WalkBuilder, WalkParallel};
 use napi::anyhow::anyhow;
 use napi::anyhow::Error;
-use napi::bindgen_prelude::Result;
+use napi::bindgen_prelude::{FromNapiValue, Result, ToNapiValue, TypeName, ValidateNapiValue};
+use napi::sys::{napi_env, napi_value};
+use napi::ValueType;
 use napi_derive::napi;
+use serde::{Deserialize, Serialize};
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 #[napi(string_enum)]
@@ -106,13 +114,6 @@ impl From<SupportLang> for Lang {
 }
 
 impl Lang {
-  pub fn find_files(
-    &self,
-    paths: Vec<String>,
-    language_globs: Option<Vec<String>>,
-  ) -> Result<WalkParallel> {
-    find_files_with_lang(self, paths, language_globs)
-  }
   pub fn lang_globs(map: HashMap<String, Vec<String>>) -> LanguageGlobs {
     let mut ret = HashMap::new();
     for (name, patterns) in map {
@@ -135,20 +136,272 @@ impl FromStr for Lang {
   }
 }
 
+/// A builtin language or a tree-sitter grammar registered by `registerDynamicLanguage`.
+/// It is passed from JavaScript as the name of the language, e.g. `Lang.Python` or `'kotlin'`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum NapiLang {
+  Builtin(SupportLang),
+  Custom(DynamicLang),
+}
+
+impl NapiLang {
+  pub fn all_langs() -> Vec<Self> {
+    let builtin = SupportLang::all_langs().iter().copied().map(Self::Builtin);
+    let customs = DynamicLang::all_langs().into_iter().map(Self::Custom);
+    builtin.chain(customs).collect()
+  }
+
+  pub fn file_types(&self) -> Types {
+    match self {
+      Self::Builtin(b) => b.file_types(),
+      Self::Custom(c) => c.file_types(),
+    }
+  }
+
+  /// Grammar of the native tree-sitter crate, e.g. to run tree-sitter queries.
+  pub fn native_language(&self) -> tree_sitter_native::Language {
+    match self {
+      Self::Builtin(b) => b.native_language(),
+      Self::Custom(c) => c.native_language(),
+    }
+  }
+
+  pub fn find_files(
+    &self,
+    paths: Vec<String>,
+    language_globs: Option<Vec<String>>,
+  ) -> Result<WalkParallel> {
+    find_files_with_lang(self, paths, language_globs)
+  }
+
+  /// Visible node kinds of the grammar. Hidden kinds like `_expression` never appear in trees.
+  /// A name aliased to several ids is listed once, with the id returned by `kind`.
+  pub fn node_kinds(&self) -> Vec<NodeKind> {
+    let ts_lang = self.get_ts_language();
+    let mut seen = HashSet::new();
+    let mut kinds = vec![];
+    for id in 0..ts_lang.node_kind_count() {
+      if !ts_lang.node_kind_is_visible(id) {
+        continue;
+      }
+      let Some(name) = ts_lang.node_kind_for_id(id) else {
+        continue;
+      };
+      let named = ts_lang.node_kind_is_named(id);
+      if seen.insert((name.to_string(), named)) {
+        kinds.push(NodeKind {
+          id,
+          name: name.into(),
+          named,
+        });
+      }
+    }
+    kinds
+  }
+
+  /// Field names of the grammar, e.g. `name` or `body`.
+  pub fn field_names(&self) -> Vec<String> {
+    let ts_lang = self.get_ts_language();
+    // field id starts from 1
+    (1..=ts_lang.field_count())
+      .filter_map(|id| ts_lang.field_name_for_id(id))
+      .map(String::from)
+      .collect()
+  }
+}
+
+/// A node kind in the grammar of a language.
+#[napi(object)]
+pub struct NodeKind {
+  /// The kind id, same as `kind(lang, name)` for named kinds
+  pub id: u16,
+  /// The name used in `kind` rules
+  pub name: String,
+  /// Named kinds are grammar rules like `identifier`, others are tokens like `(` or `if`
+  pub named: bool,
+}
+
+impl From<Lang> for NapiLang {
+  fn from(lang: Lang) -> Self {
+    Self::Builtin(lang.into())
+  }
+}
+
+impl From<SupportLang> for NapiLang {
+  fn from(lang: SupportLang) -> Self {
+    Self::Builtin(lang)
+  }
+}
+
+impl FromStr for NapiLang {
+  type Err = Error;
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    if let Ok(lang) = SupportLang::from_str(s) {
+      Ok(Self::Builtin(lang))
+    } else if let Ok(lang) = DynamicLang::from_str(s) {
+      Ok(Self::Custom(lang))
+    } else {
+      Err(anyhow!(format!("{s} is not supported in napi")))
+    }
+  }
+}
+
+impl Display for NapiLang {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    match self {
+      Self::Builtin(b) => write!(f, "{b}"),
+      Self::Custom(c) => write!(f, "{}", c.name()),
+    }
+  }
+}
+
+impl Debug for NapiLang {
+  fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+    write!(f, "{self}")
+  }
+}
+
+impl Language for NapiLang {
+  fn get_ts_language(&self) -> TSLanguage {
+    match self {
+      Self::Builtin(b) => b.get_ts_language(),
+      Self::Custom(c) => c.get_ts_language(),
+    }
+  }
+
+  fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+    let path = path.as_ref();
+    DynamicLang::from_path(path)
+      .map(Self::Custom)
+      .or_else(|| SupportLang::from_path(path).map(Self::Builtin))
+  }
+
+  fn pre_process_pattern<'q>(&self, query: &'q str) -> Cow<'q, str> {
+    match self {
+      Self::Builtin(b) => b.pre_process_pattern(query),
+      Self::Custom(c) => c.pre_process_pattern(query),
+    }
+  }
+
+  #[inline]
+  fn meta_var_char(&self) -> char {
+    match self {
+      Self::Builtin(b) => b.meta_var_char(),
+      Self::Custom(c) => c.meta_var_char(),
+    }
+  }
+
+  #[inline]
+  fn expando_char(&self) -> char {
+    match self {
+      Self::Builtin(b) => b.expando_char(),
+      Self::Custom(c) => c.expando_char(),
+    }
+  }
+
+  fn injectable_languages(&self) -> Option<&'static [&'static str]> {
+    match self {
+      Self::Builtin(b) => b.injectable_languages(),
+      Self::Custom(c) => c.injectable_languages(),
+    }
+  }
+
+  fn extract_injections<D: Doc>(&self, root: Node<D>) -> HashMap<String, Vec<TSRange>> {
+    match self {
+      Self::Builtin(b) => b.extract_injections(root),
+      Self::Custom(c) => c.extract_injections(root),
+    }
+  }
+}
+
+impl TypeName for NapiLang {
+  fn type_name() -> &'static str {
+    "Lang"
+  }
+  fn value_type() -> ValueType {
+    ValueType::String
+  }
+}
+
+impl ValidateNapiValue for NapiLang {}
+
+impl FromNapiValue for NapiLang {
+  unsafe fn from_napi_value(env: napi_env, napi_val: napi_value) -> Result<Self> {
+    let name = String::from_napi_value(env, napi_val)?;
+    Ok(Self::from_str(&name)?)
+  }
+}
+
+impl ToNapiValue for NapiLang {
+  unsafe fn to_napi_value(env: napi_env, val: Self) -> Result<napi_value> {
+    String::to_napi_value(env, val.to_string())
+  }
+}
+
+/// A tree-sitter grammar compiled as a dynamic library.
+#[napi(object)]
+pub struct DynamicLangOption {
+  /// Name of the language used as `lang`, e.g. `'kotlin'`
+  pub name: String,
+  /// Path of the compiled grammar, e.g. `kotlin.so`, `kotlin.dylib` or `kotlin.dll`
+  pub library_path: String,
+  /// File extensions without leading dot, used to infer the language of files, e.g. `['kt']`
+  pub extensions: Vec<String>,
+  /// Char replacing `$` in patterns if `$` is not valid in identifiers of the language
+  pub expando_char: Option<String>,
+  /// Char of meta variables, `$` by default
+  pub meta_var_char: Option<String>,
+  /// Symbol of the function returning the grammar, `tree_sitter_{name}` by default
+  pub language_symbol: Option<String>,
+}
+
+fn to_char(field: &str, s: Option<String>) -> Result<Option<char>> {
+  let Some(s) = s else {
+    return Ok(None);
+  };
+  let mut chars = s.chars();
+  match (chars.next(), chars.next()) {
+    (Some(c), None) => Ok(Some(c)),
+    _ => Err(anyhow!("{field} must be a single char, got `{s}`").into()),
+  }
+}
+
+pub fn register_dynamic_language_impl(option: DynamicLangOption) -> Result<()> {
+  let name = option.name;
+  if NapiLang::from_str(&name).is_ok() {
+    return Err(anyhow!("language `{name}` is already registered").into());
+  }
+  let registration = Registration {
+    symbol: option
+      .language_symbol
+      .unwrap_or_else(|| format!("tree_sitter_{name}")),
+    lib_path: PathBuf::from(option.library_path),
+    meta_var_char: to_char("metaVarChar", option.meta_var_char)?,
+    expando_char: to_char("expandoChar", option.expando_char)?,
+    extensions: option.extensions,
+    lang_name: name.clone(),
+  };
+  // SAFETY: the library is trusted to export a tree-sitter grammar by the caller
+  unsafe { DynamicLang::register(vec![registration]) }
+    .map_err(|e| anyhow!("cannot register language `{name}`: {e}"))?;
+  Ok(())
+}
+
 pub enum LangOption {
   /// Used when language is inferred from file path
   /// e.g. in parse_files
-  Inferred(Vec<(SupportLang, Types)>),
+  Inferred(Vec<(NapiLang, Types)>),
   /// Used when language is specified
   /// e.g. in frontend_lang.find_in_files
-  Specified(Lang),
+  Specified(NapiLang),
 }
 
 impl LangOption {
-  pub fn get_lang(&self, path: &Path) -> Option<SupportLang> {
+  pub fn get_lang(&self, path: &Path) -> Option<NapiLang> {
     use LangOption::*;
     match self {
-      Specified(lang) => Some((*lang).into()),
+      Specified(lang) => Some(*lang),
       Inferred(pairs) => pairs
         .iter()
         .find_map(|(lang, types)| types.matched(path, false).is_whitelist().then_some(*lang)),
@@ -157,17 +410,18 @@ impl LangOption {
   pub fn infer(language_globs: &LanguageGlobs) -> Self {
     let mut types = vec![];
     let empty = vec![];
-    for lang in SupportLang::all_langs() {
+    for lang in NapiLang::all_langs() {
       let mut builder = TypesBuilder::new();
       let tpe = lang.to_string();
       let file_types = lang.file_types();
       add_types(&mut builder, &file_types);
-      let fe_lang = Lang::from(*lang);
-      for pattern in language_globs.get(&fe_lang).unwrap_or(&empty) {
-        builder.add(&tpe, pattern).expect("should build");
+      if let NapiLang::Builtin(b) = lang {
+        for pattern in language_globs.get(&Lang::from(b)).unwrap_or(&empty) {
+          builder.add(&tpe, pattern).expect("should build");
+        }
       }
       builder.select(&tpe);
-      types.push((*lang, builder.build().unwrap()));
+      types.push((lang, builder.build().unwrap()));
     }
     Self::Inferred(types)
   }
@@ -179,10 +433,12 @@ pub fn build_files(paths: Vec<String>, language_globs: &LanguageGlobs) -> Result
   }
   let mut types = TypesBuilder::new();
   let empty = vec![];
-  for lang in SupportLang::all_langs() {
+  for lang in NapiLang::all_langs() {
     let type_name = lang.to_string();
-    let l = Lang::from(*lang);
-    let custom = language_globs.get(&l).unwrap_or(&empty);
+    let custom = match lang {
+      NapiLang::Builtin(b) => language_globs.get(&Lang::from(b)).unwrap_or(&empty),
+      NapiLang::Custom(_) => &empty,
+    };
     let default_types = lang.file_types();
     select_custom(&mut types, &type_name, &default_types, custom);
   }
@@ -221,7 +477,7 @@ fn select_custom<'b>(
 }
 
 fn find_files_with_lang(
-  lang: &Lang,
+  lang: &NapiLang,
   paths: Vec<String>,
   language_globs: Option<Vec<String>>,
 ) -> Result<WalkParallel> {
@@ -230,10 +486,9 @@ fn find_files_with_lang(
   }
 
   let mut types = TypesBuilder::new();
-  let sg_lang: SupportLang = (*lang).into();
-  let type_name = sg_lang.to_string();
+  let type_name = lang.to_string();
   let custom_file_type = language_globs.unwrap_or_default();
-  let default_types = sg_lang.file_types();
+  let default_types = lang.file_types();
   let types = select_custom(&mut types, &type_name, &default_types, &custom_file_type)
     .build()
     .unwrap();
@@ -269,11 +524,11 @@ mod test {
     let globs = lang_globs();
     let option = LangOption::infer(&globs);
     let lang = option.get_lang(Path::new("test.vue"));
-    assert_eq!(lang, Some(SupportLang::Html));
+    assert_eq!(lang, Some(NapiLang::Builtin(SupportLang::Html)));
     let lang = option.get_lang(Path::new("test.html"));
-    assert_eq!(lang, Some(SupportLang::Html));
+    assert_eq!(lang, Some(NapiLang::Builtin(SupportLang::Html)));
     let lang = option.get_lang(Path::new("test.js"));
-    assert_eq!(lang, Some(SupportLang::JavaScript));
+    assert_eq!(lang, Some(NapiLang::Builtin(SupportLang::JavaScript)));
     let lang = option.get_lang(Path::new("test.xss"));
     assert_eq!(lang, None);
   }
@@ -289,4 +544,37 @@ mod test {
     let lang = Lang::from_str("ocaml");
     assert!(lang.is_err());
   }
+
+  #[test]
+  fn test_napi_lang_from_str() {
+    let lang = NapiLang::from_str("python").expect("should parse");
+    assert_eq!(lang, NapiLang::Builtin(SupportLang::Python));
+    assert_eq!(lang.to_string(), "Python");
+    assert!(NapiLang::from_str("not-registered").is_err());
+  }
+
+  #[test]
+  fn test_node_kinds() {
+    let lang = NapiLang::Builtin(SupportLang::JavaScript);
+    let kinds = lang.node_kinds();
+    let find = |name: &str, named: bool| kinds.iter().find(|k| k.name == name && k.named == named);
+    let identifier = find("identifier", true).expect("should have identifier");
+    assert_eq!(
+      identifier.id,
+      lang.get_ts_language().id_for_node_kind("identifier", true)
+    );
+    assert!(find("(", false).is_some());
+    assert!(kinds.iter().all(|k| !k.name.starts_with('_')));
+    let fields = lang.field_names();
+    assert!(fields.iter().any(|f| f == "body"));
+    assert!(fields.iter().any(|f| f == "name"));
+  }
+
+  #[test]
+  fn test_to_char() {
+    assert_eq!(to_char("expandoChar", None).unwrap(), None);
+    assert_eq!(to_char("expandoChar", Some("µ".into())).unwrap(), Some('µ'));
+    assert!(to_char("expandoChar", Some("ab".into())).is_err());
+    assert!(to_char("expandoChar", Some("".into())).is_err());
+  }
 }
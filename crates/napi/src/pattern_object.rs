@@ -0,0 +1,142 @@
+//! Pattern objects, the object form of `pattern` in rules, accepted wherever a pattern string is.
+//! e.g. `{ context: '{ $K: $V }', selector: 'pair' }` matches a key-value in object literals.
+use ast_grep_core::{MatchStrictness, Pattern};
+use napi::anyhow::anyhow;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde_json::{json, Value};
+
+use std::str::FromStr;
+
+use crate::napi_lang::NapiLang;
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct PatternObject {
+  /// The pattern code. Same as `context`, only one of them can be set.
+  pub pattern: Option<String>,
+  /// The surrounding code that helps to resolve any ambiguity in the syntax.
+  pub context: Option<String>,
+  /// The sub-syntax node kind that is the actual matcher of the pattern.
+  pub selector: Option<String>,
+  /// Strictness of the pattern. More strict pattern matches fewer nodes. Default `smart`.
+  #[napi(ts_type = "'cst' | 'smart' | 'ast' | 'relaxed' | 'signature'")]
+  pub strictness: Option<String>,
+}
+
+/// A pattern string or a `PatternObject`.
+pub type NapiPattern = Either<String, PatternObject>;
+
+impl PatternObject {
+  fn code(&self) -> Result<&str> {
+    match (&self.pattern, &self.context) {
+      (Some(code), None) | (None, Some(code)) => Ok(code),
+      (Some(_), Some(_)) => {
+        Err(anyhow!("pattern object cannot have both `pattern` and `context`").into())
+      }
+      (None, None) => Err(anyhow!("pattern object must have `pattern` or `context`").into()),
+    }
+  }
+
+  fn strictness(&self) -> Result<Option<MatchStrictness>> {
+    let Some(strictness) = &self.strictness else {
+      return Ok(None);
+    };
+    let strictness = MatchStrictness::from_str(strictness).map_err(|e| anyhow!(e))?;
+    Ok(Some(strictness))
+  }
+
+  pub fn build(&self, lang: NapiLang) -> Result<Pattern<NapiLang>> {
+    let code = self.code()?;
+    let pattern = match &self.selector {
+      Some(selector) => Pattern::contextual(code, selector, lang),
+      None => Pattern::try_new(code, lang),
+    }
+    .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+    Ok(match self.strictness()? {
+      Some(strictness) => pattern.with_strictness(strictness),
+      None => pattern,
+    })
+  }
+
+  /// The `pattern` field of a rule.
+  fn to_rule_pattern(&self) -> Result<Value> {
+    let code = self.code()?;
+    // validate strictness before it is used in the rule
+    self.strictness()?;
+    let mut pattern = json!({ "context": code });
+    if let Some(selector) = &self.selector {
+      pattern["selector"] = json!(selector);
+    }
+    if let Some(strictness) = &self.strictness {
+      pattern["strictness"] = json!(strictness);
+    }
+    Ok(pattern)
+  }
+}
+
+/// The `pattern` field of a rule for a pattern string or object.
+pub fn rule_pattern(pattern: NapiPattern) -> Result<Value> {
+  match pattern {
+    Either::A(pattern) => Ok(json!(pattern)),
+    Either::B(pattern) => pattern.to_rule_pattern(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_core::AstGrep;
+  use ast_grep_language::SupportLang;
+
+  fn pattern_object(context: &str, selector: Option<&str>) -> PatternObject {
+    PatternObject {
+      pattern: None,
+      context: Some(context.into()),
+      selector: selector.map(Into::into),
+      strictness: None,
+    }
+  }
+
+  #[test]
+  fn test_contextual_pattern() {
+    let lang = SupportLang::JavaScript.into();
+    let pattern = pattern_object("({ $K: $V })", Some("pair"))
+      .build(lang)
+      .expect("should build");
+    let root = AstGrep::new("const a = { b: 1, c: 2 }", lang);
+    let pairs: Vec<_> = root
+      .root()
+      .find_all(pattern)
+      .map(|n| n.text().to_string())
+      .collect();
+    assert_eq!(pairs, vec!["b: 1", "c: 2"]);
+  }
+
+  #[test]
+  fn test_strictness() {
+    let lang = SupportLang::JavaScript.into();
+    let root = AstGrep::new("foo(/* comment */ a)", lang);
+    let mut pattern = pattern_object("foo(a)", None);
+    assert!(root.root().find(pattern.build(lang).unwrap()).is_none());
+    // relaxed strictness skips comments
+    pattern.strictness = Some("relaxed".into());
+    assert!(root.root().find(pattern.build(lang).unwrap()).is_some());
+    pattern.strictness = Some("loose".into());
+    assert!(pattern.build(lang).is_err());
+  }
+
+  #[test]
+  fn test_invalid_pattern_object() {
+    let lang = SupportLang::JavaScript.into();
+    let mut pattern = pattern_object("a", None);
+    pattern.pattern = Some("b".into());
+    assert!(pattern.build(lang).is_err());
+    pattern.context = None;
+    pattern.pattern = None;
+    assert!(rule_pattern(Either::B(pattern)).is_err());
+    assert!(pattern_object("let a = 1", Some("pair"))
+      .build(lang)
+      .is_err());
+  }
+}
@@ -0,0 +1,231 @@
+//! Heuristics to find rules that are redundant in a rule set.
+//! Fields in a rule object are all required to match, so a rule whose fields and constraints
+//! are a subset of another rule's matches everything the other rule matches.
+use ast_grep_core::{Language, Matcher, Pattern};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde_json::{Map, Value};
+
+use crate::doc::NapiConfig;
+use crate::napi_lang::NapiLang;
+
+#[napi(object)]
+pub struct RedundantRule {
+  /// Id of the rule that matches everything matched by `specific`
+  pub general: String,
+  /// Id of the rule made redundant by `general`
+  pub specific: String,
+  /// `exact`: the two rules are identical.
+  /// `high`: `general` has a subset of the rule fields and constraints of `specific`.
+  /// `medium`: `general` pattern matches `specific` pattern, which is parsed as code.
+  pub confidence: String,
+}
+
+struct RuleInfo {
+  id: String,
+  lang: NapiLang,
+  rule: Map<String, Value>,
+  constraints: Map<String, Value>,
+  utils: Option<Value>,
+  kinds: Option<Vec<usize>>,
+}
+
+impl RuleInfo {
+  fn new(config: NapiConfig, default_lang: NapiLang, default_id: String) -> Result<Self> {
+    let id = config.id.clone().unwrap_or(default_id);
+    let lang = config.language.unwrap_or(default_lang);
+    let rule = to_map(&config.rule);
+    let constraints = config.constraints.as_ref().map(to_map).unwrap_or_default();
+    let utils = config.utils.clone();
+    // validate the rule and collect kinds it can match
+    let core = config.parse_with(lang)?;
+    let kinds = core.potential_kinds().map(|k| k.iter().collect());
+    Ok(Self {
+      id,
+      lang,
+      rule,
+      constraints,
+      utils,
+      kinds,
+    })
+  }
+
+  /// The only field is `kind`, e.g. `{ kind: 'call_expression' }`
+  fn kind_only(&self) -> Option<u16> {
+    if self.rule.len() != 1 || !self.constraints.is_empty() {
+      return None;
+    }
+    let kind = self.rule.get("kind")?.as_str()?;
+    let id = self.lang.get_ts_language().id_for_node_kind(kind, true);
+    (id != 0).then_some(id)
+  }
+
+  fn pattern(&self) -> Option<&str> {
+    self.rule.get("pattern")?.as_str()
+  }
+}
+
+fn to_map(value: &Value) -> Map<String, Value> {
+  value.as_object().cloned().unwrap_or_default()
+}
+
+fn is_sub_map(sub: &Map<String, Value>, sup: &Map<String, Value>) -> bool {
+  sub.iter().all(|(k, v)| sup.get(k) == Some(v))
+}
+
+fn without_pattern(map: &Map<String, Value>) -> Map<String, Value> {
+  let mut map = map.clone();
+  map.remove("pattern");
+  map
+}
+
+/// Check if pattern `general` matches the whole `specific` pattern as code.
+/// Meta variables in `specific` are parsed as identifiers so only meta variables in `general` match them.
+fn pattern_subsumes(lang: NapiLang, general: &str, specific: &str) -> bool {
+  let Ok(pattern) = Pattern::try_new(general, lang) else {
+    return false;
+  };
+  let specific = specific.trim();
+  let root = lang.ast_grep(specific);
+  let mut nodes = root.root().dfs();
+  nodes.any(|n| n.text() == specific && pattern.match_node(n).is_some())
+}
+
+fn get_confidence(general: &RuleInfo, specific: &RuleInfo) -> Option<&'static str> {
+  if general.lang != specific.lang || general.utils != specific.utils {
+    return None;
+  }
+  if let (Some(kind), Some(kinds)) = (general.kind_only(), &specific.kinds) {
+    if kinds == &[kind as usize] && general.rule != specific.rule {
+      return Some("high");
+    }
+  }
+  if !is_sub_map(&general.constraints, &specific.constraints) {
+    return None;
+  }
+  if is_sub_map(&general.rule, &specific.rule) {
+    let exact = general.rule == specific.rule && general.constraints == specific.constraints;
+    return Some(if exact { "exact" } else { "high" });
+  }
+  let (Some(p1), Some(p2)) = (general.pattern(), specific.pattern()) else {
+    return None;
+  };
+  let rest_is_subset = is_sub_map(
+    &without_pattern(&general.rule),
+    &without_pattern(&specific.rule),
+  );
+  if rest_is_subset && pattern_subsumes(general.lang, p1, p2) {
+    Some("medium")
+  } else {
+    None
+  }
+}
+
+pub fn find_redundant_rules_impl(
+  lang: NapiLang,
+  rules: Vec<NapiConfig>,
+) -> Result<Vec<RedundantRule>> {
+  let infos = rules
+    .into_iter()
+    .enumerate()
+    .map(|(i, r)| RuleInfo::new(r, lang, i.to_string()))
+    .collect::<Result<Vec<_>>>()?;
+  let mut ret = vec![];
+  for (i, a) in infos.iter().enumerate() {
+    for b in &infos[i + 1..] {
+      let pair = match get_confidence(a, b) {
+        Some(confidence) => Some((a, b, confidence)),
+        None => get_confidence(b, a).map(|c| (b, a, c)),
+      };
+      if let Some((general, specific, confidence)) = pair {
+        ret.push(RedundantRule {
+          general: general.id.clone(),
+          specific: specific.id.clone(),
+          confidence: confidence.to_string(),
+        });
+      }
+    }
+  }
+  Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::napi_lang::Lang;
+  use serde_json::json;
+
+  fn config(id: &str, rule: Value, constraints: Option<Value>) -> NapiConfig {
+    NapiConfig {
+      id: Some(id.into()),
+      constraints,
+      ..NapiConfig::from_rule(rule)
+    }
+  }
+
+  fn find(rules: Vec<NapiConfig>) -> Vec<(String, String, String)> {
+    let found = find_redundant_rules_impl(Lang::TypeScript.into(), rules).expect("should work");
+    found
+      .into_iter()
+      .map(|r| (r.general, r.specific, r.confidence))
+      .collect()
+  }
+
+  fn triple(general: &str, specific: &str, confidence: &str) -> (String, String, String) {
+    (general.into(), specific.into(), confidence.into())
+  }
+
+  #[test]
+  fn test_exact_duplicate() {
+    let found = find(vec![
+      config("a", json!({"pattern": "console.log($A)"}), None),
+      config("b", json!({"pattern": "console.log($A)"}), None),
+    ]);
+    assert_eq!(found, vec![triple("a", "b", "exact")]);
+  }
+
+  #[test]
+  fn test_subset_of_fields() {
+    let found = find(vec![
+      config(
+        "inside",
+        json!({"pattern": "foo()", "inside": {"kind": "class_body"}}),
+        None,
+      ),
+      config("any", json!({"pattern": "foo()"}), None),
+      config("call", json!({"kind": "call_expression"}), None),
+    ]);
+    assert_eq!(
+      found,
+      vec![
+        triple("any", "inside", "high"),
+        triple("call", "inside", "high"),
+        triple("call", "any", "high"),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_general_pattern() {
+    let found = find(vec![
+      config("log", json!({"pattern": "console.log(123)"}), None),
+      config("any-log", json!({"pattern": "console.log($A)"}), None),
+      config("error", json!({"pattern": "console.error($A)"}), None),
+    ]);
+    assert_eq!(found, vec![triple("any-log", "log", "medium")]);
+  }
+
+  #[test]
+  fn test_constraints() {
+    let constraint = json!({"A": {"regex": "^a"}});
+    let found = find(vec![
+      config(
+        "constrained",
+        json!({"pattern": "foo($A)"}),
+        Some(constraint),
+      ),
+      config("free", json!({"pattern": "foo($A)"}), None),
+    ]);
+    assert_eq!(found, vec![triple("free", "constrained", "high")]);
+  }
+}
@@ -0,0 +1,190 @@
+//! Cache validated rules on disk so tools running ast-grep frequently can skip
+//! reading and validating rule files on every start.
+//! Compiled matchers hold tree-sitter and regex state that cannot be serialized,
+//! so the cache stores rule configs that are known to compile, with their languages resolved,
+//! and loading compiles them straight into matchers.
+use napi::anyhow::anyhow;
+use napi::bindgen_prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::ErrorKind;
+
+use crate::compiled_rule::{compile_rule_impl, CompiledRule};
+use crate::doc::NapiConfig;
+use crate::napi_lang::NapiLang;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct CachedRule {
+  id: Option<String>,
+  rule: Value,
+  constraints: Option<Value>,
+  language: Option<NapiLang>,
+  transform: Option<Value>,
+  utils: Option<Value>,
+  ignore_kinds: Option<Vec<String>>,
+  fix: Option<Value>,
+  rewriters: Option<Value>,
+}
+
+impl From<NapiConfig> for CachedRule {
+  fn from(config: NapiConfig) -> Self {
+    Self {
+      id: config.id,
+      rule: config.rule,
+      constraints: config.constraints,
+      language: config.language,
+      transform: config.transform,
+      utils: config.utils,
+      ignore_kinds: config.ignore_kinds,
+      fix: config.fix,
+      rewriters: config.rewriters,
+    }
+  }
+}
+
+impl From<CachedRule> for NapiConfig {
+  fn from(rule: CachedRule) -> Self {
+    Self {
+      id: rule.id,
+      rule: rule.rule,
+      constraints: rule.constraints,
+      language: rule.language,
+      transform: rule.transform,
+      utils: rule.utils,
+      ignore_kinds: rule.ignore_kinds,
+      fix: rule.fix,
+      rewriters: rule.rewriters,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RuleCache {
+  /// cache written by another ast-grep version is stale
+  version: String,
+  /// hash of the source rule config
+  hash: String,
+  /// default language of rules without `language`
+  lang: NapiLang,
+  /// rules with `language` resolved
+  rules: Vec<CachedRule>,
+}
+
+/// A different hash, e.g. from another Rust release, only causes a cache miss.
+fn hash_source(source: &str) -> String {
+  let mut hasher = DefaultHasher::new();
+  source.hash(&mut hasher);
+  format!("{:016x}", hasher.finish())
+}
+
+pub fn save_rule_cache_impl(
+  cache_path: String,
+  source: String,
+  lang: NapiLang,
+  rules: Vec<NapiConfig>,
+) -> Result<()> {
+  let mut cached = Vec::with_capacity(rules.len());
+  for mut rule in rules {
+    let rule_lang = *rule.language.get_or_insert(lang);
+    // only cache rules that compile so loading them never fails
+    rule.clone().parse_with(rule_lang)?;
+    cached.push(CachedRule::from(rule));
+  }
+  let cache = RuleCache {
+    version: env!("CARGO_PKG_VERSION").to_string(),
+    hash: hash_source(&source),
+    lang,
+    rules: cached,
+  };
+  let content = serde_json::to_string(&cache)?;
+  fs::write(&cache_path, content)
+    .map_err(|e| anyhow!("cannot write rule cache `{cache_path}`: {e}"))?;
+  Ok(())
+}
+
+/// Returns None if the cache is missing, corrupted or stale.
+pub fn load_rule_cache_impl(
+  cache_path: String,
+  source: String,
+  lang: NapiLang,
+) -> Result<Option<Vec<CompiledRule>>> {
+  let content = match fs::read_to_string(&cache_path) {
+    Ok(content) => content,
+    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+    Err(e) => return Err(anyhow!("cannot read rule cache `{cache_path}`: {e}").into()),
+  };
+  let Ok(cache) = serde_json::from_str::<RuleCache>(&content) else {
+    return Ok(None);
+  };
+  if cache.version != env!("CARGO_PKG_VERSION")
+    || cache.hash != hash_source(&source)
+    || cache.lang != lang
+  {
+    return Ok(None);
+  }
+  let rules = cache
+    .rules
+    .into_iter()
+    .map(|rule| compile_rule_impl(lang, rule.into()))
+    .collect::<Result<_>>()?;
+  Ok(Some(rules))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_language::SupportLang;
+  use serde_json::json;
+  use tempfile::TempDir;
+
+  const TS: NapiLang = NapiLang::Builtin(SupportLang::TypeScript);
+  const JS: NapiLang = NapiLang::Builtin(SupportLang::JavaScript);
+
+  fn config(id: &str, pattern: &str) -> NapiConfig {
+    NapiConfig {
+      id: Some(id.into()),
+      ..NapiConfig::from_rule(json!({ "pattern": pattern }))
+    }
+  }
+
+  fn cache_path(dir: &TempDir) -> String {
+    dir.path().join("rules.json").to_string_lossy().to_string()
+  }
+
+  #[test]
+  fn test_save_and_load() {
+    let dir = TempDir::new().expect("should create dir");
+    let path = cache_path(&dir);
+    let source = "id: a\nrule: {pattern: foo()}";
+    let loaded = load_rule_cache_impl(path.clone(), source.into(), TS).expect("should load");
+    assert!(loaded.is_none(), "missing cache should not hit");
+    let mut js_rule = config("b", "bar()");
+    js_rule.language = Some(JS);
+    let rules = vec![config("a", "foo()"), js_rule];
+    save_rule_cache_impl(path.clone(), source.into(), TS, rules).expect("should save");
+    let loaded = load_rule_cache_impl(path.clone(), source.into(), TS).expect("should load");
+    let loaded = loaded.expect("should hit cache");
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded[0].lang(), TS);
+    assert_eq!(loaded[1].lang(), JS);
+    let stale = load_rule_cache_impl(path.clone(), "id: b".into(), TS).expect("should load");
+    assert!(stale.is_none(), "changed source should not hit");
+    let stale = load_rule_cache_impl(path, source.into(), JS).expect("should load");
+    assert!(stale.is_none(), "changed default language should not hit");
+  }
+
+  #[test]
+  fn test_invalid_rule_not_cached() {
+    let dir = TempDir::new().expect("should create dir");
+    let path = cache_path(&dir);
+    let rules = vec![config("a", "foo()"), config("b", "")];
+    let ret = save_rule_cache_impl(path.clone(), "".into(), TS, rules);
+    assert!(ret.is_err());
+    assert!(!std::path::Path::new(&path).exists());
+  }
+}
@@ -0,0 +1,60 @@
+//! Locate where rules are defined so scan results, keyed by rule id, can be traced back to rule files.
+use ast_grep_config::from_yaml_string_with_source;
+use ast_grep_language::SupportLang;
+use napi::anyhow::anyhow;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[napi(object)]
+pub struct RuleLocation {
+  /// Path of the rule file, as passed to `ruleSources`
+  pub file: String,
+  /// 1-based line of the rule `id`
+  pub line: u32,
+}
+
+pub fn rule_sources_impl(paths: Vec<String>) -> Result<HashMap<String, RuleLocation>> {
+  let mut ret = HashMap::new();
+  for path in paths {
+    let yaml =
+      fs::read_to_string(&path).map_err(|e| anyhow!("cannot read rule file `{path}`: {e}"))?;
+    let rules =
+      from_yaml_string_with_source::<SupportLang>(&yaml, &Default::default(), Path::new(&path))
+        .map_err(|e| anyhow!("cannot parse rule file `{path}`: {e}"))?;
+    for rule in rules {
+      let Some(source) = rule.source() else {
+        continue;
+      };
+      let location = RuleLocation {
+        file: path.clone(),
+        line: source.line as u32,
+      };
+      ret.insert(rule.id.clone(), location);
+    }
+  }
+  Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use tempfile::TempDir;
+
+  #[test]
+  fn test_rule_sources() {
+    let dir = TempDir::new().expect("should create dir");
+    let path = dir.path().join("rules.yml");
+    let yaml = "id: a\nlanguage: TypeScript\nrule: {pattern: a}\n---\nid: b\nlanguage: TypeScript\nrule: {pattern: b}\n";
+    fs::write(&path, yaml).expect("should write");
+    let file = path.to_string_lossy().to_string();
+    let sources = rule_sources_impl(vec![file.clone()]).expect("should locate");
+    assert_eq!(sources["a"].line, 1);
+    assert_eq!(sources["b"].line, 5);
+    assert_eq!(sources["b"].file, file);
+    assert!(rule_sources_impl(vec!["not-exist.yml".into()]).is_err());
+  }
+}
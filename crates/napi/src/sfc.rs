@@ -0,0 +1,97 @@
+//! Single-file components of Vue and Svelte. The file is parsed as HTML for `<template>`,
+//! and `<script>`, `<script setup>` and `<style>` blocks are parsed with their own grammar.
+//! Blocks are parsed with tree-sitter included ranges of the whole file,
+//! so positions of their nodes are relative to the `.vue`/`.svelte` file.
+use ast_grep_core::AstGrep;
+use ast_grep_language::SupportLang;
+
+use std::path::Path;
+
+use crate::doc::JsDoc;
+use crate::napi_lang::NapiLang;
+
+pub fn is_sfc(path: &Path) -> bool {
+  path
+    .extension()
+    .map_or(false, |ext| ext == "vue" || ext == "svelte")
+}
+
+/// Language of a block by its `lang` attribute. Blocks in unsupported languages, e.g. scss, are skipped.
+fn block_lang(lang: &str) -> Option<NapiLang> {
+  let lang = match lang {
+    "js" | "javascript" | "jsx" => SupportLang::JavaScript,
+    "ts" | "typescript" => SupportLang::TypeScript,
+    "tsx" => SupportLang::Tsx,
+    "css" => SupportLang::Css,
+    _ => return None,
+  };
+  Some(lang.into())
+}
+
+/// Parse the component into the HTML root followed by one root per block language.
+/// Blocks of the same language, e.g. `<script>` and `<script setup>`, share one root.
+pub fn sfc_roots(src: String) -> Vec<AstGrep<JsDoc>> {
+  let html = AstGrep::doc(JsDoc::new(src, SupportLang::Html.into()));
+  let mut blocks: Vec<_> = html
+    .inner
+    .get_injections(block_lang)
+    .into_iter()
+    .map(|inner| AstGrep { inner })
+    .collect();
+  // injections are grouped in a HashMap, sort for deterministic order
+  blocks.sort_by_key(|b| b.root().range().start);
+  let mut roots = vec![html];
+  roots.extend(blocks);
+  roots
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::sg_node::to_pos;
+
+  const VUE: &str = r#"<template>
+  <div>{{ msg }}</div>
+</template>
+<script setup lang="ts">
+const msg: string = 'hello'
+</script>
+<style>
+div { color: red; }
+</style>
+"#;
+
+  #[test]
+  fn test_is_sfc() {
+    assert!(is_sfc(Path::new("src/App.vue")));
+    assert!(is_sfc(Path::new("Button.svelte")));
+    assert!(!is_sfc(Path::new("index.html")));
+  }
+
+  #[test]
+  fn test_sfc_roots() {
+    let roots = sfc_roots(VUE.into());
+    let langs: Vec<_> = roots.iter().map(|r| r.lang().to_string()).collect();
+    assert_eq!(langs, vec!["Html", "TypeScript", "Css"]);
+    let node = roots[1]
+      .root()
+      .find("const $A: string = $B")
+      .expect("should match");
+    // positions are relative to the component file
+    let pos = to_pos(node.start_pos(), node.range().start);
+    assert_eq!((pos.line, pos.column, pos.index), (4, 0, 71));
+    assert_eq!(node.text(), "const msg: string = 'hello'");
+    let node = roots[2].root().find("color: $C").expect("should match");
+    let pos = to_pos(node.start_pos(), node.range().start);
+    assert_eq!((pos.line, pos.column, pos.index), (7, 6, 123));
+    assert!(roots[0].root().find("<div>$$$</div>").is_some());
+  }
+
+  #[test]
+  fn test_unsupported_block() {
+    let src = "<script>let a = 1</script><style lang=\"scss\">a { b: c }</style>";
+    let roots = sfc_roots(src.into());
+    assert_eq!(roots.len(), 2);
+    assert_eq!(*roots[1].lang(), SupportLang::JavaScript.into());
+  }
+}
@@ -1,12 +1,25 @@
-use ast_grep_core::{matcher::KindMatcher, AstGrep, NodeMatch, Pattern};
+use ast_grep_config::{CombinedScan, RuleCore};
+use ast_grep_core::{matcher::KindMatcher, AstGrep, Matcher, NodeMatch, Pattern};
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use super::NapiConfig;
+use crate::compiled_rule::{compile_matcher, RuleMatcher};
+use crate::definition::find_definition;
 use crate::doc::{JsDoc, Wrapper};
-use ast_grep_core::source::Content;
+use crate::dump_ast::{dump_ast, DumpNode, DumpOptions};
+use crate::napi_lang::NapiLang;
+use crate::pattern_object::{NapiPattern, PatternObject};
+use crate::shared_root::share_root;
+use crate::source_map::{OriginalRange, SourceMap};
+use crate::ts_query::{query_ts_impl, QueryCapture};
+use ast_grep_core::source::{Content, Edit as CoreEdit};
 
 #[napi(object)]
+#[derive(Clone)]
 pub struct Edit {
   /// The start position of the edit
   pub start_pos: u32,
@@ -26,7 +39,7 @@ pub struct Pos {
   pub index: u32,
 }
 
-fn to_pos(pos: (usize, usize), offset: usize) -> Pos {
+pub(crate) fn to_pos(pos: (usize, usize), offset: usize) -> Pos {
   Pos {
     line: pos.0 as u32,
     column: pos.1 as u32 / 2,
@@ -45,6 +58,22 @@ pub struct Range {
 #[napi]
 pub struct SgNode {
   pub(super) inner: SharedReference<SgRoot, NodeMatch<'static, JsDoc>>,
+  /// fix of the rule with `fix` that found the node
+  pub(super) fix: Option<Edit>,
+  /// the tree of the node, kept alive if `SgRoot.applyEdit` replaces it
+  pub(super) tree: Arc<AstGrep<JsDoc>>,
+}
+
+/// The edit of the rule's fixer on the match, e.g. for `SgNode.fix`.
+pub(crate) fn fix_edit(node_match: &NodeMatch<JsDoc>, rule: &RuleCore<NapiLang>) -> Option<Edit> {
+  let fixer = rule.fixer.as_ref()?;
+  let edit = node_match.make_edit(rule, fixer);
+  // the text is u16, need to convert to JS str length
+  Some(Edit {
+    start_pos: (edit.position / 2) as u32,
+    end_pos: ((edit.position + edit.deleted_length) / 2) as u32,
+    inserted_text: Wrapper::encode_bytes(&edit.inserted_text).to_string(),
+  })
 }
 
 #[napi]
@@ -60,6 +89,13 @@ impl SgNode {
     }
   }
 
+  /// Range of the node in the original source of transpiled code, e.g. `.tsx` of compiled `.js`.
+  /// Returns null if the node is not mapped by the source map.
+  #[napi]
+  pub fn original_range(&self, source_map: &SourceMap) -> Option<OriginalRange> {
+    source_map.original_range(self.range())
+  }
+
   #[napi]
   pub fn is_leaf(&self) -> bool {
     self.inner.is_leaf()
@@ -86,28 +122,43 @@ impl SgNode {
 #[napi]
 impl SgNode {
   #[napi]
-  pub fn matches(&self, m: String) -> bool {
-    self.inner.matches(&*m)
+  pub fn matches(&self, m: NapiPattern) -> Result<bool> {
+    match m {
+      Either::A(m) => Ok(self.inner.matches(&*m)),
+      Either::B(m) => Ok(self.inner.matches(m.build(*self.inner.lang())?)),
+    }
   }
 
   #[napi]
-  pub fn inside(&self, m: String) -> bool {
-    self.inner.inside(&*m)
+  pub fn inside(&self, m: NapiPattern) -> Result<bool> {
+    match m {
+      Either::A(m) => Ok(self.inner.inside(&*m)),
+      Either::B(m) => Ok(self.inner.inside(m.build(*self.inner.lang())?)),
+    }
   }
 
   #[napi]
-  pub fn has(&self, m: String) -> bool {
-    self.inner.has(&*m)
+  pub fn has(&self, m: NapiPattern) -> Result<bool> {
+    match m {
+      Either::A(m) => Ok(self.inner.has(&*m)),
+      Either::B(m) => Ok(self.inner.has(m.build(*self.inner.lang())?)),
+    }
   }
 
   #[napi]
-  pub fn precedes(&self, m: String) -> bool {
-    self.inner.precedes(&*m)
+  pub fn precedes(&self, m: NapiPattern) -> Result<bool> {
+    match m {
+      Either::A(m) => Ok(self.inner.precedes(&*m)),
+      Either::B(m) => Ok(self.inner.precedes(m.build(*self.inner.lang())?)),
+    }
   }
 
   #[napi]
-  pub fn follows(&self, m: String) -> bool {
-    self.inner.follows(&*m)
+  pub fn follows(&self, m: NapiPattern) -> Result<bool> {
+    match m {
+      Either::A(m) => Ok(self.inner.follows(&*m)),
+      Either::B(m) => Ok(self.inner.follows(m.build(*self.inner.lang())?)),
+    }
   }
 
   #[napi]
@@ -167,24 +218,31 @@ impl SgNode {
     &self,
     reference: Reference<SgNode>,
     env: Env,
-    matcher: Either3<String, u16, NapiConfig>,
+    matcher: Either4<String, u16, RuleMatcher, PatternObject>,
   ) -> Result<Option<SgNode>> {
     let lang = *reference.inner.lang();
-    let node_match = match matcher {
-      Either3::A(pattern) => {
+    let (node_match, fix) = match matcher {
+      Either4::A(pattern) => {
         let pattern = Pattern::new(&pattern, lang);
-        reference.inner.find(pattern)
+        (reference.inner.find(pattern), None)
       }
-      Either3::B(kind) => {
+      Either4::B(kind) => {
         let pattern = KindMatcher::from_id(kind);
-        reference.inner.find(pattern)
+        (reference.inner.find(pattern), None)
       }
-      Either3::C(config) => {
-        let pattern = config.parse_with(lang.into())?;
-        reference.inner.find(pattern)
+      Either4::C(matcher) => {
+        let rule = compile_matcher(matcher, lang)?;
+        let node_match = reference.inner.find(&*rule);
+        let fix = node_match.as_ref().and_then(|n| fix_edit(n, &rule));
+        (node_match, fix)
       }
+      Either4::D(pattern) => (reference.inner.find(pattern.build(lang)?), None),
     };
-    Self::transpose_option(reference, env, node_match)
+    let mut found = Self::transpose_option(reference, env, node_match)?;
+    if let Some(node) = &mut found {
+      node.fix = fix;
+    }
+    Ok(found)
   }
 
   fn transpose_option(
@@ -195,7 +253,12 @@ impl SgNode {
     if let Some(node) = node {
       let root_ref = reference.inner.clone_owner(env)?;
       let inner = root_ref.share_with(env, move |_| Ok(node))?;
-      Ok(Some(SgNode { inner }))
+      let tree = reference.tree.clone();
+      Ok(Some(SgNode {
+        inner,
+        fix: None,
+        tree,
+      }))
     } else {
       Ok(None)
     }
@@ -206,28 +269,52 @@ impl SgNode {
     &self,
     reference: Reference<SgNode>,
     env: Env,
-    matcher: Either3<String, u16, NapiConfig>,
+    matcher: Either4<String, u16, RuleMatcher, PatternObject>,
   ) -> Result<Vec<SgNode>> {
     let mut ret = vec![];
     let lang = *reference.inner.lang();
     let all_matches: Vec<_> = match matcher {
-      Either3::A(pattern) => {
+      Either4::A(pattern) => {
         let pattern = Pattern::new(&pattern, lang);
-        reference.inner.find_all(pattern).collect()
+        reference
+          .inner
+          .find_all(pattern)
+          .map(|n| (n, None))
+          .collect()
       }
-      Either3::B(kind) => {
+      Either4::B(kind) => {
         let pattern = KindMatcher::from_id(kind);
-        reference.inner.find_all(pattern).collect()
+        reference
+          .inner
+          .find_all(pattern)
+          .map(|n| (n, None))
+          .collect()
+      }
+      Either4::D(pattern) => {
+        let pattern = pattern.build(lang)?;
+        reference
+          .inner
+          .find_all(pattern)
+          .map(|n| (n, None))
+          .collect()
       }
-      Either3::C(config) => {
-        let pattern = config.parse_with(lang.into())?;
-        reference.inner.find_all(pattern).collect()
+      Either4::C(matcher) => {
+        let rule = compile_matcher(matcher, lang)?;
+        let found = reference.inner.find_all(&*rule);
+        found
+          .map(|n| {
+            let fix = fix_edit(&n, &rule);
+            (n, fix)
+          })
+          .collect()
       }
     };
-    for node_match in all_matches {
+    for (node_match, fix) in all_matches {
       let root_ref = reference.inner.clone_owner(env)?;
       let sg_node = SgNode {
         inner: root_ref.share_with(env, move |_| Ok(node_match))?,
+        fix,
+        tree: reference.tree.clone(),
       };
       ret.push(sg_node);
     }
@@ -244,6 +331,8 @@ impl SgNode {
       let root_ref = reference.inner.clone_owner(env)?;
       let sg_node = SgNode {
         inner: root_ref.share_with(env, move |_| Ok(node))?,
+        fix: None,
+        tree: reference.tree.clone(),
       };
       ret.push(sg_node);
     }
@@ -303,14 +392,28 @@ impl SgNode {
     let inner = reference.inner.prev_all().map(NodeMatch::from);
     Self::from_iter_to_vec(&reference, env, inner)
   }
+
+  /// Finds the declaration of the identifier in the same file, e.g. a variable, function or import.
+  /// This is best-effort and scope-unaware: the nearest preceding declaration in enclosing nodes is returned.
+  #[napi]
+  pub fn find_definition(&self, reference: Reference<SgNode>, env: Env) -> Result<Option<SgNode>> {
+    let inner = find_definition(&reference.inner).map(NodeMatch::from);
+    Self::transpose_option(reference, env, inner)
+  }
 }
 
 /// Edit API
 #[napi]
 impl SgNode {
+  /// Create an edit replacing the node.
+  /// If `includeTrivia` is true, leading comments and a trailing comment on the same line are also replaced.
   #[napi]
-  pub fn replace(&self, text: String) -> Edit {
-    let byte_range = self.inner.range();
+  pub fn replace(&self, text: String, include_trivia: Option<bool>) -> Edit {
+    let byte_range = if include_trivia.unwrap_or(false) {
+      self.inner.range_with_trivia()
+    } else {
+      self.inner.range()
+    };
     // the text is u16, need to convert to JS str length
     Edit {
       start_pos: (byte_range.start / 2) as u32,
@@ -319,34 +422,105 @@ impl SgNode {
     }
   }
 
+  /// The edit of the `fix` in the rule that found the node, e.g. by `findAll` or `SgRoot.scan`.
+  /// Returns null if the node is not found by a rule with `fix`.
+  #[napi]
+  pub fn fix(&self) -> Option<Edit> {
+    self.fix.clone()
+  }
+
+  /// The text replacing the node by the rule's `fix`, i.e. `fix()?.insertedText`.
+  #[napi]
+  pub fn get_fixed_text(&self) -> Option<String> {
+    self.fix.as_ref().map(|edit| edit.inserted_text.clone())
+  }
+
+  /// Apply edits to the node's text. Edits overlapping with a preceding one are skipped.
+  /// If `maxEdits` is set and more edits are passed, an error naming the file is thrown
+  /// instead of applying any edit, so the file can be reviewed manually.
   #[napi]
-  pub fn commit_edits(&self, mut edits: Vec<Edit>) -> String {
-    edits.sort_by_key(|edit| edit.start_pos);
-    let mut new_content = Vec::new();
+  pub fn commit_edits(
+    &self,
+    reference: Reference<SgNode>,
+    env: Env,
+    edits: Vec<Edit>,
+    max_edits: Option<u32>,
+  ) -> Result<String> {
+    let root = reference.inner.clone_owner(env)?;
+    check_max_edits(edits.len(), max_edits, &root.1)?;
     let text = self.text();
-    let old_content = Wrapper::decode_str(&text);
     let offset = self.inner.range().start / 2;
-    let mut start = 0;
-    for diff in edits {
-      let pos = diff.start_pos as usize - offset;
-      // skip overlapping edits
-      if start > pos {
-        continue;
-      }
-      new_content.extend(&old_content[start..pos]);
-      let bytes = Wrapper::decode_str(&diff.inserted_text);
-      new_content.extend(&*bytes);
-      start = diff.end_pos as usize - offset;
-    }
-    // add trailing statements
-    new_content.extend(&old_content[start..]);
-    Wrapper::encode_bytes(&new_content).to_string()
+    Ok(splice_edits(&text, offset, sort_edits(edits)))
   }
 }
 
+fn check_max_edits(count: usize, max_edits: Option<u32>, filename: &str) -> Result<()> {
+  let Some(max_edits) = max_edits else {
+    return Ok(());
+  };
+  if count > max_edits as usize {
+    return Err(Error::new(
+      Status::GenericFailure,
+      format!(
+        "{count} edits to `{filename}` exceed maxEdits {max_edits}. Please review the file manually."
+      ),
+    ));
+  }
+  Ok(())
+}
+
+/// Sort edits by position and skip edits overlapping with a preceding one.
+fn sort_edits(mut edits: Vec<Edit>) -> Vec<Edit> {
+  edits.sort_by_key(|edit| edit.start_pos);
+  let mut end = 0;
+  edits.retain(|edit| {
+    let keep = edit.start_pos >= end;
+    if keep {
+      end = edit.end_pos;
+    }
+    keep
+  });
+  edits
+}
+
+/// Apply sorted edits to the text starting at `offset`, in JS string positions.
+fn splice_edits(text: &str, offset: usize, edits: Vec<Edit>) -> String {
+  let mut new_content = Vec::new();
+  let old_content = Wrapper::decode_str(text);
+  let mut start = 0;
+  for diff in edits {
+    let pos = diff.start_pos as usize - offset;
+    new_content.extend(&old_content[start..pos]);
+    let bytes = Wrapper::decode_str(&diff.inserted_text);
+    new_content.extend(&*bytes);
+    start = diff.end_pos as usize - offset;
+  }
+  // add trailing statements
+  new_content.extend(&old_content[start..]);
+  Wrapper::encode_bytes(&new_content).to_string()
+}
+
 /// Represents the parsed tree of code.
 #[napi]
-pub struct SgRoot(pub(super) AstGrep<JsDoc>, pub(super) String);
+// nodes share the tree so it outlives the root's tree replaced by `applyEdit`
+pub struct SgRoot(pub(super) Arc<AstGrep<JsDoc>>, pub(super) String);
+
+impl SgRoot {
+  pub(crate) fn new(root: AstGrep<JsDoc>, filename: String) -> Self {
+    Self(Arc::new(root), filename)
+  }
+}
+
+/// An edit of the source in byte offsets, used by `SgRoot.applyEdit`.
+#[napi(object)]
+pub struct ByteEdit {
+  /// Byte offset where the replaced text starts
+  pub start_byte: u32,
+  /// Byte offset where the replaced text ends
+  pub end_byte: u32,
+  /// The new text
+  pub text: String,
+}
 
 #[napi]
 impl SgRoot {
@@ -354,7 +528,11 @@ impl SgRoot {
   #[napi]
   pub fn root(&self, root_ref: Reference<SgRoot>, env: Env) -> Result<SgNode> {
     let inner = root_ref.share_with(env, |root| Ok(root.0.root().into()))?;
-    Ok(SgNode { inner })
+    Ok(SgNode {
+      inner,
+      fix: None,
+      tree: self.0.clone(),
+    })
   }
   /// Returns the path of the file if it is discovered by ast-grep's `findInFiles`.
   /// Returns `"anonymous"` if the instance is created by `lang.parse(source)`.
@@ -362,4 +540,139 @@ impl SgRoot {
   pub fn filename(&self) -> Result<String> {
     Ok(self.1.clone())
   }
+
+  /// Share the tree with worker threads, e.g. by `postMessage`, and return its handle.
+  /// Workers call `openSharedRoot(handle)` to get the tree without parsing the file again.
+  /// Call `releaseSharedRoot(handle)` when no worker needs it anymore.
+  #[napi]
+  pub fn share(&self) -> u32 {
+    share_root(self)
+  }
+
+  /// Run a tree-sitter query, e.g. from a `.scm` file, and return the captured nodes.
+  /// Predicates like `#eq?` and `#match?` are supported.
+  #[napi(js_name = "queryTS")]
+  pub fn query_ts(&self, query: String) -> Result<Vec<QueryCapture>> {
+    query_ts_impl(&self.0, &query)
+  }
+
+  /// Dump the tree as a JSON-compatible object of kinds, fields, ranges and text.
+  /// Only named nodes are dumped by default, like the nodes `kind` rules can match.
+  #[napi(js_name = "dumpAST")]
+  pub fn dump_ast(&self, options: Option<DumpOptions>) -> DumpNode {
+    dump_ast(self.0.root(), options)
+  }
+
+  /// Apply edits from `replace` of any nodes in the tree and return the new source.
+  /// Edits overlapping with a preceding one are skipped. `maxEdits` works as in `SgNode.commitEdits`.
+  #[napi]
+  pub fn commit_edits(&self, edits: Vec<Edit>, max_edits: Option<u32>) -> Result<String> {
+    check_max_edits(edits.len(), max_edits, &self.1)?;
+    let text = self.0.root().text();
+    Ok(splice_edits(&text, 0, sort_edits(edits)))
+  }
+
+  /// Apply edits like `commitEdits` and parse the new source incrementally from this tree.
+  /// A new root is returned so nodes of this root stay valid. `maxEdits` works as in `commitEdits`.
+  #[napi]
+  pub fn commit_edits_and_parse(&self, edits: Vec<Edit>, max_edits: Option<u32>) -> Result<SgRoot> {
+    check_max_edits(edits.len(), max_edits, &self.1)?;
+    let mut root = (*self.0).clone();
+    // later edits first so positions of earlier edits are not shifted
+    for edit in sort_edits(edits).into_iter().rev() {
+      let start = edit.start_pos as usize * 2;
+      let edit = CoreEdit {
+        position: start,
+        deleted_length: edit.end_pos as usize * 2 - start,
+        inserted_text: edit.inserted_text.encode_utf16().collect(),
+      };
+      root
+        .edit(edit)
+        .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+    }
+    Ok(SgRoot::new(root, self.1.clone()))
+  }
+
+  /// Apply an edit to the source and parse it incrementally from the current tree,
+  /// e.g. to keep the tree of a file open in an editor in sync.
+  /// Offsets are in bytes of the UTF-16 source that tree-sitter parses, i.e. twice `Pos.index`.
+  /// Nodes got before the edit still see the old source, whose tree is copied for the edit.
+  /// Otherwise the tree is edited in place.
+  #[napi]
+  pub fn apply_edit(&mut self, edit: ByteEdit) -> Result<()> {
+    let ByteEdit {
+      start_byte,
+      end_byte,
+      text,
+    } = edit;
+    let (start, end) = (start_byte as usize, end_byte as usize);
+    let len = self.0.root().range().end;
+    if start > end || end > len || start % 2 != 0 || end % 2 != 0 {
+      let msg = format!("invalid edit range {start}..{end} in source of {len} bytes");
+      return Err(Error::new(Status::InvalidArg, msg));
+    }
+    let edit = CoreEdit {
+      position: start,
+      deleted_length: end - start,
+      inserted_text: text.encode_utf16().collect(),
+    };
+    // the old tree is dropped with the last node pointing to it
+    Arc::make_mut(&mut self.0)
+      .edit(edit)
+      .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+    Ok(())
+  }
+
+  /// Scan the tree with multiple rules in a single traversal.
+  /// Returns matched nodes grouped by rule id.
+  /// Matches of rules sharing an id are merged under that id.
+  /// A rule without `id` is keyed by its index in `rules`.
+  #[napi]
+  pub fn scan(
+    &self,
+    root_ref: Reference<SgRoot>,
+    env: Env,
+    rules: Vec<NapiConfig>,
+  ) -> Result<HashMap<String, Vec<SgNode>>> {
+    let lang = *self.0.lang();
+    let mut configs = vec![];
+    for (i, rule) in rules.into_iter().enumerate() {
+      let config = rule.parse_rule_config(lang, i.to_string())?;
+      if config.matcher.potential_kinds().is_none() {
+        let msg = format!("Rule `{}` must specify a kind to scan.", config.id);
+        return Err(Error::new(Status::InvalidArg, msg));
+      }
+      configs.push(config);
+    }
+    let mut found = vec![];
+    root_ref.clone(env)?.share_with(env, |root| {
+      let scan = CombinedScan::new(configs.iter().collect());
+      let pre_scan = scan.find(&root.0);
+      for (idx, matches) in scan.scan(&root.0, pre_scan, false).matches {
+        let rule = scan.get_rule(idx);
+        let matches: Vec<_> = matches
+          .into_iter()
+          .map(|n| {
+            let fix = fix_edit(&n, &rule.matcher);
+            (n, fix)
+          })
+          .collect();
+        found.push((rule.id.clone(), matches));
+      }
+      Ok(())
+    })?;
+    let mut ret: HashMap<String, Vec<SgNode>> = HashMap::new();
+    for (id, matches) in found {
+      let mut nodes = vec![];
+      for (node_match, fix) in matches {
+        let inner = root_ref
+          .clone(env)?
+          .share_with(env, move |_| Ok(node_match))?;
+        let tree = self.0.clone();
+        nodes.push(SgNode { inner, fix, tree });
+      }
+      ret.entry(id).or_default().extend(nodes);
+    }
+    Ok(ret)
+  }
 }
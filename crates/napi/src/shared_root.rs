@@ -0,0 +1,59 @@
+//! Share parsed trees with worker threads. JS objects cannot be transferred between workers,
+//! but the native module is loaded once per process, so a tree registered by `SgRoot.share`
+//! can be opened in another worker by its handle without reading and parsing the file again.
+use ast_grep_core::AstGrep;
+use napi::anyhow::anyhow;
+use napi::bindgen_prelude::Result;
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use crate::doc::JsDoc;
+use crate::sg_node::SgRoot;
+
+static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
+static SHARED_ROOTS: Mutex<BTreeMap<u32, (AstGrep<JsDoc>, String)>> = Mutex::new(BTreeMap::new());
+
+pub fn share_root(root: &SgRoot) -> u32 {
+  let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+  let shared = ((*root.0).clone(), root.1.clone());
+  let mut roots = SHARED_ROOTS.lock().expect("should not poison");
+  roots.insert(handle, shared);
+  handle
+}
+
+/// Each opened root has its own copy of the tree, so workers never share mutable state.
+/// Copying a tree is cheap since tree-sitter shares unchanged subtrees.
+pub fn open_shared_root_impl(handle: u32) -> Result<SgRoot> {
+  let roots = SHARED_ROOTS.lock().expect("should not poison");
+  let (root, filename) = roots
+    .get(&handle)
+    .ok_or_else(|| anyhow!("shared root {handle} is not found or already released"))?;
+  Ok(SgRoot::new(root.clone(), filename.clone()))
+}
+
+pub fn release_shared_root_impl(handle: u32) -> bool {
+  let mut roots = SHARED_ROOTS.lock().expect("should not poison");
+  roots.remove(&handle).is_some()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_language::SupportLang;
+
+  #[test]
+  fn test_share_root() {
+    let doc = JsDoc::new("let a = 1".into(), SupportLang::JavaScript.into());
+    let root = SgRoot::new(AstGrep::doc(doc), "a.js".into());
+    let handle = share_root(&root);
+    let opened = open_shared_root_impl(handle).expect("should open");
+    assert_eq!(opened.0.root().text(), "let a = 1");
+    assert_eq!(opened.1, "a.js");
+    assert!(opened.0.root().find("let $A = 1").is_some());
+    assert!(release_shared_root_impl(handle));
+    assert!(!release_shared_root_impl(handle));
+    assert!(open_shared_root_impl(handle).is_err());
+  }
+}
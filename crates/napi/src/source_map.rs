@@ -0,0 +1,265 @@
+//! Source map v3 support to report matches in build output, e.g. compiled JSX,
+//! against the original sources. Only the fields needed for positions are decoded.
+use napi::anyhow::{anyhow, Context, Result as Ret};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde::Deserialize;
+
+use crate::sg_node::{Pos, Range};
+
+#[derive(Deserialize)]
+struct RawSourceMap {
+  version: u32,
+  #[serde(default)]
+  sources: Vec<Option<String>>,
+  #[serde(rename = "sourceRoot")]
+  source_root: Option<String>,
+  mappings: String,
+}
+
+/// A segment mapping a generated column to a position in a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mapping {
+  generated_column: u32,
+  source: u32,
+  line: u32,
+  column: u32,
+}
+
+/// A decoded source map, passed with the generated code to translate positions back.
+#[napi]
+pub struct SourceMap {
+  sources: Vec<String>,
+  /// mappings of each generated line, sorted by generated column
+  lines: Vec<Vec<Mapping>>,
+}
+
+/// A position in an original source file.
+#[napi(object)]
+#[derive(Clone)]
+pub struct OriginalPos {
+  /// line number starting from 0
+  pub line: u32,
+  /// column number starting from 0
+  pub column: u32,
+}
+
+/// A range in an original source file.
+#[napi(object)]
+pub struct OriginalRange {
+  /// The source file of the range, joined with `sourceRoot` of the map
+  pub source: String,
+  /// starting position in the source
+  pub start: OriginalPos,
+  /// ending position in the source, same as `start` if the end is mapped to another source
+  pub end: OriginalPos,
+}
+
+#[napi]
+impl SourceMap {
+  /// Decode a source map in JSON. Index maps with `sections` are not supported.
+  #[napi(constructor)]
+  pub fn new(json: String) -> Result<Self> {
+    Ok(Self::parse(&json)?)
+  }
+
+  /// Translate a range in the generated code, e.g. `node.range()`, to the original source.
+  /// Returns null if the start of the range is not mapped.
+  #[napi]
+  pub fn original_range(&self, range: Range) -> Option<OriginalRange> {
+    let start = self.lookup(&range.start)?;
+    let end = self
+      .lookup_end(&range.end)
+      .filter(|e| e.source == start.source);
+    let start_pos = start.shifted(range.start.column);
+    let end_pos = match end {
+      Some(end) => end.shifted(range.end.column),
+      None => start_pos.clone(),
+    };
+    Some(OriginalRange {
+      source: self.sources[start.source as usize].clone(),
+      start: start_pos,
+      end: end_pos,
+    })
+  }
+}
+
+impl Mapping {
+  /// Columns in the same segment are shifted by the same offset as the generated column.
+  fn shifted(&self, generated_column: u32) -> OriginalPos {
+    OriginalPos {
+      line: self.line,
+      column: self.column + generated_column.saturating_sub(self.generated_column),
+    }
+  }
+}
+
+impl SourceMap {
+  fn parse(json: &str) -> Ret<Self> {
+    let raw: RawSourceMap = serde_json::from_str(json).context("invalid source map")?;
+    if raw.version != 3 {
+      return Err(anyhow!("unsupported source map version {}", raw.version));
+    }
+    let root = raw.source_root.unwrap_or_default();
+    let sources = raw
+      .sources
+      .into_iter()
+      .map(|s| join_source(&root, &s.unwrap_or_default()))
+      .collect::<Vec<_>>();
+    let lines = decode_mappings(&raw.mappings, sources.len())?;
+    Ok(Self { sources, lines })
+  }
+
+  /// The last mapping at or before the position in the same generated line.
+  fn lookup(&self, pos: &Pos) -> Option<Mapping> {
+    let line = self.lines.get(pos.line as usize)?;
+    let idx = line.partition_point(|m| m.generated_column <= pos.column);
+    line.get(idx.checked_sub(1)?).copied()
+  }
+
+  /// End positions are exclusive, so the mapping of the char before the end is used.
+  fn lookup_end(&self, pos: &Pos) -> Option<Mapping> {
+    let column = pos.column.checked_sub(1)?;
+    self.lookup(&Pos {
+      line: pos.line,
+      column,
+      index: pos.index,
+    })
+  }
+}
+
+fn join_source(root: &str, source: &str) -> String {
+  if root.is_empty() || source.starts_with('/') || source.contains("://") {
+    source.to_string()
+  } else {
+    format!("{}/{source}", root.trim_end_matches('/'))
+  }
+}
+
+fn decode_mappings(mappings: &str, source_count: usize) -> Ret<Vec<Vec<Mapping>>> {
+  let mut lines = vec![];
+  // source, line and column are relative to the previous segment in the whole map
+  let (mut source, mut line, mut column) = (0i64, 0i64, 0i64);
+  for generated_line in mappings.split(';') {
+    let mut segments = vec![];
+    let mut generated_column = 0i64;
+    for segment in generated_line.split(',').filter(|s| !s.is_empty()) {
+      let fields = decode_vlq(segment)?;
+      generated_column += fields[0];
+      // segments without source position map to nothing
+      if fields.len() < 4 {
+        continue;
+      }
+      source += fields[1];
+      line += fields[2];
+      column += fields[3];
+      if source < 0 || source as usize >= source_count || line < 0 || column < 0 {
+        return Err(anyhow!("invalid mapping segment `{segment}`"));
+      }
+      segments.push(Mapping {
+        generated_column: generated_column as u32,
+        source: source as u32,
+        line: line as u32,
+        column: column as u32,
+      });
+    }
+    segments.sort_by_key(|m| m.generated_column);
+    lines.push(segments);
+  }
+  Ok(lines)
+}
+
+fn decode_vlq(segment: &str) -> Ret<Vec<i64>> {
+  let mut fields = vec![];
+  let (mut value, mut shift) = (0i64, 0);
+  for c in segment.bytes() {
+    let digit = match c {
+      b'A'..=b'Z' => c - b'A',
+      b'a'..=b'z' => c - b'a' + 26,
+      b'0'..=b'9' => c - b'0' + 52,
+      b'+' => 62,
+      b'/' => 63,
+      _ => {
+        return Err(anyhow!(
+          "invalid base64 char in mapping segment `{segment}`"
+        ))
+      }
+    } as i64;
+    value += (digit & 31) << shift;
+    if digit & 32 != 0 {
+      shift += 5;
+      // i64 can hold 12 digits
+      if shift >= 60 {
+        return Err(anyhow!("mapping segment `{segment}` overflows"));
+      }
+      continue;
+    }
+    // the lowest bit is the sign
+    let decoded = value >> 1;
+    fields.push(if value & 1 == 1 { -decoded } else { decoded });
+    (value, shift) = (0, 0);
+  }
+  if shift != 0 || !matches!(fields.len(), 1 | 4 | 5) {
+    return Err(anyhow!("invalid mapping segment `{segment}`"));
+  }
+  Ok(fields)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn pos(line: u32, column: u32) -> Pos {
+    Pos {
+      line,
+      column,
+      index: 0,
+    }
+  }
+
+  #[test]
+  fn test_decode_vlq() {
+    assert_eq!(decode_vlq("AAAA").unwrap(), vec![0, 0, 0, 0]);
+    assert_eq!(decode_vlq("SAAQ").unwrap(), vec![9, 0, 0, 8]);
+    assert_eq!(decode_vlq("gBAAD").unwrap(), vec![16, 0, 0, -1]);
+    assert!(decode_vlq("g").is_err());
+    assert!(decode_vlq("AA").is_err());
+    assert!(decode_vlq("gggggggggggggB").is_err());
+  }
+
+  #[test]
+  fn test_lookup() {
+    // line 0: col 0 -> a.ts 0:0, col 9 -> a.ts 0:8
+    // line 1: col 2 -> a.ts 2:4
+    let json = r#"{"version":3,"sources":["a.ts"],"sourceRoot":"src","mappings":"AAAA,SAAQ;EAEJ"}"#;
+    let map = SourceMap::parse(json).expect("should parse");
+    assert_eq!(map.sources, vec!["src/a.ts"]);
+    let m = map.lookup(&pos(0, 10)).expect("should map");
+    assert_eq!((m.line, m.column), (0, 8));
+    assert_eq!(m.shifted(10).column, 9);
+    let m = map.lookup(&pos(1, 5)).expect("should map");
+    assert_eq!((m.line, m.column), (2, 4));
+    assert!(map.lookup(&pos(1, 1)).is_none());
+    assert!(map.lookup(&pos(2, 0)).is_none());
+  }
+
+  #[test]
+  fn test_original_range() {
+    let json = r#"{"version":3,"sources":["a.ts"],"mappings":"AAAA,SAAQ"}"#;
+    let map = SourceMap::parse(json).expect("should parse");
+    let range = Range {
+      start: pos(0, 9),
+      end: pos(0, 12),
+    };
+    let original = map.original_range(range).expect("should map");
+    assert_eq!(original.source, "a.ts");
+    assert_eq!((original.start.line, original.start.column), (0, 8));
+    assert_eq!((original.end.line, original.end.column), (0, 11));
+  }
+
+  #[test]
+  fn test_invalid_map() {
+    assert!(SourceMap::parse(r#"{"version":2,"mappings":""}"#).is_err());
+    assert!(SourceMap::parse(r#"{"version":3,"sources":[],"mappings":"AAAA"}"#).is_err());
+  }
+}
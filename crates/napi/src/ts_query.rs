@@ -0,0 +1,114 @@
+//! Raw tree-sitter queries (`.scm`) for users migrating queries from other tooling.
+//! ast-grep trees are built on a facade of tree-sitter without query cursors,
+//! so the source is parsed again by the native tree-sitter crate to run the query.
+use ast_grep_core::AstGrep;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use tree_sitter_native::{Node, Parser, Query, QueryCursor};
+
+use crate::doc::JsDoc;
+use crate::sg_node::{to_pos, Range};
+
+/// A node captured by a tree-sitter query.
+#[napi(object)]
+pub struct QueryCapture {
+  /// Name of the capture without `@`, e.g. `name` for `@name`
+  pub name: String,
+  /// Index of the pattern in the query that captured the node
+  pub pattern_index: u32,
+  pub text: String,
+  pub range: Range,
+}
+
+/// Run the query on the tree and return captures in the order they appear in the source.
+pub fn query_ts_impl(root: &AstGrep<JsDoc>, source: &str) -> Result<Vec<QueryCapture>> {
+  let lang = root.lang().native_language();
+  let query = Query::new(&lang, source).map_err(|e| {
+    Error::new(
+      Status::InvalidArg,
+      format!("invalid tree-sitter query: {e}"),
+    )
+  })?;
+  let src = root.inner.get_source().as_slice();
+  let mut parser = Parser::new();
+  parser
+    .set_language(&lang)
+    .map_err(|e| Error::new(Status::GenericFailure, e.to_string()))?;
+  let tree = parser.parse_utf16(src, None).ok_or_else(|| {
+    Error::new(
+      Status::GenericFailure,
+      "cannot parse the source".to_string(),
+    )
+  })?;
+  // byte offsets of a UTF-16 tree are two times of the string index
+  let text =
+    |node: Node| String::from_utf16_lossy(&src[node.start_byte() / 2..node.end_byte() / 2]);
+  let mut cursor = QueryCursor::new();
+  // text predicates like `#eq?` compare UTF-8 text of the captured nodes
+  let text_provider = |node: Node| std::iter::once(text(node).into_bytes());
+  let names = query.capture_names();
+  let mut captures = vec![];
+  for (m, idx) in cursor.captures(&query, tree.root_node(), text_provider) {
+    let capture = m.captures[idx];
+    let node = capture.node;
+    let (start, end) = (node.start_position(), node.end_position());
+    captures.push(QueryCapture {
+      name: names[capture.index as usize].to_string(),
+      pattern_index: m.pattern_index as u32,
+      text: text(node),
+      range: Range {
+        start: to_pos((start.row, start.column), node.start_byte()),
+        end: to_pos((end.row, end.column), node.end_byte()),
+      },
+    });
+  }
+  Ok(captures)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_language::SupportLang;
+
+  fn query(src: &str, query: &str) -> Result<Vec<QueryCapture>> {
+    let doc = JsDoc::new(src.into(), SupportLang::JavaScript.into());
+    query_ts_impl(&AstGrep::doc(doc), query)
+  }
+
+  #[test]
+  fn test_query_ts() {
+    let captures = query(
+      "let 你好 = 1\nfoo(你好, bar)",
+      "(call_expression function: (identifier) @callee) (arguments (identifier) @arg)",
+    )
+    .expect("should query");
+    let captured: Vec<_> = captures
+      .iter()
+      .map(|c| (c.name.as_str(), c.pattern_index, c.text.as_str()))
+      .collect();
+    assert_eq!(
+      captured,
+      vec![("callee", 0, "foo"), ("arg", 1, "你好"), ("arg", 1, "bar")]
+    );
+    // ranges are in JS string index like `SgNode.range`
+    let bar = &captures[2].range.start;
+    assert_eq!((bar.line, bar.column, bar.index), (1, 8, 19));
+  }
+
+  #[test]
+  fn test_query_predicate() {
+    let captures = query(
+      "foo(1); bar(2)",
+      r#"((call_expression function: (identifier) @f) (#eq? @f "bar"))"#,
+    )
+    .expect("should query");
+    assert_eq!(captures.len(), 1);
+    assert_eq!(captures[0].text, "bar");
+  }
+
+  #[test]
+  fn test_invalid_query() {
+    let err = query("a", "(not_a_kind) @a").err().expect("should fail");
+    assert!(err.reason.contains("invalid tree-sitter query"));
+  }
+}
@@ -0,0 +1,222 @@
+//! UTF-8 counterpart of `SgRoot`/`SgNode` for `parseBuffer`.
+//! Source is kept as UTF-8 instead of converted to UTF-16 like `JsDoc`,
+//! so positions and ranges are reported in byte offsets, not JS string indices.
+use ast_grep_core::source::StrDoc;
+use ast_grep_core::{matcher::KindMatcher, AstGrep, NodeMatch, Pattern};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use crate::compiled_rule::{compile_matcher, RuleMatcher};
+use crate::napi_lang::NapiLang;
+use crate::pattern_object::{NapiPattern, PatternObject};
+use crate::sg_node::{Pos, Range};
+
+type Utf8Doc = StrDoc<NapiLang>;
+
+fn to_byte_pos(pos: (usize, usize), offset: usize) -> Pos {
+  Pos {
+    line: pos.0 as u32,
+    column: pos.1 as u32,
+    index: offset as u32,
+  }
+}
+
+/// Parsed tree of UTF-8 source from `parseBuffer`.
+#[napi]
+pub struct Utf8Root(pub(crate) AstGrep<Utf8Doc>);
+
+#[napi]
+impl Utf8Root {
+  /// Returns the root node of the tree.
+  #[napi]
+  pub fn root(&self, root_ref: Reference<Utf8Root>, env: Env) -> Result<Utf8Node> {
+    let inner = root_ref.share_with(env, |root| Ok(root.0.root().into()))?;
+    Ok(Utf8Node { inner })
+  }
+}
+
+/// Node of a `Utf8Root`. `column` and `index` of its range are in bytes.
+#[napi]
+pub struct Utf8Node {
+  inner: SharedReference<Utf8Root, NodeMatch<'static, Utf8Doc>>,
+}
+
+#[napi]
+impl Utf8Node {
+  /// Range of the node in byte offsets of the UTF-8 source.
+  #[napi]
+  pub fn range(&self) -> Range {
+    let byte_range = self.inner.range();
+    Range {
+      start: to_byte_pos(self.inner.start_pos(), byte_range.start),
+      end: to_byte_pos(self.inner.end_pos(), byte_range.end),
+    }
+  }
+  #[napi]
+  pub fn is_leaf(&self) -> bool {
+    self.inner.is_leaf()
+  }
+  #[napi]
+  pub fn is_named(&self) -> bool {
+    self.inner.is_named()
+  }
+  /// Returns the string name of the node kind
+  #[napi]
+  pub fn kind(&self) -> String {
+    self.inner.kind().to_string()
+  }
+  #[napi]
+  pub fn text(&self) -> String {
+    self.inner.text().to_string()
+  }
+  #[napi]
+  pub fn matches(&self, m: NapiPattern) -> Result<bool> {
+    match m {
+      Either::A(m) => Ok(self.inner.matches(&*m)),
+      Either::B(m) => Ok(self.inner.matches(m.build(*self.inner.lang())?)),
+    }
+  }
+
+  #[napi]
+  pub fn get_match(
+    &self,
+    reference: Reference<Utf8Node>,
+    env: Env,
+    m: String,
+  ) -> Result<Option<Utf8Node>> {
+    let node = self
+      .inner
+      .get_env()
+      .get_match(&m)
+      .cloned()
+      .map(NodeMatch::from);
+    Self::transpose_option(reference, env, node)
+  }
+  #[napi]
+  pub fn get_multiple_matches(
+    &self,
+    reference: Reference<Utf8Node>,
+    env: Env,
+    m: String,
+  ) -> Result<Vec<Utf8Node>> {
+    let nodes = self
+      .inner
+      .get_env()
+      .get_multiple_matches(&m)
+      .into_iter()
+      .map(NodeMatch::from);
+    Self::from_iter_to_vec(&reference, env, nodes)
+  }
+  /// Returns the value of a metavariable computed by `transform` of the rule that found the node.
+  #[napi]
+  pub fn get_transformed(&self, m: String) -> Option<String> {
+    let bytes = self.inner.get_env().get_transformed(&m)?;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+  }
+
+  #[napi]
+  pub fn find(
+    &self,
+    reference: Reference<Utf8Node>,
+    env: Env,
+    matcher: Either4<String, u16, RuleMatcher, PatternObject>,
+  ) -> Result<Option<Utf8Node>> {
+    let lang = *reference.inner.lang();
+    let node_match = match matcher {
+      Either4::A(pattern) => reference.inner.find(Pattern::new(&pattern, lang)),
+      Either4::B(kind) => reference.inner.find(KindMatcher::from_id(kind)),
+      Either4::C(matcher) => reference.inner.find(&*compile_matcher(matcher, lang)?),
+      Either4::D(pattern) => reference.inner.find(pattern.build(lang)?),
+    };
+    Self::transpose_option(reference, env, node_match)
+  }
+
+  #[napi]
+  pub fn find_all(
+    &self,
+    reference: Reference<Utf8Node>,
+    env: Env,
+    matcher: Either4<String, u16, RuleMatcher, PatternObject>,
+  ) -> Result<Vec<Utf8Node>> {
+    let lang = *reference.inner.lang();
+    let all_matches: Vec<_> = match matcher {
+      Either4::A(pattern) => {
+        let pattern = Pattern::new(&pattern, lang);
+        reference.inner.find_all(pattern).collect()
+      }
+      Either4::B(kind) => {
+        let pattern = KindMatcher::from_id(kind);
+        reference.inner.find_all(pattern).collect()
+      }
+      Either4::C(matcher) => {
+        let rule = compile_matcher(matcher, lang)?;
+        reference.inner.find_all(&*rule).collect()
+      }
+      Either4::D(pattern) => {
+        let pattern = pattern.build(lang)?;
+        reference.inner.find_all(pattern).collect()
+      }
+    };
+    Self::from_iter_to_vec(&reference, env, all_matches.into_iter())
+  }
+
+  #[napi]
+  pub fn children(&self, reference: Reference<Utf8Node>, env: Env) -> Result<Vec<Utf8Node>> {
+    let children = reference.inner.children().map(NodeMatch::from);
+    Self::from_iter_to_vec(&reference, env, children)
+  }
+
+  /// Finds the child node in the `field`
+  #[napi]
+  pub fn field(
+    &self,
+    reference: Reference<Utf8Node>,
+    env: Env,
+    name: String,
+  ) -> Result<Option<Utf8Node>> {
+    let node = reference.inner.field(&name).map(NodeMatch::from);
+    Self::transpose_option(reference, env, node)
+  }
+
+  #[napi]
+  pub fn parent(&self, reference: Reference<Utf8Node>, env: Env) -> Result<Option<Utf8Node>> {
+    let node = reference.inner.parent().map(NodeMatch::from);
+    Self::transpose_option(reference, env, node)
+  }
+
+  fn transpose_option(
+    reference: Reference<Utf8Node>,
+    env: Env,
+    node: Option<NodeMatch<'static, Utf8Doc>>,
+  ) -> Result<Option<Utf8Node>> {
+    if let Some(node) = node {
+      let root_ref = reference.inner.clone_owner(env)?;
+      let inner = root_ref.share_with(env, move |_| Ok(node))?;
+      Ok(Some(Utf8Node { inner }))
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn from_iter_to_vec(
+    reference: &Reference<Utf8Node>,
+    env: Env,
+    iter: impl Iterator<Item = NodeMatch<'static, Utf8Doc>>,
+  ) -> Result<Vec<Utf8Node>> {
+    let mut ret = vec![];
+    for node in iter {
+      let root_ref = reference.inner.clone_owner(env)?;
+      let inner = root_ref.share_with(env, move |_| Ok(node))?;
+      ret.push(Utf8Node { inner });
+    }
+    Ok(ret)
+  }
+}
+
+/// Parse UTF-8 bytes without converting them to UTF-16 like `parse`.
+pub fn parse_buffer_impl(lang: NapiLang, src: Buffer) -> Result<Utf8Root> {
+  let src = String::from_utf8(src.to_vec())
+    .map_err(|e| Error::new(Status::InvalidArg, format!("source is not UTF-8: {e}")))?;
+  let doc = StrDoc { src, lang };
+  Ok(Utf8Root(AstGrep::doc(doc)))
+}
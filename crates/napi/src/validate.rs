@@ -0,0 +1,303 @@
+//! Validate a rule directory in one call, e.g. as a CI gate before shipping a rule library.
+use ast_grep_config::{
+  from_str, from_yaml_string_with_source, DeserializeEnv, GlobalRules, ReferentRuleError,
+  RuleConfig,
+};
+use ast_grep_language::SupportLang;
+use ignore::WalkBuilder;
+use napi::anyhow::{anyhow, Result as Ret};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[napi(object)]
+pub struct ValidateConfigOptions {
+  /// Directories of global utility rules, relative to the validated directory.
+  /// Files in them are registered as utils instead of validated as rules.
+  pub util_dirs: Option<Vec<String>>,
+}
+
+#[napi(object)]
+pub struct RuleFileError {
+  pub file: String,
+  pub error: String,
+}
+
+#[napi(object)]
+pub struct DuplicateRuleId {
+  pub id: String,
+  /// files defining the id, one entry per definition
+  pub files: Vec<String>,
+}
+
+#[napi(object)]
+pub struct UndefinedUtil {
+  pub file: String,
+  /// id used in `matches` but not defined in `utils` or global utility rules
+  pub util: String,
+}
+
+#[napi(object)]
+pub struct UnusedCapture {
+  pub id: String,
+  pub file: String,
+  /// meta variables captured by the rule but never referenced elsewhere in it
+  pub captures: Vec<String>,
+}
+
+#[napi(object)]
+pub struct ConfigDirReport {
+  /// false if any rule file fails to compile, references undefined utils or has duplicate ids
+  pub valid: bool,
+  /// ids of compiled rules, in file path order
+  pub rule_ids: Vec<String>,
+  pub errors: Vec<RuleFileError>,
+  pub duplicate_ids: Vec<DuplicateRuleId>,
+  pub undefined_utils: Vec<UndefinedUtil>,
+  /// unused captures are reported but do not fail the validation
+  pub unused_captures: Vec<UnusedCapture>,
+}
+
+pub fn validate_config_dir_impl(
+  path: String,
+  options: Option<ValidateConfigOptions>,
+) -> Result<ConfigDirReport> {
+  let base = Path::new(&path);
+  if !base.is_dir() {
+    return Err(anyhow!("`{path}` is not a directory").into());
+  }
+  let util_dirs: Vec<PathBuf> = options
+    .and_then(|o| o.util_dirs)
+    .unwrap_or_default()
+    .iter()
+    .map(|dir| base.join(dir))
+    .collect();
+  let mut report = ConfigDirReport {
+    valid: true,
+    rule_ids: vec![],
+    errors: vec![],
+    duplicate_ids: vec![],
+    undefined_utils: vec![],
+    unused_captures: vec![],
+  };
+  let globals = match read_global_utils(&util_dirs) {
+    Ok(globals) => globals,
+    Err(error) => {
+      report.errors.push(RuleFileError {
+        file: path.clone(),
+        error: format!("invalid global utility rules: {error}"),
+      });
+      GlobalRules::default()
+    }
+  };
+  let rule_files = find_yaml_files(base)?
+    .into_iter()
+    .filter(|file| !util_dirs.iter().any(|dir| file.starts_with(dir)));
+  let mut id_files: HashMap<String, Vec<String>> = HashMap::new();
+  for file in rule_files {
+    let file_name = file.to_string_lossy().to_string();
+    let rules = fs::read_to_string(&file)
+      .map_err(|e| anyhow!("cannot read rule file: {e}"))
+      .and_then(|yaml| Ok(from_yaml_string_with_source(&yaml, &globals, &file)?));
+    let rules: Vec<RuleConfig<SupportLang>> = match rules {
+      Ok(rules) => rules,
+      Err(error) => {
+        if let Some(util) = find_undefined_util(error.as_ref()) {
+          report.undefined_utils.push(UndefinedUtil {
+            file: file_name,
+            util,
+          });
+        } else {
+          report.errors.push(RuleFileError {
+            file: file_name,
+            error: format_error_chain(error.as_ref()),
+          });
+        }
+        continue;
+      }
+    };
+    for rule in rules {
+      let captures = find_unused_captures(&rule);
+      if !captures.is_empty() {
+        report.unused_captures.push(UnusedCapture {
+          id: rule.id.clone(),
+          file: file_name.clone(),
+          captures,
+        });
+      }
+      id_files
+        .entry(rule.id.clone())
+        .or_default()
+        .push(file_name.clone());
+      report.rule_ids.push(rule.id.clone());
+    }
+  }
+  let mut duplicates: Vec<_> = id_files
+    .into_iter()
+    .filter(|(_, files)| files.len() > 1)
+    .map(|(id, files)| DuplicateRuleId { id, files })
+    .collect();
+  duplicates.sort_by(|a, b| a.id.cmp(&b.id));
+  report.duplicate_ids = duplicates;
+  report.valid = report.errors.is_empty()
+    && report.undefined_utils.is_empty()
+    && report.duplicate_ids.is_empty();
+  Ok(report)
+}
+
+/// YAML files under the directory, sorted by path for a stable report.
+fn find_yaml_files(dir: &Path) -> Ret<Vec<PathBuf>> {
+  let mut files = vec![];
+  for entry in WalkBuilder::new(dir).build() {
+    let entry = entry?;
+    let path = entry.path();
+    let is_yaml = path
+      .extension()
+      .map_or(false, |ext| ext == "yml" || ext == "yaml");
+    if is_yaml && entry.file_type().map_or(false, |t| t.is_file()) {
+      files.push(path.to_path_buf());
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+fn read_global_utils(util_dirs: &[PathBuf]) -> Ret<GlobalRules<SupportLang>> {
+  let mut utils = vec![];
+  for dir in util_dirs {
+    for file in find_yaml_files(dir)? {
+      let yaml = fs::read_to_string(&file)?;
+      let util = from_str(&yaml).map_err(|e| anyhow!("`{}`: {e}", file.display()))?;
+      utils.push(util);
+    }
+  }
+  Ok(DeserializeEnv::parse_global_utils(utils)?)
+}
+
+fn find_undefined_util(error: &(dyn StdError + 'static)) -> Option<String> {
+  let mut source = Some(error);
+  while let Some(err) = source {
+    if let Some(ReferentRuleError::UndefinedUtil(util)) = err.downcast_ref() {
+      return Some(util.clone());
+    }
+    source = err.source();
+  }
+  None
+}
+
+fn format_error_chain(error: &(dyn StdError + 'static)) -> String {
+  let mut message = error.to_string();
+  let mut source = error.source();
+  while let Some(err) = source {
+    message.push_str(&format!(" {err}"));
+    source = err.source();
+  }
+  message
+}
+
+/// A capture is unused if its name appears only once in the whole rule config.
+/// Names starting with `_` are meant to be unused. It is heuristic, e.g. it cannot see
+/// meta variables referenced by utility rules defined in other files.
+fn find_unused_captures(rule: &RuleConfig<SupportLang>) -> Vec<String> {
+  let Ok(config) = serde_json::to_string(&**rule) else {
+    return vec![];
+  };
+  let constraints = rule.constraints.as_ref();
+  let mut unused: Vec<_> = rule
+    .matcher
+    .defined_vars()
+    .into_iter()
+    .filter(|var| !var.starts_with('_'))
+    .filter(|var| !constraints.map_or(false, |c| c.contains_key(*var)))
+    .filter(|var| count_var(&config, var) <= 1)
+    .map(String::from)
+    .collect();
+  unused.sort();
+  unused
+}
+
+fn count_var(text: &str, var: &str) -> usize {
+  let needle = format!("${var}");
+  text
+    .match_indices(&needle)
+    .filter(|(i, _)| {
+      let rest = &text[i + needle.len()..];
+      !rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+    })
+    .count()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use tempfile::TempDir;
+
+  fn write(dir: &Path, name: &str, content: &str) {
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).expect("should create dir");
+    fs::write(path, content).expect("should write");
+  }
+
+  #[test]
+  fn test_validate_config_dir() {
+    let tmp = TempDir::new().expect("should create dir");
+    let dir = tmp.path();
+    let lang = "language: TypeScript";
+    write(
+      dir,
+      "a.yml",
+      &format!("id: a\n{lang}\nrule: {{pattern: foo($A)}}"),
+    );
+    write(
+      dir,
+      "b.yml",
+      &format!("id: a\n{lang}\nrule: {{pattern: $A + $A}}"),
+    );
+    write(
+      dir,
+      "c.yml",
+      &format!("id: c\n{lang}\nrule: {{matches: nope}}"),
+    );
+    write(
+      dir,
+      "d.yml",
+      &format!("id: d\n{lang}\nrule: {{kind: wrong}}"),
+    );
+    write(
+      dir,
+      "e.yml",
+      &format!("id: e\n{lang}\nrule: {{matches: util}}"),
+    );
+    write(
+      dir,
+      "utils/u.yml",
+      &format!("id: util\n{lang}\nrule: {{kind: number}}"),
+    );
+    let options = ValidateConfigOptions {
+      util_dirs: Some(vec!["utils".into()]),
+    };
+    let path = dir.to_string_lossy().to_string();
+    let report = validate_config_dir_impl(path, Some(options)).expect("should validate");
+    assert!(!report.valid);
+    assert_eq!(report.rule_ids, ["a", "a", "e"]);
+    assert_eq!(report.duplicate_ids.len(), 1);
+    assert_eq!(report.duplicate_ids[0].files.len(), 2);
+    assert_eq!(report.undefined_utils.len(), 1);
+    assert_eq!(report.undefined_utils[0].util, "nope");
+    assert_eq!(report.errors.len(), 1);
+    assert!(report.errors[0].file.ends_with("d.yml"));
+    assert_eq!(report.unused_captures.len(), 1);
+    assert_eq!(report.unused_captures[0].captures, ["A"]);
+    assert!(report.unused_captures[0].file.ends_with("a.yml"));
+  }
+
+  #[test]
+  fn test_count_var() {
+    assert_eq!(count_var("$A + $AB + $$$A", "A"), 2);
+    assert_eq!(count_var("$A_1", "A"), 0);
+  }
+}
@@ -0,0 +1,245 @@
+//! `findInVirtualFiles`, searching sources already held in memory, e.g. by bundler plugins,
+//! with the same language inference and parallel matching as `findInFiles` but without touching disk.
+use ast_grep_config::RuleCore;
+use ast_grep_core::pinned::PinnedNodeData;
+use ast_grep_core::AstGrep;
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{JsFunction, Task};
+use napi_derive::napi;
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::compiled_rule::{compile_matcher, RuleMatcher};
+use crate::doc::{JsDoc, NapiConfig};
+use crate::find_files::{from_pinned_data, PinnedNodes, THREAD_FUNC_QUEUE_SIZE};
+use crate::napi_lang::{Lang, LangOption, NapiLang};
+use crate::sfc::{is_sfc, sfc_roots};
+use crate::sg_node::fix_edit;
+
+#[napi(object)]
+pub struct VirtualFilesOptions {
+  /// Globs of files to treat as a language, e.g. `{ html: ['*.vue'] }`, like `FileOption`.
+  pub language_globs: Option<HashMap<String, Vec<String>>>,
+}
+
+/// Rules for the languages of files.
+enum VirtualRules {
+  /// A `Matcher`, or a config with `language`, only matches files in the language.
+  Fixed(NapiLang, Arc<RuleCore<NapiLang>>),
+  /// A config without `language` is compiled for each language found in files.
+  /// Languages the config cannot compile for, e.g. the pattern is invalid, are skipped as None.
+  Inferred(
+    Box<NapiConfig>,
+    Mutex<HashMap<NapiLang, Option<Arc<RuleCore<NapiLang>>>>>,
+  ),
+}
+
+impl VirtualRules {
+  fn new(matcher: RuleMatcher) -> Result<Self> {
+    let lang = match &matcher {
+      Either::A(compiled) => compiled.lang(),
+      Either::B(config) => match config.language {
+        Some(lang) => lang,
+        None => return Ok(Self::Inferred(Box::new(config.clone()), Mutex::default())),
+      },
+    };
+    Ok(Self::Fixed(lang, compile_matcher(matcher, lang)?))
+  }
+
+  /// The rule for files in `lang`, or None if they are not matched.
+  fn get(&self, lang: NapiLang) -> Option<Arc<RuleCore<NapiLang>>> {
+    match self {
+      Self::Fixed(expected, rule) => (*expected == lang).then(|| rule.clone()),
+      Self::Inferred(config, rules) => {
+        let mut rules = rules.lock().expect("should not poison");
+        rules
+          .entry(lang)
+          .or_insert_with(|| {
+            let config = NapiConfig::clone(config);
+            config.parse_with(lang).ok().map(Arc::new)
+          })
+          .clone()
+      }
+    }
+  }
+}
+
+/// Matches in each root of the file with matches.
+/// Files of unknown languages, or languages the rule cannot compile for, are skipped.
+fn find_virtual(
+  path: String,
+  src: String,
+  lang_option: &LangOption,
+  rules: &VirtualRules,
+) -> Vec<PinnedNodes> {
+  let Some(lang) = lang_option.get_lang(Path::new(&path)) else {
+    return vec![];
+  };
+  let roots = if is_sfc(Path::new(&path)) {
+    sfc_roots(src)
+  } else {
+    vec![AstGrep::doc(JsDoc::new(src, lang))]
+  };
+  let mut ret = vec![];
+  for root in roots {
+    let Some(rule) = rules.get(*root.lang()) else {
+      continue;
+    };
+    let mut pinned = PinnedNodeData::new(root.inner, |r| r.root().find_all(&*rule).collect());
+    let hits: &Vec<_> = pinned.get_data();
+    if !hits.is_empty() {
+      let fixes = hits.iter().map(|n| fix_edit(n, &rule)).collect();
+      ret.push(PinnedNodes(pinned, path.clone(), fixes));
+    }
+  }
+  ret
+}
+
+pub struct FindInVirtualFiles {
+  files: Vec<(String, String)>,
+  lang_option: LangOption,
+  rules: VirtualRules,
+  tsfn: ThreadsafeFunction<PinnedNodes, ErrorStrategy::CalleeHandled>,
+}
+
+impl Task for FindInVirtualFiles {
+  type Output = u32;
+  type JsValue = u32;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let files = Mutex::new(std::mem::take(&mut self.files).into_iter());
+    let next_file = || files.lock().expect("should not poison").next();
+    let sent = AtomicU32::new(0);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    std::thread::scope(|s| {
+      for _ in 0..threads {
+        s.spawn(|| {
+          while let Some((path, src)) = next_file() {
+            let pinned_roots = find_virtual(path, src, &self.lang_option, &self.rules);
+            sent.fetch_add(pinned_roots.len() as u32, Ordering::AcqRel);
+            for pinned in pinned_roots {
+              self
+                .tsfn
+                .call(Ok(pinned), ThreadsafeFunctionCallMode::Blocking);
+            }
+          }
+        });
+      }
+    });
+    Ok(sent.load(Ordering::Acquire))
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+}
+
+/// Find nodes in sources held in memory, keyed by their paths, without reading files from disk.
+/// Languages are inferred from paths, and Vue or Svelte files treated as html by `languageGlobs`
+/// are matched in their blocks.
+/// A `Matcher`, or a config with `language`, only matches files in the language.
+/// A config without `language` skips files in languages it cannot compile for.
+/// `callback` is called with the matches of each root, and the promise resolves to the number of calls.
+#[napi(
+  ts_args_type = "files: Record<string, string>, matcher: NapiConfig | Matcher, callback: (err: null | Error, result: SgNode[]) => void, options?: VirtualFilesOptions",
+  ts_return_type = "Promise<number>"
+)]
+pub fn find_in_virtual_files(
+  files: HashMap<String, String>,
+  matcher: RuleMatcher,
+  callback: JsFunction,
+  options: Option<VirtualFilesOptions>,
+) -> Result<AsyncTask<FindInVirtualFiles>> {
+  let globs = options
+    .and_then(|o| o.language_globs)
+    .map(Lang::lang_globs)
+    .unwrap_or_default();
+  let tsfn = callback.create_threadsafe_function(THREAD_FUNC_QUEUE_SIZE, |ctx| {
+    from_pinned_data(vec![ctx.value], ctx.env)
+  })?;
+  Ok(AsyncTask::new(FindInVirtualFiles {
+    files: files.into_iter().collect(),
+    lang_option: LangOption::infer(&globs),
+    rules: VirtualRules::new(matcher)?,
+    tsfn,
+  }))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use ast_grep_language::SupportLang;
+  use serde_json::json;
+
+  fn config(language: Option<NapiLang>) -> NapiConfig {
+    config_with_pattern("console.log($A)", language)
+  }
+
+  fn config_with_pattern(pattern: &str, language: Option<NapiLang>) -> NapiConfig {
+    NapiConfig {
+      language,
+      ..NapiConfig::from_rule(json!({ "pattern": pattern }))
+    }
+  }
+
+  fn match_count(path: &str, src: &str, rules: &VirtualRules) -> usize {
+    let globs = HashMap::from([("html".to_string(), vec!["*.vue".to_string()])]);
+    let lang_option = LangOption::infer(&Lang::lang_globs(globs));
+    let pinned_roots = find_virtual(path.into(), src.into(), &lang_option, rules);
+    pinned_roots
+      .into_iter()
+      .map(|mut p| p.0.get_data().len())
+      .sum()
+  }
+
+  #[test]
+  fn test_inferred_rules() {
+    let rules = VirtualRules::Inferred(Box::new(config(None)), Mutex::default());
+    assert_eq!(
+      match_count("a.ts", "console.log(1); console.log(2)", &rules),
+      2
+    );
+    assert_eq!(match_count("a.js", "console.log(1)", &rules), 1);
+    let vue = "<script>console.log(1)</script>\n<script lang='ts'>console.log(2)</script>";
+    assert_eq!(match_count("a.vue", vue, &rules), 2);
+    assert_eq!(match_count("a.unknown", "console.log(1)", &rules), 0);
+  }
+
+  #[test]
+  fn test_skip_invalid_languages() {
+    // the pattern is valid in TypeScript but not in Python
+    let config = config_with_pattern("let $A = 1", None);
+    let rules = VirtualRules::Inferred(Box::new(config), Mutex::default());
+    assert_eq!(match_count("a.py", "a = 1", &rules), 0);
+    assert_eq!(match_count("a.ts", "let a = 1", &rules), 1);
+  }
+
+  #[test]
+  fn test_fixes_of_matches() {
+    let mut config = config(None);
+    config.fix = Some(json!("logger.log($A)"));
+    let rules = VirtualRules::Inferred(Box::new(config), Mutex::default());
+    let globs = HashMap::new();
+    let lang_option = LangOption::infer(&Lang::lang_globs(globs));
+    let src = "console.log(1); console.log(22)";
+    let pinned = find_virtual("a.ts".into(), src.into(), &lang_option, &rules);
+    let fixes: Vec<_> = pinned[0].2.iter().flatten().collect();
+    assert_eq!(fixes.len(), 2);
+    assert_eq!(fixes[0].inserted_text, "logger.log(1)");
+    assert_eq!((fixes[1].start_pos, fixes[1].end_pos), (16, 31));
+  }
+
+  #[test]
+  fn test_fixed_rules() {
+    let lang = NapiLang::from(SupportLang::TypeScript);
+    let rules = VirtualRules::Fixed(lang, Arc::new(config(None).parse_with(lang).unwrap()));
+    assert_eq!(match_count("a.ts", "console.log(1)", &rules), 1);
+    assert_eq!(match_count("a.js", "console.log(1)", &rules), 0);
+    let vue = "<script>console.log(1)</script>\n<script lang='ts'>console.log(2)</script>";
+    assert_eq!(match_count("a.vue", vue, &rules), 1);
+  }
+}